@@ -0,0 +1,145 @@
+use ethers::prelude::H256;
+
+use crate::{HashedLightMerkle, Hasher, KeccakHasher, LightMerkle, Merkle, VerifyingError};
+
+/// A proof that the tree at `old_count` leaves is a prefix of the tree at
+/// `new_count` leaves, i.e. that the newer tree was produced only by
+/// appending leaves to the older one.
+///
+/// The proof follows the path of the first leaf slot not in the old tree.
+/// At heights where that slot is a right child, the path holds the complete
+/// left subtree shared by both trees. Those nodes make up the old tree's
+/// leading branch, and determine its root. At heights where the slot is a
+/// left child, the path holds the right sibling in the new tree.
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsistencyProof<const N: usize> {
+    /// The number of leaves in the old tree
+    pub old_count: usize,
+    /// The number of leaves in the new tree
+    pub new_count: usize,
+    /// The node at index `old_count` in the new tree's leaf layer
+    pub leaf: H256,
+    /// The merkle branch
+    #[serde(with = "crate::proof::const_array_serde")]
+    pub path: [H256; N],
+}
+
+impl<const N: usize> ConsistencyProof<N> {
+    fn check_counts(&self) -> Result<(), VerifyingError> {
+        if self.old_count > self.new_count || LightMerkle::<N>::max_leaves() < self.new_count.into()
+        {
+            return Err(VerifyingError::MalformedConsistencyProof);
+        }
+        Ok(())
+    }
+
+    /// Calculate the keccak256 root of the old tree
+    pub fn old_root(&self) -> Result<H256, VerifyingError> {
+        self.old_root_with::<KeccakHasher>()
+    }
+
+    /// Calculate the root of the old tree using hasher `H`
+    pub fn old_root_with<H: Hasher>(&self) -> Result<H256, VerifyingError> {
+        self.check_counts()?;
+        Ok(HashedLightMerkle::<N, H>::from_parts(self.path, self.old_count).root())
+    }
+
+    /// Calculate the keccak256 root of the new tree
+    pub fn new_root(&self) -> Result<H256, VerifyingError> {
+        self.new_root_with::<KeccakHasher>()
+    }
+
+    /// Calculate the root of the new tree using hasher `H`
+    pub fn new_root_with<H: Hasher>(&self) -> Result<H256, VerifyingError> {
+        self.check_counts()?;
+
+        let mut node = self.leaf;
+        for (height, sibling) in self.path.iter().enumerate() {
+            node = if (self.old_count >> height) & 1 == 1 {
+                H::hash_concat(sibling, node)
+            } else {
+                H::hash_concat(node, sibling)
+            };
+        }
+        Ok(node)
+    }
+
+    /// Verify that the proof connects keccak256 roots `old_root` to
+    /// `new_root`
+    pub fn verify(&self, old_root: H256, new_root: H256) -> Result<(), VerifyingError> {
+        self.verify_with::<KeccakHasher>(old_root, new_root)
+    }
+
+    /// Verify that the proof connects `old_root` to `new_root` using hasher
+    /// `H`
+    pub fn verify_with<H: Hasher>(
+        &self,
+        old_root: H256,
+        new_root: H256,
+    ) -> Result<(), VerifyingError> {
+        for (expected, actual) in [
+            (old_root, self.old_root_with::<H>()?),
+            (new_root, self.new_root_with::<H>()?),
+        ] {
+            if expected != actual {
+                return Err(VerifyingError::VerificationFailed { expected, actual });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Tree;
+
+    fn leaves(count: u64) -> Vec<H256> {
+        (1..=count).map(H256::from_low_u64_be).collect()
+    }
+
+    #[test]
+    fn it_proves_consistency() {
+        let tree = Tree::<8>::from_leaves(&leaves(37));
+
+        for old_count in 0..=37 {
+            let old = LightMerkle::<8>::from_leaves(&leaves(old_count as u64));
+
+            for new_count in old_count..=37 {
+                let proof = tree.prove_consistency(old_count, new_count).unwrap();
+                let new_root = tree.root_at(new_count).unwrap();
+
+                proof.verify(old.root(), new_root).unwrap();
+                assert!(old.verify_consistency(&proof, new_root));
+            }
+        }
+
+        assert!(tree.prove_consistency(5, 4).is_err());
+        assert!(tree.prove_consistency(5, 38).is_err());
+    }
+
+    #[test]
+    fn it_rejects_forks() {
+        let tree = Tree::<8>::from_leaves(&leaves(20));
+        let mut forked_leaves = leaves(20);
+        forked_leaves[11] = H256::repeat_byte(0xff);
+        let fork = Tree::<8>::from_leaves(&forked_leaves);
+
+        let old = LightMerkle::<8>::from_leaves(&leaves(13));
+        let proof = fork.prove_consistency(13, 20).unwrap();
+        assert!(!old.verify_consistency(&proof, fork.root()));
+        assert!(proof.verify(old.root(), fork.root()).is_err());
+
+        let proof = tree.prove_consistency(13, 20).unwrap();
+        assert!(!old.verify_consistency(&proof, fork.root()));
+
+        let mut tampered = proof;
+        tampered.path[7] = H256::zero();
+        assert!(!old.verify_consistency(&tampered, tree.root()));
+
+        let mut wrong_count = proof;
+        wrong_count.old_count = 12;
+        assert!(!old.verify_consistency(&wrong_count, tree.root()));
+    }
+}
@@ -15,6 +15,17 @@ pub enum ProvingError {
         /// The number of leaves
         count: usize,
     },
+    /// Requested a historical root or proof at a size the tree has not reached
+    #[error("Requested a proof at tree size {count}. Tree has: {current}")]
+    CountTooHigh {
+        /// The tree size requested
+        count: usize,
+        /// The number of leaves
+        current: usize,
+    },
+    /// Requested a multiproof for no leaves
+    #[error("Requested a multiproof for an empty set of leaves")]
+    EmptyBatch,
 }
 
 /// Tree Errors
@@ -29,6 +40,13 @@ pub enum VerifyingError {
         /// The root produced by branch evaluation
         actual: H256,
     },
+    /// Multiproof is empty, unsorted, or has the wrong number of nodes
+    #[error("Multiproof is malformed")]
+    MalformedMultiProof,
+    /// Consistency proof old tree is larger than its new tree, or the new
+    /// tree is larger than the maximum tree size
+    #[error("Consistency proof is malformed")]
+    MalformedConsistencyProof,
 }
 
 /// Error type for merkle tree ops.
@@ -47,3 +65,47 @@ pub enum IngestionError {
     #[error("Incorrect Depth provided")]
     DepthTooSmall,
 }
+
+/// Error type for trees backed by a `NodeStore`
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError<E: std::error::Error + 'static> {
+    /// Error in the underlying node store
+    #[error(transparent)]
+    Store(E),
+    /// A node that must exist was not found in the store
+    #[error("Node at height {height} and index {index} missing from store")]
+    MissingNode {
+        /// The node's height
+        height: usize,
+        /// The node's index within its layer
+        index: usize,
+    },
+    /// Error while ingesting a leaf
+    #[error(transparent)]
+    Ingestion(#[from] IngestionError),
+    /// Error while proving a leaf
+    #[error(transparent)]
+    Proving(#[from] ProvingError),
+}
+
+/// Error type for restoring trees from snapshots
+#[derive(Debug, PartialEq, Clone, Copy, thiserror::Error)]
+pub enum SnapshotError {
+    /// Snapshot holds more leaves than the tree can
+    #[error("Snapshot leaf count {0} exceeds tree capacity")]
+    CountTooHigh(usize),
+    /// Restoring a full tree requires the node cache
+    #[error("Snapshot does not contain the full node cache")]
+    MissingNodes,
+    /// Node cache is shorter or longer than the leaf count requires
+    #[error("Snapshot node cache does not match its leaf count")]
+    MalformedNodes,
+    /// Node cache does not agree with the leading branch
+    #[error("Snapshot node cache root {actual} does not match branch root {expected}")]
+    RootMismatch {
+        /// The root computed from the leading branch
+        expected: H256,
+        /// The root in the node cache
+        actual: H256,
+    },
+}
@@ -1,7 +1,10 @@
 use ethers::core::types::H256;
 use lazy_static::lazy_static;
 
-use crate::{error::IngestionError, hash_concat, EMPTY_SLICE, TREE_DEPTH, ZERO_HASHES};
+use crate::{error::IngestionError, Hasher, EMPTY_SLICE, TREE_DEPTH};
+
+#[cfg(test)]
+use crate::KeccakHasher;
 
 // Some code has been derived from
 // https://github.com/sigp/lighthouse/blob/c6baa0eed131c5e8ecc5860778ffc7d4a4c18d2d/consensus/merkle_proof/src/lib.rs#L25
@@ -36,17 +39,18 @@ pub enum MerkleTree {
 }
 
 impl MerkleTree {
-    /// Retrieve the root hash of this Merkle tree.
-    pub fn hash(&self) -> H256 {
+    /// Retrieve the root hash of this Merkle tree, built with hasher `H`.
+    pub fn hash_with<H: Hasher>(&self) -> H256 {
         match *self {
             MerkleTree::Leaf(h) => h,
             MerkleTree::Node(h, _, _) => h,
-            MerkleTree::Zero(depth) => ZERO_HASHES[depth],
+            MerkleTree::Zero(depth) => H::zero_hashes()[depth],
         }
     }
 
-    /// Create a new Merkle tree from a list of leaves and a fixed depth.
-    pub fn create(leaves: &[H256], depth: usize) -> Self {
+    /// Create a new Merkle tree from a list of leaves and a fixed depth,
+    /// using hasher `H`.
+    pub fn create_with<H: Hasher>(leaves: &[H256], depth: usize) -> Self {
         use MerkleTree::*;
 
         if leaves.is_empty() {
@@ -67,18 +71,25 @@ impl MerkleTree {
                     leaves.split_at(subtree_capacity)
                 };
 
-                let left_subtree = MerkleTree::create(left_leaves, depth - 1);
-                let right_subtree = MerkleTree::create(right_leaves, depth - 1);
-                let hash = hash_concat(left_subtree.hash(), right_subtree.hash());
+                let left_subtree = MerkleTree::create_with::<H>(left_leaves, depth - 1);
+                let right_subtree = MerkleTree::create_with::<H>(right_leaves, depth - 1);
+                let hash = H::hash_concat(
+                    left_subtree.hash_with::<H>(),
+                    right_subtree.hash_with::<H>(),
+                );
 
                 Node(hash, Box::new(left_subtree), Box::new(right_subtree))
             }
         }
     }
 
-    /// Push an element in the MerkleTree.
+    /// Push an element in a MerkleTree built with hasher `H`.
     /// MerkleTree and depth must be correct, as the algorithm expects valid data.
-    pub fn push_leaf(&mut self, elem: H256, depth: usize) -> Result<(), IngestionError> {
+    pub fn push_leaf_with<H: Hasher>(
+        &mut self,
+        elem: H256,
+        depth: usize,
+    ) -> Result<(), IngestionError> {
         use MerkleTree::*;
 
         if depth == 0 {
@@ -88,7 +99,7 @@ impl MerkleTree {
         match self {
             Leaf(_) => return Err(IngestionError::LeafReached),
             Zero(_) => {
-                *self = MerkleTree::create(&[elem], depth);
+                *self = MerkleTree::create_with::<H>(&[elem], depth);
             }
             Node(ref mut hash, ref mut left, ref mut right) => {
                 let left: &mut MerkleTree = &mut *left;
@@ -98,25 +109,25 @@ impl MerkleTree {
                     (Leaf(_), Leaf(_)) => return Err(IngestionError::MerkleTreeFull),
                     // There is a right node so insert in right node
                     (Node(_, _, _), Node(_, _, _)) => {
-                        if let Err(e) = right.push_leaf(elem, depth - 1) {
+                        if let Err(e) = right.push_leaf_with::<H>(elem, depth - 1) {
                             return Err(e);
                         }
                     }
                     // Both branches are zero, insert in left one
                     (Zero(_), Zero(_)) => {
-                        *left = MerkleTree::create(&[elem], depth - 1);
+                        *left = MerkleTree::create_with::<H>(&[elem], depth - 1);
                     }
                     // Leaf on left branch and zero on right branch, insert on right side
                     (Leaf(_), Zero(_)) => {
-                        *right = MerkleTree::create(&[elem], depth - 1);
+                        *right = MerkleTree::create_with::<H>(&[elem], depth - 1);
                     }
                     // Try inserting on the left node -> if it fails because it is full, insert in right side.
                     (Node(_, _, _), Zero(_)) => {
-                        match left.push_leaf(elem, depth - 1) {
+                        match left.push_leaf_with::<H>(elem, depth - 1) {
                             Ok(_) => (),
                             // Left node is full, insert in right node
                             Err(IngestionError::MerkleTreeFull) => {
-                                *right = MerkleTree::create(&[elem], depth - 1);
+                                *right = MerkleTree::create_with::<H>(&[elem], depth - 1);
                             }
                             Err(e) => return Err(e),
                         };
@@ -124,7 +135,9 @@ impl MerkleTree {
                     // All other possibilities are invalid MerkleTrees
                     (_, _) => return Err(IngestionError::Invalid),
                 };
-                hash.assign_from_slice(hash_concat(left.hash(), right.hash()).as_ref());
+                hash.assign_from_slice(
+                    H::hash_concat(left.hash_with::<H>(), right.hash_with::<H>()).as_ref(),
+                );
             }
         }
 
@@ -145,11 +158,12 @@ impl MerkleTree {
         matches!(self, MerkleTree::Leaf(_))
     }
 
-    /// Return the leaf at `index` and a Merkle proof of its inclusion.
+    /// Return the leaf at `index` and a Merkle proof of its inclusion in a
+    /// tree built with hasher `H`.
     ///
     /// The Merkle proof is in "bottom-up" order, starting with a leaf node
     /// and moving up the tree. Its length will be exactly equal to `depth`.
-    pub fn generate_proof(&self, index: usize, depth: usize) -> (H256, Vec<H256>) {
+    pub fn generate_proof_with<H: Hasher>(&self, index: usize, depth: usize) -> (H256, Vec<H256>) {
         let mut proof = vec![];
         let mut current_node = self;
         let mut current_depth = depth;
@@ -160,10 +174,10 @@ impl MerkleTree {
 
             // Go right, include the left branch in the proof.
             if ith_bit == 1 {
-                proof.push(left.hash());
+                proof.push(left.hash_with::<H>());
                 current_node = right;
             } else {
-                proof.push(right.hash());
+                proof.push(right.hash_with::<H>());
                 current_node = left;
             }
             current_depth -= 1;
@@ -175,12 +189,123 @@ impl MerkleTree {
         // Put proof in bottom-up order.
         proof.reverse();
 
-        (current_node.hash(), proof)
+        (current_node.hash_with::<H>(), proof)
+    }
+
+    /// Retrieve the node at `height` and `index` within that layer of a tree
+    /// of depth `depth`.
+    fn node(&self, depth: usize, height: usize, index: usize) -> &MerkleTree {
+        let mut current_node = self;
+        let mut current_depth = depth;
+        while current_depth > height {
+            let ith_bit = (index >> (current_depth - height - 1)) & 0x01;
+            // Note: unwrap is safe because leaves are only ever constructed at depth == 0.
+            let (left, right) = current_node.left_and_right_branches().unwrap();
+            current_node = if ith_bit == 1 { right } else { left };
+            current_depth -= 1;
+        }
+        current_node
+    }
+
+    /// Retrieve the hash of the node at `height` and `index` within that
+    /// layer of a tree of depth `depth`.
+    pub fn node_hash<H: Hasher>(&self, depth: usize, height: usize, index: usize) -> H256 {
+        self.node(depth, height, index).hash_with::<H>()
+    }
+
+    /// Retrieve the hash that the node at `height` and `index` had when the
+    /// tree of depth `depth` held only its first `count` leaves.
+    pub fn node_hash_at<H: Hasher>(
+        &self,
+        depth: usize,
+        height: usize,
+        index: usize,
+        count: usize,
+    ) -> H256 {
+        self.node(depth, height, index)
+            .hash_at::<H>(height, count.saturating_sub(index << height))
+    }
+
+    /// Calculate the hash this subtree of depth `depth` had when it held
+    /// only its first `count` leaves.
+    pub fn hash_at<H: Hasher>(&self, depth: usize, count: usize) -> H256 {
+        if count == 0 {
+            return H::zero_hashes()[depth];
+        }
+
+        match self {
+            MerkleTree::Node(hash, left, right) => {
+                let subtree_capacity = 2usize.pow(depth as u32 - 1);
+                if count >= 2 * subtree_capacity {
+                    return *hash;
+                }
+                H::hash_concat(
+                    left.hash_at::<H>(depth - 1, std::cmp::min(count, subtree_capacity)),
+                    right.hash_at::<H>(depth - 1, count.saturating_sub(subtree_capacity)),
+                )
+            }
+            _ => self.hash_with::<H>(),
+        }
+    }
+
+    /// Append the hash of every non-zero node to `out`, in pre-order.
+    pub fn non_zero_nodes(&self, out: &mut Vec<H256>) {
+        match self {
+            MerkleTree::Zero(_) => {}
+            MerkleTree::Leaf(hash) => out.push(*hash),
+            MerkleTree::Node(hash, left, right) => {
+                out.push(*hash);
+                left.non_zero_nodes(out);
+                right.non_zero_nodes(out);
+            }
+        }
+    }
+
+    /// Rebuild a tree of depth `depth` holding `count` leaves from the
+    /// pre-order hashes of its non-zero nodes. The shape of a right-sparse
+    /// tree is determined by its leaf count, so no structure is needed.
+    ///
+    /// Returns `None` if `nodes` runs out.
+    pub fn from_non_zero_nodes(
+        nodes: &mut impl Iterator<Item = H256>,
+        depth: usize,
+        count: usize,
+    ) -> Option<Self> {
+        use MerkleTree::*;
+
+        if count == 0 {
+            return Some(Zero(depth));
+        }
+
+        let hash = nodes.next()?;
+        if depth == 0 {
+            debug_assert_eq!(count, 1);
+            return Some(Leaf(hash));
+        }
+
+        let subtree_capacity = 2usize.pow(depth as u32 - 1);
+        let left = MerkleTree::from_non_zero_nodes(
+            nodes,
+            depth - 1,
+            std::cmp::min(count, subtree_capacity),
+        )?;
+        let right = MerkleTree::from_non_zero_nodes(
+            nodes,
+            depth - 1,
+            count.saturating_sub(subtree_capacity),
+        )?;
+
+        Some(Node(hash, Box::new(left), Box::new(right)))
     }
 }
 
-/// Compute a root hash from a leaf and a Merkle proof.
-pub fn merkle_root_from_branch(leaf: H256, branch: &[H256], depth: usize, index: usize) -> H256 {
+/// Compute a root hash from a leaf and a Merkle proof, using hasher `H`.
+pub fn merkle_root_from_branch_with<H: Hasher>(
+    leaf: H256,
+    branch: &[H256],
+    depth: usize,
+    index: usize,
+) -> H256 {
     assert_eq!(branch.len(), depth, "proof length should equal depth");
 
     let mut current = leaf;
@@ -188,18 +313,43 @@ pub fn merkle_root_from_branch(leaf: H256, branch: &[H256], depth: usize, index:
     for (i, next) in branch.iter().enumerate().take(depth) {
         let ith_bit = (index >> i) & 0x01;
         if ith_bit == 1 {
-            current = hash_concat(next, current);
+            current = H::hash_concat(next, current);
         } else {
-            current = hash_concat(current, next);
+            current = H::hash_concat(current, next);
         }
     }
 
     current
 }
 
+/// Keccak256 shorthands for the tests
+#[cfg(test)]
+impl MerkleTree {
+    fn hash(&self) -> H256 {
+        self.hash_with::<KeccakHasher>()
+    }
+
+    fn create(leaves: &[H256], depth: usize) -> Self {
+        Self::create_with::<KeccakHasher>(leaves, depth)
+    }
+
+    fn push_leaf(&mut self, elem: H256, depth: usize) -> Result<(), IngestionError> {
+        self.push_leaf_with::<KeccakHasher>(elem, depth)
+    }
+
+    fn generate_proof(&self, index: usize, depth: usize) -> (H256, Vec<H256>) {
+        self.generate_proof_with::<KeccakHasher>(index, depth)
+    }
+}
+
+#[cfg(test)]
+fn merkle_root_from_branch(leaf: H256, branch: &[H256], depth: usize, index: usize) -> H256 {
+    merkle_root_from_branch_with::<KeccakHasher>(leaf, branch, depth, index)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{light, Merkle};
+    use crate::{hash_concat, light, Merkle, ZERO_HASHES};
 
     use super::*;
 
@@ -418,6 +568,35 @@ mod tests {
         assert_eq!(second.hash(), incr.root());
         assert_eq!(full.hash(), incr.root());
     }
+
+    #[test]
+    fn it_proves_against_historical_roots() {
+        use crate::{MerkleProof, ProvingError, Tree};
+
+        let leaves: Vec<_> = (1..=23).map(H256::from_low_u64_be).collect();
+        let tree = Tree::<8>::from_leaves(&leaves);
+
+        for count in 0..=leaves.len() {
+            let past = Tree::<8>::from_leaves(&leaves[..count]);
+            assert_eq!(tree.root_at(count).unwrap(), past.root());
+
+            for index in 0..count {
+                let proof = tree.prove_at(index, count).unwrap();
+                assert_eq!(proof, past.prove(index).unwrap());
+                assert_eq!(proof.root(), past.root());
+            }
+        }
+
+        assert!(matches!(
+            tree.prove_at(3, 24),
+            Err(ProvingError::CountTooHigh { .. })
+        ));
+        assert!(matches!(
+            tree.prove_at(5, 5),
+            Err(ProvingError::ZeroProof { .. })
+        ));
+        assert!(tree.root_at(24).is_err());
+    }
 }
 
 /*
@@ -0,0 +1,291 @@
+use ethers::core::types::H256;
+
+use crate::TREE_DEPTH;
+
+/// A hash function for merkle tree nodes.
+///
+/// Each hasher provides its own table of zero subtree hashes. Implementors
+/// should compute it once, e.g. in a `lazy_static`, with
+/// [`compute_zero_hashes`].
+pub trait Hasher:
+    std::fmt::Debug + Default + Clone + Copy + PartialEq + Send + Sync + 'static
+{
+    /// Return the digest of the preimage
+    fn hash(preimage: &[u8]) -> H256;
+
+    /// Return the digest of the concatenation of the arguments
+    fn hash_concat(left: impl AsRef<[u8]>, right: impl AsRef<[u8]>) -> H256 {
+        let mut preimage = Vec::with_capacity(64);
+        preimage.extend_from_slice(left.as_ref());
+        preimage.extend_from_slice(right.as_ref());
+        Self::hash(&preimage)
+    }
+
+    /// The zero hashes for each layer of the tree. The zero leaf is
+    /// `H256::zero()`.
+    fn zero_hashes() -> &'static [H256; TREE_DEPTH + 1];
+}
+
+/// Compute the zero hashes for each layer of the tree under hasher `H`
+pub fn compute_zero_hashes<H: Hasher>() -> [H256; TREE_DEPTH + 1] {
+    let mut hashes = [H256::zero(); TREE_DEPTH + 1];
+    for i in 0..TREE_DEPTH {
+        hashes[i + 1] = H::hash_concat(hashes[i], hashes[i]);
+    }
+    hashes
+}
+
+/// The keccak256 hasher. This is the Nomad protocol standard, and is used
+/// by the EVM contracts.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct KeccakHasher;
+
+impl Hasher for KeccakHasher {
+    fn hash(preimage: &[u8]) -> H256 {
+        crate::utils::hash(preimage)
+    }
+
+    fn hash_concat(left: impl AsRef<[u8]>, right: impl AsRef<[u8]>) -> H256 {
+        crate::utils::hash_concat(left, right)
+    }
+
+    fn zero_hashes() -> &'static [H256; TREE_DEPTH + 1] {
+        &crate::ZERO_HASHES
+    }
+}
+
+#[cfg(feature = "sha256")]
+lazy_static::lazy_static! {
+    static ref SHA256_ZERO_HASHES: [H256; TREE_DEPTH + 1] = compute_zero_hashes::<Sha256Hasher>();
+}
+
+/// The sha256 hasher
+#[cfg(feature = "sha256")]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Sha256Hasher;
+
+#[cfg(feature = "sha256")]
+impl Hasher for Sha256Hasher {
+    fn hash(preimage: &[u8]) -> H256 {
+        use sha2::{Digest, Sha256};
+        H256::from_slice(Sha256::digest(preimage).as_slice())
+    }
+
+    fn zero_hashes() -> &'static [H256; TREE_DEPTH + 1] {
+        &SHA256_ZERO_HASHES
+    }
+}
+
+#[cfg(feature = "poseidon")]
+pub use poseidon::PoseidonHasher;
+
+#[cfg(feature = "poseidon")]
+mod poseidon {
+    use ethers::core::types::{U256, U512};
+    use std::convert::TryFrom;
+
+    use super::*;
+
+    /// The BN254 scalar field modulus
+    const MODULUS: U256 = U256([
+        0x43e1f593f0000001,
+        0x2833e84879b97091,
+        0xb85045b68181585d,
+        0x30644e72e131a029,
+    ]);
+
+    /// State width. Two elements of rate and one of capacity
+    const WIDTH: usize = 3;
+    /// Full rounds, split evenly before and after the partial rounds
+    const FULL_ROUNDS: usize = 8;
+    /// Partial rounds
+    const PARTIAL_ROUNDS: usize = 57;
+    /// Bytes packed into each field element. Always less than the modulus
+    const CHUNK_SIZE: usize = 31;
+
+    lazy_static::lazy_static! {
+        static ref ROUND_CONSTANTS: Vec<U256> = (0..(FULL_ROUNDS + PARTIAL_ROUNDS) * WIDTH)
+            .map(|i| {
+                let seed = crate::utils::hash_concat(b"nomad-poseidon", (i as u32).to_be_bytes());
+                U256::from_big_endian(seed.as_bytes()) % MODULUS
+            })
+            .collect();
+
+        // Cauchy matrix with x_i = i and y_j = WIDTH + j, which is MDS
+        static ref MDS: [[U256; WIDTH]; WIDTH] = {
+            let mut mds = [[U256::zero(); WIDTH]; WIDTH];
+            for (i, row) in mds.iter_mut().enumerate() {
+                for (j, elem) in row.iter_mut().enumerate() {
+                    *elem = inverse(U256::from(i + WIDTH + j));
+                }
+            }
+            mds
+        };
+
+        static ref POSEIDON_ZERO_HASHES: [H256; TREE_DEPTH + 1] =
+            compute_zero_hashes::<PoseidonHasher>();
+    }
+
+    fn add(a: U256, b: U256) -> U256 {
+        // both are below the 254-bit modulus, so this cannot overflow
+        let sum = a + b;
+        if sum >= MODULUS {
+            sum - MODULUS
+        } else {
+            sum
+        }
+    }
+
+    fn mul(a: U256, b: U256) -> U256 {
+        let product = (U512::from(a) * U512::from(b)) % U512::from(MODULUS);
+        U256::try_from(product).expect("reduced below modulus")
+    }
+
+    fn pow(base: U256, exponent: U256) -> U256 {
+        let mut result = U256::one();
+        for i in (0..exponent.bits()).rev() {
+            result = mul(result, result);
+            if exponent.bit(i) {
+                result = mul(result, base);
+            }
+        }
+        result
+    }
+
+    fn inverse(a: U256) -> U256 {
+        pow(a, MODULUS - 2)
+    }
+
+    fn sbox(x: U256) -> U256 {
+        let x2 = mul(x, x);
+        mul(mul(x2, x2), x)
+    }
+
+    fn permute(state: &mut [U256; WIDTH]) {
+        for round in 0..FULL_ROUNDS + PARTIAL_ROUNDS {
+            for (i, elem) in state.iter_mut().enumerate() {
+                *elem = add(*elem, ROUND_CONSTANTS[round * WIDTH + i]);
+            }
+
+            let partial = (FULL_ROUNDS / 2..FULL_ROUNDS / 2 + PARTIAL_ROUNDS).contains(&round);
+            if !partial {
+                state.iter_mut().for_each(|elem| *elem = sbox(*elem));
+            } else {
+                state[0] = sbox(state[0]);
+            }
+
+            let mut mixed = [U256::zero(); WIDTH];
+            for (i, elem) in mixed.iter_mut().enumerate() {
+                for (j, input) in state.iter().enumerate() {
+                    *elem = add(*elem, mul(MDS[i][j], *input));
+                }
+            }
+            *state = mixed;
+        }
+    }
+
+    /// A Poseidon-style hasher over the BN254 scalar field, suitable for
+    /// trees that are proven in zk circuits.
+    ///
+    /// Preimages are packed into field elements 31 bytes at a time and
+    /// absorbed into a width 3 sponge. The preimage length is placed in the
+    /// capacity element. Round constants are derived from keccak256, so
+    /// digests do NOT match other Poseidon instantiations (e.g. circomlib).
+    #[derive(Debug, Default, Clone, Copy, PartialEq)]
+    pub struct PoseidonHasher;
+
+    impl Hasher for PoseidonHasher {
+        fn hash(preimage: &[u8]) -> H256 {
+            let elements: Vec<U256> = preimage
+                .chunks(CHUNK_SIZE)
+                .map(U256::from_big_endian)
+                .collect();
+
+            let mut state = [U256::zero(), U256::zero(), U256::from(preimage.len())];
+            if elements.is_empty() {
+                permute(&mut state);
+            }
+            for pair in elements.chunks(WIDTH - 1) {
+                for (elem, input) in state.iter_mut().zip(pair.iter()) {
+                    *elem = add(*elem, *input);
+                }
+                permute(&mut state);
+            }
+
+            let mut digest = H256::zero();
+            state[0].to_big_endian(digest.as_bytes_mut());
+            digest
+        }
+
+        fn zero_hashes() -> &'static [H256; TREE_DEPTH + 1] {
+            &POSEIDON_ZERO_HASHES
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_matches_the_keccak_zero_hashes() {
+        assert_eq!(
+            &compute_zero_hashes::<KeccakHasher>(),
+            KeccakHasher::zero_hashes()
+        );
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn it_builds_sha256_trees() {
+        use crate::{HashedLightMerkle, HashedTree, Merkle, Tree};
+
+        let leaves: Vec<_> = (1..=11).map(H256::from_low_u64_be).collect();
+        let tree = HashedTree::<16, Sha256Hasher>::from_leaves(&leaves);
+        let light = HashedLightMerkle::<16, Sha256Hasher>::from_leaves(&leaves);
+        assert_eq!(tree.root(), light.root());
+        assert_ne!(tree.root(), Tree::<16>::from_leaves(&leaves).root());
+
+        let proof = tree.prove(7).unwrap();
+        assert!(light.verify(&proof));
+        tree.verify(&proof).unwrap();
+        assert!(Tree::<16>::from_leaves(&leaves).verify(&proof).is_err());
+
+        let multi = tree.prove_many(&[1, 2, 9]).unwrap();
+        assert!(light.verify_multi(&multi));
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn it_hashes_sha256() {
+        assert_eq!(
+            Sha256Hasher::hash(b"abc"),
+            "0xba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+                .parse()
+                .unwrap()
+        );
+    }
+
+    #[cfg(feature = "poseidon")]
+    #[test]
+    fn it_hashes_into_the_field() {
+        let a = PoseidonHasher::hash(b"nomad");
+        assert_eq!(a, PoseidonHasher::hash(b"nomad"));
+        assert_ne!(a, PoseidonHasher::hash(b"nomad\0"));
+        assert_ne!(PoseidonHasher::hash(&[]), PoseidonHasher::hash(&[0]));
+
+        let leaves: Vec<_> = (1..=5).map(H256::from_low_u64_be).collect();
+        let tree = crate::HashedTree::<8, PoseidonHasher>::from_leaves(&leaves);
+        let proof = tree.prove(3).unwrap();
+        assert_eq!(
+            crate::MerkleProof::root_with::<PoseidonHasher>(&proof),
+            crate::Merkle::root(&tree)
+        );
+
+        let max = ethers::core::types::U256::from_big_endian(&[0xff; 32]);
+        for digest in PoseidonHasher::zero_hashes().iter().skip(1) {
+            assert_ne!(*digest, H256::zero());
+            assert!(ethers::core::types::U256::from_big_endian(digest.as_bytes()) < max >> 2);
+        }
+    }
+}
@@ -9,18 +9,33 @@ mod full;
 /// Hashing utils
 pub mod utils;
 
+/// Pluggable hash functions for merkle trees
+pub mod hasher;
+
 /// Common error types for the merkle trees.
 pub mod error;
 
 /// A lightweight incremental merkle, suitable for running on-chain. Stores O
 /// (1) data
 pub mod light;
+/// Batched Merkle Proof struct
+pub mod multiproof;
 /// Merkle Proof struct
 pub mod proof;
 
 /// A full incremental merkle tree. Suitable for proving.
 pub mod tree;
 
+/// Versioned snapshots of merkle trees
+pub mod snapshot;
+
+/// Append-only consistency proofs between tree sizes
+pub mod consistency;
+
+/// A sparse merkle tree backed by a pluggable node store. Suitable for
+/// proving large trees without holding them in memory.
+pub mod store;
+
 #[cfg(target_arch = "wasm32")]
 /// Wasm bindings for common operations
 pub mod wasm;
@@ -40,32 +55,42 @@ pub type NomadTree = tree::Tree<TREE_DEPTH>;
 pub type NomadLightMerkle = light::LightMerkle<TREE_DEPTH>;
 /// A Nomad protocol standard-depth proof
 pub type NomadProof = proof::Proof<TREE_DEPTH>;
+/// A Nomad protocol standard-depth multiproof
+pub type NomadMultiProof = multiproof::MultiProof<TREE_DEPTH>;
+/// A Nomad protocol standard-depth consistency proof
+pub type NomadConsistencyProof = consistency::ConsistencyProof<TREE_DEPTH>;
+/// A Nomad protocol standard-depth tree snapshot
+pub type NomadTreeSnapshot = snapshot::TreeSnapshot<TREE_DEPTH>;
 
 const EMPTY_SLICE: &[H256] = &[];
 
+pub use consistency::*;
 pub use error::*;
 use full::*;
+pub use hasher::*;
 pub use light::*;
+pub use multiproof::*;
 pub use proof::*;
+pub use snapshot::*;
+pub use store::*;
 pub use tree::*;
 
 pub use utils::*;
 
 lazy_static! {
-    /// A cache of the zero hashes for each layer of the tree.
-    pub static ref ZERO_HASHES: [H256; TREE_DEPTH + 1] = {
-        let mut hashes = [H256::zero(); TREE_DEPTH + 1];
-        for i in 0..TREE_DEPTH {
-            hashes[i + 1] = hash_concat(hashes[i], hashes[i]);
-        }
-        hashes
-    };
+    /// A cache of the keccak256 zero hashes for each layer of the tree.
+    pub static ref ZERO_HASHES: [H256; TREE_DEPTH + 1] = compute_zero_hashes::<KeccakHasher>();
 }
 
 /// A merkle proof
 pub trait MerkleProof {
-    /// Calculate the merkle root of this proof's branch
-    fn root(&self) -> H256;
+    /// Calculate the merkle root of this proof's branch using hasher `H`
+    fn root_with<H: Hasher>(&self) -> H256;
+
+    /// Calculate the merkle root of this proof's branch using keccak256
+    fn root(&self) -> H256 {
+        self.root_with::<KeccakHasher>()
+    }
 }
 
 /// A simple trait for merkle-based accumulators
@@ -73,6 +98,9 @@ pub trait Merkle: std::fmt::Debug + Default {
     /// A proof of some leaf in this tree
     type Proof: MerkleProof;
 
+    /// The hash function used by this tree
+    type Hasher: Hasher;
+
     /// The maximum number of elements the tree can ingest
     fn max_elements() -> U256;
 
@@ -90,7 +118,7 @@ pub trait Merkle: std::fmt::Debug + Default {
 
     /// Verify a proof against this tree's root.
     fn verify(&self, proof: &Self::Proof) -> Result<(), VerifyingError> {
-        let actual = proof.root();
+        let actual = proof.root_with::<Self::Hasher>();
         let expected = self.root();
         if expected == actual {
             Ok(())
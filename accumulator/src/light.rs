@@ -1,29 +1,38 @@
+use std::marker::PhantomData;
+
 use ethers::{core::types::H256, prelude::U256};
 
 use crate::{
-    error::IngestionError, utils::hash_concat, Merkle, MerkleProof, Proof, TREE_DEPTH, ZERO_HASHES,
+    error::IngestionError, ConsistencyProof, Hasher, KeccakHasher, Merkle, MerkleProof, MultiProof,
+    Proof, SnapshotError, TreeSnapshot, TREE_DEPTH,
 };
 
 #[derive(Debug, Clone, Copy)]
-/// An incremental merkle tree, modeled on the eth2 deposit contract
-pub struct LightMerkle<const N: usize> {
+/// An incremental merkle tree, modeled on the eth2 deposit contract, using
+/// hasher `H`
+pub struct HashedLightMerkle<const N: usize, H = KeccakHasher> {
     branch: [H256; N],
     count: usize,
+    _hasher: PhantomData<H>,
 }
 
-impl<const N: usize> Default for LightMerkle<N> {
+/// An incremental merkle tree using keccak256
+pub type LightMerkle<const N: usize> = HashedLightMerkle<N>;
+
+impl<H: Hasher, const N: usize> Default for HashedLightMerkle<N, H> {
     fn default() -> Self {
         let mut branch: [H256; N] = [Default::default(); N];
         branch
             .iter_mut()
             .enumerate()
-            .for_each(|(i, elem)| *elem = ZERO_HASHES[i]);
-        Self { branch, count: 0 }
+            .for_each(|(i, elem)| *elem = H::zero_hashes()[i]);
+        Self::from_parts(branch, 0)
     }
 }
 
-impl<const N: usize> Merkle for LightMerkle<N> {
+impl<H: Hasher, const N: usize> Merkle for HashedLightMerkle<N, H> {
     type Proof = Proof<N>;
+    type Hasher = H;
 
     /// Return the maximum number of leaves in this tree
     fn max_elements() -> U256 {
@@ -40,9 +49,9 @@ impl<const N: usize> Merkle for LightMerkle<N> {
 
         self.branch.iter().enumerate().for_each(|(i, elem)| {
             node = if (size & 1) == 1 {
-                crate::utils::hash_concat(elem, node)
+                H::hash_concat(elem, node)
             } else {
-                crate::utils::hash_concat(node, ZERO_HASHES[i])
+                H::hash_concat(node, H::zero_hashes()[i])
             };
             size /= 2;
         });
@@ -67,14 +76,14 @@ impl<const N: usize> Merkle for LightMerkle<N> {
                 self.branch[i] = node;
                 return Ok(self.root());
             }
-            node = hash_concat(self.branch[i], node);
+            node = H::hash_concat(self.branch[i], node);
             size /= 2;
         }
         unreachable!()
     }
 }
 
-impl<const N: usize> LightMerkle<N> {
+impl<H: Hasher, const N: usize> HashedLightMerkle<N, H> {
     /// Return the maximum number of leaves in this tree
     pub fn max_leaves() -> U256 {
         crate::utils::max_leaves(N)
@@ -91,9 +100,35 @@ impl<const N: usize> LightMerkle<N> {
         tree
     }
 
+    /// Instantiate a tree from its leading-edge branch and leaf count
+    pub fn from_parts(branch: [H256; N], count: usize) -> Self {
+        Self {
+            branch,
+            count,
+            _hasher: PhantomData,
+        }
+    }
+
+    /// Take a snapshot of the tree. Light snapshots have no node cache.
+    pub fn snapshot(&self) -> TreeSnapshot<N> {
+        TreeSnapshot {
+            count: self.count,
+            branch: self.branch,
+            nodes: None,
+        }
+    }
+
+    /// Restore a tree from a snapshot. Any node cache is ignored.
+    pub fn from_snapshot(snapshot: &TreeSnapshot<N>) -> Result<Self, SnapshotError> {
+        if Self::max_leaves() < snapshot.count.into() {
+            return Err(SnapshotError::CountTooHigh(snapshot.count));
+        }
+        Ok(Self::from_parts(snapshot.branch, snapshot.count))
+    }
+
     /// Calculate the initital root of a tree of this depth
     pub fn initial_root() -> H256 {
-        LightMerkle::<N>::default().root()
+        Self::default().root()
     }
     /// Get the leading-edge branch.
     pub fn branch(&self) -> &[H256; N] {
@@ -102,7 +137,18 @@ impl<const N: usize> LightMerkle<N> {
 
     /// Verify a incremental merkle proof of inclusion
     pub fn verify(&self, proof: &Proof<N>) -> bool {
-        proof.root() == self.root()
+        proof.root_with::<H>() == self.root()
+    }
+
+    /// Verify a multiproof of inclusion
+    pub fn verify_multi(&self, proof: &MultiProof<N>) -> bool {
+        matches!(proof.root_with::<H>(), Ok(root) if root == self.root())
+    }
+
+    /// Verify that `new_root` is the root of a tree produced by appending
+    /// leaves to this tree
+    pub fn verify_consistency(&self, proof: &ConsistencyProof<N>, new_root: H256) -> bool {
+        proof.old_count == self.count && proof.verify_with::<H>(self.root(), new_root).is_ok()
     }
 }
 
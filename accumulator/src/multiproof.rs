@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+
+use ethers::prelude::H256;
+
+use crate::{Hasher, KeccakHasher, MerkleProof, Proof, VerifyingError};
+
+/// A merkle proof of several leaves under a single root. Sibling nodes that
+/// are shared between leaves, or that can be computed from other proven
+/// leaves, are included only once.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiProof<const N: usize> {
+    /// The leaves, ordered by index
+    pub leaves: Vec<H256>,
+    /// The leaf indices, strictly increasing
+    pub indices: Vec<usize>,
+    /// The sibling nodes needed to compute the root, bottom-up and
+    /// left-to-right within each layer
+    pub nodes: Vec<H256>,
+}
+
+impl<const N: usize> MultiProof<N> {
+    /// Build a multiproof from single-leaf keccak256 proofs under the same
+    /// root. Duplicate proofs are ignored.
+    pub fn from_proofs(proofs: &[Proof<N>]) -> Result<Self, VerifyingError> {
+        Self::from_proofs_with::<KeccakHasher>(proofs)
+    }
+
+    /// Build a multiproof from single-leaf proofs under the same root using
+    /// hasher `H`. Duplicate proofs are ignored.
+    pub fn from_proofs_with<H: Hasher>(proofs: &[Proof<N>]) -> Result<Self, VerifyingError> {
+        let first = proofs.first().ok_or(VerifyingError::MalformedMultiProof)?;
+        let expected = first.root_with::<H>();
+
+        let mut leaves: Vec<(usize, H256)> = Vec::with_capacity(proofs.len());
+        let mut known_siblings: HashMap<(usize, usize), H256> = HashMap::new();
+        for proof in proofs {
+            let actual = proof.root_with::<H>();
+            if actual != expected {
+                return Err(VerifyingError::VerificationFailed { expected, actual });
+            }
+
+            leaves.push((proof.index, proof.leaf));
+            for (height, sibling) in proof.path.iter().enumerate() {
+                known_siblings.insert((height, (proof.index >> height) ^ 1), *sibling);
+            }
+        }
+        leaves.sort_by_key(|(index, _)| *index);
+        leaves.dedup_by_key(|(index, _)| *index);
+
+        let indices: Vec<usize> = leaves.iter().map(|(index, _)| *index).collect();
+        let nodes = Self::required_siblings(&indices)
+            .into_iter()
+            .map(|position| known_siblings[&position])
+            .collect();
+
+        Ok(Self {
+            leaves: leaves.into_iter().map(|(_, leaf)| leaf).collect(),
+            indices,
+            nodes,
+        })
+    }
+
+    /// The `(height, index)` of each sibling node that must be supplied to
+    /// prove the leaves at `indices`, in the order the verifier consumes
+    /// them. `indices` must be sorted and deduplicated.
+    fn required_siblings(indices: &[usize]) -> Vec<(usize, usize)> {
+        let mut required = vec![];
+        let mut known = indices.to_vec();
+
+        for height in 0..N {
+            let mut i = 0;
+            while i < known.len() {
+                let position = known[i];
+                if position & 1 == 0 && known.get(i + 1) == Some(&(position + 1)) {
+                    // both children known, skip the right one
+                    i += 1;
+                } else {
+                    required.push((height, position ^ 1));
+                }
+                i += 1;
+            }
+
+            known = known.iter().map(|position| position >> 1).collect();
+            known.dedup();
+        }
+
+        required
+    }
+
+    /// Calculate the keccak256 merkle root produced by evaluating the
+    /// multiproof.
+    ///
+    /// Errors if the proof is empty, its indices are not strictly
+    /// increasing, or it does not contain exactly the nodes it needs.
+    pub fn root(&self) -> Result<H256, VerifyingError> {
+        self.root_with::<KeccakHasher>()
+    }
+
+    /// Calculate the merkle root produced by evaluating the multiproof
+    /// using hasher `H`. Errors as `MultiProof::root`.
+    pub fn root_with<H: Hasher>(&self) -> Result<H256, VerifyingError> {
+        if self.leaves.is_empty()
+            || self.leaves.len() != self.indices.len()
+            || self.indices.windows(2).any(|w| w[0] >= w[1])
+            || self
+                .indices
+                .iter()
+                .any(|index| index.checked_shr(N as u32).unwrap_or(0) != 0)
+        {
+            return Err(VerifyingError::MalformedMultiProof);
+        }
+
+        let mut layer: Vec<(usize, H256)> = self
+            .indices
+            .iter()
+            .copied()
+            .zip(self.leaves.iter().copied())
+            .collect();
+        let mut nodes = self.nodes.iter();
+
+        for _ in 0..N {
+            let mut next = Vec::with_capacity(layer.len());
+            let mut i = 0;
+            while i < layer.len() {
+                let (position, node) = layer[i];
+                let parent = match layer.get(i + 1) {
+                    Some((right, right_node)) if position & 1 == 0 && *right == position + 1 => {
+                        i += 1;
+                        H::hash_concat(node, right_node)
+                    }
+                    _ => {
+                        let sibling = nodes.next().ok_or(VerifyingError::MalformedMultiProof)?;
+                        if position & 1 == 1 {
+                            H::hash_concat(sibling, node)
+                        } else {
+                            H::hash_concat(node, sibling)
+                        }
+                    }
+                };
+                next.push((position >> 1, parent));
+                i += 1;
+            }
+            layer = next;
+        }
+
+        if nodes.next().is_some() {
+            return Err(VerifyingError::MalformedMultiProof);
+        }
+
+        debug_assert_eq!(layer.len(), 1);
+        Ok(layer[0].1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{LightMerkle, Merkle, Tree};
+
+    fn tree(count: u64) -> Tree<8> {
+        (1..=count).map(H256::from_low_u64_be).collect()
+    }
+
+    #[test]
+    fn it_proves_and_verifies_many_leaves() {
+        let tree = tree(45);
+        let light =
+            LightMerkle::<8>::from_leaves(&(1..=45).map(H256::from_low_u64_be).collect::<Vec<_>>());
+
+        let indices = [44, 0, 3, 2, 17, 17, 30];
+        let proof = tree.prove_many(&indices).unwrap();
+
+        assert_eq!(proof.indices, vec![0, 2, 3, 17, 30, 44]);
+        assert_eq!(proof.root().unwrap(), tree.root());
+        assert!(light.verify_multi(&proof));
+        tree.verify_multi(&proof).unwrap();
+
+        // fewer nodes than the sum of the single proofs
+        assert!(proof.nodes.len() < 6 * 8);
+    }
+
+    #[test]
+    fn it_matches_single_proofs() {
+        let tree = tree(9);
+        for i in 0..9 {
+            let single = tree.prove(i).unwrap();
+            let multi = tree.prove_many(&[i]).unwrap();
+            assert_eq!(multi.nodes, single.path.to_vec());
+            assert_eq!(multi.root().unwrap(), single.root());
+        }
+    }
+
+    #[test]
+    fn it_builds_from_single_proofs() {
+        let tree = tree(20);
+        let proofs: Vec<_> = [19, 4, 5, 11]
+            .iter()
+            .map(|i| tree.prove(*i).unwrap())
+            .collect();
+
+        let multi = MultiProof::from_proofs(&proofs).unwrap();
+        assert_eq!(multi, tree.prove_many(&[4, 5, 11, 19]).unwrap());
+
+        let other = self::tree(21).prove(0).unwrap();
+        assert!(MultiProof::from_proofs(&[proofs[0], other]).is_err());
+    }
+
+    #[test]
+    fn it_rejects_malformed_proofs() {
+        let tree = tree(16);
+        let proof = tree.prove_many(&[1, 6, 7]).unwrap();
+
+        let mut extra = proof.clone();
+        extra.nodes.push(H256::zero());
+        assert!(extra.root().is_err());
+
+        let mut short = proof.clone();
+        short.nodes.pop();
+        assert!(short.root().is_err());
+
+        let mut unsorted = proof.clone();
+        unsorted.indices.swap(0, 1);
+        assert!(unsorted.root().is_err());
+
+        let mut wrong_leaf = proof;
+        wrong_leaf.leaves[0] = H256::zero();
+        assert!(tree.verify_multi(&wrong_leaf).is_err());
+
+        assert!(tree.prove_many(&[]).is_err());
+        assert!(tree.prove_many(&[3, 16]).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{merkle_root_from_branch, MerkleProof};
+use crate::{merkle_root_from_branch_with, Hasher, MerkleProof};
 use ethers::prelude::H256;
 
 /// A merkle proof object. The leaf, its path to the root, and its index in the
@@ -15,7 +15,7 @@ pub struct Proof<const N: usize> {
     pub path: [H256; N],
 }
 
-mod const_array_serde {
+pub(crate) mod const_array_serde {
     use super::H256;
     use serde::{ser::SerializeSeq, Deserialize, Deserializer, Serializer};
 
@@ -51,7 +51,7 @@ mod const_array_serde {
 
 impl<const N: usize> MerkleProof for Proof<N> {
     /// Calculate the merkle root produced by evaluating the proof
-    fn root(&self) -> H256 {
-        merkle_root_from_branch(self.leaf, self.path.as_ref(), N, self.index)
+    fn root_with<H: Hasher>(&self) -> H256 {
+        merkle_root_from_branch_with::<H>(self.leaf, self.path.as_ref(), N, self.index)
     }
 }
@@ -0,0 +1,114 @@
+use ethers::prelude::H256;
+
+use crate::{HashedLightMerkle, Hasher, KeccakHasher, Merkle};
+
+/// The current version of the tree snapshot format
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+/// A point-in-time copy of an incremental merkle tree, from which the tree
+/// can be restored without re-ingesting its leaves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeSnapshot<const N: usize> {
+    /// The number of leaves in the tree
+    pub count: usize,
+    /// The leading-edge branch
+    pub branch: [H256; N],
+    /// The hash of every non-zero node in the tree, in pre-order. Only
+    /// present in snapshots of a full `Tree`.
+    pub nodes: Option<Vec<H256>>,
+}
+
+impl<const N: usize> TreeSnapshot<N> {
+    /// Calculate the keccak256 root of the snapshotted tree
+    pub fn root(&self) -> H256 {
+        self.root_with::<KeccakHasher>()
+    }
+
+    /// Calculate the root of the snapshotted tree using hasher `H`
+    pub fn root_with<H: Hasher>(&self) -> H256 {
+        HashedLightMerkle::<N, H>::from_parts(self.branch, self.count).root()
+    }
+
+    /// True if the snapshot contains the full node cache
+    pub fn is_full(&self) -> bool {
+        self.nodes.is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{LightMerkle, SnapshotError, Tree};
+
+    fn leaves(count: u64) -> Vec<H256> {
+        (1..=count).map(H256::from_low_u64_be).collect()
+    }
+
+    #[test]
+    fn it_restores_full_trees() {
+        for count in [0, 1, 2, 7, 8, 33] {
+            let tree = Tree::<8>::from_leaves(&leaves(count));
+            let snapshot = tree.snapshot();
+            assert_eq!(snapshot.count, count as usize);
+            assert_eq!(snapshot.root(), tree.root());
+
+            let mut restored = Tree::<8>::from_snapshot(snapshot).unwrap();
+            assert_eq!(restored, tree);
+
+            restored.ingest(H256::repeat_byte(9)).unwrap();
+            let mut expected = leaves(count);
+            expected.push(H256::repeat_byte(9));
+            assert_eq!(restored.root(), Tree::<8>::from_leaves(&expected).root());
+        }
+    }
+
+    #[test]
+    fn it_restores_light_trees() {
+        let light = LightMerkle::<16>::from_leaves(&leaves(21));
+        let snapshot = light.snapshot();
+        assert!(!snapshot.is_full());
+
+        let mut restored = LightMerkle::<16>::from_snapshot(&snapshot).unwrap();
+        assert_eq!(restored.root(), light.root());
+        assert_eq!(restored.count(), 21);
+
+        restored.ingest(H256::repeat_byte(1)).unwrap();
+        let mut expected = leaves(21);
+        expected.push(H256::repeat_byte(1));
+        assert_eq!(
+            restored.root(),
+            LightMerkle::<16>::from_leaves(&expected).root()
+        );
+
+        // A full tree's snapshot restores a light tree too
+        let full = Tree::<16>::from_leaves(&leaves(21));
+        let restored = LightMerkle::<16>::from_snapshot(&full.snapshot()).unwrap();
+        assert_eq!(restored.root(), full.root());
+    }
+
+    #[test]
+    fn it_rejects_bad_snapshots() {
+        let tree = Tree::<8>::from_leaves(&leaves(5));
+
+        let light = LightMerkle::<8>::from_leaves(&leaves(5)).snapshot();
+        assert!(matches!(
+            Tree::<8>::from_snapshot(light),
+            Err(SnapshotError::MissingNodes)
+        ));
+
+        let mut short = tree.snapshot();
+        short.nodes.as_mut().unwrap().pop();
+        assert!(Tree::<8>::from_snapshot(short).is_err());
+
+        let mut corrupt = tree.snapshot();
+        corrupt.nodes.as_mut().unwrap()[0] = H256::zero();
+        assert!(matches!(
+            Tree::<8>::from_snapshot(corrupt),
+            Err(SnapshotError::RootMismatch { .. })
+        ));
+
+        let mut too_many = tree.snapshot();
+        too_many.count = 256;
+        assert!(Tree::<8>::from_snapshot(too_many).is_err());
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+
+use ethers::core::types::H256;
+
+use crate::{
+    error::StoreError, HashedLightMerkle, Hasher, IngestionError, KeccakHasher, Merkle, Proof,
+    ProvingError,
+};
+
+/// A backing store for the nodes of a [`StoredTree`].
+///
+/// Nodes are addressed by their `height` (0 for leaves, `N` for the root) and
+/// their `index` within that layer. Nodes that have never been written are
+/// zero subtrees, and stores should return `Ok(None)` for them.
+pub trait NodeStore {
+    /// The error produced by the underlying storage
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Retrieve the node at `height` and `index`, if it has been written.
+    fn node(&self, height: usize, index: usize) -> Result<Option<H256>, Self::Error>;
+
+    /// Write the node at `height` and `index`.
+    fn store_node(&mut self, height: usize, index: usize, node: H256) -> Result<(), Self::Error>;
+
+    /// Delete the node at `height` and `index`, if it has been written.
+    fn delete_node(&mut self, height: usize, index: usize) -> Result<(), Self::Error>;
+
+    /// Retrieve the number of leaves in the stored tree, if any were stored.
+    fn leaf_count(&self) -> Result<Option<usize>, Self::Error>;
+
+    /// Store the number of leaves in the tree.
+    fn store_leaf_count(&mut self, count: usize) -> Result<(), Self::Error>;
+
+    /// Write the `(height, index, node)` nodes on the path of a new leaf,
+    /// and the new number of leaves. Stores that support it should write
+    /// them atomically, so that an interrupted write does not leave a
+    /// partial path behind.
+    fn store_path(
+        &mut self,
+        nodes: &[(usize, usize, H256)],
+        count: usize,
+    ) -> Result<(), Self::Error> {
+        for (height, index, node) in nodes {
+            self.store_node(*height, *index, *node)?;
+        }
+        self.store_leaf_count(count)
+    }
+}
+
+/// An in-memory `NodeStore`. Useful for testing and for short-lived trees.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MemoryStore {
+    nodes: HashMap<(usize, usize), H256>,
+    count: Option<usize>,
+}
+
+impl NodeStore for MemoryStore {
+    type Error = std::convert::Infallible;
+
+    fn node(&self, height: usize, index: usize) -> Result<Option<H256>, Self::Error> {
+        Ok(self.nodes.get(&(height, index)).copied())
+    }
+
+    fn store_node(&mut self, height: usize, index: usize, node: H256) -> Result<(), Self::Error> {
+        self.nodes.insert((height, index), node);
+        Ok(())
+    }
+
+    fn delete_node(&mut self, height: usize, index: usize) -> Result<(), Self::Error> {
+        self.nodes.remove(&(height, index));
+        Ok(())
+    }
+
+    fn leaf_count(&self) -> Result<Option<usize>, Self::Error> {
+        Ok(self.count)
+    }
+
+    fn store_leaf_count(&mut self, count: usize) -> Result<(), Self::Error> {
+        self.count = Some(count);
+        Ok(())
+    }
+}
+
+/// A sparse merkle tree whose nodes live in a `NodeStore`, using hasher `H`.
+///
+/// Only the leading branch is held in memory, so ingestion never reads from
+/// the store. Proving reads one sibling per layer. Re-opening a tree over an
+/// existing store reads only the leading branch, rather than every leaf.
+#[derive(Debug)]
+pub struct HashedStoredTree<S, const N: usize, H = KeccakHasher> {
+    store: S,
+    branch: HashedLightMerkle<N, H>,
+}
+
+/// A sparse merkle tree whose nodes live in a `NodeStore`, using keccak256
+pub type StoredTree<S, const N: usize> = HashedStoredTree<S, N>;
+
+impl<H, S, const N: usize> HashedStoredTree<S, N, H>
+where
+    H: Hasher,
+    S: NodeStore,
+{
+    /// Open a tree over `store`, resuming from any nodes it already contains
+    pub fn new(store: S) -> Result<Self, StoreError<S::Error>> {
+        let branch = Self::read_branch(&store)?;
+        Ok(Self { store, branch })
+    }
+
+    /// Read the leading branch of the tree in `store`
+    fn read_branch(store: &S) -> Result<HashedLightMerkle<N, H>, StoreError<S::Error>> {
+        let count = store.leaf_count().map_err(StoreError::Store)?.unwrap_or(0);
+
+        let mut branch = [H256::zero(); N];
+        for (height, elem) in branch.iter_mut().enumerate() {
+            *elem = if (count >> height) & 1 == 1 {
+                // the left sibling of the next insertion is complete and
+                // must have been written
+                store
+                    .node(height, (count >> height) - 1)
+                    .map_err(StoreError::Store)?
+                    .ok_or(StoreError::MissingNode {
+                        height,
+                        index: (count >> height) - 1,
+                    })?
+            } else {
+                H::zero_hashes()[height]
+            };
+        }
+
+        Ok(HashedLightMerkle::from_parts(branch, count))
+    }
+
+    /// Calculate the initital root of a tree of this depth
+    pub fn initial_root() -> H256 {
+        HashedLightMerkle::<N, H>::initial_root()
+    }
+
+    /// The number of leaves in the tree
+    pub fn count(&self) -> usize {
+        self.branch.count()
+    }
+
+    /// The tree's depth
+    pub fn depth(&self) -> usize {
+        N
+    }
+
+    /// Calculate the current root of the tree
+    pub fn root(&self) -> H256 {
+        self.branch.root()
+    }
+
+    /// Get a reference to the underlying store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Consume the tree and return the underlying store
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Push a leaf to the tree, writing each node on its path to the store
+    /// in a single `store_path` call. Returns the new root.
+    pub fn push_leaf(&mut self, leaf: H256) -> Result<H256, StoreError<S::Error>> {
+        let index = self.count();
+        if HashedLightMerkle::<N, H>::max_leaves() <= index.into() {
+            return Err(IngestionError::MerkleTreeFull.into());
+        }
+
+        let mut nodes = Vec::with_capacity(N + 1);
+        let mut node = leaf;
+        for height in 0..N {
+            let position = index >> height;
+            nodes.push((height, position, node));
+
+            node = if position & 1 == 1 {
+                H::hash_concat(self.branch.branch()[height], node)
+            } else {
+                H::hash_concat(node, H::zero_hashes()[height])
+            };
+        }
+        nodes.push((N, 0, node));
+
+        self.store
+            .store_path(&nodes, index + 1)
+            .map_err(StoreError::Store)?;
+        self.branch.ingest(leaf)?;
+
+        debug_assert_eq!(node, self.root());
+        Ok(node)
+    }
+
+    /// Drop the leaves at and after `count`, e.g. when they were reorged out.
+    /// Nodes covering dropped leaves are deleted, and those also covering
+    /// kept leaves are recomputed, so that the store is as if only `count`
+    /// leaves had been pushed. Does nothing if the tree is not larger.
+    pub fn truncate(&mut self, count: usize) -> Result<(), StoreError<S::Error>> {
+        let previous = self.count();
+        if count >= previous {
+            return Ok(());
+        }
+
+        for height in 0..=N {
+            // the first node covering a dropped leaf, up to the last written
+            let first = count >> height;
+            for index in first..=(previous - 1) >> height {
+                self.store
+                    .delete_node(height, index)
+                    .map_err(StoreError::Store)?;
+            }
+
+            // its children were recomputed on the layer below
+            if height > 0 && first << height < count {
+                let node = H::hash_concat(
+                    self.read_node(height - 1, 2 * first)?,
+                    self.read_node(height - 1, 2 * first + 1)?,
+                );
+                self.store
+                    .store_node(height, first, node)
+                    .map_err(StoreError::Store)?;
+            }
+        }
+
+        self.store
+            .store_leaf_count(count)
+            .map_err(StoreError::Store)?;
+        self.branch = Self::read_branch(&self.store)?;
+        Ok(())
+    }
+
+    /// Return the leaf at `index` and a Merkle proof of its inclusion.
+    ///
+    /// The Merkle proof is in "bottom-up" order, starting with a leaf node
+    /// and moving up the tree. Its length will be exactly equal to `depth`.
+    pub fn prove(&self, index: usize) -> Result<Proof<N>, StoreError<S::Error>> {
+        if index > 2usize.pow(N.try_into().unwrap()) - 1 {
+            return Err(ProvingError::IndexTooHigh(index).into());
+        }
+
+        let count = self.count();
+        if index >= count {
+            return Err(ProvingError::ZeroProof { index, count }.into());
+        }
+
+        let leaf = self.read_node(0, index)?;
+        let mut path = [H256::zero(); N];
+        for (height, sibling) in path.iter_mut().enumerate() {
+            *sibling = self.read_node(height, (index >> height) ^ 1)?;
+        }
+
+        Ok(Proof { leaf, index, path })
+    }
+
+    /// Read a node, falling back to the zero subtree if it was never written
+    fn read_node(&self, height: usize, index: usize) -> Result<H256, StoreError<S::Error>> {
+        Ok(self
+            .store
+            .node(height, index)
+            .map_err(StoreError::Store)?
+            .unwrap_or(H::zero_hashes()[height]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{MerkleProof, Tree};
+
+    #[test]
+    fn it_matches_the_full_tree() {
+        let leaves: Vec<_> = (1..=37).map(H256::from_low_u64_be).collect();
+
+        let mut stored = StoredTree::<_, 16>::new(MemoryStore::default()).unwrap();
+        assert_eq!(stored.root(), Tree::<16>::initial_root());
+
+        let mut full = Tree::<16>::default();
+        for leaf in leaves.iter() {
+            let root = stored.push_leaf(*leaf).unwrap();
+            full.ingest(*leaf).unwrap();
+            assert_eq!(root, full.root());
+        }
+
+        for i in 0..leaves.len() {
+            let proof = stored.prove(i).unwrap();
+            assert_eq!(proof, full.prove(i).unwrap());
+            assert_eq!(proof.root(), full.root());
+        }
+        assert!(stored.prove(leaves.len()).is_err());
+    }
+
+    #[test]
+    fn it_resumes_from_its_store() {
+        let leaves: Vec<_> = (1..=21).map(H256::from_low_u64_be).collect();
+
+        let mut stored = StoredTree::<_, 8>::new(MemoryStore::default()).unwrap();
+        for leaf in leaves[..13].iter() {
+            stored.push_leaf(*leaf).unwrap();
+        }
+
+        let mut resumed = StoredTree::<_, 8>::new(stored.into_store()).unwrap();
+        assert_eq!(resumed.count(), 13);
+        for leaf in leaves[13..].iter() {
+            resumed.push_leaf(*leaf).unwrap();
+        }
+
+        let full = Tree::<8>::from_leaves(&leaves);
+        assert_eq!(resumed.root(), full.root());
+        assert_eq!(resumed.prove(4).unwrap(), full.prove(4).unwrap());
+    }
+
+    #[test]
+    fn it_truncates_to_fewer_leaves() {
+        let leaves: Vec<_> = (1..=21).map(H256::from_low_u64_be).collect();
+
+        let mut stored = StoredTree::<_, 8>::new(MemoryStore::default()).unwrap();
+        for leaf in leaves.iter() {
+            stored.push_leaf(*leaf).unwrap();
+        }
+
+        stored.truncate(11).unwrap();
+        let full = Tree::<8>::from_leaves(&leaves[..11]);
+        assert_eq!(stored.count(), 11);
+        assert_eq!(stored.root(), full.root());
+        for i in 0..11 {
+            assert_eq!(stored.prove(i).unwrap(), full.prove(i).unwrap());
+        }
+        assert!(stored.prove(11).is_err());
+
+        // pushing again matches a tree that never had the dropped leaves
+        let mut resumed = StoredTree::<_, 8>::new(stored.into_store()).unwrap();
+        assert_eq!(resumed.root(), full.root());
+        let replacement = H256::from_low_u64_be(99);
+        resumed.push_leaf(replacement).unwrap();
+        let mut expected = leaves[..11].to_vec();
+        expected.push(replacement);
+        assert_eq!(resumed.root(), Tree::<8>::from_leaves(&expected).root());
+
+        resumed.truncate(0).unwrap();
+        assert_eq!(resumed.root(), StoredTree::<MemoryStore, 8>::initial_root());
+        assert_eq!(
+            resumed.into_store(),
+            MemoryStore {
+                nodes: Default::default(),
+                count: Some(0)
+            }
+        );
+    }
+
+    #[test]
+    fn it_errors_when_full() {
+        let mut stored = StoredTree::<_, 2>::new(MemoryStore::default()).unwrap();
+        for i in 0..3 {
+            stored.push_leaf(H256::from_low_u64_be(i)).unwrap();
+        }
+        assert!(matches!(
+            stored.push_leaf(H256::zero()),
+            Err(StoreError::Ingestion(IngestionError::MerkleTreeFull))
+        ));
+    }
+}
@@ -1,21 +1,31 @@
-use crate::{full::MerkleTree, IngestionError, LightMerkle, Merkle, Proof, ProvingError};
+use std::marker::PhantomData;
+
+use crate::{
+    full::MerkleTree, ConsistencyProof, HashedLightMerkle, Hasher, IngestionError, KeccakHasher,
+    Merkle, MultiProof, Proof, ProvingError, SnapshotError, TreeSnapshot, VerifyingError,
+};
 use ethers::{core::types::H256, prelude::U256};
 
-/// A simplified interface for a full sparse merkle tree
+/// A simplified interface for a full sparse merkle tree using hasher `H`
 #[derive(Debug, PartialEq)]
-pub struct Tree<const N: usize> {
+pub struct HashedTree<const N: usize, H = KeccakHasher> {
     count: usize,
     tree: Box<MerkleTree>,
+    _hasher: PhantomData<H>,
 }
 
-impl<const N: usize> Default for Tree<N> {
+/// A full sparse merkle tree using keccak256
+pub type Tree<const N: usize> = HashedTree<N>;
+
+impl<H: Hasher, const N: usize> Default for HashedTree<N, H> {
     fn default() -> Self {
         Self::from_leaves(&[])
     }
 }
 
-impl<const N: usize> Merkle for Tree<N> {
+impl<H: Hasher, const N: usize> Merkle for HashedTree<N, H> {
     type Proof = Proof<N>;
+    type Hasher = H;
 
     /// Return the maximum number of leaves in this tree
     fn max_elements() -> U256 {
@@ -27,7 +37,7 @@ impl<const N: usize> Merkle for Tree<N> {
     }
 
     fn root(&self) -> H256 {
-        self.tree.hash()
+        self.tree.hash_with::<H>()
     }
 
     fn depth(&self) -> usize {
@@ -36,23 +46,77 @@ impl<const N: usize> Merkle for Tree<N> {
 
     fn ingest(&mut self, element: H256) -> Result<H256, IngestionError> {
         self.count += 1;
-        self.tree.push_leaf(element, N)?;
-        Ok(self.tree.hash())
+        self.tree.push_leaf_with::<H>(element, N)?;
+        Ok(self.tree.hash_with::<H>())
     }
 }
 
-impl<const N: usize> Tree<N> {
+impl<H: Hasher, const N: usize> HashedTree<N, H> {
     /// Instantiate a new tree with a known depth and a starting leaf-set
     pub fn from_leaves(leaves: &[H256]) -> Self {
         Self {
             count: leaves.len(),
-            tree: Box::new(MerkleTree::create(leaves, N)),
+            tree: Box::new(MerkleTree::create_with::<H>(leaves, N)),
+            _hasher: PhantomData,
         }
     }
 
     /// Calculate the initital root of a tree of this depth
     pub fn initial_root() -> H256 {
-        LightMerkle::<N>::default().root()
+        HashedLightMerkle::<N, H>::default().root()
+    }
+
+    /// Take a snapshot of the tree, including its full node cache
+    pub fn snapshot(&self) -> TreeSnapshot<N> {
+        let mut branch = [H256::zero(); N];
+        for (height, elem) in branch.iter_mut().enumerate() {
+            let position = self.count >> height;
+            *elem = if position & 1 == 1 {
+                self.tree.node_hash::<H>(N, height, position - 1)
+            } else {
+                H::zero_hashes()[height]
+            };
+        }
+
+        let mut nodes = vec![];
+        self.tree.non_zero_nodes(&mut nodes);
+
+        TreeSnapshot {
+            count: self.count,
+            branch,
+            nodes: Some(nodes),
+        }
+    }
+
+    /// Restore a tree from a snapshot taken with `Tree::snapshot`
+    pub fn from_snapshot(snapshot: TreeSnapshot<N>) -> Result<Self, SnapshotError> {
+        if Self::max_elements() < snapshot.count.into() {
+            return Err(SnapshotError::CountTooHigh(snapshot.count));
+        }
+
+        let expected = snapshot.root_with::<H>();
+        let count = snapshot.count;
+        let mut nodes = snapshot
+            .nodes
+            .ok_or(SnapshotError::MissingNodes)?
+            .into_iter();
+
+        let tree = MerkleTree::from_non_zero_nodes(&mut nodes, N, count)
+            .ok_or(SnapshotError::MalformedNodes)?;
+        if nodes.next().is_some() {
+            return Err(SnapshotError::MalformedNodes);
+        }
+
+        let actual = tree.hash_with::<H>();
+        if actual != expected {
+            return Err(SnapshotError::RootMismatch { expected, actual });
+        }
+
+        Ok(Self {
+            count,
+            tree: Box::new(tree),
+            _hasher: PhantomData,
+        })
     }
 
     /// Return the leaf at `index` and a Merkle proof of its inclusion.
@@ -69,24 +133,132 @@ impl<const N: usize> Tree<N> {
             return Err(ProvingError::ZeroProof { index, count });
         }
 
-        let (leaf, nodes) = self.tree.generate_proof(index, N);
+        let (leaf, nodes) = self.tree.generate_proof_with::<H>(index, N);
         debug_assert_eq!(nodes.len(), N);
         let mut path = [H256::default(); N];
         path.copy_from_slice(&nodes[..N]);
         Ok(Proof { leaf, index, path })
     }
+
+    /// Calculate the root the tree had when it held `count` leaves.
+    pub fn root_at(&self, count: usize) -> Result<H256, ProvingError> {
+        if count > self.count {
+            return Err(ProvingError::CountTooHigh {
+                count,
+                current: self.count,
+            });
+        }
+        Ok(self.tree.hash_at::<H>(N, count))
+    }
+
+    /// Return the leaf at `index` and a Merkle proof of its inclusion under
+    /// the root the tree had when it held `count` leaves.
+    ///
+    /// This allows proving against a historical root, e.g. the latest root
+    /// confirmed on a replica, rather than the current root.
+    pub fn prove_at(&self, index: usize, count: usize) -> Result<Proof<N>, ProvingError> {
+        if index > 2usize.pow(N.try_into().unwrap()) - 1 {
+            return Err(ProvingError::IndexTooHigh(index));
+        }
+        if count > self.count {
+            return Err(ProvingError::CountTooHigh {
+                count,
+                current: self.count,
+            });
+        }
+        if index >= count {
+            return Err(ProvingError::ZeroProof { index, count });
+        }
+
+        let leaf = self.tree.node_hash::<H>(N, 0, index);
+        let mut path = [H256::default(); N];
+        for (height, sibling) in path.iter_mut().enumerate() {
+            *sibling = self
+                .tree
+                .node_hash_at::<H>(N, height, (index >> height) ^ 1, count);
+        }
+        Ok(Proof { leaf, index, path })
+    }
+
+    /// Return a proof that the tree at `old_count` leaves is a prefix of the
+    /// tree at `new_count` leaves. Both sizes may be historical.
+    pub fn prove_consistency(
+        &self,
+        old_count: usize,
+        new_count: usize,
+    ) -> Result<ConsistencyProof<N>, ProvingError> {
+        if new_count > self.count {
+            return Err(ProvingError::CountTooHigh {
+                count: new_count,
+                current: self.count,
+            });
+        }
+        if old_count > new_count {
+            return Err(ProvingError::CountTooHigh {
+                count: old_count,
+                current: new_count,
+            });
+        }
+
+        let leaf = self.tree.node_hash_at::<H>(N, 0, old_count, new_count);
+        let mut path = [H256::default(); N];
+        for (height, node) in path.iter_mut().enumerate() {
+            let position = old_count >> height;
+            *node = if position & 1 == 1 {
+                self.tree.node_hash::<H>(N, height, position - 1)
+            } else {
+                self.tree
+                    .node_hash_at::<H>(N, height, position + 1, new_count)
+            };
+        }
+
+        Ok(ConsistencyProof {
+            old_count,
+            new_count,
+            leaf,
+            path,
+        })
+    }
+
+    /// Return a single multiproof of inclusion for the leaves at `indices`.
+    ///
+    /// Indices may be given in any order. Duplicates are ignored.
+    pub fn prove_many(&self, indices: &[usize]) -> Result<MultiProof<N>, ProvingError> {
+        if indices.is_empty() {
+            return Err(ProvingError::EmptyBatch);
+        }
+
+        let proofs = indices
+            .iter()
+            .map(|index| self.prove(*index))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(MultiProof::from_proofs_with::<H>(&proofs).expect("!proofs share a root"))
+    }
+
+    /// Verify a multiproof against this tree's root.
+    pub fn verify_multi(&self, proof: &MultiProof<N>) -> Result<(), VerifyingError> {
+        let actual = proof.root_with::<H>()?;
+        let expected = self.root();
+        if expected == actual {
+            Ok(())
+        } else {
+            Err(VerifyingError::VerificationFailed { expected, actual })
+        }
+    }
 }
 
-impl<T, const N: usize> From<T> for Tree<N>
+impl<T, H, const N: usize> From<T> for HashedTree<N, H>
 where
     T: AsRef<[H256]>,
+    H: Hasher,
 {
     fn from(t: T) -> Self {
         Self::from_leaves(t.as_ref())
     }
 }
 
-impl<const N: usize> std::iter::FromIterator<H256> for Tree<N> {
+impl<H: Hasher, const N: usize> std::iter::FromIterator<H256> for HashedTree<N, H> {
     /// Will panic if the tree fills
     fn from_iter<I: IntoIterator<Item = H256>>(iter: I) -> Self {
         let mut prover = Self::default();
@@ -95,7 +267,7 @@ impl<const N: usize> std::iter::FromIterator<H256> for Tree<N> {
     }
 }
 
-impl<const N: usize> std::iter::Extend<H256> for Tree<N> {
+impl<H: Hasher, const N: usize> std::iter::Extend<H256> for HashedTree<N, H> {
     /// Will panic if the tree fills
     fn extend<I: IntoIterator<Item = H256>>(&mut self, iter: I) {
         for i in iter {
@@ -7,7 +7,7 @@ pub fn hash(preimage: impl AsRef<[u8]>) -> H256 {
     H256::from_slice(Keccak256::digest(preimage.as_ref()).as_slice())
 }
 
-/// Return the keccak256 disgest of the concatenation of the arguments
+/// Return the keccak256 digest of the concatenation of the arguments
 pub fn hash_concat(left: impl AsRef<[u8]>, right: impl AsRef<[u8]>) -> H256 {
     H256::from_slice(
         Keccak256::new()
@@ -18,8 +18,14 @@ macro_rules! export_tree {
                 #[doc = "A merkle proof of depth " $depth]
                 pub struct [<Proof $depth>](pub(crate) crate::Proof<$depth>);
 
+                #[wasm_bindgen(inspectable)]
+                #[derive(Debug, Clone, PartialEq)]
+                #[doc = "A merkle multiproof of depth " $depth]
+                pub struct [<MultiProof $depth>](pub(crate) crate::MultiProof<$depth>);
+
                 type Internal = crate::Tree<$depth>;
                 type InternalProof = crate::Proof<$depth>;
+                type InternalMultiProof = crate::MultiProof<$depth>;
 
                 impl From<InternalProof> for [<Proof $depth>]{
                     fn from(p: InternalProof) -> [<Proof $depth>]{
@@ -27,6 +33,12 @@ macro_rules! export_tree {
                     }
                 }
 
+                impl From<InternalMultiProof> for [<MultiProof $depth>]{
+                    fn from(p: InternalMultiProof) -> [<MultiProof $depth>]{
+                        [<MultiProof $depth>](p)
+                    }
+                }
+
                 impl From<Internal> for [<Tree $depth>] {
                     fn from(p: Internal) -> [<Tree $depth>] {
                         [<Tree $depth>](p)
@@ -91,6 +103,25 @@ macro_rules! export_tree {
                             .map_err(|e| JsValue::from(format!("Unable to get proof for index {}: {}", index, e)))
                     }
 
+                    #[wasm_bindgen(js_name = "proveAt")]
+                    /// Return the leaf at `index` and a Merkle proof of its inclusion
+                    /// under the root the tree had when it held `count` leaves.
+                    pub fn prove_at(&self, index: usize, count: usize) -> Result<[<Proof $depth>], JsValue> {
+                        self.0
+                            .prove_at(index, count)
+                            .map(Into::into)
+                            .map_err(|e| JsValue::from(format!("Unable to get proof for index {} at size {}: {}", index, count, e)))
+                    }
+
+                    #[wasm_bindgen(js_name = "rootAt")]
+                    /// Retrieve the root hash this tree had when it held `count` leaves.
+                    pub fn root_at(&self, count: usize) -> Result<String, JsValue> {
+                        self.0
+                            .root_at(count)
+                            .map(|root| format!("{:?}", root))
+                            .map_err(|e| JsValue::from(format!("Unable to get root at size {}: {}", count, e)))
+                    }
+
                     #[wasm_bindgen]
                     /// Verify a proof against this tree's root.
                     pub fn verify(&self, proof: [<Proof $depth>]) -> Result<(), JsValue> {
@@ -98,6 +129,24 @@ macro_rules! export_tree {
                             .verify(&proof.0)
                             .map_err(|e| JsValue::from(format!("Proof verification failed: {}", e)))
                     }
+
+                    #[wasm_bindgen(js_name = "proveMany")]
+                    /// Return a single Merkle multiproof of inclusion for the leaves at
+                    /// `indices`. Indices may be in any order.
+                    pub fn prove_many(&self, indices: Vec<usize>) -> Result<[<MultiProof $depth>], JsValue> {
+                        self.0
+                            .prove_many(&indices)
+                            .map(Into::into)
+                            .map_err(|e| JsValue::from(format!("Unable to get multiproof: {}", e)))
+                    }
+
+                    #[wasm_bindgen(js_name = "verifyMulti")]
+                    /// Verify a multiproof against this tree's root.
+                    pub fn verify_multi(&self, proof: &[<MultiProof $depth>]) -> Result<(), JsValue> {
+                        self.0
+                            .verify_multi(&proof.0)
+                            .map_err(|e| JsValue::from(format!("Multiproof verification failed: {}", e)))
+                    }
                 }
 
                 #[wasm_bindgen]
@@ -137,6 +186,69 @@ macro_rules! export_tree {
                             .collect()
                     }
                 }
+
+                #[wasm_bindgen]
+                impl [<MultiProof $depth>] {
+                    #[wasm_bindgen(js_name = "fromJSON")]
+                    /// Parse a multiproof from its JSON representation.
+                    pub fn from_json(json: &JsValue) -> Result<[<MultiProof $depth>], JsValue> {
+                        json.into_serde::<InternalMultiProof>()
+                            .map(Into::into)
+                            .map_err(|e| JsValue::from(format!("Unable to parse multiproof: {}", e)))
+                    }
+
+                    #[wasm_bindgen(js_name = "toJSON")]
+                    /// Serialize this multiproof to its JSON representation.
+                    pub fn to_json(&self) -> Result<JsValue, JsValue> {
+                        JsValue::from_serde(&self.0)
+                            .map_err(|e| JsValue::from(format!("Unable to serialize multiproof: {}", e)))
+                    }
+
+                    #[wasm_bindgen]
+                    /// Retrieve the root hash of this Merkle multiproof.
+                    ///
+                    /// This will fail if the multiproof is malformed.
+                    pub fn root(&self) -> Result<String, JsValue> {
+                        self.0
+                            .root()
+                            .map(|root| format!("{:?}", root))
+                            .map_err(|e| JsValue::from(format!("Unable to evaluate multiproof: {}", e)))
+                    }
+
+                    #[wasm_bindgen(getter)]
+                    /// Retrieve the leaf hashes of this multiproof, ordered by index.
+                    pub fn leaves(&self) -> js_sys::Array {
+                        self.0
+                            .leaves
+                            .iter()
+                            .map(|hash| format!("{:?}", hash))
+                            .map(JsValue::from)
+                            .collect()
+                    }
+
+                    #[wasm_bindgen(getter)]
+                    /// Retrieve the leaf indices of this multiproof.
+                    pub fn indices(&self) -> Vec<usize> {
+                        self.0.indices.clone()
+                    }
+
+                    #[wasm_bindgen(getter)]
+                    /// Get the depth of the tree associated with this multiproof.
+                    pub fn depth(&self) -> usize {
+                        $depth
+                    }
+
+                    #[wasm_bindgen(getter)]
+                    /// Retrieve the deduplicated intermediate nodes of this multiproof.
+                    pub fn nodes(&self) -> js_sys::Array {
+                        self.0
+                            .nodes
+                            .iter()
+                            .map(|hash| format!("{:?}", hash))
+                            .map(JsValue::from)
+                            .collect()
+                    }
+                }
             }
         }
     };
@@ -215,3 +215,72 @@ impl ChatGenerator {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use nomad_base::{
+        chains::PageSettings, ContractSync, ContractSyncMetrics, CoreMetrics, HomeIndexers,
+        IndexSettings, NomadDB, MAX_RPC_AGE,
+    };
+    use nomad_core::State;
+    use nomad_test::mocks::{MockHomeContract, MockIndexer};
+    use nomad_test::test_utils;
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn it_stays_healthy_without_syncing_the_home() {
+        test_utils::run_test_db(|db| async move {
+            let metrics = Arc::new(
+                CoreMetrics::new(
+                    "kathy_test",
+                    "home",
+                    None,
+                    Arc::new(prometheus::Registry::new()),
+                )
+                .expect("could not make metrics"),
+            );
+
+            let home_indexer: Arc<HomeIndexers> = Arc::new(MockIndexer::new().into());
+            let home_db = NomadDB::new("home_1", db.clone());
+            let home_sync = ContractSync::new(
+                Kathy::AGENT_NAME.to_owned(),
+                "home_1".to_owned(),
+                home_db.clone(),
+                home_indexer,
+                IndexSettings::default(),
+                PageSettings::default(),
+                Default::default(),
+                ContractSyncMetrics::new(metrics.clone()),
+            );
+            let mut home_mock = MockHomeContract::new();
+            home_mock.expect__name().return_const("home_1".to_owned());
+            home_mock.expect__state().returning(|| Ok(State::Active));
+            let home = CachingHome::new(home_mock.into(), home_sync, home_db).into();
+
+            let core = AgentCore {
+                home,
+                replicas: Arc::new(std::sync::RwLock::new(HashMap::new())),
+                db,
+                metrics: metrics.clone(),
+                indexer: IndexSettings::default(),
+                settings: nomad_base::Settings::default(),
+                shutdown: Default::default(),
+                channel_shutdowns: Default::default(),
+            };
+            let agent = Kathy::new(1, ChatGenerator::Default, core);
+            let routes = metrics.health().routes();
+
+            // Kathy never syncs the home, but its state checks record RPCs
+            // past the window for the first one
+            let run_task = agent.run_all().into_inner();
+            sleep(MAX_RPC_AGE * 2).await;
+            let res = warp::test::request().path("/health").reply(&routes).await;
+            assert_eq!(res.status(), warp::http::StatusCode::OK);
+
+            run_task.abort();
+        })
+        .await
+    }
+}
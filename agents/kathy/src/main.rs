@@ -19,7 +19,7 @@ async fn main() -> Result<()> {
     let agent = Kathy::from_settings(settings).await?;
 
     agent.start_tracing(agent.metrics().span_duration())?;
-    let _ = agent.metrics().run_http_server();
+    let _ = agent.run_http_server();
 
-    agent.run_all().await?
+    agent.run_until_shutdown().await
 }
@@ -16,19 +16,13 @@ mod settings;
 use color_eyre::Result;
 
 use crate::{processor::Processor, settings::ProcessorSettings as Settings};
-use nomad_base::NomadAgent;
+use nomad_base::run_homes;
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
     color_eyre::install()?;
-    let settings = Settings::new()?;
+    let settings = Settings::new_all()?;
 
     // TODO: top-level root span customizations?
-    let agent = Processor::from_settings(settings).await?;
-    agent.start_tracing(agent.metrics().span_duration())?;
-
-    let _ = agent.metrics().run_http_server();
-
-    agent.run_all().await??;
-    Ok(())
+    run_homes::<Processor>(settings).await
 }
@@ -1,29 +1,31 @@
 use async_trait::async_trait;
 use color_eyre::{eyre::bail, Result};
 use ethers::prelude::H256;
-use futures_util::future::select_all;
-use nomad_xyz_configuration::agent::processor::S3Config;
+use nomad_xyz_configuration::agent::processor::{RetentionConfig, S3Config};
+use rusoto_core::Region;
 use std::{
     collections::{HashMap, HashSet},
     sync::Arc,
     time::Duration,
 };
-use tokio::{sync::RwLock, task::JoinHandle, time::sleep};
-use tracing::{debug, error, info, info_span, instrument, instrument::Instrumented, Instrument};
+use tokio::{sync::RwLock, task::JoinHandle};
+use tracing::{
+    debug, error, info, info_span, instrument, instrument::Instrumented, warn, Instrument,
+};
 
 use nomad_base::{
-    cancel_task, decl_agent, decl_channel, AgentCore, CachingHome, CachingReplica, NomadAgent,
-    NomadDB, ProcessorError,
+    decl_agent, decl_channel, AgentCore, CachingHome, CachingReplica, NomadAgent, NomadDB,
+    ProcessorError, Pruner, RestartPolicy, ShutdownToken, SupervisedTask,
 };
 use nomad_core::{
     accumulator::{MerkleProof, NomadProof},
-    CommittedMessage, Common, Home, HomeEvents, MessageStatus,
+    CommittedMessage, Common, Home, HomeEvents, LifecycleEvent, LifecycleStage, MessageKind,
+    MessageStatus, XAppRouters,
 };
 
 use crate::{prover_sync::ProverSync, push::Pusher, settings::ProcessorSettings as Settings};
 
 const AGENT_NAME: &str = "processor";
-static CURRENT_NONCE: &str = "current_nonce_";
 
 enum Flow {
     Advance,
@@ -40,15 +42,19 @@ pub(crate) struct Replica {
     db: NomadDB,
     allowed: Option<Arc<HashSet<H256>>>,
     denied: Option<Arc<HashSet<H256>>>,
+    allowed_kinds: Option<Arc<HashSet<MessageKind>>>,
+    denied_kinds: Option<Arc<HashSet<MessageKind>>>,
+    routers: XAppRouters,
     next_message_nonce: prometheus::IntGauge,
+    shutdown: ShutdownToken,
 }
 
 impl std::fmt::Display for Replica {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "ReplicaProcessor: {{ home: {:?}, replica: {:?}, allowed: {:?}, denied: {:?} }}",
-            self.home, self.replica, self.allowed, self.denied
+            "ReplicaProcessor: {{ home: {:?}, replica: {:?}, allowed: {:?}, denied: {:?}, allowed_kinds: {:?}, denied_kinds: {:?} }}",
+            self.home, self.replica, self.allowed, self.denied, self.allowed_kinds, self.denied_kinds
         )
     }
 }
@@ -72,7 +78,7 @@ impl Replica {
                 // 5. Submit the proof to the replica
                 let mut next_message_nonce: u32 = self
                     .db
-                    .retrieve_keyed_decodable(CURRENT_NONCE, &replica_domain)?
+                    .retrieve_processor_current_nonce(replica_domain)?
                     .map(|n: u32| n + 1)
                     .unwrap_or_default();
 
@@ -104,10 +110,13 @@ impl Replica {
                     {
                         Ok(Flow::Advance) => {
                             self.db
-                            .store_keyed_encodable(CURRENT_NONCE, &replica_domain, &next_message_nonce)?;
+                            .store_processor_current_nonce(replica_domain, next_message_nonce)?;
 
                             next_message_nonce += 1;
                             self.next_message_nonce.set(next_message_nonce as i64);
+                            if self.shutdown.is_triggered() {
+                                return Ok(());
+                            }
                         }
                         Ok(Flow::Repeat) => {
                             // there was some fault, let's wait and then try again later when state may have moved
@@ -120,7 +129,9 @@ impl Replica {
                                 next_message_nonce,
                                 replica_domain,
                             );
-                            sleep(Duration::from_secs(self.interval)).await
+                            if self.shutdown.sleep(Duration::from_secs(self.interval)).await {
+                                return Ok(());
+                            }
                         }
                         Err(e) => {
                             error!("fatal error in processor::Replica: {}", e);
@@ -160,7 +171,8 @@ impl Replica {
             Err(e) => bail!(e),
         };
 
-        info!(target: "seen_committed_messages", leaf_index = message.leaf_index);
+        let kind = message.message.decoded_body(&self.routers).kind();
+        info!(target: "seen_committed_messages", leaf_index = message.leaf_index, kind = %kind);
         let sender = message.message.sender;
 
         // if we have an allow list, filter senders not on it
@@ -189,6 +201,32 @@ impl Replica {
             return Ok(Flow::Advance);
         }
 
+        // if we have a kind allow list, filter kinds not on it
+        if let Some(false) = self.allowed_kinds.as_ref().map(|set| set.contains(&kind)) {
+            info!(
+                kind = %kind,
+                nonce = nonce,
+                "Skipping message because kind not on allow list. Kind: {}. Domain: {}. Nonce: {}",
+                kind,
+                domain,
+                nonce
+            );
+            return Ok(Flow::Advance);
+        }
+
+        // if we have a kind deny list, filter kinds on it
+        if let Some(true) = self.denied_kinds.as_ref().map(|set| set.contains(&kind)) {
+            info!(
+                kind = %kind,
+                nonce = nonce,
+                "Skipping message because kind on deny list. Kind: {}. Domain: {}. Nonce: {}",
+                kind,
+                domain,
+                nonce
+            );
+            return Ok(Flow::Advance);
+        }
+
         let proof = match self.db.proof_by_leaf_index(message.leaf_index) {
             Ok(Some(p)) => p,
             Ok(None) => {
@@ -217,7 +255,13 @@ impl Replica {
                 "Proof under {root} not yet valid here, waiting until Replica confirms",
                 root = proof.root(),
             );
-            sleep(Duration::from_secs(self.interval)).await;
+            if self
+                .shutdown
+                .sleep(Duration::from_secs(self.interval))
+                .await
+            {
+                return Ok(Flow::Repeat);
+            }
         }
 
         info!(
@@ -228,27 +272,57 @@ impl Replica {
             nonce
         );
 
-        self.process(message, proof).await?;
-
-        Ok(Flow::Advance)
+        self.process(message, proof).await
     }
 
     #[instrument(err, level = "trace", skip(self), fields(self = %self))]
     /// Dispatch a message for processing. If the message is already proven, process only.
-    async fn process(&self, message: CommittedMessage, proof: NomadProof) -> Result<()> {
+    async fn process(&self, message: CommittedMessage, proof: NomadProof) -> Result<Flow> {
         use nomad_core::Replica;
+
+        // Let the transaction and the db writes recording it finish if we
+        // are asked to shut down
+        let _in_flight = match self.shutdown.in_flight().await {
+            Some(guard) => guard,
+            None => return Ok(Flow::Repeat),
+        };
+
         let status = self.replica.message_status(message.to_leaf()).await?;
 
         match status {
             MessageStatus::None => {
-                self.replica
+                let outcome = self
+                    .replica
                     .prove_and_process(message.as_ref(), &proof)
                     .await?;
+                let event = LifecycleEvent::submitted(outcome.txid);
+                self.db
+                    .store_message_event(message.leaf_index, LifecycleStage::Proven, &event)?;
+                self.db.store_message_event(
+                    message.leaf_index,
+                    LifecycleStage::Processed,
+                    &event,
+                )?;
             }
             MessageStatus::Proven => {
-                self.replica.process(message.as_ref()).await?;
+                let outcome = self.replica.process(message.as_ref()).await?;
+                self.db.store_message_event(
+                    message.leaf_index,
+                    LifecycleStage::Processed,
+                    &LifecycleEvent::submitted(outcome.txid),
+                )?;
             }
             MessageStatus::Processed => {
+                // processed by someone else, or by us before a restart
+                let event = match self.replica.process_transaction(message.to_leaf()).await? {
+                    Some(txid) => LifecycleEvent::submitted(txid),
+                    None => LifecycleEvent::default(),
+                };
+                self.db.store_message_event(
+                    message.leaf_index,
+                    LifecycleStage::Processed,
+                    &event,
+                )?;
                 info!(
                     domain = message.message.destination,
                     nonce = message.message.nonce,
@@ -272,32 +346,57 @@ impl Replica {
             message.leaf_index,
         );
 
-        Ok(())
+        Ok(Flow::Advance)
+    }
+}
+
+/// Processor settings which can be changed live, on reload
+#[derive(Debug, Clone, PartialEq)]
+struct ProcessorOptions {
+    interval: u64,
+    allowed: Option<Arc<HashSet<H256>>>,
+    denied: Option<Arc<HashSet<H256>>>,
+    allowed_kinds: Option<Arc<HashSet<MessageKind>>>,
+    denied_kinds: Option<Arc<HashSet<MessageKind>>>,
+    subsidized_remotes: Vec<String>,
+}
+
+impl ProcessorOptions {
+    /// Whether running channels must be restarted to go from `self` to
+    /// `other`. Subsidized remotes only change which channels run.
+    fn channels_differ(&self, other: &Self) -> bool {
+        self.interval != other.interval
+            || self.allowed != other.allowed
+            || self.denied != other.denied
+            || self.allowed_kinds != other.allowed_kinds
+            || self.denied_kinds != other.denied_kinds
     }
 }
 
 decl_agent!(
     /// A processor agent
     Processor {
-        interval: u64,
         replica_tasks: RwLock<HashMap<String, JoinHandle<Result<()>>>>,
-        allowed: Option<Arc<HashSet<H256>>>,
-        denied: Option<Arc<HashSet<H256>>>,
-        subsidized_remotes: Vec<String>,
+        options: std::sync::RwLock<ProcessorOptions>,
         next_message_nonces: prometheus::IntGaugeVec,
         config: Option<S3Config>,
+        retention: Option<RetentionConfig>,
     }
 );
 
 impl Processor {
     /// Instantiate a new processor
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         interval: u64,
         core: AgentCore,
         allowed: Option<HashSet<H256>>,
         denied: Option<HashSet<H256>>,
+        allowed_kinds: Option<HashSet<MessageKind>>,
+        denied_kinds: Option<HashSet<MessageKind>>,
         subsidized_remotes: Vec<String>,
         config: Option<S3Config>,
+        retention: Option<RetentionConfig>,
     ) -> Self {
         let next_message_nonces = core
             .metrics
@@ -308,26 +407,65 @@ impl Processor {
             )
             .expect("processor metric already registered -- should have be a singleton");
 
-        Self {
+        let options = ProcessorOptions {
             interval,
-            core,
-            replica_tasks: Default::default(),
             allowed: allowed.map(Arc::new),
             denied: denied.map(Arc::new),
-            next_message_nonces,
+            allowed_kinds: allowed_kinds.map(Arc::new),
+            denied_kinds: denied_kinds.map(Arc::new),
             subsidized_remotes,
+        };
+
+        Self {
+            core,
+            replica_tasks: Default::default(),
+            options: std::sync::RwLock::new(options),
+            next_message_nonces,
             config,
+            retention,
         }
     }
+
+    /// Current live settings
+    fn options(&self) -> ProcessorOptions {
+        self.options.read().expect("!poisoned").clone()
+    }
+
+    /// Domains of the replicas the processor handles messages for
+    fn channel_domains(&self) -> Vec<u32> {
+        use nomad_core::Replica;
+
+        let replicas = self.replicas();
+        self.channel_replicas()
+            .iter()
+            .filter_map(|name| replicas.get(name))
+            .map(|replica| replica.local_domain())
+            .collect()
+    }
 }
 
 decl_channel!(Processor {
     next_message_nonce: prometheus::IntGauge,
     allowed: Option<Arc<HashSet<H256>>>,
     denied: Option<Arc<HashSet<H256>>>,
+    allowed_kinds: Option<Arc<HashSet<MessageKind>>>,
+    denied_kinds: Option<Arc<HashSet<MessageKind>>>,
+    routers: XAppRouters,
     interval: u64,
 });
 
+/// Parse a configured set of message kind names
+fn parse_kinds(kinds: Option<HashSet<String>>) -> Result<Option<HashSet<MessageKind>>> {
+    Ok(kinds
+        .map(|kinds| {
+            kinds
+                .iter()
+                .map(|kind| kind.parse::<MessageKind>())
+                .collect::<Result<_, _>>()
+        })
+        .transpose()?)
+}
+
 #[async_trait]
 #[allow(clippy::unit_arg)]
 impl NomadAgent for Processor {
@@ -346,12 +484,23 @@ impl NomadAgent for Processor {
             settings.as_ref().try_into_core(AGENT_NAME).await?,
             settings.agent.allowed,
             settings.agent.denied,
+            parse_kinds(settings.agent.allowed_kinds)?,
+            parse_kinds(settings.agent.denied_kinds)?,
             settings.agent.subsidized_remotes,
             settings.agent.s3,
+            settings.agent.retention,
         ))
     }
 
     fn build_channel(&self, replica: &str) -> Self::Channel {
+        let options = self.options();
+        let settings = &self.as_ref().settings;
+        let routers = settings
+            .replicas
+            .get(replica)
+            .and_then(|setup| settings.xapp_routers.get(&setup.domain))
+            .copied()
+            .unwrap_or_default();
         Self::Channel {
             base: self.channel_base(replica),
             next_message_nonce: self.next_message_nonces.with_label_values(&[
@@ -359,12 +508,65 @@ impl NomadAgent for Processor {
                 replica,
                 Self::AGENT_NAME,
             ]),
-            allowed: self.allowed.clone(),
-            denied: self.denied.clone(),
-            interval: self.interval,
+            allowed: options.allowed,
+            denied: options.denied,
+            allowed_kinds: options.allowed_kinds,
+            denied_kinds: options.denied_kinds,
+            routers,
+            interval: options.interval,
         }
     }
 
+    fn channel_replicas(&self) -> Vec<String> {
+        let replicas = self.replicas();
+        self.options()
+            .subsidized_remotes
+            .into_iter()
+            .filter(|remote| {
+                let known = replicas.contains_key(remote);
+                if !known {
+                    warn!(remote = %remote, "Subsidized remote {} is not a replica", remote);
+                }
+                known
+            })
+            .collect()
+    }
+
+    fn apply_settings(&self, settings: &Self::Settings) -> bool {
+        let agent = &settings.agent;
+        if agent.s3 != self.config || agent.retention != self.retention {
+            warn!("S3 or retention settings changed. Restart the agent to apply them");
+        }
+
+        let (allowed_kinds, denied_kinds) = match (
+            parse_kinds(agent.allowed_kinds.clone()),
+            parse_kinds(agent.denied_kinds.clone()),
+        ) {
+            (Ok(allowed_kinds), Ok(denied_kinds)) => (allowed_kinds, denied_kinds),
+            (Err(e), _) | (_, Err(e)) => {
+                error!(error = ?e, "Invalid message kinds. Keeping current processor settings");
+                return false;
+            }
+        };
+        let options = ProcessorOptions {
+            interval: agent.interval,
+            allowed: agent.allowed.clone().map(Arc::new),
+            denied: agent.denied.clone().map(Arc::new),
+            allowed_kinds: allowed_kinds.map(Arc::new),
+            denied_kinds: denied_kinds.map(Arc::new),
+            subsidized_remotes: agent.subsidized_remotes.clone(),
+        };
+
+        let mut current = self.options.write().expect("!poisoned");
+        if *current == options {
+            return false;
+        }
+        info!(options = ?options, "Applying reloaded processor settings");
+        let restart = current.channels_differ(&options);
+        *current = options;
+        restart
+    }
+
     fn run(channel: Self::Channel) -> Instrumented<JoinHandle<Result<()>>> {
         tokio::spawn(async move {
             Replica {
@@ -374,7 +576,11 @@ impl NomadAgent for Processor {
                 db: channel.db(),
                 allowed: channel.allowed,
                 denied: channel.denied,
+                allowed_kinds: channel.allowed_kinds,
+                denied_kinds: channel.denied_kinds,
+                routers: channel.routers,
                 next_message_nonce: channel.next_message_nonce,
+                shutdown: channel.shutdown_token(),
             }
             .main()
             .await?
@@ -386,55 +592,84 @@ impl NomadAgent for Processor {
     where
         Self: Sized + 'static,
     {
+        let agent = Arc::new(self);
         tokio::spawn(async move {
-            self.assert_home_not_failed().await??;
+            agent.assert_home_not_failed().await??;
 
             info!("Starting Processor tasks");
+            let mut supervisor = agent.supervisor();
 
-            // tree sync
+            // tree sync. Rebuilt from disk on restart
             info!("Starting ProverSync");
-            let db = NomadDB::new(self.home().name(), self.db());
-            let sync = ProverSync::from_disk(db.clone());
-            let prover_sync_task = sync.spawn();
+            let db = NomadDB::new(agent.home().name(), agent.db());
+            let prover_db = db.clone();
+            let shutdown = agent.shutdown_token();
+            supervisor.add(SupervisedTask::new(
+                "prover_sync",
+                RestartPolicy::limited(5),
+                move || ProverSync::spawn_from_disk(prover_db.clone(), shutdown.clone()),
+            ));
 
             info!("Starting indexer");
-            let home_sync_task = self.home().sync();
-
-            let home_fail_watch_task = self.watch_home_fail(self.interval);
+            let home = agent.home();
+            supervisor.add(SupervisedTask::new(
+                "home_sync",
+                RestartPolicy::ALWAYS,
+                move || home.sync(),
+            ));
+
+            // a failed home is not transient
+            supervisor.add(SupervisedTask::once(
+                "home_fail_watch",
+                agent.watch_home_fail(agent.options().interval),
+            ));
 
             info!("started indexer, sync and home fail watch");
 
-            // instantiate task array here so we can optionally push run_task
-            let mut tasks = vec![home_sync_task, prover_sync_task, home_fail_watch_task];
-
-            if !self.subsidized_remotes.is_empty() {
-                let remotes: Vec<&str> =
-                    self.subsidized_remotes.iter().map(|r| r.as_str()).collect();
-                tasks.push(self.run_many(&remotes));
-            }
+            // channels to subsidized remotes, following reloaded settings
+            supervisor.add(SupervisedTask::once(
+                "channels",
+                agent.clone().run_channels(),
+            ));
 
             // if we have a bucket, add a task to push to it
-            if let Some(config) = &self.config {
+            if let Some(config) = agent.config.clone() {
                 info!(bucket = %config.bucket, "Starting S3 push tasks");
-                tasks.push(
-                    Pusher::new(
-                        self.core.home.name(),
-                        &config.bucket,
-                        config.region.parse().expect("invalid s3 region"),
-                        db.clone(),
-                    )
-                    .spawn(),
-                )
+                let home_name = agent.home().name().to_owned();
+                let region: Region = config.region.parse().expect("invalid s3 region");
+                let db = db.clone();
+                supervisor.add(SupervisedTask::new(
+                    "s3_push",
+                    RestartPolicy::ALWAYS,
+                    move || {
+                        Pusher::new(&home_name, &config.bucket, region.clone(), db.clone()).spawn()
+                    },
+                ));
             }
 
-            // find the first task to shut down. Then cancel all others
-            debug!(tasks = tasks.len(), "Selecting across Processor tasks");
-            let (res, _, remaining) = select_all(tasks).await;
-            for task in remaining.into_iter() {
-                cancel_task!(task);
+            // if we have a retention policy, add a task to prune the db. Keep
+            // proofs until they are pushed, if pushing, and messages until
+            // they are processed
+            if let Some(retention) = agent.retention.clone() {
+                info!(retention = ?retention, "Starting pruning task");
+                let require_pushed = agent.config.is_some();
+                let pruner_agent = agent.clone();
+                supervisor.add(SupervisedTask::new(
+                    "pruner",
+                    RestartPolicy::ALWAYS,
+                    move || {
+                        let agent = pruner_agent.clone();
+                        Pruner::new(db.clone(), retention.clone())
+                            .require_pushed(require_pushed)
+                            .keep_unprocessed(move || agent.channel_domains())
+                            .spawn()
+                    },
+                ));
             }
 
-            res?
+            // run until a task finishes or fails for good
+            debug!("Supervising Processor tasks");
+            supervisor.spawn().await?
         })
         .instrument(info_span!("Processor::run_all"))
     }
@@ -1,23 +1,27 @@
 use color_eyre::eyre::{bail, Result};
 use ethers::core::types::H256;
-use nomad_base::NomadDB;
+use nomad_base::{NomadDB, ShutdownToken};
 use nomad_core::{
-    accumulator::{Merkle, NomadTree, ProvingError},
+    accumulator::{error::IngestionError, Merkle, MerkleProof, NomadTree, ProvingError},
     db::DbError,
     ChainCommunicationError,
 };
 use std::{fmt::Display, time::Duration};
-use tokio::{
-    task::JoinHandle,
-    time::{sleep, timeout},
-};
-use tracing::{debug, error, info, info_span, instrument, instrument::Instrumented, Instrument};
+use tokio::{task::JoinHandle, time::timeout};
+use tracing::{debug, info, info_span, instrument, instrument::Instrumented, warn, Instrument};
+
+/// Number of leaves between prover tree snapshots
+const SNAPSHOT_INTERVAL: usize = 10_000;
 
 /// Struct to sync prover.
 #[derive(Debug)]
 pub struct ProverSync {
     db: NomadDB,
     prover: NomadTree,
+    /// Latest committed root in the db when the tree was last built or
+    /// advanced. It changing under the tree means the db was rolled back
+    committed: Option<H256>,
+    shutdown: ShutdownToken,
 }
 
 impl Display for ProverSync {
@@ -62,6 +66,9 @@ pub enum ProverSyncError {
     /// ProverSync attempts Prover operation and receives ProvingError
     #[error(transparent)]
     ProvingError(#[from] ProvingError),
+    /// ProverSync ingests a leaf and receives IngestionError
+    #[error(transparent)]
+    IngestionError(#[from] IngestionError),
     /// ProverSync receives ChainCommunicationError from chain API
     #[error(transparent)]
     ChainCommunicationError(#[from] ChainCommunicationError),
@@ -82,13 +89,30 @@ impl ProverSync {
         }
     }
 
+    // The size of the smallest tree containing `leaf_index` whose root was
+    // committed by an update. Replicas confirm roots in order, so proofs
+    // against this root become valid soonest.
+    fn earliest_committed_count(&self, leaf_index: usize) -> Result<usize, ProverSyncError> {
+        for count in leaf_index + 1..self.prover.count() {
+            let root = self.prover.root_at(count)?;
+            if self.db.update_by_new_root(root)?.is_some() {
+                return Ok(count);
+            }
+        }
+        Ok(self.prover.count())
+    }
+
     fn store_proof(&self, leaf_index: u32) -> Result<(), ProverSyncError> {
-        match self.prover.prove(leaf_index as usize) {
+        self.store_proof_at(leaf_index, self.prover.count())
+    }
+
+    fn store_proof_at(&self, leaf_index: u32, count: usize) -> Result<(), ProverSyncError> {
+        match self.prover.prove_at(leaf_index as usize, count) {
             Ok(proof) => {
                 self.db.store_proof(leaf_index, &proof)?;
                 info!(
                     leaf_index,
-                    root = ?self.prover.root(),
+                    root = ?proof.root(),
                     "Storing proof for leaf {}",
                     leaf_index
                 );
@@ -102,48 +126,120 @@ impl ProverSync {
         }
     }
 
+    // Snapshot the tree if it is at the latest committed root, so that the
+    // next start does not need to re-ingest leaves
+    fn flush(&self) -> Result<(), ProverSyncError> {
+        if self.prover.count() > 0
+            && self
+                .db
+                .store_committed_prover_snapshot(&self.prover.snapshot())?
+        {
+            info!(
+                root = ?self.prover.root(),
+                count = self.prover.count(),
+                "Stored prover tree snapshot before shutdown"
+            );
+        }
+        Ok(())
+    }
+
     /// Given rocksdb handle `db` containing merkle tree leaves,
     /// instantiates new `ProverSync` and fills prover's merkle tree
-    #[instrument(level = "debug", skip(db))]
-    pub fn from_disk(db: NomadDB) -> Self {
-        // Ingest all leaves in db into prover tree
+    #[instrument(level = "debug", skip(db, shutdown))]
+    pub fn from_disk(db: NomadDB, shutdown: ShutdownToken) -> Result<Self, ProverSyncError> {
         let mut prover = NomadTree::default();
+        // Proofs for leaves under a snapshot were stored before it was taken
+        let mut checked = 0;
 
-        if let Some(root) = db.retrieve_prover_latest_committed().expect("db error") {
-            for i in 0.. {
-                match db.leaf_by_leaf_index(i) {
-                    Ok(Some(leaf)) => {
-                        debug!(leaf_index = i, "Ingesting leaf from_disk");
-                        prover.ingest(leaf).expect("!tree full");
-                        if prover.root() == root {
-                            break;
-                        }
+        let committed = db.retrieve_prover_latest_committed()?;
+        if let Some(root) = committed {
+            // Resume from the latest snapshot, if there is a valid one
+            if let Some(snapshot) = db.retrieve_latest_prover_snapshot()? {
+                let snapshot_root = snapshot.root();
+                match NomadTree::from_snapshot(snapshot) {
+                    Ok(tree) => {
+                        info!(
+                            snapshot_root = ?snapshot_root,
+                            count = tree.count(),
+                            "Restored ProverSync tree from snapshot"
+                        );
+                        checked = tree.count();
+                        prover = tree;
                     }
-                    Ok(None) => break,
                     Err(e) => {
-                        error!(error = %e, "Error in ProverSync::from_disk");
-                        panic!("Error in ProverSync::from_disk");
+                        warn!(
+                            error = %e,
+                            snapshot_root = ?snapshot_root,
+                            "Ignoring invalid prover snapshot"
+                        );
                     }
                 }
             }
+
+            // Ingest remaining leaves in db into prover tree
+            let start = prover.count() as u32;
+            for i in start.. {
+                if prover.root() == root {
+                    break;
+                }
+
+                match db.leaf_by_leaf_index(i)? {
+                    Some(leaf) => {
+                        debug!(leaf_index = i, "Ingesting leaf from_disk");
+                        prover.ingest(leaf)?;
+                    }
+                    None => break,
+                }
+            }
             info!(target_latest_root = ?root, root = ?prover.root(), "Reloaded ProverSync from disk");
         }
 
-        let sync = Self { prover, db };
+        let sync = Self {
+            prover,
+            db,
+            committed,
+            shutdown,
+        };
 
-        // Ensure proofs exist for all leaves
-        for i in 0..sync.prover.count() as u32 {
+        // Ensure proofs exist for all leaves. Prove against the earliest
+        // committed root containing each leaf, rather than the tip, so that
+        // replicas which have not caught up can still accept them.
+        let mut committed_count = 0;
+        for i in checked as u32..sync.prover.count() as u32 {
             match (
-                sync.db.leaf_by_leaf_index(i).expect("db error"),
-                sync.db.proof_by_leaf_index(i).expect("db error"),
+                sync.db.leaf_by_leaf_index(i)?,
+                sync.db.proof_by_leaf_index(i)?,
             ) {
-                (Some(_), None) => sync.store_proof(i).expect("db error"),
+                (Some(_), None) if !sync.db.is_pruned(i)? => {
+                    if committed_count <= i as usize {
+                        committed_count = sync.earliest_committed_count(i as usize)?;
+                    }
+                    sync.store_proof_at(i, committed_count)?
+                }
                 (None, _) => break,
                 _ => {}
             }
         }
 
-        sync
+        Ok(sync)
+    }
+
+    /// Spawn a task rebuilding the tree from `db` off the runtime, then
+    /// polling for signed updates at regular interval. The local merkle
+    /// tree is updated with all leaves between the local root and each new
+    /// root. Errors rebuilding the tree fail the task, so that it can be
+    /// restarted.
+    pub fn spawn_from_disk(
+        db: NomadDB,
+        shutdown: ShutdownToken,
+    ) -> Instrumented<JoinHandle<Result<()>>> {
+        let span = info_span!("ProverSync::spawn_from_disk");
+        tokio::spawn(async move {
+            let sync = tokio::task::spawn_blocking(move || Self::from_disk(db, shutdown)).await??;
+            let span = info_span!("ProverSync", self = %sync);
+            sync.run().instrument(span).await
+        })
+        .instrument(span)
     }
 
     /// Given new root, update prover tree with leaves until prover tree root
@@ -157,13 +253,17 @@ impl ProverSync {
 
             // Wait for leaf for 60 seconds and error out if not found
             info!("Waiting for leaf at index {}...", tree_size);
-            let leaf = match timeout(Duration::from_secs(60), leaf_fut).await {
-                Ok(res) => res.map_err(ProverSyncError::DbError),
-                Err(_) => Err(ProverSyncError::LeafNotFound {
-                    new_root,
-                    leaf_index: tree_size,
-                }),
-            }?;
+            let leaf = tokio::select! {
+                res = timeout(Duration::from_secs(60), leaf_fut) => match res {
+                    Ok(res) => res.map_err(ProverSyncError::DbError),
+                    Err(_) => Err(ProverSyncError::LeafNotFound {
+                        new_root,
+                        leaf_index: tree_size,
+                    }),
+                }?,
+                // Leave the tree short of new_root, the caller stops
+                _ = self.shutdown.triggered() => return Ok(()),
+            };
 
             info!(
                 index = tree_size,
@@ -178,63 +278,101 @@ impl ProverSync {
         Ok(())
     }
 
-    /// Consume self and poll for signed updates at regular interval. Update
-    /// local merkle tree with all leaves between local root and
-    /// new root. Use short interval for bootup syncing and longer
-    /// interval for regular polling.
-    pub fn spawn(mut self) -> Instrumented<JoinHandle<Result<()>>> {
-        let span = info_span!("ProverSync", self = %self);
-        tokio::spawn(async move {
-            loop {
-                // Try to retrieve new signed update
-                let local_root = self.local_root();
-                let signed_update_opt = self.db.update_by_previous_root(local_root)?;
-
-                if let Some(signed_update) = signed_update_opt {
-                    let previous_root = signed_update.update.previous_root;
-                    let new_root = signed_update.update.new_root;
-
-                    info!(
-                        previous_root = ?previous_root,
-                        new_root = ?new_root,
-                        "Have signed update from {} to {}",
-                        previous_root,
-                        new_root
-                    );
-
-                    // Update in-memory prover tree until local tree root
-                    // matches newly found new_root
-                    let pre_update_size = self.prover.count();
-                    self.update_prover_tree(new_root).await?;
-
-                    // Double check that update new root now equals current prover root
-                    let current_root = self.prover.root();
-                    if current_root != new_root {
-                        bail!(ProverSyncError::MismatchedRoots {
-                            local_root: current_root,
-                            new_root,
-                        });
+    async fn run(mut self) -> Result<()> {
+        // Hold off shutdown until the tree is flushed
+        let _in_flight = match self.shutdown.in_flight().await {
+            Some(guard) => guard,
+            None => return Ok(()),
+        };
+
+        loop {
+            // Rebuild the tree if the db was rolled back under it
+            if self.db.retrieve_prover_latest_committed()? != self.committed {
+                warn!(
+                    committed = ?self.committed,
+                    "Prover state was rolled back. Rebuilding tree from disk"
+                );
+                let (db, shutdown) = (self.db.clone(), self.shutdown.clone());
+                self = tokio::task::spawn_blocking(move || Self::from_disk(db, shutdown)).await??;
+                continue;
+            }
+
+            // Try to retrieve new signed update
+            let local_root = self.local_root();
+            let signed_update_opt = self.db.update_by_previous_root(local_root)?;
+
+            if let Some(signed_update) = signed_update_opt {
+                let previous_root = signed_update.update.previous_root;
+                let new_root = signed_update.update.new_root;
+
+                info!(
+                    previous_root = ?previous_root,
+                    new_root = ?new_root,
+                    "Have signed update from {} to {}",
+                    previous_root,
+                    new_root
+                );
+
+                // Update in-memory prover tree until local tree root
+                // matches newly found new_root
+                let pre_update_size = self.prover.count();
+                self.update_prover_tree(new_root).await?;
+                if self.shutdown.is_triggered() {
+                    break;
+                }
+
+                // Double check that update new root now equals current prover root
+                let current_root = self.prover.root();
+                if current_root != new_root {
+                    bail!(ProverSyncError::MismatchedRoots {
+                        local_root: current_root,
+                        new_root,
+                    });
+                }
+
+                // Ensure there is a proof in the db for all leaves
+                for idx in pre_update_size..self.prover.count() {
+                    if self.db.proof_by_leaf_index(idx as u32)?.is_none() {
+                        self.store_proof(idx as u32)?;
                     }
+                }
 
-                    // Ensure there is a proof in the db for all leaves
-                    for idx in pre_update_size..self.prover.count() {
-                        if self.db.proof_by_leaf_index(idx as u32)?.is_none() {
-                            self.store_proof(idx as u32)?;
-                        }
+                // Store latest root for which we know we have all leaves/
+                // proofs for, unless it was rolled back meanwhile. The proofs
+                // just stored may then be against reorged leaves
+                let count = self.prover.count();
+                if !self.db.advance_prover_latest_committed(
+                    self.committed,
+                    new_root,
+                    count as u32,
+                )? {
+                    for idx in pre_update_size..count {
+                        self.db.delete_proof(idx as u32)?;
                     }
+                    continue;
+                }
+                self.committed = Some(new_root);
 
-                    // Store latest root for which we know we have all leaves/
-                    // proofs for
-                    self.db.store_prover_latest_committed(new_root)?;
-                } else if !local_root.is_zero() && self.db.update_by_new_root(local_root)?.is_none()
+                // Periodically snapshot the tree so that restarts do not
+                // need to re-ingest every leaf
+                if count / SNAPSHOT_INTERVAL > pre_update_size / SNAPSHOT_INTERVAL
+                    && self
+                        .db
+                        .store_committed_prover_snapshot(&self.prover.snapshot())?
                 {
-                    bail!(ProverSyncError::InvalidLocalRoot { local_root });
+                    info!(root = ?new_root, count, "Stored prover tree snapshot");
                 }
+            } else if !local_root.is_zero() && self.db.update_by_new_root(local_root)?.is_none() {
+                bail!(ProverSyncError::InvalidLocalRoot { local_root });
+            }
 
-                // kludge
-                sleep(Duration::from_millis(100)).await;
+            // kludge
+            if self.shutdown.sleep(Duration::from_millis(100)).await {
+                break;
             }
-        })
-        .instrument(span)
+        }
+
+        self.flush()?;
+        Ok(())
     }
 }
@@ -115,8 +115,15 @@ impl Pusher {
             home = %self.name,
         );
         tokio::spawn(async move {
-            let mut index = 0;
+            let mut index = self.db.retrieve_proofs_pushed_below()?.unwrap_or_default();
             loop {
+                // pruned proofs were either pushed or pruned before a bucket
+                // was configured
+                if self.db.is_pruned(index)? {
+                    index += 1;
+                    continue;
+                }
+
                 let proof = self.db.proof_by_leaf_index(index)?;
                 match proof {
                     Some(proof) => {
@@ -133,6 +140,7 @@ impl Pusher {
                         }
 
                         index += 1;
+                        self.db.store_proofs_pushed_below(index)?;
                     }
                     None => sleep(Duration::from_millis(500)).await,
                 }
@@ -36,10 +36,13 @@ mod test {
         assert_eq!(settings.agent.interval, agent_config.interval);
         assert_eq!(settings.agent.allowed, agent_config.allowed);
         assert_eq!(settings.agent.denied, agent_config.denied);
+        assert_eq!(settings.agent.allowed_kinds, agent_config.allowed_kinds);
+        assert_eq!(settings.agent.denied_kinds, agent_config.denied_kinds);
         assert_eq!(
             settings.agent.subsidized_remotes,
             agent_config.subsidized_remotes
         );
         assert_eq!(settings.agent.s3, agent_config.s3);
+        assert_eq!(settings.agent.retention, agent_config.retention);
     }
 }
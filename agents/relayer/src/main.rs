@@ -12,19 +12,12 @@ mod settings;
 
 use crate::{relayer::Relayer, settings::RelayerSettings as Settings};
 use color_eyre::Result;
-use nomad_base::NomadAgent;
+use nomad_base::run_homes;
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
     color_eyre::install()?;
-    let settings = Settings::new()?;
+    let settings = Settings::new_all()?;
 
-    let agent = Relayer::from_settings(settings).await?;
-
-    agent.start_tracing(agent.metrics().span_duration())?;
-
-    let _ = agent.metrics().run_http_server();
-
-    agent.run_all().await??;
-    Ok(())
+    run_homes::<Relayer>(settings).await
 }
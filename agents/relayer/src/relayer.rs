@@ -1,11 +1,13 @@
 use async_trait::async_trait;
 use color_eyre::Result;
 use std::{sync::Arc, time::Duration};
-use tokio::{sync::Mutex, task::JoinHandle, time::sleep};
+use tokio::{sync::Mutex, task::JoinHandle};
 use tracing::{info, instrument::Instrumented, Instrument};
 
-use nomad_base::{decl_agent, decl_channel, AgentCore, CachingHome, CachingReplica, NomadAgent};
-use nomad_core::{Common, CommonEvents};
+use nomad_base::{
+    decl_agent, decl_channel, AgentCore, CachingHome, CachingReplica, NomadAgent, ShutdownToken,
+};
+use nomad_core::{Common, CommonEvents, LifecycleEvent, Replica};
 
 use crate::settings::RelayerSettings as Settings;
 
@@ -16,6 +18,7 @@ struct UpdatePoller {
     replica: Arc<CachingReplica>,
     semaphore: Mutex<()>,
     updates_relayed_count: prometheus::IntCounter,
+    shutdown: ShutdownToken,
 }
 
 impl std::fmt::Display for UpdatePoller {
@@ -34,6 +37,7 @@ impl UpdatePoller {
         replica: Arc<CachingReplica>,
         interval: u64,
         updates_relayed_count: prometheus::IntCounter,
+        shutdown: ShutdownToken,
     ) -> Self {
         Self {
             home,
@@ -41,6 +45,7 @@ impl UpdatePoller {
             interval,
             semaphore: Mutex::new(()),
             updates_relayed_count,
+            shutdown,
         }
     }
 
@@ -73,12 +78,24 @@ impl UpdatePoller {
                 return Ok(()); // tx in flight. just do nothing
             }
 
-            // Relay update and increment counters if tx successful
-            if self.replica.update(&signed_update).await.is_ok() {
+            // Let the relay finish if we are asked to shut down
+            let _in_flight = match self.shutdown.in_flight().await {
+                Some(guard) => guard,
+                None => return Ok(()),
+            };
+
+            // Relay update, increment counters and record the relay if tx
+            // successful
+            if let Ok(outcome) = self.replica.update(&signed_update).await {
                 self.updates_relayed_count.inc();
+                self.home.db().store_relayed_update(
+                    self.replica.local_domain(),
+                    signed_update.update.previous_root,
+                    &LifecycleEvent::submitted(outcome.txid),
+                )?;
             }
 
-            // lock dropped here
+            // lock and in-flight guard dropped here
         } else {
             info!(
                 "No update. Current root for replica {} is {}",
@@ -94,7 +111,13 @@ impl UpdatePoller {
         tokio::spawn(async move {
             loop {
                 self.poll_and_relay_update().await?;
-                sleep(Duration::from_secs(self.interval)).await;
+                if self
+                    .shutdown
+                    .sleep(Duration::from_secs(self.interval))
+                    .await
+                {
+                    return Ok(());
+                }
             }
         })
     }
@@ -170,6 +193,7 @@ impl NomadAgent for Relayer {
                 channel.replica(),
                 channel.interval,
                 channel.updates_relayed_count,
+                channel.shutdown_token(),
             );
             update_poller.spawn().await?
         })
@@ -274,11 +298,13 @@ mod test {
             // Setting agent
             let core = AgentCore {
                 home,
-                replicas,
+                replicas: Arc::new(std::sync::RwLock::new(replicas)),
                 db,
                 metrics,
                 indexer: IndexSettings::default(),
                 settings,
+                shutdown: Default::default(),
+                channel_shutdowns: Default::default(),
             };
 
             let agent = Relayer::new(2, core);
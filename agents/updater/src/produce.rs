@@ -8,7 +8,7 @@ use nomad_core::{Common, Home, SignedUpdate, Signers};
 use tokio::{task::JoinHandle, time::sleep};
 use tracing::{debug, error, info, info_span, instrument::Instrumented, Instrument};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct UpdateProducer {
     home: Arc<CachingHome>,
     db: NomadDB,
@@ -1,20 +1,22 @@
 use std::sync::Arc;
 
-use nomad_base::{CachingHome, NomadDB};
+use nomad_base::{CachingHome, NomadDB, ShutdownToken};
 use nomad_core::Common;
 use prometheus::IntCounter;
 use std::time::Duration;
 
 use color_eyre::Result;
-use tokio::{task::JoinHandle, time::sleep};
+use tokio::task::JoinHandle;
 use tracing::{info, info_span, instrument::Instrumented, Instrument};
 
+#[derive(Clone)]
 pub(crate) struct UpdateSubmitter {
     home: Arc<CachingHome>,
     db: NomadDB,
     interval_seconds: u64,
     finalization_seconds: u64,
     submitted_update_count: IntCounter,
+    shutdown: ShutdownToken,
 }
 
 impl UpdateSubmitter {
@@ -24,6 +26,7 @@ impl UpdateSubmitter {
         interval_seconds: u64,
         finalization_seconds: u64,
         submitted_update_count: IntCounter,
+        shutdown: ShutdownToken,
     ) -> Self {
         Self {
             home,
@@ -31,6 +34,7 @@ impl UpdateSubmitter {
             interval_seconds,
             finalization_seconds,
             submitted_update_count,
+            shutdown,
         }
     }
 
@@ -42,11 +46,23 @@ impl UpdateSubmitter {
             let mut committed_root = self.home.committed_root().await?;
 
             loop {
-                sleep(Duration::from_secs(self.interval_seconds)).await;
+                if self
+                    .shutdown
+                    .sleep(Duration::from_secs(self.interval_seconds))
+                    .await
+                {
+                    return Ok(());
+                }
 
                 // if we have produced an update building off the committed root
                 // submit it
                 if let Some(signed) = self.db.retrieve_produced_update(committed_root)? {
+                    // Let the submission finish if we are asked to shut down
+                    let in_flight = match self.shutdown.in_flight().await {
+                        Some(guard) => guard,
+                        None => return Ok(()),
+                    };
+
                     let hex_signature = format!("0x{}", hex::encode(signed.signature.to_vec()));
                     info!(
                         previous_root = ?signed.update.previous_root,
@@ -71,7 +87,14 @@ impl UpdateSubmitter {
                         sleep = self.finalization_seconds,
                         "Submitted update with tx hash {:?}. Sleeping before next tx submission.", tx.txid,
                     );
-                    sleep(Duration::from_secs(self.finalization_seconds)).await;
+                    drop(in_flight);
+                    if self
+                        .shutdown
+                        .sleep(Duration::from_secs(self.finalization_seconds))
+                        .await
+                    {
+                        return Ok(());
+                    }
                 } else {
                     info!(
                         committed_root = ?committed_root,
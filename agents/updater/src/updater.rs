@@ -3,7 +3,6 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use color_eyre::{eyre::ensure, Result};
 use ethers::{signers::Signer, types::Address};
-use futures_util::future::select_all;
 use prometheus::IntCounter;
 use tokio::task::JoinHandle;
 use tracing::{info, instrument::Instrumented, Instrument};
@@ -11,7 +10,7 @@ use tracing::{info, instrument::Instrumented, Instrument};
 use crate::{
     produce::UpdateProducer, settings::UpdaterSettings as Settings, submit::UpdateSubmitter,
 };
-use nomad_base::{AgentCore, NomadAgent, NomadDB};
+use nomad_base::{AgentCore, NomadAgent, NomadDB, RestartPolicy, SupervisedTask};
 use nomad_core::{Common, Signers};
 
 /// An updater agent
@@ -144,10 +143,12 @@ impl NomadAgent for Updater {
             self.interval_seconds,
             self.finalization_seconds,
             self.submitted_update_count.clone(),
+            self.shutdown_token(),
         );
 
         let fail_check = self.assert_home_not_failed();
         let home_fail_watch_task = self.watch_home_fail(self.interval_seconds);
+        let mut supervisor = self.supervisor();
 
         tokio::spawn(async move {
             fail_check.await??;
@@ -161,25 +162,32 @@ impl NomadAgent for Updater {
             );
 
             info!("Spawning sync task for updater...");
-            let sync_task = home.sync();
+            supervisor.add(SupervisedTask::new(
+                "home_sync",
+                RestartPolicy::ALWAYS,
+                move || home.sync(),
+            ));
 
             // Only spawn updater tasks once syncing has finished
             info!("Spawning produce and submit tasks...");
-            let produce_task = produce.spawn();
-            let submit_task = submit.spawn();
-
-            let (res, _, rem) = select_all(vec![
-                sync_task,
-                produce_task,
-                submit_task,
+            supervisor.add(SupervisedTask::new(
+                "update_producer",
+                RestartPolicy::limited(10),
+                move || produce.clone().spawn(),
+            ));
+            supervisor.add(SupervisedTask::new(
+                "update_submitter",
+                RestartPolicy::limited(10),
+                move || submit.clone().spawn(),
+            ));
+
+            // a failed home is not transient
+            supervisor.add(SupervisedTask::once(
+                "home_fail_watch",
                 home_fail_watch_task,
-            ])
-            .await;
+            ));
 
-            for task in rem.into_iter() {
-                task.into_inner().abort();
-            }
-            res?
+            supervisor.spawn().await?
         })
         .in_current_span()
     }
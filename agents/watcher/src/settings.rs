@@ -59,6 +59,14 @@ mod test {
                 manager_setup.page_settings.page_size,
                 config_manager_domain.specs.index_page_size
             );
+            assert_eq!(
+                manager_setup.page_settings.min_page_size,
+                config_manager_domain.specs.min_index_page_size
+            );
+            assert_eq!(
+                manager_setup.page_settings.max_page_size,
+                config_manager_domain.specs.max_index_page_size
+            );
             assert_eq!(
                 manager_setup.finality,
                 config_manager_domain.specs.finalization_blocks
@@ -3,7 +3,7 @@ use color_eyre::{eyre::bail, Report, Result};
 use thiserror::Error;
 
 use ethers::core::types::H256;
-use futures_util::future::{join, join_all, select_all};
+use futures_util::future::{join, join_all};
 use prometheus::{IntGauge, IntGaugeVec};
 use std::{collections::HashMap, fmt::Display, sync::Arc, time::Duration};
 use tokio::{
@@ -15,7 +15,9 @@ use tokio::{
 use tracing::{error, info, info_span, instrument::Instrumented, Instrument};
 
 use nomad_base::{
-    cancel_task, AgentCore, BaseError, CachingHome, ConnectionManagers, NomadAgent, NomadDB,
+    cancel_task, watch_settings, AgentCore, BaseError, CachingHome, CachingReplica,
+    ConnectionManagers, NomadAgent, NomadDB, RestartPolicy, SupervisedTask, Supervisor,
+    RELOAD_INTERVAL,
 };
 use nomad_core::{
     ChainCommunicationError, Common, CommonEvents, ConnectionManager, DoubleUpdate,
@@ -289,6 +291,7 @@ pub struct Watcher {
     interval_seconds: u64,
     sync_tasks: TaskMap,
     watch_tasks: TaskMap,
+    replica_syncs: TaskMap,
     connection_managers: Vec<Arc<ConnectionManagers>>,
     core: AgentCore,
     double_updates_observed: IntGauge,
@@ -334,6 +337,7 @@ impl Watcher {
             interval_seconds,
             sync_tasks: Default::default(),
             watch_tasks: Default::default(),
+            replica_syncs: Default::default(),
             connection_managers,
             core,
             double_updates_observed,
@@ -343,53 +347,23 @@ impl Watcher {
 
     /// Spawn UpdateHandler and sync tasks. Have sync tasks send UpdateHandler
     /// signed updates through mpsc. Return Some(double_update) if any
-    /// conflicting updates are found.
+    /// conflicting updates are found. Replicas are read every interval, so
+    /// their tasks follow the replicas as settings are reloaded.
     fn watch_double_update(&self) -> Instrumented<JoinHandle<Result<Option<DoubleUpdate>>>> {
+        let core = self.core.clone();
         let home = self.home();
-        let replicas = self.replicas().clone();
         let watcher_db_name = format!("{}_{}", home.name(), AGENT_NAME);
         let watcher_db = NomadDB::new(watcher_db_name, self.db());
         let interval_seconds = self.interval_seconds;
         let sync_tasks = self.sync_tasks.clone();
         let watch_tasks = self.watch_tasks.clone();
+        let replica_syncs = self.replica_syncs.clone();
         let updates_inspected_for_double = self.updates_inspected_for_double.clone();
 
         tokio::spawn(async move {
             // Spawn update handler
             let (tx, rx) = mpsc::channel(200);
-            let handler = UpdateHandler::new(rx, watcher_db, home.clone()).spawn();
-
-            // For each replica, spawn polling and history syncing tasks
-            info!("Spawning replica watch and sync tasks...");
-            for (name, replica) in replicas {
-                info!("Spawning watch and sync tasks for replica {}.", name);
-                let from = replica.committed_root().await?;
-
-                let inspected = updates_inspected_for_double.with_label_values(&[
-                    home.name(),
-                    replica.name(),
-                    Self::AGENT_NAME,
-                ]);
-
-                watch_tasks.write().await.insert(
-                    (*name).to_owned(),
-                    ContractWatcher::new(
-                        interval_seconds,
-                        from,
-                        tx.clone(),
-                        replica.clone(),
-                        inspected.clone(),
-                    )
-                    .spawn()
-                    .in_current_span(),
-                );
-                sync_tasks.write().await.insert(
-                    (*name).to_owned(),
-                    HistorySync::new(interval_seconds, from, tx.clone(), replica, inspected)
-                        .spawn()
-                        .in_current_span(),
-                );
-            }
+            let mut handler = UpdateHandler::new(rx, watcher_db, home.clone()).spawn();
 
             // Spawn polling and history syncing tasks for home
             info!("Starting watch and sync tasks for home {}.", home.name());
@@ -409,13 +383,98 @@ impl Watcher {
             )
             .spawn()
             .in_current_span();
-            let home_sync = HistorySync::new(interval_seconds, from, tx.clone(), home, inspected)
-                .spawn()
-                .in_current_span();
+            let home_sync =
+                HistorySync::new(interval_seconds, from, tx.clone(), home.clone(), inspected)
+                    .spawn()
+                    .in_current_span();
+
+            // Until the update handler finishes (should only happen watcher
+            // is manually shut down), keep a sync, polling and history
+            // syncing task running for each replica
+            let mut watched: HashMap<String, Arc<CachingReplica>> = HashMap::new();
+            let double_update_res = loop {
+                let replicas = core.replicas.read().expect("!poisoned").clone();
+
+                let stale: Vec<String> = watched
+                    .iter()
+                    .filter(|(name, replica)| {
+                        replicas
+                            .get(*name)
+                            .map_or(true, |current| !Arc::ptr_eq(current, replica))
+                    })
+                    .map(|(name, _)| name.clone())
+                    .collect();
+                for name in stale {
+                    info!("Stopping watch and sync tasks for replica {}.", name);
+                    watched.remove(&name);
+                    for tasks in [&watch_tasks, &sync_tasks, &replica_syncs] {
+                        let task = tasks.write().await.remove(&name);
+                        if let Some(task) = task {
+                            cancel_task!(task);
+                        }
+                    }
+                }
 
-            // Wait for update handler to finish (should only happen watcher is
-            // manually shut down)
-            let double_update_res = handler.await?;
+                for (name, replica) in replicas {
+                    if watched.contains_key(&name) {
+                        continue;
+                    }
+                    info!("Spawning watch and sync tasks for replica {}.", name);
+                    let from = replica.committed_root().await?;
+
+                    let inspected = updates_inspected_for_double.with_label_values(&[
+                        home.name(),
+                        replica.name(),
+                        Self::AGENT_NAME,
+                    ]);
+
+                    // An error syncing one contract should not stop syncing
+                    // the others
+                    let mut supervisor =
+                        Supervisor::new(core.metrics.clone(), core.shutdown.clone());
+                    let syncing = replica.clone();
+                    supervisor.add(SupervisedTask::new(
+                        format!("replica_sync:{}", name),
+                        RestartPolicy::ALWAYS,
+                        move || syncing.sync(),
+                    ));
+                    replica_syncs
+                        .write()
+                        .await
+                        .insert(name.clone(), supervisor.spawn().in_current_span());
+
+                    watch_tasks.write().await.insert(
+                        name.clone(),
+                        ContractWatcher::new(
+                            interval_seconds,
+                            from,
+                            tx.clone(),
+                            replica.clone(),
+                            inspected.clone(),
+                        )
+                        .spawn()
+                        .in_current_span(),
+                    );
+                    sync_tasks.write().await.insert(
+                        name.clone(),
+                        HistorySync::new(
+                            interval_seconds,
+                            from,
+                            tx.clone(),
+                            replica.clone(),
+                            inspected,
+                        )
+                        .spawn()
+                        .in_current_span(),
+                    );
+                    watched.insert(name, replica);
+                }
+
+                select! {
+                    res = &mut handler => break res?,
+                    _ = sleep(Duration::from_secs(interval_seconds)) => {}
+                }
+            };
 
             // Cancel running tasks
             tracing::info!("Update handler has resolved. Cancelling all other tasks");
@@ -430,6 +489,35 @@ impl Watcher {
         .in_current_span()
     }
 
+    /// Apply reloaded settings to the replicas. Their watch and sync tasks
+    /// follow on the next interval.
+    fn follow_settings(&self) -> Instrumented<JoinHandle<Result<()>>> {
+        let core = self.core.clone();
+        let mut reloads = watch_settings::<Settings>(
+            core.home.name().to_owned(),
+            RELOAD_INTERVAL,
+            self.shutdown_token(),
+        );
+
+        tokio::spawn(async move {
+            let mut current: Option<Settings> = None;
+            while let Some(settings) = reloads.recv().await {
+                let previous = current
+                    .as_ref()
+                    .map_or(&core.settings, |settings| settings.as_ref());
+                let res = core
+                    .reload_replicas(AGENT_NAME, previous, settings.as_ref())
+                    .await;
+                match res {
+                    Ok(_) => current = Some(settings),
+                    Err(e) => error!(error = ?e, "Failed to apply reloaded settings"),
+                }
+            }
+            Ok(())
+        })
+        .in_current_span()
+    }
+
     async fn create_signed_failure(&self) -> SignedFailureNotification {
         FailureNotification {
             home_domain: self.home().local_domain(),
@@ -447,10 +535,13 @@ impl Watcher {
         &self,
         double: &DoubleUpdate,
     ) -> Vec<Result<TxOutcome, ChainCommunicationError>> {
+        // Hold off shutdown until all submissions finish. Submit even if
+        // already shutting down
+        let _in_flight = self.shutdown_token().critical_in_flight().await;
+
         // Create vector of double update futures
-        let mut double_update_futs: Vec<_> = self
-            .core
-            .replicas
+        let replicas = self.replicas();
+        let mut double_update_futs: Vec<_> = replicas
             .values()
             .map(|replica| replica.double_update(double))
             .collect();
@@ -482,6 +573,10 @@ impl Watcher {
     async fn handle_improper_update_failure(
         &self,
     ) -> Vec<Result<TxOutcome, ChainCommunicationError>> {
+        // Hold off shutdown until all submissions finish. Submit even if
+        // already shutting down
+        let _in_flight = self.shutdown_token().critical_in_flight().await;
+
         let signed_failure = self.create_signed_failure().await;
         let mut unenroll_futs = Vec::new();
         for connection_manager in self.connection_managers.iter() {
@@ -498,6 +593,9 @@ impl Watcher {
         for (_, v) in self.sync_tasks.write().await.drain() {
             cancel_task!(v);
         }
+        for (_, v) in self.replica_syncs.write().await.drain() {
+            cancel_task!(v);
+        }
     }
 }
 
@@ -581,17 +679,17 @@ impl NomadAgent for Watcher {
         tokio::spawn(async move {
             info!("Starting Watcher tasks");
 
-            let home_sync_task = self
-                .home()
-                .sync();
-
-            let replica_sync_tasks: Vec<Instrumented<JoinHandle<Result<()>>>> = self.replicas().values().map(|replica| {
-                replica.sync()
-            }).collect();
-
-            let mut sync_tasks = vec![home_sync_task];
-            sync_tasks.extend(replica_sync_tasks);
-            let sync_task_unified = select_all(sync_tasks);
+            // Replicas are synced alongside their watch tasks, so they
+            // follow reloaded settings
+            let mut sync_supervisor = self.supervisor();
+            let home = self.home();
+            sync_supervisor.add(SupervisedTask::new(
+                "home_sync",
+                RestartPolicy::ALWAYS,
+                move || home.sync(),
+            ));
+            let sync_task_unified = sync_supervisor.spawn();
+            let _reloads = self.follow_settings();
 
             let double_update_watch_task = self.watch_double_update();
             let improper_update_watch_task = self.watch_home_fail(self.interval_seconds);
@@ -1185,10 +1283,12 @@ mod test {
 
                 let core = AgentCore {
                     home: home.clone(),
-                    replicas: replica_map,
+                    replicas: Arc::new(std::sync::RwLock::new(replica_map)),
                     db,
                     indexer: IndexSettings::default(),
                     settings: nomad_base::Settings::default(),
+                    shutdown: Default::default(),
+                    channel_shutdowns: Default::default(),
                     metrics: Arc::new(
                         nomad_base::CoreMetrics::new(
                             "watcher_test",
@@ -1378,10 +1478,12 @@ mod test {
 
                 let core = AgentCore {
                     home: home.clone(),
-                    replicas: replica_map,
+                    replicas: Arc::new(std::sync::RwLock::new(replica_map)),
                     db,
                     indexer: IndexSettings::default(),
                     settings: nomad_base::Settings::default(),
+                    shutdown: Default::default(),
+                    channel_shutdowns: Default::default(),
                     metrics: Arc::new(
                         nomad_base::CoreMetrics::new(
                             "watcher_test",
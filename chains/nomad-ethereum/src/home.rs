@@ -9,15 +9,23 @@ use ethers::{
 };
 use futures_util::future::join_all;
 use nomad_core::{
-    ChainCommunicationError, Common, CommonIndexer, ContractLocator, DoubleUpdate, Home,
-    HomeIndexer, Message, RawCommittedMessage, SignedUpdate, SignedUpdateWithMeta, State,
-    TxOutcome, Update, UpdateMeta,
+    BlockHeader, ChainCommunicationError, Common, CommonIndexer, ContractLocator, DoubleUpdate,
+    Home, HomeIndexer, Message, MessageMeta, RawCommittedMessage, RawCommittedMessageWithMeta,
+    SignedUpdate, SignedUpdateWithMeta, State, TxOutcome, Update, UpdateMeta,
 };
 use nomad_xyz_configuration::HomeGasLimits;
-use std::{convert::TryFrom, error::Error as StdError, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    error::Error as StdError,
+    sync::Arc,
+    time::Duration,
+};
 use tracing::instrument;
 
-use crate::{bindings::home::Home as EthereumHomeInternal, report_tx};
+use crate::{
+    bindings::home::Home as EthereumHomeInternal, block_header, report_tx, HeadSubscription,
+};
 
 impl<M> std::fmt::Display for EthereumHomeInternal<M>
 where
@@ -36,6 +44,7 @@ where
 {
     contract: Arc<EthereumHomeInternal<R>>,
     provider: Arc<R>,
+    subscription: Option<Arc<HeadSubscription>>,
     from_height: u32,
     chunk_size: u32,
 }
@@ -61,10 +70,20 @@ where
                 provider.clone(),
             )),
             provider,
+            subscription: None,
             from_height,
             chunk_size,
         }
     }
+
+    /// Wait for new blocks with `subscription` while it is connected,
+    /// instead of polling
+    pub fn with_subscription(self, subscription: Option<Arc<HeadSubscription>>) -> Self {
+        Self {
+            subscription,
+            ..self
+        }
+    }
 }
 
 #[async_trait]
@@ -77,6 +96,19 @@ where
         Ok(self.provider.get_block_number().await?.as_u32())
     }
 
+    async fn wait_for_block(&self, number: u32, timeout: Duration) -> Result<bool> {
+        match &self.subscription {
+            Some(subscription) => Ok(subscription.wait_for_block(number, timeout).await),
+            None => Ok(false),
+        }
+    }
+
+    #[instrument(err, skip(self))]
+    async fn get_block_header(&self, number: u32) -> Result<Option<BlockHeader>> {
+        let block = self.provider.get_block(number as u64).await?;
+        Ok(block.as_ref().and_then(block_header))
+    }
+
     #[instrument(err, skip(self))]
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {
         let mut events = self
@@ -109,19 +141,18 @@ where
                 };
 
                 let block_number = event.1.block_number.as_u64();
-                let timestamp = self
-                    .provider
-                    .get_block(block_number)
-                    .await
-                    .ok()
-                    .flatten()
-                    .map(|b| b.timestamp.as_u64());
+                let block_hash = event.1.block_hash;
+                let block = self.provider.get_block(block_hash).await.ok().flatten();
+                let timestamp = block.as_ref().map(|b| b.timestamp.as_u64());
+                let parent_hash = block.map(|b| b.parent_hash).unwrap_or_default();
 
                 SignedUpdateWithMeta {
                     signed_update: SignedUpdate { update, signature },
                     metadata: UpdateMeta {
                         block_number,
                         timestamp,
+                        block_hash,
+                        parent_hash,
                     },
                 }
             })
@@ -137,23 +168,55 @@ where
     R: ethers::providers::Middleware + 'static,
 {
     #[instrument(err, skip(self))]
-    async fn fetch_sorted_messages(&self, from: u32, to: u32) -> Result<Vec<RawCommittedMessage>> {
+    async fn fetch_sorted_messages(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> Result<Vec<RawCommittedMessageWithMeta>> {
         let mut events = self
             .contract
             .dispatch_filter()
             .from_block(from)
             .to_block(to)
-            .query()
+            .query_with_meta()
             .await?;
 
-        events.sort_by(|a, b| a.leaf_index.cmp(&b.leaf_index));
+        events.sort_by(|a, b| a.0.leaf_index.cmp(&b.0.leaf_index));
 
-        Ok(events
+        // Fetch each block once, however many messages it dispatched
+        let block_hashes: HashSet<H256> = events.iter().map(|event| event.1.block_hash).collect();
+        let block_futs: Vec<_> = block_hashes
             .into_iter()
-            .map(|f| RawCommittedMessage {
-                leaf_index: f.leaf_index.as_u32(),
-                committed_root: f.committed_root.into(),
-                message: f.message.to_vec(),
+            .map(|block_hash| async move {
+                let block = self.provider.get_block(block_hash).await.ok().flatten();
+                (block_hash, block)
+            })
+            .collect();
+        let blocks: HashMap<_, _> = join_all(block_futs).await.into_iter().collect();
+
+        Ok(events
+            .iter()
+            .map(|event| {
+                let block_number = event.1.block_number.as_u64();
+                let block_hash = event.1.block_hash;
+                let block = blocks.get(&block_hash).and_then(Option::as_ref);
+                let timestamp = block.map(|b| b.timestamp.as_u64());
+                let parent_hash = block.map(|b| b.parent_hash).unwrap_or_default();
+
+                RawCommittedMessageWithMeta {
+                    raw_message: RawCommittedMessage {
+                        leaf_index: event.0.leaf_index.as_u32(),
+                        committed_root: event.0.committed_root.into(),
+                        message: event.0.message.to_vec(),
+                    },
+                    metadata: MessageMeta {
+                        block_number,
+                        transaction_hash: event.1.transaction_hash,
+                        timestamp,
+                        block_hash,
+                        parent_hash,
+                    },
+                }
             })
             .collect())
     }
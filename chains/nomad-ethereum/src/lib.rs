@@ -39,6 +39,13 @@ mod xapp;
 /// Gas increasing Middleware
 mod gas;
 
+/// Providers and signers shared across contracts
+mod shared;
+
+/// Websocket subscriptions to new heads
+mod subscription;
+pub use subscription::HeadSubscription;
+
 #[cfg(not(doctest))]
 pub use crate::{home::*, replica::*, xapp::*};
 
@@ -96,3 +103,13 @@ impl nomad_core::Chain for Chain {
         )?))
     }
 }
+
+/// The header of a mined block. None for pending blocks, which have no hash
+/// yet
+pub(crate) fn block_header<TX>(block: &Block<TX>) -> Option<BlockHeader> {
+    Some(BlockHeader {
+        number: block.number?.as_u64(),
+        hash: block.hash?,
+        parent_hash: block.parent_hash,
+    })
+}
@@ -71,24 +71,46 @@ macro_rules! log_tx_details {
     };
 }
 
+macro_rules! shared_provider {
+    (@ws $url:expr) => {{
+        let url = $url;
+        crate::shared::shared(format!("ws:{}", url), || async move {
+            let ws = ethers::providers::Ws::connect(url).await?;
+            Ok::<_, color_eyre::Report>(ethers::providers::Provider::new(ws))
+        })
+        .await?
+    }};
+    (@http $url:expr) => {{
+        let url = $url;
+        crate::shared::shared(format!("http:{}", url), || async move {
+            let provider: crate::retrying::RetryingProvider<ethers::providers::Http> =
+                url.parse()?;
+            Ok::<_, color_eyre::Report>(ethers::providers::Provider::new(provider))
+        })
+        .await?
+    }};
+}
+
 macro_rules! boxed_indexer {
-    (@timelag $provider:expr, $abi:ident, $timelag:ident, $($tail:tt)*) => {{
+    (@timelag $provider:expr, $subscription:expr, $abi:ident, $timelag:ident, $($tail:tt)*) => {{
         if let Some(lag) = $timelag {
             let provider: Arc<_> = ethers::middleware::TimeLag::new($provider, lag).into();
-            Box::new(crate::$abi::new(provider, $($tail)*))
+            Box::new(crate::$abi::new(provider, $($tail)*).with_subscription($subscription))
         } else {
-            Box::new(crate::$abi::new($provider, $($tail)*))
+            Box::new(crate::$abi::new($provider, $($tail)*).with_subscription($subscription))
         }
     }};
-    (@ws $url:expr, $($tail:tt)*) => {{
-        let ws = ethers::providers::Ws::connect($url).await?;
-        let provider = Arc::new(ethers::providers::Provider::new(ws));
-        boxed_indexer!(@timelag provider, $($tail)*)
+    (@ws $url:expr, $abi:ident, $timelag:ident, $($tail:tt)*) => {{
+        let url = $url;
+        let provider = shared_provider!(@ws url.clone());
+        // Subscriptions get their own connection, so that they reconnect
+        // independently of the shared provider
+        let subscription = crate::HeadSubscription::spawn(url, $timelag);
+        boxed_indexer!(@timelag provider, Some(subscription), $abi, $timelag, $($tail)*)
     }};
     (@http $url:expr, $($tail:tt)*) => {{
-        let provider: crate::retrying::RetryingProvider<ethers::providers::Http> = $url.parse()?;
-        let provider = Arc::new(ethers::providers::Provider::new(provider));
-        boxed_indexer!(@timelag provider, $($tail)*)
+        let provider = shared_provider!(@http $url);
+        boxed_indexer!(@timelag provider, None, $($tail)*)
     }};
     ($name:ident, $abi:ident, $trait:ident, $($n:ident:$t:ty),*)  => {
         #[doc = "Cast a contract locator to a live contract handle"]
@@ -116,26 +138,30 @@ macro_rules! boxed_contract {
                 Box::new(crate::$abi::new(write_provider, $provider, $($tail)*))
             }
     }};
-    (@signer $provider:expr, $signer:ident, $($tail:tt)*) => {{
+    (@signer $provider:expr, $key:expr, $signer:ident, $($tail:tt)*) => {{
         if let Some(signer) = $signer {
             // If there's a provided signer, we want to manage every aspect
-            // locally
-
-            // First set the chain ID locally
-            let provider_chain_id = $provider.get_chainid().await?;
-            let signer = ethers::signers::Signer::with_chain_id(signer, provider_chain_id.as_u64());
-
-            // Manage the nonce locally
+            // locally. The signing middleware is shared by all contracts
+            // using this signer on this chain, so they share a nonce manager
             let address = ethers::prelude::Signer::address(&signer);
-            let provider =
-                ethers::middleware::nonce_manager::NonceManagerMiddleware::new($provider, address);
-
-            // Kludge. Increase the gas by multiplication of every estimated gas by 2
-            // except the gas for chain id 1 (Ethereum Mainnet)
-            let provider = crate::gas::GasAdjusterMiddleware::with_default_policy(provider, provider_chain_id.as_u64());
-
-            // Manage signing locally
-            let signing_provider = Arc::new(ethers::middleware::SignerMiddleware::new(provider, signer));
+            let provider = $provider;
+            let signing_provider = crate::shared::shared(format!("{}:{:?}", $key, address), || async move {
+                // First set the chain ID locally
+                let provider_chain_id = provider.get_chainid().await?;
+                let signer = ethers::signers::Signer::with_chain_id(signer, provider_chain_id.as_u64());
+
+                // Manage the nonce locally
+                let provider =
+                    ethers::middleware::nonce_manager::NonceManagerMiddleware::new(provider, address);
+
+                // Kludge. Increase the gas by multiplication of every estimated gas by 2
+                // except the gas for chain id 1 (Ethereum Mainnet)
+                let provider = crate::gas::GasAdjusterMiddleware::with_default_policy(provider, provider_chain_id.as_u64());
+
+                // Manage signing locally
+                Ok::<_, color_eyre::Report>(ethers::middleware::SignerMiddleware::new(provider, signer))
+            })
+            .await?;
 
             boxed_contract!(@timelag signing_provider, $($tail)*)
         } else {
@@ -143,14 +169,16 @@ macro_rules! boxed_contract {
         }
     }};
     (@ws $url:expr, $($tail:tt)*) => {{
-        let ws = ethers::providers::Ws::connect($url).await?;
-        let provider = Arc::new(ethers::providers::Provider::new(ws));
-        boxed_contract!(@signer provider, $($tail)*)
+        let url = $url;
+        let key = format!("ws:{}", url);
+        let provider = shared_provider!(@ws url);
+        boxed_contract!(@signer provider, key, $($tail)*)
     }};
     (@http $url:expr, $($tail:tt)*) => {{
-        let provider: crate::retrying::RetryingProvider<ethers::providers::Http> = $url.parse()?;
-        let provider = Arc::new(ethers::providers::Provider::new(provider));
-        boxed_contract!(@signer provider, $($tail)*)
+        let url = $url;
+        let key = format!("http:{}", url);
+        let provider = shared_provider!(@http url);
+        boxed_contract!(@signer provider, key, $($tail)*)
     }};
     ($name:ident, $abi:ident, $trait:ident, $($n:ident:$t:ty),*)  => {
         #[doc = "Cast a contract locator to a live contract handle"]
@@ -6,15 +6,18 @@ use color_eyre::Result;
 use ethers::core::types::{Signature, H256, U256};
 use futures_util::future::join_all;
 use nomad_core::{
-    accumulator::NomadProof, ChainCommunicationError, Common, CommonIndexer, ContractLocator,
-    DoubleUpdate, Encode, MessageStatus, NomadMessage, Replica, SignedUpdate, SignedUpdateWithMeta,
-    State, TxOutcome, Update, UpdateMeta,
+    accumulator::NomadProof, BlockHeader, ChainCommunicationError, Common, CommonIndexer,
+    ContractLocator, DoubleUpdate, Encode, MessageStatus, NomadMessage, Replica, SignedUpdate,
+    SignedUpdateWithMeta, State, TxOutcome, Update, UpdateMeta,
 };
 use nomad_xyz_configuration::ReplicaGasLimits;
-use std::{convert::TryFrom, error::Error as StdError, sync::Arc};
+use std::{convert::TryFrom, error::Error as StdError, sync::Arc, time::Duration};
 use tracing::instrument;
 
-use crate::{bindings::replica::Replica as EthereumReplicaInternal, report_tx};
+use crate::{
+    bindings::replica::Replica as EthereumReplicaInternal, block_header, report_tx,
+    HeadSubscription,
+};
 
 #[derive(Debug)]
 /// Struct that retrieves indexes event data for Ethereum replica
@@ -24,6 +27,7 @@ where
 {
     contract: Arc<EthereumReplicaInternal<R>>,
     provider: Arc<R>,
+    subscription: Option<Arc<HeadSubscription>>,
     from_height: u32,
     chunk_size: u32,
 }
@@ -49,10 +53,20 @@ where
                 provider.clone(),
             )),
             provider,
+            subscription: None,
             from_height,
             chunk_size,
         }
     }
+
+    /// Wait for new blocks with `subscription` while it is connected,
+    /// instead of polling
+    pub fn with_subscription(self, subscription: Option<Arc<HeadSubscription>>) -> Self {
+        Self {
+            subscription,
+            ..self
+        }
+    }
 }
 
 #[async_trait]
@@ -65,6 +79,19 @@ where
         Ok(self.provider.get_block_number().await?.as_u32())
     }
 
+    async fn wait_for_block(&self, number: u32, timeout: Duration) -> Result<bool> {
+        match &self.subscription {
+            Some(subscription) => Ok(subscription.wait_for_block(number, timeout).await),
+            None => Ok(false),
+        }
+    }
+
+    #[instrument(err, skip(self))]
+    async fn get_block_header(&self, number: u32) -> Result<Option<BlockHeader>> {
+        let block = self.provider.get_block(number as u64).await?;
+        Ok(block.as_ref().and_then(block_header))
+    }
+
     #[instrument(err, skip(self))]
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {
         let mut events = self
@@ -97,19 +124,18 @@ where
                 };
 
                 let block_number = event.1.block_number.as_u64();
-                let timestamp = self
-                    .provider
-                    .get_block(block_number)
-                    .await
-                    .ok()
-                    .flatten()
-                    .map(|b| b.timestamp.as_u64());
+                let block_hash = event.1.block_hash;
+                let block = self.provider.get_block(block_hash).await.ok().flatten();
+                let timestamp = block.as_ref().map(|b| b.timestamp.as_u64());
+                let parent_hash = block.map(|b| b.parent_hash).unwrap_or_default();
 
                 SignedUpdateWithMeta {
                     signed_update: SignedUpdate { update, signature },
                     metadata: UpdateMeta {
                         block_number,
                         timestamp,
+                        block_hash,
+                        parent_hash,
                     },
                 }
             })
@@ -325,6 +351,21 @@ where
         }
     }
 
+    #[tracing::instrument(err)]
+    async fn process_transaction(
+        &self,
+        leaf: H256,
+    ) -> Result<Option<H256>, ChainCommunicationError> {
+        let events = self
+            .read_contract
+            .process_filter()
+            .topic1(leaf)
+            .from_block(0u64)
+            .query_with_meta()
+            .await?;
+        Ok(events.first().map(|(_, meta)| meta.transaction_hash))
+    }
+
     async fn acceptable_root(&self, root: H256) -> Result<bool, ChainCommunicationError> {
         Ok(self
             .read_contract
@@ -0,0 +1,87 @@
+use color_eyre::Result;
+use once_cell::sync::OnceCell;
+use std::{
+    any::Any,
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex, Weak},
+};
+use tokio::sync::Mutex as AsyncMutex;
+
+/// The value shared under a key, if still in use. Locked while it is built,
+/// so that concurrent callers for the same key wait for a single build
+type Slot = Arc<AsyncMutex<Option<Weak<dyn Any + Send + Sync>>>>;
+
+static SHARED: OnceCell<Mutex<HashMap<String, Slot>>> = OnceCell::new();
+
+/// Get the slot of `key`, dropping the slots of values no longer in use
+fn slot(key: String) -> Slot {
+    let mut slots = SHARED
+        .get_or_init(Default::default)
+        .lock()
+        .expect("!poisoned");
+
+    // slots being built are locked, or held by a waiting caller
+    slots.retain(|_, slot| {
+        Arc::strong_count(slot) > 1
+            || slot.try_lock().map_or(true, |value| {
+                value
+                    .as_ref()
+                    .map_or(false, |value| value.strong_count() > 0)
+            })
+    });
+    slots.entry(key).or_default().clone()
+}
+
+/// Get the value cached under `key`, or build it with `make`.
+///
+/// Providers and signing middlewares are shared by all contracts and indexers
+/// built in the process, keyed by connection and signer. An agent serving
+/// several homes then opens a single connection per chain, and a signer used
+/// on the same chain by several homes goes through a single nonce manager.
+/// Values are only cached while in use, and building one only blocks callers
+/// for the same key.
+pub(crate) async fn shared<T, F, Fut>(key: String, make: F) -> Result<Arc<T>>
+where
+    T: Any + Send + Sync,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let slot = slot(key);
+    let mut cached = slot.lock().await;
+
+    if let Some(value) = cached.as_ref().and_then(Weak::upgrade) {
+        if let Ok(value) = value.downcast::<T>() {
+            return Ok(value);
+        }
+    }
+
+    let value = Arc::new(make().await?);
+    let erased: Arc<dyn Any + Send + Sync> = value.clone();
+    *cached = Some(Arc::downgrade(&erased));
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn it_shares_values_while_in_use() {
+        let builds = AtomicU32::new(0);
+        let make = || async {
+            builds.fetch_add(1, Ordering::SeqCst);
+            Ok(1u32)
+        };
+
+        let first = shared("it_shares_values".to_owned(), make).await.unwrap();
+        let second = shared("it_shares_values".to_owned(), make).await.unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(builds.load(Ordering::SeqCst), 1);
+
+        drop((first, second));
+        shared("it_shares_values".to_owned(), make).await.unwrap();
+        assert_eq!(builds.load(Ordering::SeqCst), 2);
+    }
+}
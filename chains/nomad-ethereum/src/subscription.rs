@@ -0,0 +1,241 @@
+use color_eyre::{eyre::eyre, Result};
+use ethers::providers::{Middleware, Provider, Ws};
+use futures_util::{future::ready, Stream, StreamExt};
+use std::{
+    future::Future,
+    sync::{Arc, RwLock, Weak},
+    time::Duration,
+};
+use tokio::{
+    sync::Notify,
+    time::{sleep, timeout, Instant},
+};
+use tracing::{info, warn};
+
+/// Delay before reconnecting after the subscription dropped
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Time without a new head after which the socket is assumed half-open. The
+/// subscription is then treated as disconnected, and reconnects
+const MAX_HEAD_AGE: Duration = Duration::from_secs(60);
+
+/// Delay before waking again for a head the RPC has not caught up with yet
+const RPC_LAG_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+struct Shared {
+    /// Latest head and when it was received. None while disconnected
+    head: RwLock<Option<(u64, Instant)>>,
+    new_head: Notify,
+}
+
+/// Subscription to the new heads of a chain over websocket. While connected,
+/// indexers wait for the next head instead of sleeping between polls. Blocks
+/// and events are still read over RPC, so the subscription only decides when
+/// to read them. Indexers poll while it reconnects.
+#[derive(Debug)]
+pub struct HeadSubscription {
+    shared: Arc<Shared>,
+    timelag: u64,
+}
+
+impl HeadSubscription {
+    /// Subscribe to the heads of the chain at `url`. Heads are reported
+    /// `timelag` blocks behind. The subscription reconnects until dropped.
+    pub fn spawn(url: String, timelag: Option<u8>) -> Arc<Self> {
+        let shared: Arc<Shared> = Default::default();
+        let weak = Arc::downgrade(&shared);
+        let follow = Arc::downgrade(&shared);
+        tokio::spawn(Self::run(
+            move || Self::subscribe(url.clone(), follow.clone()),
+            weak,
+        ));
+
+        Arc::new(Self {
+            shared,
+            timelag: timelag.unwrap_or_default() as u64,
+        })
+    }
+
+    /// Call `subscribe` until `shared` is dropped, marking the subscription
+    /// disconnected between attempts
+    async fn run<F, Fut>(subscribe: F, shared: Weak<Shared>)
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        loop {
+            if let Err(err) = subscribe().await {
+                warn!(
+                    error = %err,
+                    "Subscription to new heads dropped. Polling until reconnected",
+                );
+            }
+
+            match shared.upgrade() {
+                Some(shared) => *shared.head.write().expect("poisoned") = None,
+                None => return,
+            }
+            sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn subscribe(url: String, shared: Weak<Shared>) -> Result<()> {
+        let provider = Provider::new(Ws::connect(url.as_str()).await?);
+        let heads = provider
+            .subscribe_blocks()
+            .await?
+            .filter_map(|head| ready(head.number.map(|number| number.as_u64())));
+        info!("Subscribed to new heads");
+
+        Self::follow(heads, &shared).await
+    }
+
+    /// Record the heads of `heads` until it closes, goes quiet for
+    /// `MAX_HEAD_AGE`, or `shared` is dropped
+    async fn follow<S>(heads: S, shared: &Weak<Shared>) -> Result<()>
+    where
+        S: Stream<Item = u64>,
+    {
+        futures_util::pin_mut!(heads);
+        loop {
+            let number = timeout(MAX_HEAD_AGE, heads.next())
+                .await
+                .map_err(|_| eyre!("no new head in {:?}", MAX_HEAD_AGE))?
+                .ok_or_else(|| eyre!("heads subscription closed"))?;
+
+            let shared = match shared.upgrade() {
+                Some(shared) => shared,
+                None => return Ok(()),
+            };
+            *shared.head.write().expect("poisoned") = Some((number, Instant::now()));
+            shared.new_head.notify_waiters();
+        }
+    }
+
+    /// Latest head, if connected and received within `MAX_HEAD_AGE`
+    fn latest_block(&self) -> Option<u32> {
+        let head = self.shared.head.read().expect("poisoned");
+        let (number, received) = (*head)?;
+        if received.elapsed() > MAX_HEAD_AGE {
+            return None;
+        }
+        Some(number.saturating_sub(self.timelag) as u32)
+    }
+
+    /// Wait up to `timeout` for a head above `number`. Returns false without
+    /// waiting if disconnected, or if the latest head is stale
+    pub async fn wait_for_block(&self, number: u32, timeout: Duration) -> bool {
+        let new_head = self.shared.new_head.notified();
+        match self.latest_block() {
+            Some(latest) if latest > number => {
+                // The RPC has not caught up with the head yet
+                sleep(RPC_LAG_DELAY.min(timeout)).await;
+                true
+            }
+            Some(_) => {
+                let _ = tokio::time::timeout(timeout, new_head).await;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_util::stream;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn subscription(shared: &Arc<Shared>) -> HeadSubscription {
+        HeadSubscription {
+            shared: shared.clone(),
+            timelag: 0,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_falls_back_to_polling_while_disconnected_or_stale() {
+        let shared: Arc<Shared> = Default::default();
+        let subscription = subscription(&shared);
+
+        // Disconnected
+        assert_eq!(subscription.latest_block(), None);
+        let started = Instant::now();
+        assert!(
+            !subscription
+                .wait_for_block(10, Duration::from_secs(30))
+                .await
+        );
+        assert_eq!(started.elapsed(), Duration::ZERO);
+
+        // Connected, and woken by the next head
+        *shared.head.write().unwrap() = Some((10, Instant::now()));
+        assert_eq!(subscription.latest_block(), Some(10));
+        let waiting = subscription.wait_for_block(10, Duration::from_secs(30));
+        let head = async {
+            sleep(Duration::from_secs(2)).await;
+            *shared.head.write().unwrap() = Some((11, Instant::now()));
+            shared.new_head.notify_waiters();
+        };
+        let started = Instant::now();
+        let (woken, _) = tokio::join!(waiting, head);
+        assert!(woken);
+        assert_eq!(started.elapsed(), Duration::from_secs(2));
+
+        // The socket went quiet, e.g. half-open
+        sleep(MAX_HEAD_AGE + Duration::from_secs(1)).await;
+        assert_eq!(subscription.latest_block(), None);
+        assert!(
+            !subscription
+                .wait_for_block(10, Duration::from_secs(30))
+                .await
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_reconnects_when_heads_close_or_go_quiet() {
+        let shared: Arc<Shared> = Default::default();
+        let subscription = subscription(&shared);
+
+        // The first connection closes after a head, the second goes quiet
+        // after a head, and the third keeps delivering heads
+        let attempts = AtomicU32::new(0);
+        let follow = Arc::downgrade(&shared);
+        let subscribe = || {
+            let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+            let follow = follow.clone();
+            async move {
+                let heads = match attempt {
+                    0 => stream::iter(vec![10]).boxed(),
+                    1 => stream::iter(vec![11]).chain(stream::pending()).boxed(),
+                    _ => stream::iter(12..)
+                        .then(|number| async move {
+                            sleep(Duration::from_secs(1)).await;
+                            number
+                        })
+                        .boxed(),
+                };
+                HeadSubscription::follow(heads, &follow).await
+            }
+        };
+
+        let run = HeadSubscription::run(subscribe, Arc::downgrade(&shared));
+        let check = async {
+            sleep(RECONNECT_DELAY / 2).await;
+            assert_eq!(subscription.latest_block(), None);
+
+            sleep(RECONNECT_DELAY).await;
+            assert_eq!(subscription.latest_block(), Some(11));
+
+            sleep(MAX_HEAD_AGE + RECONNECT_DELAY + Duration::from_secs(3)).await;
+            assert!(subscription.latest_block() > Some(11));
+            assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        };
+        tokio::select! {
+            _ = run => panic!("subscription stopped while in use"),
+            _ = check => {}
+        }
+    }
+}
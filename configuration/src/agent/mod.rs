@@ -34,6 +34,12 @@ pub struct AgentConfig {
     pub db: PathBuf,
     /// Metrics port
     pub metrics: Option<u16>,
+    /// Serve the read-only query API on the metrics port
+    #[serde(default)]
+    pub api: bool,
+    /// Contract sync configuration
+    #[serde(default)]
+    pub index: IndexConfig,
     /// Logging configuration
     pub logging: LogConfig,
     /// Updater configuration
@@ -48,6 +54,19 @@ pub struct AgentConfig {
     pub kathy: KathyConfig,
 }
 
+/// Contract sync configuration, shared by all agents
+#[derive(Default, Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexConfig {
+    /// Number of concurrent workers indexing historical blocks on startup.
+    /// Historical blocks are indexed sequentially if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backfill_workers: Option<u32>,
+    /// Seconds to wait for new blocks once caught up to the tip
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll_interval: Option<u64>,
+}
+
 #[macro_export]
 /// Creates agent config block on that comes with interval and enabled by
 /// default
@@ -9,10 +9,21 @@ decl_config!(Processor {
     allowed: Option<HashSet<H256>>,
     /// Deny list
     denied: Option<HashSet<H256>>,
+    /// Allow list of message kinds (e.g. `transfer`, `governanceBatch`).
+    /// Messages not sent to the destination's BridgeRouter or
+    /// GovernanceRouter are of kind `unknown`
+    #[serde(default)]
+    allowed_kinds: Option<HashSet<String>>,
+    /// Deny list of message kinds
+    #[serde(default)]
+    denied_kinds: Option<HashSet<String>>,
     /// Index only mode
     subsidized_remotes: Vec<String>,
     /// S3 config
     s3: Option<S3Config>,
+    /// Retention config. Nothing is pruned if absent
+    #[serde(default)]
+    retention: Option<RetentionConfig>,
 });
 
 /// S3 Configuration
@@ -24,3 +35,20 @@ pub struct S3Config {
     /// Region
     pub region: String,
 }
+
+/// Retention configuration. Proofs and message bodies of leaves matching
+/// either rule are pruned. Leaf hashes are always kept.
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionConfig {
+    /// Prune leaves more than this many leaves behind the latest leaf. Must
+    /// exceed how far any replica may lag, as pruned messages can no longer
+    /// be processed.
+    #[serde(default)]
+    pub keep_leaves: Option<u32>,
+    /// Prune leaves once their message is processed on its destination
+    #[serde(default)]
+    pub prune_processed: bool,
+    /// Seconds between pruning passes
+    pub interval: u64,
+}
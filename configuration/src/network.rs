@@ -67,6 +67,14 @@ pub struct NetworkSpecs {
     /// Number of blocks to include in a page while indexing
     #[serde(deserialize_with = "deser_nomad_u32")]
     pub index_page_size: u32,
+    /// Smallest page size indexing may shrink to when the RPC rejects or
+    /// times out on large ranges. Defaults to 1 block
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_index_page_size: Option<u32>,
+    /// Largest page size indexing may grow to while pages come back empty.
+    /// Defaults to `index_page_size`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_index_page_size: Option<u32>,
 }
 
 /// Specifier for deploy-time custom bridge tokens
@@ -104,6 +104,8 @@ export interface NetworkSpecs {
   confirmations: number | string;
   blockExplorer: string;
   indexPageSize: number;
+  minIndexPageSize?: number;
+  maxIndexPageSize?: number;
 }
 
 export interface CustomTokenSpecifier {
@@ -6,32 +6,36 @@ use crate::{
         fmt::{log_level_to_level_filter, LogOutputLayer},
         TimeSpanLifetime,
     },
-    BaseError, CachingHome, CachingReplica, NomadDB,
+    watch_settings, BaseError, CachingHome, CachingReplica, LoadSettings, NomadDB, QueryApi,
+    RestartPolicy, SettingsDiff, ShutdownToken, SupervisedTask, Supervisor, RELOAD_INTERVAL,
 };
 use async_trait::async_trait;
-use color_eyre::{eyre::WrapErr, Result};
-use futures_util::future::select_all;
+use color_eyre::Result;
 use nomad_core::{db::DB, Common};
 use tracing::instrument::Instrumented;
-use tracing::{error, info_span, warn, Instrument};
+use tracing::{error, info, info_span, warn, Instrument};
 use tracing_subscriber::prelude::*;
 
 use std::{
-    collections::HashMap,
-    sync::Arc,
-    time::{Duration, SystemTime},
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+    time::Duration,
 };
 use tokio::{task::JoinHandle, time::sleep};
 
-const MAX_EXPONENTIAL: u32 = 7; // 2^7 = 128 second timeout
+/// Seconds between the home state checks of agents run with the default
+/// `run_all`. Each check records a successful RPC, so that agents not
+/// syncing the home stay healthy
+const HOME_WATCH_INTERVAL: u64 = 60;
 
 /// Properties shared across all agents
 #[derive(Debug, Clone)]
 pub struct AgentCore {
     /// A boxed Home
     pub home: Arc<CachingHome>,
-    /// A map of boxed Replicas
-    pub replicas: HashMap<String, Arc<CachingReplica>>,
+    /// A map of boxed Replicas. Shared, as replicas are added and removed
+    /// when settings are reloaded
+    pub replicas: Arc<RwLock<HashMap<String, Arc<CachingReplica>>>>,
     /// A persistent KV Store (currently implemented as rocksdb)
     pub db: DB,
     /// Prometheus metrics
@@ -40,6 +44,46 @@ pub struct AgentCore {
     pub indexer: IndexSettings,
     /// Settings this agent was created with
     pub settings: crate::settings::Settings,
+    /// Triggered on SIGTERM/SIGINT
+    pub shutdown: ShutdownToken,
+    /// Children of `shutdown` for each running channel, by replica, so a
+    /// channel can be drained on its own when settings are reloaded
+    pub channel_shutdowns: Arc<RwLock<HashMap<String, ShutdownToken>>>,
+}
+
+impl AgentCore {
+    /// Rebuild the replicas added or changed between the `previous` and
+    /// reloaded `settings`, and drop the removed ones. Nothing changes if a
+    /// replica fails to build.
+    pub async fn reload_replicas(
+        &self,
+        agent_name: &str,
+        previous: &Settings,
+        settings: &Settings,
+    ) -> Result<SettingsDiff> {
+        let diff = SettingsDiff::between(previous, settings);
+        for field in diff.restart_required.iter() {
+            warn!(
+                field,
+                "Setting {} changed. Restart the agent to apply it", field
+            );
+        }
+
+        let mut rebuilt = HashMap::new();
+        for name in diff.added.iter().chain(diff.changed.iter()) {
+            let replica = settings
+                .try_caching_replica(name, agent_name, self.db.clone(), self.home.sync_metrics())
+                .await?;
+            rebuilt.insert(name.clone(), Arc::new(replica));
+        }
+
+        let mut replicas = self.replicas.write().expect("!poisoned");
+        for name in diff.removed.iter() {
+            replicas.remove(name);
+        }
+        replicas.extend(rebuilt);
+        Ok(diff)
+    }
 }
 
 /// Commmon data needed for a single agent channel
@@ -51,6 +95,8 @@ pub struct ChannelBase {
     pub replica: Arc<CachingReplica>,
     /// NomadDB keyed by home
     pub db: NomadDB,
+    /// Channel shutdown token, triggered with the agent's
+    pub shutdown: ShutdownToken,
 }
 
 /// A trait for an application:
@@ -63,7 +109,7 @@ pub trait NomadAgent: Send + Sync + Sized + std::fmt::Debug + AsRef<AgentCore> {
     const AGENT_NAME: &'static str;
 
     /// The settings object for this agent
-    type Settings: AsRef<Settings>;
+    type Settings: AsRef<Settings> + LoadSettings + Send + Sync + 'static;
 
     /// The data needed for a single channel's run task
     type Channel: 'static + Send + Sync + Clone;
@@ -82,6 +128,7 @@ pub trait NomadAgent: Send + Sync + Sized + std::fmt::Debug + AsRef<AgentCore> {
             home: self.home(),
             replica: self.replica_by_name(replica).expect("!replica exist"),
             db: NomadDB::new(self.home().name(), self.db()),
+            shutdown: self.channel_shutdown_token(replica),
         }
     }
 
@@ -90,6 +137,30 @@ pub trait NomadAgent: Send + Sync + Sized + std::fmt::Debug + AsRef<AgentCore> {
         self.as_ref().metrics.clone()
     }
 
+    /// Run the HTTP server for `/metrics`, `/health` and `/ready`, and the
+    /// query API if enabled
+    fn run_http_server(&self) -> JoinHandle<()> {
+        let core = self.as_ref();
+        let api = core.settings.api.then(|| QueryApi::new(core).routes());
+        self.metrics().run_http_server_with(api)
+    }
+
+    /// Return a handle to the shutdown token
+    fn shutdown_token(&self) -> ShutdownToken {
+        self.as_ref().shutdown.clone()
+    }
+
+    /// Return a handle to the shutdown token of the channel to `replica`
+    fn channel_shutdown_token(&self, replica: &str) -> ShutdownToken {
+        self.as_ref()
+            .channel_shutdowns
+            .write()
+            .expect("!poisoned")
+            .entry(replica.to_owned())
+            .or_insert_with(|| self.shutdown_token().child())
+            .clone()
+    }
+
     /// Return a handle to the DB
     fn db(&self) -> DB {
         self.as_ref().db.clone()
@@ -100,123 +171,248 @@ pub trait NomadAgent: Send + Sync + Sized + std::fmt::Debug + AsRef<AgentCore> {
         self.as_ref().home.clone()
     }
 
-    /// Get a reference to the replicas map
-    fn replicas(&self) -> &HashMap<String, Arc<CachingReplica>> {
-        &self.as_ref().replicas
+    /// Get a snapshot of the replicas map
+    fn replicas(&self) -> HashMap<String, Arc<CachingReplica>> {
+        self.as_ref().replicas.read().expect("!poisoned").clone()
     }
 
     /// Get a reference to a replica by its name
     fn replica_by_name(&self, name: &str) -> Option<Arc<CachingReplica>> {
-        self.replicas().get(name).map(Clone::clone)
+        self.as_ref()
+            .replicas
+            .read()
+            .expect("!poisoned")
+            .get(name)
+            .map(Clone::clone)
     }
 
     /// Run the agent with the given home and replica
     fn run(channel: Self::Channel) -> Instrumented<JoinHandle<Result<()>>>;
 
-    /// Run the agent for a given channel. If the channel dies, exponentially
-    /// retry. If failures are more than 5 minutes apart, reset exponential
-    /// backoff (likely unrelated after that point).
+    /// Build a supervisor for this agent's tasks
+    fn supervisor(&self) -> Supervisor {
+        Supervisor::new(self.metrics(), self.shutdown_token())
+    }
+
+    /// Build a supervised task running the channel to `replica`. If the
+    /// channel dies, it is restarted with exponential backoff.
+    fn channel_task(&self, replica: &str) -> SupervisedTask
+    where
+        Self: 'static,
+    {
+        let channel = self.build_channel(replica);
+        let channel_faults_gauge = self.metrics().channel_faults_gauge(replica);
+
+        SupervisedTask::new(
+            format!("channel:{}", replica),
+            RestartPolicy::ALWAYS,
+            move || Self::run(channel.clone()),
+        )
+        .on_fault(move || channel_faults_gauge.inc())
+    }
+
+    /// Run the agent for a given channel, supervised under the channel's
+    /// `RestartPolicy`. See `channel_task`.
     #[allow(clippy::unit_arg)]
     #[tracing::instrument]
-    fn run_report_error(&self, replica: String) -> Instrumented<JoinHandle<Result<()>>> {
-        let channel = self.build_channel(&replica);
-        let channel_faults_gauge = self.metrics().channel_faults_gauge(&replica);
+    fn run_report_error(&self, replica: String) -> Instrumented<JoinHandle<Result<()>>>
+    where
+        Self: 'static,
+    {
+        let mut supervisor = self.supervisor();
+        supervisor.add(self.channel_task(&replica));
+        supervisor.spawn().in_current_span()
+    }
 
-        tokio::spawn(async move {
-            let mut exponential = 0;
-            loop {
-                let running_time = SystemTime::now();
+    /// Run several agents by replica name, each under supervision
+    #[allow(clippy::unit_arg)]
+    fn run_many(&self, replicas: &[&str]) -> Instrumented<JoinHandle<Result<()>>>
+    where
+        Self: 'static,
+    {
+        let span = info_span!("run_many");
+        let mut supervisor = self.supervisor();
+        for replica in replicas {
+            supervisor.add(self.channel_task(replica));
+        }
+        supervisor.spawn().instrument(span)
+    }
 
-                let handle = Self::run(channel.clone()).in_current_span();
-                let res = handle
-                    .await?
-                    .wrap_err(format!("Task for replica named {} failed", &replica));
+    /// Names of the replicas to run a channel to
+    fn channel_replicas(&self) -> Vec<String> {
+        self.replicas().into_keys().collect()
+    }
 
-                match res {
-                    Ok(_) => return Ok(()),
-                    Err(e) => {
-                        error!(
-                            "Channel for replica {} errored out! Error: {:?}",
-                            &replica, e
-                        );
-                        channel_faults_gauge.inc();
-
-                        // If running time >= 5 minutes, current failure likely
-                        // unrelated to previous
-                        if running_time.elapsed().unwrap().as_secs() >= 300 {
-                            exponential = 0;
-                        } else if exponential < MAX_EXPONENTIAL {
-                            exponential += 1;
-                        }
-
-                        let sleep_time = 2u64.pow(exponential);
-                        warn!(
-                            "Restarting channel to {} in {} seconds",
-                            &replica, sleep_time
-                        );
-
-                        sleep(Duration::from_secs(sleep_time)).await;
+    /// Apply agent specific settings on reload. Returns `true` if running
+    /// channels must be restarted to pick up the changes.
+    fn apply_settings(&self, _settings: &Self::Settings) -> bool {
+        false
+    }
+
+    /// Apply reloaded `settings` to the replicas and the running `channels`.
+    /// They are diffed against the `previous` reload, or the settings the
+    /// agent was created with. Nothing changes if a replica fails to build.
+    async fn reload(
+        &self,
+        previous: Option<&Settings>,
+        settings: &Self::Settings,
+        channels: &mut HashMap<String, Instrumented<JoinHandle<Result<()>>>>,
+    ) -> Result<()>
+    where
+        Self: 'static,
+    {
+        let previous = previous.unwrap_or(&self.as_ref().settings);
+        let diff = self
+            .as_ref()
+            .reload_replicas(Self::AGENT_NAME, previous, settings.as_ref())
+            .await?;
+        let restart_all = self.apply_settings(settings);
+
+        let wanted: HashSet<String> = self.channel_replicas().into_iter().collect();
+        let stale: Vec<String> = channels
+            .keys()
+            .filter(|name| restart_all || !wanted.contains(*name) || diff.changed.contains(*name))
+            .cloned()
+            .collect();
+        for name in stale {
+            if let Some(channel) = channels.remove(&name) {
+                // Let in-flight work of the channel finish before cancelling
+                let token = self
+                    .as_ref()
+                    .channel_shutdowns
+                    .write()
+                    .expect("!poisoned")
+                    .remove(&name);
+                if let Some(token) = token {
+                    if let Err(e) = token.drain().await {
+                        warn!(replica = %name, error = %e, "Channel to {} did not drain", name);
                     }
                 }
+                cancel_task!(channel);
+                info!(replica = %name, "Stopped channel to {}", name);
             }
-        })
-        .in_current_span()
+        }
+        for name in wanted {
+            if !channels.contains_key(&name) {
+                info!(replica = %name, "Starting channel to {}", name);
+                let channel = self.run_report_error(name.clone());
+                channels.insert(name, channel);
+            }
+        }
+
+        Ok(())
     }
 
-    /// Run several agents by replica name
-    #[allow(clippy::unit_arg)]
-    fn run_many(&self, replicas: &[&str]) -> Instrumented<JoinHandle<Result<()>>> {
-        let span = info_span!("run_many");
-        let handles: Vec<_> = replicas
-            .iter()
-            .map(|replica| self.run_report_error(replica.to_string()))
-            .collect();
+    /// Run a channel to each of the `channel_replicas`, and keep them in line
+    /// with the settings as they are reloaded: channels are started for added
+    /// replicas, cancelled for removed ones, and restarted when their replica
+    /// or the agent settings changed.
+    fn run_channels(self: Arc<Self>) -> Instrumented<JoinHandle<Result<()>>>
+    where
+        Self: 'static,
+    {
+        let span = info_span!("run_channels");
+        let shutdown = self.shutdown_token();
+        let mut reloads = watch_settings::<Self::Settings>(
+            self.home().name().to_owned(),
+            RELOAD_INTERVAL,
+            shutdown.clone(),
+        );
 
         tokio::spawn(async move {
-            // This gets the first future to resolve.
-            let (res, _, remaining) = select_all(handles).await;
+            let mut channels = HashMap::new();
+            for name in self.channel_replicas() {
+                let channel = self.run_report_error(name.clone());
+                channels.insert(name, channel);
+            }
+
+            let mut current: Option<Self::Settings> = None;
+            loop {
+                let settings = tokio::select! {
+                    settings = reloads.recv() => settings,
+                    _ = shutdown.triggered() => break,
+                };
+
+                // hot reload disabled. Channels run until shutdown
+                let settings = match settings {
+                    Some(settings) => settings,
+                    None => {
+                        shutdown.triggered().await;
+                        break;
+                    }
+                };
 
-            for task in remaining.into_iter() {
-                cancel_task!(task);
+                let previous = current.as_ref().map(AsRef::as_ref);
+                let res = self.reload(previous, &settings, &mut channels).await;
+                match res {
+                    Ok(()) => current = Some(settings),
+                    Err(e) => error!(error = ?e, "Failed to apply reloaded settings"),
+                }
             }
 
-            res?
+            Ok(())
         })
         .instrument(span)
     }
 
-    /// Run several agents
+    /// Run several agents. Channels follow the settings as they are
+    /// reloaded, and the home is watched for failure.
     #[allow(clippy::unit_arg, unused_must_use)]
     fn run_all(self) -> Instrumented<JoinHandle<Result<()>>>
     where
         Self: Sized + 'static,
     {
         let span = info_span!("run_all");
-        tokio::spawn(async move {
-            // this is the unused must use
-            let names: Vec<&str> = self.replicas().keys().map(|k| k.as_str()).collect();
-
-            let run_task = self.run_many(&names);
-            let mut tasks = vec![run_task];
-
-            // kludge
-            if Self::AGENT_NAME != "kathy" {
-                // Only the processor needs to index messages so default is
-                // just indexing updates
-                let sync_task = self.home().sync();
+        let agent = Arc::new(self);
+        let mut supervisor = agent.supervisor();
+
+        supervisor.add(SupervisedTask::once(
+            "channels",
+            agent.clone().run_channels(),
+        ));
+
+        let watcher = agent.clone();
+        supervisor.add(SupervisedTask::new(
+            "home_watch",
+            RestartPolicy::ALWAYS,
+            move || watcher.watch_home_fail(HOME_WATCH_INTERVAL),
+        ));
+
+        // kludge
+        if Self::AGENT_NAME != "kathy" {
+            // Only the processor needs to index messages so default is
+            // just indexing updates
+            let home = agent.home();
+            supervisor.add(SupervisedTask::new(
+                "home_sync",
+                RestartPolicy::ALWAYS,
+                move || home.sync(),
+            ));
+        }
 
-                tasks.push(sync_task);
-            }
+        supervisor.spawn().instrument(span)
+    }
 
-            let (res, _, remaining) = select_all(tasks).await;
+    /// Run all tasks until they finish or SIGTERM/SIGINT is received. On
+    /// signal, wait for in-flight work to finish before returning.
+    async fn run_until_shutdown(self) -> Result<()>
+    where
+        Self: Sized + 'static,
+    {
+        let shutdown = self.shutdown_token();
+        let _ = shutdown.listen_for_signals();
 
-            for task in remaining.into_iter() {
-                cancel_task!(task);
-            }
+        let run_task = self.run_all();
+        tokio::select! {
+            res = run_task => res??,
+            _ = shutdown.triggered() => {}
+        }
 
-            res?
-        })
-        .instrument(span)
+        if shutdown.is_triggered() {
+            shutdown.drain().await?;
+            info!("Shut down cleanly");
+        }
+        Ok(())
     }
 
     /// Spawn a task which continuously watch home for getting into failed state
@@ -228,10 +424,13 @@ pub trait NomadAgent: Send + Sync + Sized + std::fmt::Debug + AsRef<AgentCore> {
         let home = self.home();
         let home_failure_checks = self.metrics().home_failure_checks();
         let home_failure_observations = self.metrics().home_failure_observations();
+        let health = self.metrics().health();
 
         tokio::spawn(async move {
             loop {
-                if home.state().await? == nomad_core::State::Failed {
+                let state = home.state().await?;
+                health.record_home_state(home.name(), &state);
+                if state == nomad_core::State::Failed {
                     home_failure_observations.inc();
                     return Err(BaseError::FailedHome.into());
                 }
@@ -0,0 +1,386 @@
+//! Read-only JSON API over an agent's db, served alongside `/metrics`.
+//!
+//! Routes:
+//! - `GET /api/messages/leaf/{leaf}`
+//! - `GET /api/messages/nonce/{destination}/{nonce}`
+//! - `GET /api/proofs/{leaf_index}`
+//! - `GET /api/updates/previous/{previous_root}`
+//! - `GET /api/updates/new/{new_root}`
+//! - `GET /api/sync`
+//! - `GET /api/processor/nonces`
+//!
+//! When the process serves several homes, each home's routes are prefixed
+//! with the home name, e.g. `GET /ethereum/api/sync`.
+
+use ethers::types::{Bytes, H256};
+use nomad_core::{
+    db::DbError, CommittedMessage, Common, MessageLifecycle, NomadMessage, RawCommittedMessage,
+    Replica, SignedUpdate, XAppRouters,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    convert::{Infallible, TryFrom},
+    fmt,
+    sync::Arc,
+};
+use warp::{
+    filters::BoxedFilter,
+    http::StatusCode,
+    reply::{Json, WithStatus},
+    Filter, Reply,
+};
+
+use crate::{
+    contract_sync::{CommonContractSyncDB, HomeContractSyncDB},
+    AgentCore, NomadDB,
+};
+
+/// Routes served alongside `/metrics`
+pub type Routes = BoxedFilter<(Box<dyn Reply>,)>;
+
+/// A replica as seen by the API
+#[derive(Debug, Clone)]
+struct ApiReplica {
+    name: String,
+    domain: u32,
+    db: NomadDB,
+}
+
+/// Read-only JSON API over the home and replica dbs of an agent
+#[derive(Clone)]
+pub struct QueryApi {
+    home_name: String,
+    db: NomadDB,
+    /// Called on every request, so replicas follow reloaded settings
+    replicas: Arc<dyn Fn() -> Vec<ApiReplica> + Send + Sync>,
+    routers: HashMap<u32, XAppRouters>,
+}
+
+impl fmt::Debug for QueryApi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueryApi")
+            .field("home_name", &self.home_name)
+            .field("db", &self.db)
+            .field("routers", &self.routers)
+            .finish()
+    }
+}
+
+impl QueryApi {
+    /// Instantiate the API over an agent's dbs
+    pub fn new(core: &AgentCore) -> Self {
+        let home_name = core.home.name().to_owned();
+        let agent_replicas = core.replicas.clone();
+        let replicas = move || {
+            agent_replicas
+                .read()
+                .expect("!poisoned")
+                .iter()
+                .map(|(name, replica)| ApiReplica {
+                    name: name.clone(),
+                    domain: replica.local_domain(),
+                    db: replica.db(),
+                })
+                .collect()
+        };
+
+        Self {
+            db: NomadDB::new(&home_name, core.db.clone()),
+            home_name,
+            replicas: Arc::new(replicas),
+            routers: core.settings.xapp_routers.clone(),
+        }
+    }
+
+    /// Build the warp routes for the API, under `/{home}`. Used when the
+    /// process serves several homes
+    pub fn home_routes(self) -> Routes {
+        warp::path(self.home_name.clone())
+            .and(self.routes())
+            .boxed()
+    }
+
+    /// Build the warp routes for the API
+    pub fn routes(self) -> Routes {
+        let api = Arc::new(self);
+        let with_api = warp::any().map(move || api.clone());
+
+        let message_by_leaf = warp::path!("api" / "messages" / "leaf" / H256)
+            .and(with_api.clone())
+            .and_then(|leaf, api| query(api, move |api| api.message_by_leaf(leaf)));
+        let message_by_nonce = warp::path!("api" / "messages" / "nonce" / u32 / u32)
+            .and(with_api.clone())
+            .and_then(|destination, nonce, api| {
+                query(api, move |api| api.message_by_nonce(destination, nonce))
+            });
+        let proof = warp::path!("api" / "proofs" / u32)
+            .and(with_api.clone())
+            .and_then(|leaf_index, api| query(api, move |api| api.proof(leaf_index)));
+        let update_by_previous = warp::path!("api" / "updates" / "previous" / H256)
+            .and(with_api.clone())
+            .and_then(|root, api| query(api, move |api| api.update_by_previous_root(root)));
+        let update_by_new = warp::path!("api" / "updates" / "new" / H256)
+            .and(with_api.clone())
+            .and_then(|root, api| query(api, move |api| api.update_by_new_root(root)));
+        let sync = warp::path!("api" / "sync")
+            .and(with_api.clone())
+            .and_then(|api| query(api, |api| api.sync().map(Some)));
+        let nonces = warp::path!("api" / "processor" / "nonces")
+            .and(with_api)
+            .and_then(|api| query(api, |api| api.processor_nonces().map(Some)));
+
+        warp::get()
+            .and(
+                message_by_leaf
+                    .or(message_by_nonce)
+                    .unify()
+                    .or(proof)
+                    .unify()
+                    .or(update_by_previous)
+                    .unify()
+                    .or(update_by_new)
+                    .unify()
+                    .or(sync)
+                    .unify()
+                    .or(nonces)
+                    .unify(),
+            )
+            .map(|reply| Box::new(reply) as Box<dyn Reply>)
+            .boxed()
+    }
+
+    fn message_by_leaf(&self, leaf: H256) -> Result<Option<Value>, DbError> {
+        match self.db.message_by_leaf(leaf)? {
+            Some(raw) => self.message_json(raw).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn message_by_nonce(&self, destination: u32, nonce: u32) -> Result<Option<Value>, DbError> {
+        match self.db.message_by_nonce(destination, nonce)? {
+            Some(raw) => self.message_json(raw).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn message_json(&self, raw: RawCommittedMessage) -> Result<Value, DbError> {
+        let leaf = raw.leaf();
+        let lifecycle = self.db.message_lifecycle(raw.leaf_index)?;
+        let committed = CommittedMessage::try_from(raw)?;
+        let message: &NomadMessage = &committed.message;
+        let routers = self
+            .routers
+            .get(&message.destination)
+            .copied()
+            .unwrap_or_default();
+
+        Ok(json!({
+            "leaf": leaf,
+            "leafIndex": committed.leaf_index,
+            "committedRoot": committed.committed_root,
+            "origin": message.origin,
+            "sender": message.sender,
+            "destination": message.destination,
+            "nonce": message.nonce,
+            "recipient": message.recipient,
+            "kind": message.decoded_body(&routers).kind().to_string(),
+            "body": Bytes::from(message.body.clone()),
+            "lifecycle": lifecycle.as_ref().map(lifecycle_json),
+        }))
+    }
+
+    fn proof(&self, leaf_index: u32) -> Result<Option<Value>, DbError> {
+        Ok(self
+            .db
+            .proof_by_leaf_index(leaf_index)?
+            .map(|proof| json!(proof)))
+    }
+
+    fn update_by_previous_root(&self, previous_root: H256) -> Result<Option<Value>, DbError> {
+        match self.db.update_by_previous_root(previous_root)? {
+            Some(update) => self.update_json(update).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn update_by_new_root(&self, new_root: H256) -> Result<Option<Value>, DbError> {
+        match self.db.update_by_new_root(new_root)? {
+            Some(update) => self.update_json(update).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn update_json(&self, update: SignedUpdate) -> Result<Value, DbError> {
+        let metadata = self.db.retrieve_update_metadata(update.update.new_root)?;
+        Ok(json!({
+            "update": update,
+            "metadata": metadata,
+        }))
+    }
+
+    /// Latest indexed blocks, leaf index and root of the home and replicas
+    fn sync(&self) -> Result<Value, DbError> {
+        let replicas = (self.replicas)()
+            .iter()
+            .map(|replica| {
+                Ok(json!({
+                    "name": replica.name,
+                    "domain": replica.domain,
+                    "updatesLastBlock": replica.db.retrieve_update_latest_block_end(),
+                    "latestRoot": replica.db.retrieve_latest_root()?,
+                }))
+            })
+            .collect::<Result<Vec<_>, DbError>>()?;
+
+        Ok(json!({
+            "home": {
+                "name": self.home_name,
+                "messagesLastBlock": self.db.retrieve_message_latest_block_end(),
+                "updatesLastBlock": self.db.retrieve_update_latest_block_end(),
+                "latestLeafIndex": self.db.retrieve_latest_leaf_index()?,
+                "latestRoot": self.db.retrieve_latest_root()?,
+            },
+            "replicas": replicas,
+        }))
+    }
+
+    /// The last nonce processed for each replica. Null if the agent is not a
+    /// processor, or has not processed a message to that replica.
+    fn processor_nonces(&self) -> Result<Value, DbError> {
+        let nonces = (self.replicas)()
+            .iter()
+            .map(|replica| {
+                Ok(json!({
+                    "name": replica.name,
+                    "domain": replica.domain,
+                    "currentNonce": self.db.retrieve_processor_current_nonce(replica.domain)?,
+                }))
+            })
+            .collect::<Result<Vec<_>, DbError>>()?;
+        Ok(json!(nonces))
+    }
+}
+
+fn lifecycle_json(lifecycle: &MessageLifecycle) -> Value {
+    let stages: serde_json::Map<String, Value> = nomad_core::LifecycleStage::ALL
+        .iter()
+        .map(|stage| {
+            let event = lifecycle.event(*stage).map(|event| {
+                json!({
+                    "blockNumber": event.block_number,
+                    "transactionHash": event.transaction_hash,
+                    "timestamp": event.timestamp,
+                })
+            });
+            (stage.to_string(), event.unwrap_or(Value::Null))
+        })
+        .collect();
+
+    json!({
+        "stage": lifecycle.stage().map(|stage| stage.to_string()),
+        "stages": stages,
+    })
+}
+
+/// Run a db query off the runtime, as agents serve the API on the runtime
+/// their tasks run on
+async fn query<F>(api: Arc<QueryApi>, f: F) -> Result<WithStatus<Json>, Infallible>
+where
+    F: FnOnce(&QueryApi) -> Result<Option<Value>, DbError> + Send + 'static,
+{
+    Ok(match tokio::task::spawn_blocking(move || f(&api)).await {
+        Ok(result) => respond(result),
+        Err(e) => error(e),
+    })
+}
+
+/// Reply with the value, 404 if absent, or 500 on db errors
+fn respond(result: Result<Option<Value>, DbError>) -> WithStatus<Json> {
+    match result {
+        Ok(Some(value)) => warp::reply::with_status(warp::reply::json(&value), StatusCode::OK),
+        Ok(None) => warp::reply::with_status(
+            warp::reply::json(&json!({ "error": "not found" })),
+            StatusCode::NOT_FOUND,
+        ),
+        Err(e) => error(e),
+    }
+}
+
+/// Reply 500 with the error
+fn error(e: impl fmt::Display) -> WithStatus<Json> {
+    tracing::error!(error = %e, "Query API db error");
+    warp::reply::with_status(
+        warp::reply::json(&json!({ "error": e.to_string() })),
+        StatusCode::INTERNAL_SERVER_ERROR,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nomad_core::accumulator::NomadProof;
+    use nomad_test::test_utils::{dispatched_message, header, run_test_db};
+
+    #[tokio::test]
+    async fn it_serves_db_queries() {
+        run_test_db(|db| async move {
+            let home = NomadDB::new("home_1", db.clone());
+            let raw = dispatched_message(0, &[], header(1, 0)).raw_message;
+            home.store_latest_message(&raw).unwrap();
+            home.store_proof(
+                0,
+                &NomadProof {
+                    leaf: raw.leaf(),
+                    index: 0,
+                    path: Default::default(),
+                },
+            )
+            .unwrap();
+            home.store_processor_current_nonce(2, 0).unwrap();
+
+            let routes = QueryApi {
+                home_name: "home_1".to_owned(),
+                db: home,
+                replicas: Arc::new(move || {
+                    vec![ApiReplica {
+                        name: "replica_1".to_owned(),
+                        domain: 2,
+                        db: NomadDB::new("replica_1", db.clone()),
+                    }]
+                }),
+                routers: Default::default(),
+            }
+            .routes();
+
+            let res = warp::test::request()
+                .path("/api/messages/nonce/2/0")
+                .reply(&routes)
+                .await;
+            assert_eq!(res.status(), StatusCode::OK);
+            let body: Value = serde_json::from_slice(res.body()).unwrap();
+            assert_eq!(body["leafIndex"], 0);
+            assert_eq!(body["lifecycle"]["stage"], Value::Null);
+
+            let res = warp::test::request()
+                .path(&format!("/api/messages/leaf/{:?}", raw.leaf()))
+                .reply(&routes)
+                .await;
+            assert_eq!(res.status(), StatusCode::OK);
+
+            let res = warp::test::request()
+                .path("/api/proofs/1")
+                .reply(&routes)
+                .await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+            let res = warp::test::request()
+                .path("/api/processor/nonces")
+                .reply(&routes)
+                .await;
+            let body: Value = serde_json::from_slice(res.body()).unwrap();
+            assert_eq!(body[0]["currentNonce"], 0);
+        })
+        .await
+    }
+}
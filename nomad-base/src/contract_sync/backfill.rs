@@ -0,0 +1,169 @@
+use super::page::is_range_error;
+use color_eyre::Result;
+use futures_util::{stream, StreamExt};
+use std::{cmp::min, collections::BTreeMap, future::Future};
+
+/// Tracks inclusive ranges indexed out of order, and the first block not yet
+/// indexed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Cursor {
+    next: u32,
+    completed: BTreeMap<u32, u32>,
+}
+
+impl Cursor {
+    /// Start at `next`, the first block not yet indexed
+    pub(crate) fn new(next: u32) -> Self {
+        Self {
+            next,
+            completed: Default::default(),
+        }
+    }
+
+    /// Mark the range `from..=to` as indexed. Returns the last block up to
+    /// which everything is indexed if it moved, i.e. if the range closed the
+    /// hole at the cursor
+    pub(crate) fn complete(&mut self, from: u32, to: u32) -> Option<u32> {
+        self.completed.insert(from, to);
+
+        let start = self.next;
+        while let Some(to) = self.completed.remove(&self.next) {
+            self.next = to + 1;
+        }
+        (self.next != start).then(|| self.next - 1)
+    }
+}
+
+/// Split `from..=to` into inclusive ranges of up to `page_size` blocks, each
+/// starting at the block after the previous one ends
+pub(crate) fn ranges(from: u32, to: u32, page_size: u32) -> Vec<(u32, u32)> {
+    let mut ranges = vec![];
+    let mut start = from;
+    while start <= to {
+        let end = min(start.saturating_add(page_size - 1), to);
+        ranges.push((start, end));
+        if end == u32::MAX {
+            break;
+        }
+        start = end + 1;
+    }
+    ranges
+}
+
+/// Fetch the events of `from..=to`, halving the ranges the provider rejects
+/// down to `min_page_size` blocks. Events are returned in block order.
+async fn fetch_range<T, F, Fut>(fetch: &F, from: u32, to: u32, min_page_size: u32) -> Result<Vec<T>>
+where
+    F: Fn(u32, u32) -> Fut,
+    Fut: Future<Output = Result<Vec<T>>>,
+{
+    let mut events = vec![];
+
+    let mut pending = vec![(from, to)];
+    while let Some((from, to)) = pending.pop() {
+        match fetch(from, to).await {
+            Ok(fetched) => events.extend(fetched),
+            Err(err) if is_range_error(&err) && to - from >= min_page_size => {
+                let middle = from + (to - from) / 2;
+                pending.push((middle + 1, to));
+                pending.push((from, middle));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(events)
+}
+
+/// Index `from..=to` in pages of `page_size` blocks, fetching up to `workers`
+/// pages concurrently.
+///
+/// `store` is called with the events of each page as soon as it is fetched,
+/// in any order, along with the last block up to which every page is indexed
+/// if the page moved it. Pages must be stored idempotently, as an interrupted backfill
+/// fetches the pages above the cursor again.
+pub(crate) async fn backfill<T, F, Fut, S>(
+    from: u32,
+    to: u32,
+    page_size: u32,
+    min_page_size: u32,
+    workers: u32,
+    fetch: F,
+    mut store: S,
+) -> Result<()>
+where
+    F: Fn(u32, u32) -> Fut,
+    Fut: Future<Output = Result<Vec<T>>>,
+    S: FnMut(Vec<T>, Option<u32>) -> Result<()>,
+{
+    let fetch = &fetch;
+    let mut pages = stream::iter(ranges(from, to, page_size))
+        .map(|(start, end)| async move {
+            let events = fetch_range(fetch, start, end, min_page_size).await?;
+            Ok::<_, color_eyre::Report>((start, end, events))
+        })
+        .buffer_unordered(workers as usize);
+
+    let mut cursor = Cursor::new(from);
+    while let Some(page) = pages.next().await {
+        let (start, end, events) = page?;
+        store(events, cursor.complete(start, end))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use color_eyre::eyre::eyre;
+    use std::sync::Mutex;
+
+    #[test]
+    fn it_moves_cursor_over_contiguous_ranges() {
+        assert_eq!(ranges(10, 35, 10), vec![(10, 19), (20, 29), (30, 35)]);
+        assert_eq!(ranges(10, 10, 10), vec![(10, 10)]);
+
+        let mut cursor = Cursor::new(10);
+        assert_eq!(cursor.complete(20, 29), None);
+        assert_eq!(cursor.complete(30, 35), None);
+        assert_eq!(cursor.complete(10, 19), Some(35));
+        assert_eq!(cursor, Cursor::new(36));
+    }
+
+    #[tokio::test]
+    async fn it_backfills_out_of_order() {
+        let queried = Mutex::new(vec![]);
+        let fetch = |from: u32, to: u32| {
+            queried.lock().unwrap().push((from, to));
+            async move {
+                // The provider rejects ranges over 10 blocks, and is slower
+                // to answer for earlier ranges
+                if to - from >= 10 {
+                    return Err(eyre!("query returned more than 10000 results"));
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(100 - from as u64)).await;
+                Ok((from..=to)
+                    .filter(|block| block % 7 == 0)
+                    .collect::<Vec<_>>())
+            }
+        };
+
+        let mut stored = vec![];
+        let mut positions = vec![];
+        backfill(0, 80, 20, 5, 4, fetch, |events, position| {
+            stored.extend(events);
+            positions.extend(position);
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        stored.sort_unstable();
+        assert_eq!(stored, vec![0, 7, 14, 21, 28, 35, 42, 49, 56, 63, 70, 77]);
+        assert_eq!(positions.last(), Some(&80));
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(queried.lock().unwrap().contains(&(10, 19)));
+        assert!(queried.lock().unwrap().contains(&(80, 80)));
+    }
+}
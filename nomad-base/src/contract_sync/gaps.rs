@@ -0,0 +1,208 @@
+use crate::NomadDB;
+use color_eyre::Result;
+use nomad_core::{HomeIndexer, RawCommittedMessageWithMeta};
+use std::{cmp::min, collections::BTreeMap, time::Duration};
+
+/// How long to wait before querying a gap's blocks again, if some of its
+/// messages were not found
+pub(crate) const GAP_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// How many times to query a gap's blocks before failing the sync, so that
+/// leaves the provider never returns surface as a task error
+pub(crate) const GAP_RETRY_ATTEMPTS: u32 = 30;
+
+/// A range of leaf indices missing from db, and the blocks the messages
+/// around it were dispatched in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Gap {
+    /// First missing leaf index
+    pub(crate) first: u32,
+    /// Last missing leaf index
+    pub(crate) last: u32,
+    /// Block of the message before the gap
+    pub(crate) from_block: u32,
+    /// Block of the message after the gap
+    pub(crate) to_block: u32,
+}
+
+impl Gap {
+    /// Number of missing leaves
+    pub(crate) fn missing(&self) -> u32 {
+        self.last - self.first + 1
+    }
+
+    fn contains(&self, leaf_index: u32) -> bool {
+        (self.first..=self.last).contains(&leaf_index)
+    }
+}
+
+/// Find the leaf indices missing before and between `messages`, which must
+/// be sorted by leaf index. The first message only has a gap before it if
+/// messages are already in db. Leaves already in db, e.g. stored past an
+/// earlier gap, are not missing.
+///
+/// `default_from` is used as the block of the latest message in db if its
+/// dispatch block is unknown.
+pub(crate) fn find_gaps(
+    db: &NomadDB,
+    messages: &[RawCommittedMessageWithMeta],
+    default_from: u32,
+) -> Result<Vec<Gap>> {
+    let mut previous = match db.retrieve_latest_leaf_index()? {
+        Some(leaf_index) => {
+            let block = db
+                .message_lifecycle(leaf_index)?
+                .and_then(|lifecycle| lifecycle.dispatched)
+                .and_then(|dispatched| dispatched.block_number)
+                .map_or(default_from, |block| block as u32);
+            Some((leaf_index, block))
+        }
+        None => None,
+    };
+
+    let mut gaps: Vec<Gap> = vec![];
+    for message in messages {
+        let leaf_index = message.raw_message.leaf_index;
+        let block = message.metadata.block_number as u32;
+
+        if let Some((previous_index, previous_block)) = previous {
+            if leaf_index <= previous_index {
+                continue;
+            }
+
+            // Scan the leaves stored in the range once, rather than reading
+            // each index
+            let mut stored = if previous_index + 1 < leaf_index {
+                db.leaf_indices_in(previous_index + 1, leaf_index - 1)
+            } else {
+                vec![]
+            }
+            .into_iter()
+            .peekable();
+
+            for missing in previous_index + 1..leaf_index {
+                if stored.next_if_eq(&missing).is_some() {
+                    continue;
+                }
+                match gaps.last_mut() {
+                    Some(gap) if gap.last + 1 == missing => gap.last = missing,
+                    _ => gaps.push(Gap {
+                        first: missing,
+                        last: missing,
+                        from_block: previous_block,
+                        to_block: block,
+                    }),
+                }
+            }
+        }
+
+        previous = Some((leaf_index, block));
+    }
+
+    Ok(gaps)
+}
+
+/// Query the blocks around `gap` in pages of `chunk_size` blocks, and
+/// return the messages found in it, sorted by leaf index. Messages still
+/// missing can be found by querying again later.
+pub(crate) async fn fill_gap<I>(
+    indexer: &I,
+    gap: &Gap,
+    chunk_size: u32,
+) -> Result<Vec<RawCommittedMessageWithMeta>>
+where
+    I: HomeIndexer + ?Sized,
+{
+    let mut found = BTreeMap::new();
+
+    let mut from = gap.from_block;
+    loop {
+        let to = min(from + chunk_size, gap.to_block);
+        for message in indexer.fetch_sorted_messages(from, to).await? {
+            if gap.contains(message.raw_message.leaf_index) {
+                found.insert(message.raw_message.leaf_index, message);
+            }
+        }
+
+        if to >= gap.to_block || found.len() as u32 == gap.missing() {
+            break;
+        }
+        from = to;
+    }
+
+    Ok(found.into_values().collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nomad_test::{
+        mocks::MockIndexer,
+        test_utils::{dispatched_message, header, run_test_db},
+    };
+
+    fn message(leaf_index: u32, block_number: u64) -> RawCommittedMessageWithMeta {
+        dispatched_message(leaf_index, &[], header(block_number, 0))
+    }
+
+    #[tokio::test]
+    async fn it_finds_and_fills_gaps() {
+        run_test_db(|db| async move {
+            let db = NomadDB::new("home_1", db);
+
+            // Without messages in db, the first message can't follow a gap
+            let page = vec![message(3, 30), message(4, 40), message(7, 70)];
+            assert_eq!(
+                find_gaps(&db, &page, 0).unwrap(),
+                vec![Gap {
+                    first: 5,
+                    last: 6,
+                    from_block: 40,
+                    to_block: 70,
+                }]
+            );
+
+            // Leaf 5 was stored past a gap, leaving only 4 and 6 missing
+            db.store_messages(&[message(3, 30), message(5, 50)])
+                .unwrap();
+            let page = vec![message(7, 70)];
+            let gaps = find_gaps(&db, &page, 0).unwrap();
+            assert_eq!(
+                gaps,
+                vec![
+                    Gap {
+                        first: 4,
+                        last: 4,
+                        from_block: 30,
+                        to_block: 70,
+                    },
+                    Gap {
+                        first: 6,
+                        last: 6,
+                        from_block: 30,
+                        to_block: 70,
+                    },
+                ]
+            );
+
+            let mut indexer = MockIndexer::new();
+            indexer
+                .expect__fetch_sorted_messages()
+                .returning(|from, to| {
+                    Ok((3..8)
+                        .map(|i| message(i, i as u64 * 10))
+                        .filter(|m| (from..=to).contains(&(m.metadata.block_number as u32)))
+                        .collect())
+                });
+            let filled = fill_gap(&indexer, &gaps[1], 15).await.unwrap();
+            assert_eq!(filled, vec![message(6, 60)]);
+
+            // Filling the first gap moves the latest leaf index past leaf 5
+            let filled = fill_gap(&indexer, &gaps[0], 15).await.unwrap();
+            assert_eq!(filled, vec![message(4, 40)]);
+            db.store_messages(&filled).unwrap();
+            assert_eq!(db.retrieve_latest_leaf_index().unwrap(), Some(5));
+        })
+        .await
+    }
+}
@@ -1,4 +1,4 @@
-use crate::CoreMetrics;
+use crate::{AgentHealth, CoreMetrics};
 use prometheus::{HistogramVec, IntGaugeVec};
 use std::sync::Arc;
 
@@ -15,6 +15,14 @@ pub struct ContractSyncMetrics {
     /// Unique occasions when agent missed an event (label values
     /// differentiate updates vs. messages)
     pub missed_events: IntGaugeVec,
+    /// Missed events found in DB and queried again (label values
+    /// differentiate updates vs. messages)
+    pub backfilled_events: IntGaugeVec,
+    /// Events deleted from DB because their block was reorged out (label
+    /// values differentiate updates vs. messages)
+    pub rolled_back_events: IntGaugeVec,
+    /// Agent health, updated with sync progress against the tip
+    pub health: Arc<AgentHealth>,
 }
 
 impl ContractSyncMetrics {
@@ -55,11 +63,30 @@ impl ContractSyncMetrics {
             )
             .expect("failed to register missed_events metric");
 
+        let backfilled_events = metrics
+            .new_int_gauge_vec(
+                "contract_sync_backfilled_events",
+                "Number of missed events stored into db after querying their blocks again",
+                &["data_type", "contract_name", "agent"],
+            )
+            .expect("failed to register backfilled_events metric");
+
+        let rolled_back_events = metrics
+            .new_int_gauge_vec(
+                "contract_sync_rolled_back_events",
+                "Number of events deleted from db after their block was reorged out",
+                &["data_type", "contract_name", "agent"],
+            )
+            .expect("failed to register rolled_back_events metric");
+
         ContractSyncMetrics {
             indexed_height,
             store_event_latency,
             stored_events,
             missed_events,
+            backfilled_events,
+            rolled_back_events,
+            health: metrics.health(),
         }
     }
 }
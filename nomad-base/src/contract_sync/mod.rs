@@ -1,21 +1,29 @@
 use crate::chains::PageSettings;
 use crate::{IndexDataTypes, IndexSettings, NomadDB};
-use color_eyre::Result;
+use color_eyre::{eyre::bail, Result};
 use futures_util::future::select_all;
 use nomad_core::{CommonIndexer, HomeIndexer};
 use tokio::{task::JoinHandle, time::sleep};
-use tracing::{info, info_span};
+use tracing::{info, info_span, warn};
 use tracing::{instrument::Instrumented, Instrument};
 
 use std::cmp::min;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+mod backfill;
+mod gaps;
 mod metrics;
+mod page;
+mod reorg;
 mod schema;
 
+use backfill::backfill;
+use gaps::{fill_gap, find_gaps, Gap, GAP_RETRY_ATTEMPTS, GAP_RETRY_DELAY};
 pub use metrics::ContractSyncMetrics;
-use schema::{CommonContractSyncDB, HomeContractSyncDB};
+use page::{is_range_error, PageSize};
+use reorg::RecentBlocks;
+pub(crate) use schema::{CommonContractSyncDB, HomeContractSyncDB};
 
 const UPDATES_LABEL: &str = "updates";
 const MESSAGES_LABEL: &str = "messages";
@@ -70,6 +78,11 @@ impl<I> ContractSync<I> {
             metrics,
         }
     }
+
+    /// Return a reference to the sync metrics
+    pub fn metrics(&self) -> &ContractSyncMetrics {
+        &self.metrics
+    }
 }
 
 impl<I> ContractSync<I>
@@ -86,11 +99,29 @@ where
     /// them in db. If run in timelag is off, will index at the tip
     /// but use a manual timelag to catch any missed updates. If timelag on,
     /// update  syncing will be run timelag blocks behind the tip.
+    ///
+    /// The headers of recently indexed blocks are kept in db. Updates from
+    /// blocks that were reorged out are deleted, and their range indexed
+    /// again.
+    ///
+    /// The page size is halved when the provider rejects a range for
+    /// exceeding its result limits, and doubled while pages are empty, within
+    /// the page settings' bounds.
+    ///
+    /// If started far behind and the index settings allow several backfill
+    /// workers, final blocks are first indexed by concurrent workers, storing
+    /// pages out of order. The cursor only moves over contiguous pages, so an
+    /// interrupted backfill resumes from the first incomplete page.
+    ///
+    /// Once caught up, the sync waits for the next block if the indexer is
+    /// subscribed to new blocks, and polls the indexer otherwise.
     pub fn sync_updates(&self) -> Instrumented<JoinHandle<Result<()>>> {
         let span = info_span!("UpdateContractSync");
 
         let db = self.db.clone();
         let indexer = self.indexer.clone();
+        let health = self.metrics.health.clone();
+        let health_name = format!("{}:{}", self.contract_name, UPDATES_LABEL);
         let indexed_height = self.metrics.indexed_height.with_label_values(&[
             UPDATES_LABEL,
             &self.contract_name,
@@ -107,30 +138,122 @@ where
             &self.contract_name,
             &self.agent_name,
         ]);
+        let rolled_back_updates = self.metrics.rolled_back_events.with_label_values(&[
+            UPDATES_LABEL,
+            &self.contract_name,
+            &self.agent_name,
+        ]);
 
         let timelag_on = self.index_settings.timelag_on();
         let finality = self.finality as u32;
+        let backfill_workers = self.index_settings.backfill_workers();
+        let poll_interval = self.index_settings.poll_interval();
         let config_from = self.page_settings.from;
-        let chunk_size = self.page_settings.page_size;
+        let min_page_size = self.page_settings.min_page_size();
+        let mut page = PageSize::new(&self.page_settings);
 
         tokio::spawn(async move {
             let mut from = db
                 .retrieve_update_latest_block_end()
                 .map_or_else(|| config_from, |h| h);
 
+            let mut recent = db.retrieve_update_recent_blocks();
+
+            // Delete updates above `fork` and move the cursor back to it
+            let rollback = |recent: &mut RecentBlocks, fork: u32| -> Result<()> {
+                let rolled_back = db.batch(|db| -> Result<_> {
+                    let rolled_back = db.rollback_updates_above(fork as u64)?;
+                    recent.truncate(fork as u64);
+                    db.store_update_recent_blocks(recent)?;
+                    db.store_update_latest_block_end(fork)?;
+                    Ok(rolled_back)
+                })?;
+
+                warn!(
+                    fork = fork,
+                    rolled_back = rolled_back.len(),
+                    "[Updates]: chain reorged. Rolled back {} updates above block {}",
+                    rolled_back.len(),
+                    fork,
+                );
+                rolled_back_updates.add(rolled_back.len().try_into()?);
+                Ok(())
+            };
+
+            // Index final blocks with concurrent workers if far behind, then
+            // follow the tip sequentially
+            if backfill_workers > 1 {
+                let tip = indexer.get_block_number().await?;
+                let end = if timelag_on {
+                    tip
+                } else {
+                    tip.saturating_sub(finality)
+                };
+
+                if end > from.saturating_add(page.get().saturating_mul(backfill_workers)) {
+                    info!(
+                        from = from,
+                        end = end,
+                        workers = backfill_workers,
+                        "[Updates]: backfilling block heights {}...{} with {} workers",
+                        from,
+                        end,
+                        backfill_workers,
+                    );
+
+                    let page_size = page.get();
+                    backfill(
+                        from,
+                        end,
+                        page_size,
+                        min_page_size,
+                        backfill_workers,
+                        |start, end| indexer.fetch_sorted_updates(start, end),
+                        |sorted_updates, position| {
+                            db.batch(|db| -> Result<()> {
+                                db.store_updates_and_meta(&sorted_updates)?;
+                                if let Some(position) = position {
+                                    db.store_update_latest_block_end(position)?;
+                                }
+                                Ok(())
+                            })?;
+                            if let Some(position) = position {
+                                indexed_height.set(position as i64);
+                                health.record_sync(&health_name, position, tip, page_size);
+                            }
+                            stored_updates.add(sorted_updates.len().try_into()?);
+                            Ok(())
+                        },
+                    )
+                    .await?;
+                    from = end;
+                }
+            }
+
             info!(from = from, "[Updates]: resuming indexer from {}", from);
 
             loop {
                 indexed_height.set(from as i64);
 
                 let tip = indexer.get_block_number().await?;
+                health.record_sync(&health_name, from, tip, page.get());
+
+                if let Some(fork) = recent.find_fork(indexer.as_ref()).await? {
+                    rollback(&mut recent, fork)?;
+                    from = fork;
+                    continue;
+                }
+
                 if tip <= from {
-                    // Sleep if we caught up to tip
-                    sleep(Duration::from_secs(100)).await;
+                    // Wait for the next block if subscribed to new blocks,
+                    // otherwise sleep until polling again
+                    if !indexer.wait_for_block(tip, poll_interval).await? {
+                        sleep(poll_interval).await;
+                    }
                     continue;
                 }
 
-                let to = min(from + chunk_size, tip);
+                let to = min(from + page.get(), tip);
 
                 let (start, end) = if timelag_on {
                     // if timelag on, don't modify range
@@ -158,18 +281,57 @@ where
                     end,
                 );
 
-                let sorted_updates = indexer.fetch_sorted_updates(start, end).await?;
+                let sorted_updates = match indexer.fetch_sorted_updates(start, end).await {
+                    Ok(sorted_updates) => sorted_updates,
+                    Err(err) if is_range_error(&err) && page.shrink() => {
+                        warn!(
+                            error = %err,
+                            page_size = page.get(),
+                            "[Updates]: failed to index block heights {}...{}. Retrying with page size {}",
+                            start,
+                            end,
+                            page.get(),
+                        );
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                };
+
+                // Record the blocks of the range. If they conflict with the
+                // recorded ones, the chain reorged during or since indexing
+                let end_block = indexer.get_block_header(end).await?;
+                let blocks = sorted_updates
+                    .iter()
+                    .map(|update| update.metadata.block())
+                    .chain(end_block);
+                if let Err(conflict) = recent.extend(blocks) {
+                    let fork = min(conflict.saturating_sub(1) as u32, from);
+                    rollback(&mut recent, fork)?;
+                    from = fork;
+                    continue;
+                }
 
-                // If no updates found, update last seen block and next height
-                // and continue
+                // If no updates found, update last seen block and next height,
+                // grow the page and continue
                 if sorted_updates.is_empty() {
-                    db.store_update_latest_block_end(to)?;
+                    db.batch(|db| -> Result<()> {
+                        db.store_update_recent_blocks(&recent)?;
+                        db.store_update_latest_block_end(to)?;
+                        Ok(())
+                    })?;
+                    page.grow();
                     from = to;
                     continue;
                 }
 
-                // Store updates
-                db.store_updates_and_meta(&sorted_updates)?;
+                // Store updates and move the cursor past them atomically, so
+                // a crash cannot skip or half-store a page
+                db.batch(|db| -> Result<()> {
+                    db.store_updates_and_meta(&sorted_updates)?;
+                    db.store_update_recent_blocks(&recent)?;
+                    db.store_update_latest_block_end(to)?;
+                    Ok(())
+                })?;
 
                 // Report latencies from emit to store if caught up
                 if to == tip {
@@ -200,7 +362,6 @@ where
                 stored_updates.add(sorted_updates.len().try_into()?);
 
                 // Move forward next height
-                db.store_update_latest_block_end(to)?;
                 from = to;
             }
         })
@@ -225,26 +386,37 @@ where
                 }
             };
 
-            let (_, _, remaining) = select_all(tasks).await;
+            // Fail with the first task to finish, so that a supervisor
+            // restarts the sync
+            let (res, _, remaining) = select_all(tasks).await;
             for task in remaining.into_iter() {
                 cancel_task!(task);
             }
 
-            Ok(())
+            res?
         })
         .instrument(span)
     }
 
     /// Spawn task that continuously looks for new on-chain messages and stores
-    /// them in db. Indexing messages should ALWAYS be done with a timelag, as
-    /// ordering of messages is not guaranteed like it is for updates. Running
-    /// without a timelag could cause messages with the incorrectly ordered
-    /// index to be stored.
+    /// them in db. If timelag is off, messages are indexed at the tip.
+    ///
+    /// The headers of recently indexed blocks are kept in db. Messages from
+    /// blocks that were reorged out are deleted, and their range indexed
+    /// again, so that stale leaves are not left in db.
+    ///
+    /// Leaf indices missing before a page's messages are found by querying
+    /// the blocks around them again, until the leaves are contiguous.
+    ///
+    /// Pages are sized, historical blocks backfilled and new blocks awaited
+    /// as in `sync_updates`.
     pub fn sync_messages(&self) -> Instrumented<JoinHandle<Result<()>>> {
         let span = info_span!("MessageContractSync");
 
         let db = self.db.clone();
         let indexer = self.indexer.clone();
+        let health = self.metrics.health.clone();
+        let health_name = format!("{}:{}", self.contract_name, MESSAGES_LABEL);
         let indexed_height = self.metrics.indexed_height.with_label_values(&[
             MESSAGES_LABEL,
             &self.contract_name,
@@ -256,64 +428,257 @@ where
             &self.contract_name,
             &self.agent_name,
         ]);
+        let missed_messages = self.metrics.missed_events.with_label_values(&[
+            MESSAGES_LABEL,
+            &self.contract_name,
+            &self.agent_name,
+        ]);
+        let backfilled_messages = self.metrics.backfilled_events.with_label_values(&[
+            MESSAGES_LABEL,
+            &self.contract_name,
+            &self.agent_name,
+        ]);
+        let rolled_back_messages = self.metrics.rolled_back_events.with_label_values(&[
+            MESSAGES_LABEL,
+            &self.contract_name,
+            &self.agent_name,
+        ]);
 
         let timelag_on = self.index_settings.timelag_on();
+        let finality = self.finality as u32;
+        let backfill_workers = self.index_settings.backfill_workers();
+        let poll_interval = self.index_settings.poll_interval();
         let config_from = self.page_settings.from;
-        let chunk_size = self.page_settings.page_size;
+        let min_page_size = self.page_settings.min_page_size();
+        let mut page = PageSize::new(&self.page_settings);
 
         tokio::spawn(async move {
             let mut from = db
                 .retrieve_message_latest_block_end()
                 .map_or_else(|| config_from, |h| h);
 
+            let mut recent = db.retrieve_message_recent_blocks();
+
+            // Delete messages above `fork` and move the cursor back to it
+            let rollback = |recent: &mut RecentBlocks, fork: u32| -> Result<()> {
+                let rolled_back = db.batch(|db| -> Result<_> {
+                    let rolled_back = db.rollback_messages_above(fork as u64)?;
+                    recent.truncate(fork as u64);
+                    db.store_message_recent_blocks(recent)?;
+                    db.store_message_latest_block_end(fork)?;
+                    Ok(rolled_back)
+                })?;
+
+                warn!(
+                    fork = fork,
+                    rolled_back = ?rolled_back,
+                    "[Messages]: chain reorged. Rolled back {} messages above block {}",
+                    rolled_back.len(),
+                    fork,
+                );
+                rolled_back_messages.add(rolled_back.len().try_into()?);
+                Ok(())
+            };
+
+            // Index final blocks with concurrent workers if far behind, then
+            // follow the tip sequentially. Leaves stored out of order are
+            // only checked for gaps once following the tip
+            if backfill_workers > 1 {
+                let tip = indexer.get_block_number().await?;
+                let end = if timelag_on {
+                    tip
+                } else {
+                    tip.saturating_sub(finality)
+                };
+
+                if end > from.saturating_add(page.get().saturating_mul(backfill_workers)) {
+                    info!(
+                        from = from,
+                        end = end,
+                        workers = backfill_workers,
+                        "[Messages]: backfilling block heights {}...{} with {} workers",
+                        from,
+                        end,
+                        backfill_workers,
+                    );
+
+                    let page_size = page.get();
+                    backfill(
+                        from,
+                        end,
+                        page_size,
+                        min_page_size,
+                        backfill_workers,
+                        |start, end| indexer.fetch_sorted_messages(start, end),
+                        |sorted_messages, position| {
+                            db.batch(|db| -> Result<()> {
+                                db.store_messages(&sorted_messages)?;
+                                if let Some(position) = position {
+                                    db.store_message_latest_block_end(position)?;
+                                }
+                                Ok(())
+                            })?;
+                            if let Some(position) = position {
+                                indexed_height.set(position as i64);
+                                health.record_sync(&health_name, position, tip, page_size);
+                            }
+                            stored_messages.add(sorted_messages.len().try_into()?);
+                            Ok(())
+                        },
+                    )
+                    .await?;
+                    from = end;
+                }
+            }
+
             info!(from = from, "[Messages]: resuming indexer from {}", from);
 
             loop {
                 indexed_height.set(from as i64);
 
                 let tip = indexer.get_block_number().await?;
+                health.record_sync(&health_name, from, tip, page.get());
+
+                if let Some(fork) = recent.find_fork(indexer.as_ref()).await? {
+                    rollback(&mut recent, fork)?;
+                    from = fork;
+                    continue;
+                }
+
                 if tip <= from {
-                    // Sleep if caught up to tip
-                    sleep(Duration::from_secs(100)).await;
+                    // Wait for the next block if subscribed to new blocks,
+                    // otherwise sleep until polling again
+                    if !indexer.wait_for_block(tip, poll_interval).await? {
+                        sleep(poll_interval).await;
+                    }
                     continue;
                 }
 
-                let candidate = from + chunk_size;
+                let candidate = from + page.get();
                 let to = min(tip, candidate);
 
-                // timelag always applied
-                let (start, end) = if timelag_on {
-                    (from, to)
-                } else {
-                    panic!("Syncing messages with timelag off should never happen!");
-                };
-
                 info!(
-                    start = start,
-                    end = end,
+                    start = from,
+                    end = to,
                     "[Messages]: indexing block heights {}...{}",
-                    start,
-                    end
+                    from,
+                    to
                 );
 
-                let sorted_messages = indexer.fetch_sorted_messages(start, end).await?;
+                let mut sorted_messages = match indexer.fetch_sorted_messages(from, to).await {
+                    Ok(sorted_messages) => sorted_messages,
+                    Err(err) if is_range_error(&err) && page.shrink() => {
+                        warn!(
+                            error = %err,
+                            page_size = page.get(),
+                            "[Messages]: failed to index block heights {}...{}. Retrying with page size {}",
+                            from,
+                            to,
+                            page.get(),
+                        );
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                };
+
+                // Query the blocks around leaf indices missing from db again,
+                // until all are found
+                let gaps = find_gaps(&db, &sorted_messages, config_from)?;
+                if !gaps.is_empty() {
+                    let missing: u32 = gaps.iter().map(Gap::missing).sum();
+                    warn!(
+                        gaps = ?gaps,
+                        "[Messages]: {} leaves missing before block {}. Querying their blocks again",
+                        missing,
+                        to,
+                    );
+                    missed_messages.add(gaps.len().try_into()?);
+
+                    let mut attempts = 0;
+                    let backfilled = loop {
+                        let mut backfilled = vec![];
+                        for gap in gaps.iter() {
+                            backfilled.extend(fill_gap(indexer.as_ref(), gap, page.get()).await?);
+                        }
+                        if backfilled.len() as u32 == missing {
+                            break backfilled;
+                        }
+
+                        attempts += 1;
+                        if attempts >= GAP_RETRY_ATTEMPTS {
+                            bail!(
+                                "[Messages]: found {} of {} missing leaves before block {} after {} attempts",
+                                backfilled.len(),
+                                missing,
+                                to,
+                                attempts,
+                            );
+                        }
+
+                        // The sync is stalled on the gap, but its provider
+                        // is responding
+                        health.record_sync(&health_name, from, tip, page.get());
+                        warn!(
+                            found = backfilled.len(),
+                            missing = missing,
+                            attempts = attempts,
+                            "[Messages]: found {} of {} missing leaves. Retrying in {:?}",
+                            backfilled.len(),
+                            missing,
+                            GAP_RETRY_DELAY,
+                        );
+                        sleep(GAP_RETRY_DELAY).await;
+                    };
+
+                    info!(
+                        backfilled = backfilled.len(),
+                        "[Messages]: backfilled {} missing leaves",
+                        backfilled.len(),
+                    );
+                    backfilled_messages.add(backfilled.len().try_into()?);
+                    sorted_messages.extend(backfilled);
+                    sorted_messages.sort_by_key(|message| message.raw_message.leaf_index);
+                }
+
+                // Record the blocks of the range. If they conflict with the
+                // recorded ones, the chain reorged during or since indexing
+                let end_block = indexer.get_block_header(to).await?;
+                let blocks = sorted_messages
+                    .iter()
+                    .map(|message| message.metadata.block())
+                    .chain(end_block);
+                if let Err(conflict) = recent.extend(blocks) {
+                    let fork = min(conflict.saturating_sub(1) as u32, from);
+                    rollback(&mut recent, fork)?;
+                    from = fork;
+                    continue;
+                }
 
-                // If no messages found, update last seen block and next height
-                // and continue
+                // If no messages found, update last seen block and next height,
+                // grow the page and continue
                 if sorted_messages.is_empty() {
-                    db.store_message_latest_block_end(to)?;
+                    db.batch(|db| -> Result<()> {
+                        db.store_message_recent_blocks(&recent)?;
+                        db.store_message_latest_block_end(to)?;
+                        Ok(())
+                    })?;
+                    page.grow();
                     from = to;
                     continue;
                 }
 
-                // Store messages
-                db.store_messages(&sorted_messages)?;
+                // Store messages and move the cursor past them atomically
+                db.batch(|db| -> Result<()> {
+                    db.store_messages(&sorted_messages)?;
+                    db.store_message_recent_blocks(&recent)?;
+                    db.store_message_latest_block_end(to)?;
+                    Ok(())
+                })?;
 
                 // Report amount of messages stored into db
                 stored_messages.add(sorted_messages.len().try_into()?);
 
                 // Move forward next height
-                db.store_message_latest_block_end(to)?;
                 from = to;
             }
         })
@@ -327,16 +692,17 @@ mod test {
     use nomad_test::mocks::MockIndexer;
 
     use std::sync::Arc;
+    use std::time::Duration;
 
     use ethers::core::types::H256;
     use ethers::signers::LocalWallet;
 
     use crate::chains::PageSettings;
-    use nomad_core::{SignedUpdateWithMeta, Update, UpdateMeta};
-    use nomad_test::test_utils;
+    use nomad_core::{SignedUpdateWithMeta, Update};
+    use nomad_test::test_utils::{self, dispatched_message, header, update};
 
     use super::*;
-    use crate::CoreMetrics;
+    use crate::{CoreMetrics, RestartPolicy, ShutdownToken, SupervisedTask, Supervisor};
 
     const FINALITY: u8 = 5;
 
@@ -413,36 +779,28 @@ mod test {
 
                 let first_update_with_meta = SignedUpdateWithMeta {
                     signed_update: first_update.clone(),
-                    metadata: UpdateMeta {
-                        block_number: 18,
-                        timestamp: Default::default(),
-                    },
+                    ..update(first_root, second_root, header(18, 0))
                 };
 
                 let second_update_with_meta = SignedUpdateWithMeta {
                     signed_update: second_update.clone(),
-                    metadata: UpdateMeta {
-                        block_number: 26,
-                        timestamp: Default::default(),
-                    },
+                    ..update(second_root, third_root, header(26, 0))
                 };
 
                 let third_update_with_meta = SignedUpdateWithMeta {
                     signed_update: third_update.clone(),
-                    metadata: UpdateMeta {
-                        block_number: 37,
-                        timestamp: Default::default(),
-                    },
+                    ..update(third_root, fourth_root, header(37, 0))
                 };
 
                 let fourth_update_with_meta = SignedUpdateWithMeta {
                     signed_update: fourth_update.clone(),
-                    metadata: UpdateMeta {
-                        block_number: 48,
-                        timestamp: Default::default(),
-                    },
+                    ..update(fourth_root, fifth_root, header(48, 0))
                 };
 
+                mock_indexer
+                    .expect__get_block_header()
+                    .returning(|number| Ok(Some(header(number as u64, 0))));
+
                 // Return first update in range 5-20
                 mock_indexer
                     .expect__get_block_number()
@@ -513,10 +871,12 @@ mod test {
             let index_settings = IndexSettings {
                 data_types: IndexDataTypes::Updates,
                 use_timelag: false,
+                ..Default::default()
             };
             let page_settings = PageSettings {
                 from: 10,
                 page_size: 10,
+                ..Default::default()
             };
 
             let indexer = Arc::new(mock_indexer);
@@ -578,4 +938,357 @@ mod test {
         })
         .await
     }
+
+    /* RPC Behavior:
+     *  Timelag off, chunk size 10 blocks, starting at block 10
+     *
+     *  - tip 20: messages 0 @ block 12 and 1 @ block 18
+     *  - tip 30: blocks above 15 reorged out. Message 1 rolled back, then
+     *    re-dispatched with another body @ block 19
+     */
+    #[tokio::test]
+    async fn rolls_back_messages_from_reorged_blocks() {
+        test_utils::run_test_db(|db| async move {
+            let first = dispatched_message(0, &[0], header(12, 0));
+            let reorged = dispatched_message(1, &[1], header(18, 0));
+            let second = dispatched_message(1, &[2], header(19, 1));
+
+            let reorged_out = Arc::new(std::sync::atomic::AtomicBool::new(false));
+            let mut mock_indexer = MockIndexer::new();
+            {
+                let reorged_out = reorged_out.clone();
+                let calls = std::sync::atomic::AtomicU32::new(0);
+                mock_indexer.expect__get_block_number().returning(move || {
+                    if calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                        Ok(20)
+                    } else {
+                        reorged_out.store(true, std::sync::atomic::Ordering::SeqCst);
+                        Ok(30)
+                    }
+                });
+            }
+            {
+                let reorged_out = reorged_out.clone();
+                mock_indexer
+                    .expect__get_block_header()
+                    .returning(move |number| {
+                        let fork =
+                            reorged_out.load(std::sync::atomic::Ordering::SeqCst) && number > 15;
+                        Ok(Some(header(number as u64, fork as u64)))
+                    });
+            }
+            {
+                let (first, reorged, second) = (first.clone(), reorged.clone(), second.clone());
+                mock_indexer
+                    .expect__fetch_sorted_messages()
+                    .returning(move |from, to| match (from, to) {
+                        (10, 20) => Ok(vec![first.clone(), reorged.clone()]),
+                        (12, 22) => Ok(vec![second.clone()]),
+                        _ => Ok(vec![]),
+                    });
+            }
+
+            let nomad_db = NomadDB::new("home_1", db);
+            let metrics = Arc::new(
+                CoreMetrics::new(
+                    "contract_sync_test",
+                    "home",
+                    None,
+                    Arc::new(prometheus::Registry::new()),
+                )
+                .expect("could not make metrics"),
+            );
+            let sync_metrics = ContractSyncMetrics::new(metrics);
+
+            let contract_sync = ContractSync::new(
+                "agent".to_owned(),
+                "home_1".to_owned(),
+                nomad_db.clone(),
+                Arc::new(mock_indexer),
+                IndexSettings {
+                    data_types: IndexDataTypes::UpdatesAndMessages,
+                    use_timelag: false,
+                    ..Default::default()
+                },
+                PageSettings {
+                    from: 10,
+                    page_size: 10,
+                    ..Default::default()
+                },
+                FINALITY,
+                sync_metrics.clone(),
+            );
+
+            let sync_task = contract_sync.sync_messages();
+            sleep(Duration::from_secs(3)).await;
+            cancel_task!(sync_task);
+
+            assert_eq!(nomad_db.retrieve_latest_leaf_index().unwrap(), Some(1));
+            assert_eq!(
+                nomad_db.message_by_leaf_index(0).unwrap(),
+                Some(first.raw_message)
+            );
+            assert_eq!(
+                nomad_db.message_by_leaf_index(1).unwrap(),
+                Some(second.raw_message)
+            );
+            assert!(nomad_db
+                .message_by_leaf(reorged.raw_message.leaf())
+                .unwrap()
+                .is_none());
+            assert_eq!(nomad_db.retrieve_message_latest_block_end(), Some(30));
+            assert_eq!(
+                sync_metrics
+                    .rolled_back_events
+                    .with_label_values(&[MESSAGES_LABEL, "home_1", "agent"])
+                    .get(),
+                1
+            );
+        })
+        .await
+    }
+
+    /* RPC Behavior:
+     *  Timelag on, chunk size 10 blocks, starting at block 10, tip 30
+     *
+     *  - 10-20: message 0 @ block 12, missing message 1 @ block 18
+     *  - 20-30: message 2 @ block 25. Message 1 found again in 12-22
+     */
+    #[tokio::test]
+    async fn backfills_missed_messages() {
+        test_utils::run_test_db(|db| async move {
+            let message = |leaf_index: u32, block_number: u64| {
+                dispatched_message(leaf_index, &[], header(block_number, 0))
+            };
+            let messages = vec![message(0, 12), message(1, 18), message(2, 25)];
+
+            let mut mock_indexer = MockIndexer::new();
+            mock_indexer.expect__get_block_number().returning(|| Ok(30));
+            mock_indexer
+                .expect__get_block_header()
+                .returning(|number| Ok(Some(header(number as u64, 0))));
+            {
+                let messages = messages.clone();
+                let calls = std::sync::atomic::AtomicU32::new(0);
+                mock_indexer
+                    .expect__fetch_sorted_messages()
+                    .returning(move |from, to| {
+                        let first_call =
+                            calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0;
+                        Ok(messages
+                            .iter()
+                            .filter(|m| (from..=to).contains(&(m.metadata.block_number as u32)))
+                            .filter(|m| !first_call || m.raw_message.leaf_index != 1)
+                            .cloned()
+                            .collect())
+                    });
+            }
+
+            let nomad_db = NomadDB::new("home_1", db);
+            let metrics = Arc::new(
+                CoreMetrics::new(
+                    "contract_sync_test",
+                    "home",
+                    None,
+                    Arc::new(prometheus::Registry::new()),
+                )
+                .expect("could not make metrics"),
+            );
+            let sync_metrics = ContractSyncMetrics::new(metrics);
+
+            let contract_sync = ContractSync::new(
+                "agent".to_owned(),
+                "home_1".to_owned(),
+                nomad_db.clone(),
+                Arc::new(mock_indexer),
+                IndexSettings {
+                    data_types: IndexDataTypes::UpdatesAndMessages,
+                    use_timelag: true,
+                    ..Default::default()
+                },
+                PageSettings {
+                    from: 10,
+                    page_size: 10,
+                    ..Default::default()
+                },
+                FINALITY,
+                sync_metrics.clone(),
+            );
+
+            let sync_task = contract_sync.sync_messages();
+            sleep(Duration::from_secs(3)).await;
+            cancel_task!(sync_task);
+
+            assert_eq!(nomad_db.retrieve_latest_leaf_index().unwrap(), Some(2));
+            for message in messages {
+                assert_eq!(
+                    nomad_db
+                        .message_by_leaf_index(message.raw_message.leaf_index)
+                        .unwrap(),
+                    Some(message.raw_message)
+                );
+            }
+
+            let labels = [MESSAGES_LABEL, "home_1", "agent"];
+            assert_eq!(
+                sync_metrics.missed_events.with_label_values(&labels).get(),
+                1
+            );
+            assert_eq!(
+                sync_metrics
+                    .backfilled_events
+                    .with_label_values(&labels)
+                    .get(),
+                1
+            );
+        })
+        .await
+    }
+
+    /* RPC Behavior:
+     *  Timelag on, chunk size 10 blocks, starting at block 10, tip 100, 4
+     *  backfill workers
+     *
+     *  - message i @ block 10 + 7 * i, for i in 0..12
+     */
+    #[tokio::test]
+    async fn backfills_history_concurrently() {
+        test_utils::run_test_db(|db| async move {
+            let message = |leaf_index: u32| {
+                dispatched_message(leaf_index, &[], header(10 + 7 * leaf_index as u64, 0))
+            };
+            let messages: Vec<_> = (0..12).map(message).collect();
+
+            let mut mock_indexer = MockIndexer::new();
+            mock_indexer
+                .expect__get_block_number()
+                .returning(|| Ok(100));
+            mock_indexer
+                .expect__get_block_header()
+                .returning(|number| Ok(Some(header(number as u64, 0))));
+            {
+                let messages = messages.clone();
+                mock_indexer
+                    .expect__fetch_sorted_messages()
+                    .returning(move |from, to| {
+                        Ok(messages
+                            .iter()
+                            .filter(|m| (from..=to).contains(&(m.metadata.block_number as u32)))
+                            .cloned()
+                            .collect())
+                    });
+            }
+
+            let nomad_db = NomadDB::new("home_1", db);
+            let metrics = Arc::new(
+                CoreMetrics::new(
+                    "contract_sync_test",
+                    "home",
+                    None,
+                    Arc::new(prometheus::Registry::new()),
+                )
+                .expect("could not make metrics"),
+            );
+            let sync_metrics = ContractSyncMetrics::new(metrics);
+
+            let contract_sync = ContractSync::new(
+                "agent".to_owned(),
+                "home_1".to_owned(),
+                nomad_db.clone(),
+                Arc::new(mock_indexer),
+                IndexSettings {
+                    data_types: IndexDataTypes::UpdatesAndMessages,
+                    use_timelag: true,
+                    backfill_workers: Some(4),
+                    ..Default::default()
+                },
+                PageSettings {
+                    from: 10,
+                    page_size: 10,
+                    ..Default::default()
+                },
+                FINALITY,
+                sync_metrics,
+            );
+
+            // Wait for the backfill to move the cursor to the end
+            let sync_task = contract_sync.sync_messages();
+            tokio::time::timeout(Duration::from_secs(10), async {
+                while nomad_db.retrieve_message_latest_block_end() != Some(100) {
+                    sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("backfill did not reach the end");
+            cancel_task!(sync_task);
+
+            assert_eq!(nomad_db.retrieve_latest_leaf_index().unwrap(), Some(11));
+            assert_eq!(nomad_db.retrieve_message_latest_block_end(), Some(100));
+            for message in messages {
+                assert_eq!(
+                    nomad_db
+                        .message_by_leaf_index(message.raw_message.leaf_index)
+                        .unwrap(),
+                    Some(message.raw_message)
+                );
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn failed_home_sync_is_restarted() {
+        test_utils::run_test_db(|db| async move {
+            // The provider is down, and stays down past the restart
+            let mut mock_indexer = MockIndexer::new();
+            mock_indexer
+                .expect__get_block_number()
+                .times(2)
+                .returning(|| Err(color_eyre::eyre::eyre!("connection refused")));
+
+            let metrics = Arc::new(
+                CoreMetrics::new(
+                    "contract_sync_test",
+                    "home",
+                    None,
+                    Arc::new(prometheus::Registry::new()),
+                )
+                .expect("could not make metrics"),
+            );
+            let db = NomadDB::new("home_1", db);
+            let indexer = Arc::new(mock_indexer);
+            let sync_metrics = ContractSyncMetrics::new(metrics.clone());
+            let policy = RestartPolicy {
+                max_restarts: Some(1),
+                backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(1),
+            };
+            let mut supervisor = Supervisor::new(metrics.clone(), ShutdownToken::default());
+            supervisor.add(SupervisedTask::new("home_sync", policy, move || {
+                ContractSync::new(
+                    "agent".to_owned(),
+                    "home_1".to_owned(),
+                    db.clone(),
+                    indexer.clone(),
+                    IndexSettings {
+                        data_types: IndexDataTypes::Updates,
+                        ..Default::default()
+                    },
+                    PageSettings {
+                        from: 10,
+                        page_size: 10,
+                        ..Default::default()
+                    },
+                    FINALITY,
+                    sync_metrics.clone(),
+                )
+                .spawn_home()
+            }));
+
+            assert!(supervisor.spawn().await.unwrap().is_err());
+            assert_eq!(metrics.task_restarts("home_sync").get(), 1);
+            assert_eq!(metrics.task_failures("home_sync").get(), 1);
+        })
+        .await
+    }
 }
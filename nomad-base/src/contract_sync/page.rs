@@ -0,0 +1,121 @@
+use crate::chains::PageSettings;
+use color_eyre::Report;
+
+/// Lowercase fragments of the errors RPC providers return when a query
+/// exceeds their result or block range limits. Timeouts and rate limits are
+/// not range errors, as a smaller range would not help
+const RANGE_ERRORS: &[&str] = &[
+    "-32005",
+    "query returned more than",
+    "response size exceeded",
+    "block range is too large",
+    "block range too large",
+    "maximum block range",
+];
+
+/// Whether `err` means the queried block range was too large for the
+/// provider, and querying a smaller range may succeed
+pub(crate) fn is_range_error(err: &Report) -> bool {
+    err.chain().any(|cause| {
+        let cause = cause.to_string().to_lowercase();
+        RANGE_ERRORS.iter().any(|fragment| cause.contains(fragment))
+    })
+}
+
+/// Number of blocks to query per page. Halved when the provider rejects a
+/// range, and doubled while pages come back empty, within the configured
+/// bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PageSize {
+    size: u32,
+    min: u32,
+    max: u32,
+}
+
+impl PageSize {
+    /// Start at the configured page size
+    pub(crate) fn new(settings: &PageSettings) -> Self {
+        let min = settings.min_page_size();
+        let max = settings.max_page_size();
+        Self {
+            size: settings.page_size.max(min).min(max),
+            min,
+            max,
+        }
+    }
+
+    /// Current page size
+    pub(crate) fn get(&self) -> u32 {
+        self.size
+    }
+
+    /// Halve the page size. Returns false if it is already at its minimum
+    pub(crate) fn shrink(&mut self) -> bool {
+        if self.size <= self.min {
+            return false;
+        }
+        self.size = (self.size / 2).max(self.min);
+        true
+    }
+
+    /// Double the page size, up to its maximum
+    pub(crate) fn grow(&mut self) {
+        self.size = self.size.saturating_mul(2).min(self.max);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use color_eyre::eyre::eyre;
+
+    #[test]
+    fn it_adapts_page_size() {
+        let mut page = PageSize::new(&PageSettings {
+            from: 0,
+            page_size: 1000,
+            min_page_size: Some(200),
+            max_page_size: Some(3000),
+        });
+        assert_eq!(page.get(), 1000);
+
+        assert!(page.shrink());
+        assert_eq!(page.get(), 500);
+        assert!(page.shrink());
+        assert!(page.shrink());
+        assert_eq!(page.get(), 200);
+        assert!(!page.shrink());
+
+        page.grow();
+        page.grow();
+        page.grow();
+        page.grow();
+        assert_eq!(page.get(), 3000);
+
+        // Without bounds, the page size only shrinks from the configured one
+        let mut page = PageSize::new(&PageSettings {
+            from: 0,
+            page_size: 10,
+            ..Default::default()
+        });
+        page.grow();
+        assert_eq!(page.get(), 10);
+        for _ in 0..4 {
+            assert!(page.shrink());
+        }
+        assert_eq!(page.get(), 1);
+        assert!(!page.shrink());
+    }
+
+    #[test]
+    fn it_recognizes_range_errors() {
+        let err = eyre!("query returned more than 10000 results");
+        assert!(is_range_error(&err));
+        let err = eyre!("(code: -32005, message: limit exceeded, data: None)")
+            .wrap_err("fetching updates");
+        assert!(is_range_error(&err));
+        assert!(!is_range_error(&eyre!("Request timed out")));
+        assert!(!is_range_error(&eyre!("too many requests")));
+        assert!(!is_range_error(&eyre!("connection refused")));
+    }
+}
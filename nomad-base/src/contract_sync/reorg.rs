@@ -0,0 +1,196 @@
+use color_eyre::Result;
+use nomad_core::{BlockHeader, CommonIndexer, Decode, Encode, NomadError};
+
+/// Number of recent block headers kept per sync. Reorgs deeper than the
+/// oldest kept header can't be located precisely
+pub(crate) const RECENT_BLOCKS: usize = 256;
+
+/// Headers of recently indexed blocks, sorted by number. Holds the blocks
+/// events were indexed from, and the last block of each indexed range.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct RecentBlocks(Vec<BlockHeader>);
+
+impl RecentBlocks {
+    /// Record `headers`, dropping the oldest above `RECENT_BLOCKS`. Headers
+    /// with an unknown (zero) hash are skipped.
+    ///
+    /// Records nothing if any header conflicts with the recorded ones: a
+    /// different hash for a recorded block number, or a parent hash not
+    /// matching the recorded parent. This happens when the chain reorgs
+    /// while a range is being indexed. Errors with the lowest recorded block
+    /// number that can't be trusted anymore.
+    pub(crate) fn extend(
+        &mut self,
+        headers: impl IntoIterator<Item = BlockHeader>,
+    ) -> Result<(), u64> {
+        let mut extended = self.0.clone();
+        let mut conflict: Option<u64> = None;
+
+        for header in headers.into_iter().filter(|header| !header.hash.is_zero()) {
+            match extended.binary_search_by_key(&header.number, |recorded| recorded.number) {
+                Ok(i) => {
+                    if extended[i].hash != header.hash {
+                        conflict = Some(conflict.map_or(header.number, |c| c.min(header.number)));
+                    }
+                }
+                Err(i) => {
+                    let parent = i.checked_sub(1).map(|i| &extended[i]);
+                    let child = extended.get(i);
+                    let breaks_parent = parent.map_or(false, |parent| {
+                        parent.number + 1 == header.number
+                            && !header.parent_hash.is_zero()
+                            && !header.builds_on(parent)
+                    });
+                    let breaks_child = child.map_or(false, |child| {
+                        header.number + 1 == child.number
+                            && !child.parent_hash.is_zero()
+                            && !child.builds_on(&header)
+                    });
+                    if breaks_parent || breaks_child {
+                        // The recorded parent or child can't be trusted
+                        let number = if breaks_parent {
+                            header.number - 1
+                        } else {
+                            header.number + 1
+                        };
+                        conflict = Some(conflict.map_or(number, |c| c.min(number)));
+                        continue;
+                    }
+                    extended.insert(i, header);
+                }
+            }
+        }
+
+        if let Some(conflict) = conflict {
+            return Err(conflict);
+        }
+
+        let excess = extended.len().saturating_sub(RECENT_BLOCKS);
+        extended.drain(..excess);
+        self.0 = extended;
+        Ok(())
+    }
+
+    /// Drop the headers above `number`
+    pub(crate) fn truncate(&mut self, number: u64) {
+        self.0.retain(|header| header.number <= number);
+    }
+
+    /// Find the highest recorded block still on the canonical chain of
+    /// `indexer`, if the latest recorded block was reorged out. Blocks the
+    /// indexer doesn't know of yet are not considered reorged.
+    ///
+    /// If every recorded block was reorged out, the block before the oldest
+    /// one is returned.
+    pub(crate) async fn find_fork<I>(&self, indexer: &I) -> Result<Option<u32>>
+    where
+        I: CommonIndexer + ?Sized,
+    {
+        let mut recorded = self.0.iter().rev();
+
+        let latest = match recorded.next() {
+            Some(latest) => latest,
+            None => return Ok(None),
+        };
+        match indexer.get_block_header(latest.number as u32).await? {
+            Some(canonical) if canonical.hash != latest.hash => {}
+            _ => return Ok(None),
+        }
+
+        for header in recorded {
+            if let Some(canonical) = indexer.get_block_header(header.number as u32).await? {
+                if canonical.hash == header.hash {
+                    return Ok(Some(header.number as u32));
+                }
+            }
+        }
+
+        Ok(Some(self.0[0].number.saturating_sub(1) as u32))
+    }
+}
+
+impl Encode for RecentBlocks {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        let mut written = (self.0.len() as u32).write_to(writer)?;
+        for header in self.0.iter() {
+            written += header.write_to(writer)?;
+        }
+        Ok(written)
+    }
+}
+
+impl Decode for RecentBlocks {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+        Self: Sized,
+    {
+        let len = u32::read_from(reader)?;
+        let headers = (0..len)
+            .map(|_| BlockHeader::read_from(reader))
+            .collect::<Result<_, _>>()?;
+        Ok(Self(headers))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nomad_test::{mocks::MockIndexer, test_utils::header};
+
+    #[test]
+    fn it_rejects_conflicting_headers() {
+        let mut recent = RecentBlocks::default();
+        assert_eq!(recent.extend(vec![header(10, 0), header(20, 0)]), Ok(()));
+        assert_eq!(recent.extend(vec![header(11, 0), header(20, 0)]), Ok(()));
+
+        // Same number, other hash
+        assert_eq!(recent.extend(vec![header(30, 0), header(20, 1)]), Err(20));
+        // Parent not matching the recorded block
+        assert_eq!(recent.extend(vec![header(21, 1)]), Err(20));
+        // Child of the new block recorded with another parent
+        assert_eq!(recent.extend(vec![header(19, 1)]), Err(20));
+        assert_eq!(recent.0, vec![header(10, 0), header(11, 0), header(20, 0)]);
+
+        recent.truncate(15);
+        assert_eq!(recent.0, vec![header(10, 0), header(11, 0)]);
+
+        let encoded = recent.to_vec();
+        assert_eq!(
+            RecentBlocks::read_from(&mut encoded.as_slice()).unwrap(),
+            recent
+        );
+    }
+
+    #[tokio::test]
+    async fn it_finds_fork() {
+        let mut recent = RecentBlocks::default();
+        recent
+            .extend(vec![header(10, 0), header(20, 0), header(30, 0)])
+            .unwrap();
+
+        // Blocks above 20 reorged out
+        let mut indexer = MockIndexer::new();
+        indexer
+            .expect__get_block_header()
+            .returning(|number| Ok(Some(header(number as u64, (number > 20) as u64))));
+        assert_eq!(recent.find_fork(&indexer).await.unwrap(), Some(20));
+
+        // No reorg
+        let mut indexer = MockIndexer::new();
+        indexer
+            .expect__get_block_header()
+            .returning(|number| Ok(Some(header(number as u64, 0))));
+        assert_eq!(recent.find_fork(&indexer).await.unwrap(), None);
+
+        // Every recorded block reorged out
+        let mut indexer = MockIndexer::new();
+        indexer
+            .expect__get_block_header()
+            .returning(|number| Ok(Some(header(number as u64, 1))));
+        assert_eq!(recent.find_fork(&indexer).await.unwrap(), Some(9));
+    }
+}
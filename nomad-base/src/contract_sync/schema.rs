@@ -1,38 +1,73 @@
+use super::reorg::RecentBlocks;
 use crate::NomadDB;
 use color_eyre::Result;
-use nomad_core::db::DbError;
+use nomad_core::db::{Column, DbError};
 
 static UPDATES_LAST_BLOCK_END: &str = "updates_last_block";
 static MESSAGES_LAST_BLOCK_END: &str = "messages_last_block";
+static UPDATES_RECENT_BLOCKS: &str = "updates_recent_blocks";
+static MESSAGES_RECENT_BLOCKS: &str = "messages_recent_blocks";
 
 pub(crate) trait CommonContractSyncDB {
     fn store_update_latest_block_end(&self, latest_block: u32) -> Result<(), DbError>;
     fn retrieve_update_latest_block_end(&self) -> Option<u32>;
+    fn store_update_recent_blocks(&self, recent: &RecentBlocks) -> Result<(), DbError>;
+    fn retrieve_update_recent_blocks(&self) -> RecentBlocks;
 }
 
 pub(crate) trait HomeContractSyncDB {
     fn store_message_latest_block_end(&self, latest_block: u32) -> Result<(), DbError>;
     fn retrieve_message_latest_block_end(&self) -> Option<u32>;
+    fn store_message_recent_blocks(&self, recent: &RecentBlocks) -> Result<(), DbError>;
+    fn retrieve_message_recent_blocks(&self) -> RecentBlocks;
 }
 
 impl CommonContractSyncDB for NomadDB {
     fn store_update_latest_block_end(&self, latest_block: u32) -> Result<(), DbError> {
-        self.store_encodable("", UPDATES_LAST_BLOCK_END, &latest_block)
+        self.column(Column::Metadata)
+            .store_encodable("", UPDATES_LAST_BLOCK_END, &latest_block)
     }
 
     fn retrieve_update_latest_block_end(&self) -> Option<u32> {
-        self.retrieve_decodable("", UPDATES_LAST_BLOCK_END)
+        self.column(Column::Metadata)
+            .retrieve_decodable("", UPDATES_LAST_BLOCK_END)
             .expect("db failure")
     }
+
+    fn store_update_recent_blocks(&self, recent: &RecentBlocks) -> Result<(), DbError> {
+        self.column(Column::Metadata)
+            .store_encodable("", UPDATES_RECENT_BLOCKS, recent)
+    }
+
+    fn retrieve_update_recent_blocks(&self) -> RecentBlocks {
+        self.column(Column::Metadata)
+            .retrieve_decodable("", UPDATES_RECENT_BLOCKS)
+            .expect("db failure")
+            .unwrap_or_default()
+    }
 }
 
 impl HomeContractSyncDB for NomadDB {
     fn store_message_latest_block_end(&self, latest_block: u32) -> Result<(), DbError> {
-        self.store_encodable("", MESSAGES_LAST_BLOCK_END, &latest_block)
+        self.column(Column::Metadata)
+            .store_encodable("", MESSAGES_LAST_BLOCK_END, &latest_block)
     }
 
     fn retrieve_message_latest_block_end(&self) -> Option<u32> {
-        self.retrieve_decodable("", MESSAGES_LAST_BLOCK_END)
+        self.column(Column::Metadata)
+            .retrieve_decodable("", MESSAGES_LAST_BLOCK_END)
+            .expect("db failure")
+    }
+
+    fn store_message_recent_blocks(&self, recent: &RecentBlocks) -> Result<(), DbError> {
+        self.column(Column::Metadata)
+            .store_encodable("", MESSAGES_RECENT_BLOCKS, recent)
+    }
+
+    fn retrieve_message_recent_blocks(&self) -> RecentBlocks {
+        self.column(Column::Metadata)
+            .retrieve_decodable("", MESSAGES_RECENT_BLOCKS)
             .expect("db failure")
+            .unwrap_or_default()
     }
 }
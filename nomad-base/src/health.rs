@@ -0,0 +1,258 @@
+//! Agent health and readiness, served on `/health` and `/ready`.
+//!
+//! Tasks record what they observe into a shared `AgentHealth`. `/health`
+//! (liveness) fails if the agent is stuck: the home has failed, or no RPC has
+//! succeeded recently. `/ready` additionally requires every contract sync to
+//! be within a page of its tip and no supervised task to be restarting.
+
+use nomad_core::State;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::Instant;
+use warp::{http::StatusCode, Filter, Reply};
+
+use crate::Routes;
+
+/// How long without a successful RPC before the agent is unhealthy
+pub const MAX_RPC_AGE: Duration = Duration::from_secs(600);
+
+/// Progress of a single contract sync
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SyncStatus {
+    indexed: u32,
+    tip: u32,
+    page_size: u32,
+}
+
+impl SyncStatus {
+    fn lag(&self) -> u32 {
+        self.tip.saturating_sub(self.indexed)
+    }
+
+    fn caught_up(&self) -> bool {
+        self.lag() <= self.page_size
+    }
+}
+
+/// State of a supervised task
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct TaskStatus {
+    running: bool,
+    faults: u64,
+}
+
+#[derive(Debug)]
+struct HealthState {
+    syncs: HashMap<String, SyncStatus>,
+    tasks: HashMap<String, TaskStatus>,
+    last_rpc: Option<Instant>,
+    home_failed: HashMap<String, bool>,
+    /// Until the first RPC succeeds, RPCs are stale `MAX_RPC_AGE` after this
+    started: Instant,
+}
+
+impl Default for HealthState {
+    fn default() -> Self {
+        Self {
+            syncs: Default::default(),
+            tasks: Default::default(),
+            last_rpc: None,
+            home_failed: Default::default(),
+            started: Instant::now(),
+        }
+    }
+}
+
+/// Health of an agent, as observed by its tasks
+#[derive(Debug, Default)]
+pub struct AgentHealth {
+    state: RwLock<HealthState>,
+}
+
+fn unix_secs(time: Instant) -> u64 {
+    (SystemTime::now() - time.elapsed())
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl AgentHealth {
+    /// Record the progress of the contract sync named `name`. Implies a
+    /// successful RPC, as the tip was just fetched.
+    pub fn record_sync(&self, name: &str, indexed: u32, tip: u32, page_size: u32) {
+        let mut state = self.state.write().expect("!poisoned");
+        state.last_rpc = Some(Instant::now());
+        state.syncs.insert(
+            name.to_owned(),
+            SyncStatus {
+                indexed,
+                tip,
+                page_size,
+            },
+        );
+    }
+
+    /// Record the state of the home named `home`
+    pub fn record_home_state(&self, home: &str, home_state: &State) {
+        let mut state = self.state.write().expect("!poisoned");
+        state.last_rpc = Some(Instant::now());
+        state
+            .home_failed
+            .insert(home.to_owned(), *home_state == State::Failed);
+    }
+
+    /// Record that the task named `task` is running
+    pub fn task_started(&self, task: &str) {
+        let mut state = self.state.write().expect("!poisoned");
+        state.tasks.entry(task.to_owned()).or_default().running = true;
+    }
+
+    /// Record that the task named `task` errored
+    pub fn task_faulted(&self, task: &str) {
+        let mut state = self.state.write().expect("!poisoned");
+        let status = state.tasks.entry(task.to_owned()).or_default();
+        status.running = false;
+        status.faults += 1;
+    }
+
+    /// Whether the agent is live. Fails on any failed home or stale RPC.
+    /// Syncs and home state checks record successful RPCs. An agent gets
+    /// `MAX_RPC_AGE` from startup for its first one.
+    pub fn healthy(&self) -> bool {
+        let state = self.state.read().expect("!poisoned");
+        let rpc_fresh = state.last_rpc.unwrap_or(state.started).elapsed() <= MAX_RPC_AGE;
+        rpc_fresh && !state.home_failed.values().any(|failed| *failed)
+    }
+
+    /// Whether the agent is healthy, caught up and all tasks are running
+    pub fn ready(&self) -> bool {
+        if !self.healthy() {
+            return false;
+        }
+
+        let state = self.state.read().expect("!poisoned");
+        state.syncs.values().all(SyncStatus::caught_up)
+            && state.tasks.values().all(|task| task.running)
+    }
+
+    /// Json report of everything observed
+    pub fn report(&self) -> Value {
+        let healthy = self.healthy();
+        let ready = self.ready();
+        let state = self.state.read().expect("!poisoned");
+
+        let syncs: serde_json::Map<String, Value> = state
+            .syncs
+            .iter()
+            .map(|(name, sync)| {
+                (
+                    name.clone(),
+                    json!({
+                        "indexed": sync.indexed,
+                        "tip": sync.tip,
+                        "lag": sync.lag(),
+                        "caughtUp": sync.caught_up(),
+                    }),
+                )
+            })
+            .collect();
+        let tasks: serde_json::Map<String, Value> = state
+            .tasks
+            .iter()
+            .map(|(name, task)| {
+                (
+                    name.clone(),
+                    json!({
+                        "running": task.running,
+                        "faults": task.faults,
+                    }),
+                )
+            })
+            .collect();
+
+        json!({
+            "healthy": healthy,
+            "ready": ready,
+            "homeFailed": state.home_failed,
+            "lastRpc": state.last_rpc.map(unix_secs),
+            "syncs": syncs,
+            "tasks": tasks,
+        })
+    }
+
+    /// `/health` and `/ready` routes, answering 200 or 503 with the report
+    pub fn routes(self: Arc<Self>) -> Routes {
+        let health = self.clone();
+        let live = warp::path!("health").map(move || reply(health.healthy(), health.report()));
+        let ready = warp::path!("ready").map(move || reply(self.ready(), self.report()));
+
+        warp::get()
+            .and(live.or(ready).unify())
+            .map(|reply| Box::new(reply) as Box<dyn Reply>)
+            .boxed()
+    }
+}
+
+fn reply(ok: bool, report: Value) -> warp::reply::WithStatus<warp::reply::Json> {
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    warp::reply::with_status(warp::reply::json(&report), status)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_reports_readiness() {
+        let health = Arc::new(AgentHealth::default());
+        let routes = health.clone().routes();
+
+        let res = warp::test::request().path("/health").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // more than a page behind the tip
+        health.record_sync("home_1:updates", 900, 2000, 500);
+        health.task_started("channel:replica_1");
+        assert!(!health.ready());
+        let res = warp::test::request().path("/ready").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        health.record_sync("home_1:updates", 1600, 2000, 500);
+        assert!(health.ready());
+        let res = warp::test::request().path("/ready").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        health.task_faulted("channel:replica_1");
+        assert!(!health.ready());
+        assert!(health.healthy());
+        assert_eq!(health.report()["tasks"]["channel:replica_1"]["faults"], 1);
+
+        health.task_started("channel:replica_1");
+        health.record_home_state("home_1", &State::Failed);
+        assert!(!health.healthy());
+        let res = warp::test::request().path("/health").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_requires_a_first_rpc_after_startup() {
+        let health = AgentHealth::default();
+        assert!(health.healthy());
+
+        tokio::time::advance(MAX_RPC_AGE * 2).await;
+        assert!(!health.healthy());
+
+        health.record_sync("home_1:updates", 100, 100, 10);
+        assert!(health.healthy());
+        tokio::time::advance(MAX_RPC_AGE * 2).await;
+        assert!(!health.healthy());
+    }
+}
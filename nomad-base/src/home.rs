@@ -1,4 +1,4 @@
-use crate::{ContractSync, HomeIndexers, NomadDB};
+use crate::{ContractSync, ContractSyncMetrics, HomeIndexers, NomadDB};
 use async_trait::async_trait;
 use color_eyre::eyre::Result;
 use ethers::core::types::{H256, U256};
@@ -47,6 +47,12 @@ impl CachingHome {
         self.db.clone()
     }
 
+    /// Return handle on the contract sync metrics, to be shared with
+    /// replicas built later on
+    pub fn sync_metrics(&self) -> ContractSyncMetrics {
+        self.contract_sync.metrics().clone()
+    }
+
     /// Spawn a task that syncs the CachingHome's db with the on-chain event
     /// data
     pub fn sync(&self) -> Instrumented<JoinHandle<Result<()>>> {
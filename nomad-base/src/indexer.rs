@@ -1,8 +1,10 @@
 use async_trait::async_trait;
 use color_eyre::Result;
-use nomad_core::{CommonIndexer, HomeIndexer, RawCommittedMessage, SignedUpdateWithMeta};
+use nomad_core::{
+    BlockHeader, CommonIndexer, HomeIndexer, RawCommittedMessageWithMeta, SignedUpdateWithMeta,
+};
 use nomad_test::mocks::MockIndexer;
-use std::{ops::Deref, sync::Arc};
+use std::{ops::Deref, sync::Arc, time::Duration};
 
 #[derive(Debug, Clone)]
 /// Arc wrapper for HomeVariants enum
@@ -42,6 +44,14 @@ impl CommonIndexer for CommonIndexers {
         self.deref().get_block_number().await
     }
 
+    async fn get_block_header(&self, number: u32) -> Result<Option<BlockHeader>> {
+        self.deref().get_block_header(number).await
+    }
+
+    async fn wait_for_block(&self, number: u32, timeout: Duration) -> Result<bool> {
+        self.deref().wait_for_block(number, timeout).await
+    }
+
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {
         self.deref().fetch_sorted_updates(from, to).await
     }
@@ -68,6 +78,24 @@ impl CommonIndexer for CommonIndexerVariants {
         }
     }
 
+    async fn get_block_header(&self, number: u32) -> Result<Option<BlockHeader>> {
+        match self {
+            CommonIndexerVariants::Ethereum(indexer) => indexer.get_block_header(number).await,
+            CommonIndexerVariants::Mock(indexer) => indexer.get_block_header(number).await,
+            CommonIndexerVariants::Other(indexer) => indexer.get_block_header(number).await,
+        }
+    }
+
+    async fn wait_for_block(&self, number: u32, timeout: Duration) -> Result<bool> {
+        match self {
+            CommonIndexerVariants::Ethereum(indexer) => {
+                indexer.wait_for_block(number, timeout).await
+            }
+            CommonIndexerVariants::Mock(indexer) => indexer.wait_for_block(number, timeout).await,
+            CommonIndexerVariants::Other(indexer) => indexer.wait_for_block(number, timeout).await,
+        }
+    }
+
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {
         match self {
             CommonIndexerVariants::Ethereum(indexer) => {
@@ -115,6 +143,14 @@ impl CommonIndexer for HomeIndexers {
         self.deref().get_block_number().await
     }
 
+    async fn get_block_header(&self, number: u32) -> Result<Option<BlockHeader>> {
+        self.deref().get_block_header(number).await
+    }
+
+    async fn wait_for_block(&self, number: u32, timeout: Duration) -> Result<bool> {
+        self.deref().wait_for_block(number, timeout).await
+    }
+
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {
         self.deref().fetch_sorted_updates(from, to).await
     }
@@ -122,7 +158,11 @@ impl CommonIndexer for HomeIndexers {
 
 #[async_trait]
 impl HomeIndexer for HomeIndexers {
-    async fn fetch_sorted_messages(&self, from: u32, to: u32) -> Result<Vec<RawCommittedMessage>> {
+    async fn fetch_sorted_messages(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> Result<Vec<RawCommittedMessageWithMeta>> {
         self.deref().fetch_sorted_messages(from, to).await
     }
 }
@@ -148,6 +188,22 @@ impl CommonIndexer for HomeIndexerVariants {
         }
     }
 
+    async fn get_block_header(&self, number: u32) -> Result<Option<BlockHeader>> {
+        match self {
+            HomeIndexerVariants::Ethereum(indexer) => indexer.get_block_header(number).await,
+            HomeIndexerVariants::Mock(indexer) => indexer.get_block_header(number).await,
+            HomeIndexerVariants::Other(indexer) => indexer.get_block_header(number).await,
+        }
+    }
+
+    async fn wait_for_block(&self, number: u32, timeout: Duration) -> Result<bool> {
+        match self {
+            HomeIndexerVariants::Ethereum(indexer) => indexer.wait_for_block(number, timeout).await,
+            HomeIndexerVariants::Mock(indexer) => indexer.wait_for_block(number, timeout).await,
+            HomeIndexerVariants::Other(indexer) => indexer.wait_for_block(number, timeout).await,
+        }
+    }
+
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {
         match self {
             HomeIndexerVariants::Ethereum(indexer) => indexer.fetch_sorted_updates(from, to).await,
@@ -159,7 +215,11 @@ impl CommonIndexer for HomeIndexerVariants {
 
 #[async_trait]
 impl HomeIndexer for HomeIndexerVariants {
-    async fn fetch_sorted_messages(&self, from: u32, to: u32) -> Result<Vec<RawCommittedMessage>> {
+    async fn fetch_sorted_messages(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> Result<Vec<RawCommittedMessageWithMeta>> {
         match self {
             HomeIndexerVariants::Ethereum(indexer) => indexer.fetch_sorted_messages(from, to).await,
             HomeIndexerVariants::Mock(indexer) => indexer.fetch_sorted_messages(from, to).await,
@@ -27,6 +27,22 @@ pub use macros::*;
 mod nomad_db;
 pub use nomad_db::*;
 
+/// Pruning of proofs and messages
+mod retention;
+pub use retention::*;
+
+/// Graceful shutdown
+mod shutdown;
+pub use shutdown::*;
+
+/// Supervision of agent tasks
+mod supervisor;
+pub use supervisor::*;
+
+/// Serving several homes from one process
+mod multi_home;
+pub use multi_home::*;
+
 /// Base errors
 mod error;
 pub use error::*;
@@ -45,6 +61,14 @@ pub use xapp::*;
 mod metrics;
 pub use metrics::*;
 
+/// Health and readiness reporting
+mod health;
+pub use health::*;
+
+/// Read-only query API
+mod api;
+pub use api::*;
+
 mod contract_sync;
 pub use contract_sync::*;
 
@@ -88,6 +88,10 @@ macro_rules! decl_channel {
                 pub fn db(&self) -> nomad_base::NomadDB {
                     self.as_ref().db.clone()
                 }
+
+                pub fn shutdown_token(&self) -> nomad_base::ShutdownToken {
+                    self.as_ref().shutdown.clone()
+                }
             }
         }
     }
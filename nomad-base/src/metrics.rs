@@ -2,11 +2,33 @@
 
 use color_eyre::Result;
 use prometheus::{
-    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry,
+};
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
 };
-use std::sync::Arc;
 use tokio::task::JoinHandle;
 
+use crate::{AgentHealth, Routes};
+
+/// Collectors registered with `CoreMetrics::new_*`, by metric name. Shared by
+/// the metrics of all homes served by the process, so that each home's agent
+/// gets the same collectors.
+#[derive(Default)]
+struct Registered(Mutex<HashMap<String, Box<dyn Any + Send + Sync>>>);
+
+impl fmt::Debug for Registered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set()
+            .entries(self.0.lock().expect("!poisoned").keys())
+            .finish()
+    }
+}
+
 #[derive(Debug)]
 /// Metrics for a particular domain
 pub struct CoreMetrics {
@@ -15,11 +37,15 @@ pub struct CoreMetrics {
     transactions: Box<IntGaugeVec>,
     wallet_balance: Box<IntGaugeVec>,
     channel_faults: Box<IntGaugeVec>,
+    task_restarts: Box<IntCounterVec>,
+    task_failures: Box<IntCounterVec>,
     rpc_latencies: Box<HistogramVec>,
     span_durations: Box<HistogramVec>,
     home_failure_checks: Box<IntGaugeVec>,
     home_failure_observations: Box<IntGaugeVec>,
     listen_port: Option<u16>,
+    health: Arc<AgentHealth>,
+    registered: Arc<Registered>,
     /// Metrics registry for adding new metrics and gathering reports
     registry: Arc<Registry>,
 }
@@ -62,6 +88,24 @@ impl CoreMetrics {
                 .const_label("VERSION", env!("CARGO_PKG_VERSION")),
                 &["home", "replica", "agent"],
             )?),
+            task_restarts: Box::new(IntCounterVec::new(
+                Opts::new(
+                    "task_restarts",
+                    "Number of times a supervised task was restarted after an error",
+                )
+                .namespace("nomad")
+                .const_label("VERSION", env!("CARGO_PKG_VERSION")),
+                &["home", "task", "agent"],
+            )?),
+            task_failures: Box::new(IntCounterVec::new(
+                Opts::new(
+                    "task_failures",
+                    "Number of times a supervised task failed without being restarted",
+                )
+                .namespace("nomad")
+                .const_label("VERSION", env!("CARGO_PKG_VERSION")),
+                &["home", "task", "agent"],
+            )?),
             rpc_latencies: Box::new(HistogramVec::new(
                 HistogramOpts::new(
                     "rpc_duration_ms",
@@ -100,6 +144,8 @@ impl CoreMetrics {
             )?),
             registry,
             listen_port,
+            health: Default::default(),
+            registered: Default::default(),
         };
 
         // TODO: only register these if they aren't already registered?
@@ -109,6 +155,8 @@ impl CoreMetrics {
         metrics.registry.register(metrics.rpc_latencies.clone())?;
         metrics.registry.register(metrics.span_durations.clone())?;
         metrics.registry.register(metrics.channel_faults.clone())?;
+        metrics.registry.register(metrics.task_restarts.clone())?;
+        metrics.registry.register(metrics.task_failures.clone())?;
         metrics
             .registry
             .register(metrics.home_failure_checks.clone())?;
@@ -119,6 +167,50 @@ impl CoreMetrics {
         Ok(metrics)
     }
 
+    /// Metrics for another home served by the same process. Collectors,
+    /// registry and health are shared, labels use `home_name`.
+    pub fn for_home(&self, home_name: &str) -> CoreMetrics {
+        CoreMetrics {
+            agent_name: self.agent_name.clone(),
+            home_name: home_name.to_owned(),
+            transactions: self.transactions.clone(),
+            wallet_balance: self.wallet_balance.clone(),
+            channel_faults: self.channel_faults.clone(),
+            task_restarts: self.task_restarts.clone(),
+            task_failures: self.task_failures.clone(),
+            rpc_latencies: self.rpc_latencies.clone(),
+            span_durations: self.span_durations.clone(),
+            home_failure_checks: self.home_failure_checks.clone(),
+            home_failure_observations: self.home_failure_observations.clone(),
+            listen_port: self.listen_port,
+            health: self.health.clone(),
+            registered: self.registered.clone(),
+            registry: self.registry.clone(),
+        }
+    }
+
+    /// Register the collector built by `make` under `metric_name`. Returns
+    /// the collector already registered under that name if any.
+    fn register_once<C, F>(&self, metric_name: &str, make: F) -> Result<C>
+    where
+        C: prometheus::core::Collector + Clone + Send + Sync + 'static,
+        F: FnOnce() -> prometheus::Result<C>,
+    {
+        let mut registered = self.registered.0.lock().expect("!poisoned");
+        if let Some(collector) = registered
+            .get(metric_name)
+            .and_then(|collector| collector.downcast_ref::<C>())
+        {
+            return Ok(collector.clone());
+        }
+
+        let collector = make()?;
+        self.registry.register(Box::new(collector.clone()))?;
+        registered.insert(metric_name.to_owned(), Box::new(collector.clone()));
+
+        Ok(collector)
+    }
+
     /// Register an int gauge vec
     pub fn new_int_gauge_vec(
         &self,
@@ -126,15 +218,14 @@ impl CoreMetrics {
         help: &str,
         labels: &[&str],
     ) -> Result<prometheus::IntGaugeVec> {
-        let gauge_vec = IntGaugeVec::new(
-            Opts::new(metric_name, help)
-                .namespace("nomad")
-                .const_label("VERSION", env!("CARGO_PKG_VERSION")),
-            labels,
-        )?;
-        self.registry.register(Box::new(gauge_vec.clone()))?;
-
-        Ok(gauge_vec)
+        self.register_once(metric_name, || {
+            IntGaugeVec::new(
+                Opts::new(metric_name, help)
+                    .namespace("nomad")
+                    .const_label("VERSION", env!("CARGO_PKG_VERSION")),
+                labels,
+            )
+        })
     }
 
     /// Register an int counter.
@@ -144,16 +235,14 @@ impl CoreMetrics {
         help: &str,
         labels: &[&str],
     ) -> Result<prometheus::IntCounterVec> {
-        let counter = IntCounterVec::new(
-            Opts::new(metric_name, help)
-                .namespace("nomad")
-                .const_label("VERSION", env!("CARGO_PKG_VERSION")),
-            labels,
-        )?;
-
-        self.registry.register(Box::new(counter.clone()))?;
-
-        Ok(counter)
+        self.register_once(metric_name, || {
+            IntCounterVec::new(
+                Opts::new(metric_name, help)
+                    .namespace("nomad")
+                    .const_label("VERSION", env!("CARGO_PKG_VERSION")),
+                labels,
+            )
+        })
     }
 
     /// Register a histogram.
@@ -164,17 +253,15 @@ impl CoreMetrics {
         labels: &[&str],
         buckets: &[f64],
     ) -> Result<prometheus::HistogramVec> {
-        let histogram = HistogramVec::new(
-            HistogramOpts::new(metric_name, help)
-                .namespace("nomad")
-                .buckets(buckets.to_owned())
-                .const_label("VERSION", env!("CARGO_PKG_VERSION")),
-            labels,
-        )?;
-
-        self.registry.register(Box::new(histogram.clone()))?;
-
-        Ok(histogram)
+        self.register_once(metric_name, || {
+            HistogramVec::new(
+                HistogramOpts::new(metric_name, help)
+                    .namespace("nomad")
+                    .buckets(buckets.to_owned())
+                    .const_label("VERSION", env!("CARGO_PKG_VERSION")),
+                labels,
+            )
+        })
     }
 
     /// Call with the new balance when gas is spent.
@@ -195,6 +282,18 @@ impl CoreMetrics {
             .with_label_values(&[&self.home_name, replica, &self.agent_name])
     }
 
+    /// Return restart counter for a supervised task
+    pub fn task_restarts(&self, task: &str) -> IntCounter {
+        self.task_restarts
+            .with_label_values(&[&self.home_name, task, &self.agent_name])
+    }
+
+    /// Return failure counter for a supervised task
+    pub fn task_failures(&self, task: &str) -> IntCounter {
+        self.task_failures
+            .with_label_values(&[&self.home_name, task, &self.agent_name])
+    }
+
     /// Return home failure checks gauge
     pub fn home_failure_checks(&self) -> IntGauge {
         self.home_failure_checks
@@ -207,6 +306,16 @@ impl CoreMetrics {
             .with_label_values(&[&self.home_name, &self.agent_name])
     }
 
+    /// Name of the home these metrics are labelled with
+    pub fn home_name(&self) -> &str {
+        &self.home_name
+    }
+
+    /// Return the agent's shared health state
+    pub fn health(&self) -> Arc<AgentHealth> {
+        self.health.clone()
+    }
+
     /// Call with RPC duration after it is complete
     pub fn rpc_complete(&self, chain: &str, method: &str, duration_ms: f64) {
         self.rpc_latencies
@@ -230,10 +339,17 @@ impl CoreMetrics {
         Ok(out_buf)
     }
 
-    /// Run an HTTP server serving OpenMetrics format reports on `/metrics`
+    /// Run an HTTP server serving OpenMetrics format reports on `/metrics`,
+    /// and `/health` and `/ready`
     ///
     /// This is compatible with Prometheus, which ought to be configured to scrape me!
     pub fn run_http_server(self: Arc<CoreMetrics>) -> JoinHandle<()> {
+        self.run_http_server_with(None)
+    }
+
+    /// Run an HTTP server serving OpenMetrics format reports on `/metrics`,
+    /// `/health` and `/ready`, and any additional `routes`
+    pub fn run_http_server_with(self: Arc<CoreMetrics>, routes: Option<Routes>) -> JoinHandle<()> {
         use warp::Filter;
 
         // Default to port 9090
@@ -244,28 +360,61 @@ impl CoreMetrics {
             port = port
         );
 
+        let health = self.health().routes();
+        let metrics = warp::path!("metrics").map(move || {
+            Box::new(warp::reply::with_header(
+                self.gather().expect("failed to encode metrics"),
+                "Content-Type",
+                // OpenMetrics specs demands "application/openmetrics-text; version=1.0.0; charset=utf-8"
+                // but the prometheus scraper itself doesn't seem to care?
+                // try text/plain to make web browsers happy.
+                "text/plain; charset=utf-8",
+            )) as Box<dyn warp::Reply>
+        });
+        let metrics = metrics.or(health).unify();
+        let routes = match routes {
+            Some(routes) => metrics.or(routes).unify().boxed(),
+            None => metrics.boxed(),
+        };
+
         tokio::spawn(async move {
-            warp::serve(
-                warp::path!("metrics")
-                    .map(move || {
-                        warp::reply::with_header(
-                            self.gather().expect("failed to encode metrics"),
-                            "Content-Type",
-                            // OpenMetrics specs demands "application/openmetrics-text; version=1.0.0; charset=utf-8"
-                            // but the prometheus scraper itself doesn't seem to care?
-                            // try text/plain to make web browsers happy.
-                            "text/plain; charset=utf-8",
-                        )
-                    })
-                    .or(warp::any().map(|| {
-                        warp::reply::with_status(
-                            "go look at /metrics",
-                            warp::http::StatusCode::NOT_FOUND,
-                        )
-                    })),
-            )
+            warp::serve(routes.or(warp::any().map(|| {
+                warp::reply::with_status("go look at /metrics", warp::http::StatusCode::NOT_FOUND)
+            })))
             .run(([0, 0, 0, 0], port))
             .await;
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_shares_collectors_across_homes() {
+        let registry = Arc::new(Registry::new());
+        let metrics = CoreMetrics::new("relayer", "home_1", None, registry.clone()).unwrap();
+        let other = metrics.for_home("home_2");
+
+        let counter = metrics
+            .new_int_counter("relayed", "Relayed", &["home"])
+            .unwrap();
+        let other_counter = other
+            .new_int_counter("relayed", "Relayed", &["home"])
+            .unwrap();
+        counter.with_label_values(&["home_1"]).inc();
+        other_counter.with_label_values(&["home_2"]).inc_by(2);
+
+        other.task_restarts("channel:replica_1").inc();
+        assert_eq!(metrics.task_restarts("channel:replica_1").get(), 0);
+        assert_eq!(other.task_restarts("channel:replica_1").get(), 1);
+
+        let relayed = registry
+            .gather()
+            .into_iter()
+            .find(|family| family.get_name() == "nomad_relayed")
+            .unwrap();
+        assert_eq!(relayed.get_metric().len(), 2);
+    }
+}
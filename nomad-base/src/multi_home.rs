@@ -0,0 +1,130 @@
+//! Serving several homes from one agent process.
+//!
+//! `run_homes` builds one agent per home. The agents share the db (each home
+//! having its own entities), the metrics registry and HTTP server, and the
+//! shutdown token. Contracts on the same chain share their provider and,
+//! per signer, their nonce manager.
+
+use color_eyre::{eyre::eyre, Result};
+use futures_util::future::select_all;
+use nomad_core::db::DB;
+use std::sync::Arc;
+use tracing::{error, info};
+use warp::Filter;
+
+use crate::{
+    CoreMetrics, NomadAgent, QueryApi, Settings, ShutdownToken, SupervisedTask, Supervisor,
+};
+
+/// Resources shared by the agents of all homes served by the process
+#[derive(Debug, Clone)]
+pub struct SharedResources {
+    /// The db, holding the entities of every home
+    pub db: DB,
+    /// Metrics of the first home, relabelled per home with
+    /// `CoreMetrics::for_home`
+    pub metrics: Arc<CoreMetrics>,
+    /// Triggered on SIGTERM/SIGINT, stopping every home
+    pub shutdown: ShutdownToken,
+}
+
+impl SharedResources {
+    /// Open the db and metrics registry configured in `settings`
+    pub fn open(agent_name: &str, settings: &Settings) -> Result<Self> {
+        let metrics = Arc::new(CoreMetrics::new(
+            agent_name,
+            &settings.home.name,
+            settings.metrics,
+            Arc::new(prometheus::Registry::new()),
+        )?);
+        let db = DB::from_path(&settings.db)?;
+
+        Ok(Self {
+            db,
+            metrics,
+            shutdown: Default::default(),
+        })
+    }
+}
+
+/// Run an agent for each home in `settings` until all of them stop, or
+/// SIGTERM/SIGINT is received. Each home is supervised on its own: one
+/// failing for good is reported and leaves the others running. On signal,
+/// wait for in-flight work of all homes to finish before returning.
+///
+/// The db, metrics port and logging of the first home are used for all.
+pub async fn run_homes<A>(settings: Vec<A::Settings>) -> Result<()>
+where
+    A: NomadAgent + 'static,
+    A::Settings: AsMut<Settings>,
+{
+    let first = settings.first().ok_or_else(|| eyre!("No home to serve"))?;
+    let shared = SharedResources::open(A::AGENT_NAME, first.as_ref())?;
+
+    let mut agents = Vec::with_capacity(settings.len());
+    for mut home_settings in settings {
+        home_settings.as_mut().shared = Some(shared.clone());
+        agents.push(A::from_settings(home_settings).await?);
+    }
+
+    let first = &agents[0];
+    first.start_tracing(first.metrics().span_duration())?;
+
+    let multi_home = agents.len() > 1;
+    let api = agents
+        .iter()
+        .filter(|agent| agent.as_ref().settings.api)
+        .map(|agent| {
+            let api = QueryApi::new(agent.as_ref());
+            if multi_home {
+                api.home_routes()
+            } else {
+                api.routes()
+            }
+        })
+        .reduce(|routes, home_routes| routes.or(home_routes).unify().boxed());
+    let _ = first.metrics().run_http_server_with(api);
+
+    info!(homes = agents.len(), "Starting {} agents", A::AGENT_NAME);
+    let shutdown = shared.shutdown;
+    let _ = shutdown.listen_for_signals();
+
+    let (mut homes, mut running): (Vec<_>, Vec<_>) = agents
+        .into_iter()
+        .map(|agent| {
+            let home = agent.home().name().to_owned();
+            let mut supervisor = Supervisor::new(agent.metrics(), shutdown.clone());
+            supervisor.add(SupervisedTask::once(
+                format!("home:{}", home),
+                agent.run_all(),
+            ));
+            (home, supervisor.spawn())
+        })
+        .unzip();
+    let run_homes = async move {
+        let mut failed = Ok(());
+        while !running.is_empty() {
+            let (res, index, rest) = select_all(running).await;
+            running = rest;
+            let home = homes.remove(index);
+            match res.map_err(Into::into).and_then(|res| res) {
+                Ok(()) => info!(home = %home, "Home {} stopped", home),
+                Err(e) => {
+                    error!(home = %home, error = ?e, "Home {} failed", home);
+                    failed = Err(e);
+                }
+            }
+        }
+        failed
+    };
+    tokio::select! {
+        res = run_homes => res?,
+        _ = shutdown.triggered() => {}
+    }
+
+    if shutdown.is_triggered() {
+        shutdown.drain().await?;
+        info!("Shut down cleanly");
+    }
+    Ok(())
+}
@@ -1,9 +1,11 @@
 use color_eyre::Result;
 use ethers::core::types::H256;
-use nomad_core::db::{DbError, TypedDB, DB};
+use nomad_core::db::{Column, DbError, Direction, TypedDB, DB};
 use nomad_core::{
-    accumulator::NomadProof, utils, CommittedMessage, Decode, NomadMessage, RawCommittedMessage,
-    SignedUpdate, SignedUpdateWithMeta, UpdateMeta,
+    accumulator::{MerkleProof, NomadProof, NomadTreeSnapshot},
+    utils, CommittedMessage, Decode, LifecycleEvent, LifecycleStage, MessageLifecycle,
+    NomadMessage, RawCommittedMessage, RawCommittedMessageWithMeta, SignedUpdate,
+    SignedUpdateWithMeta, UpdateMeta,
 };
 use tokio::time::sleep;
 use tracing::{debug, info};
@@ -24,10 +26,23 @@ static LATEST_ROOT: &str = "update_latest_root_";
 static LATEST_LEAF_INDEX: &str = "latest_known_leaf_index_";
 static UPDATER_PRODUCED_UPDATE: &str = "updater_produced_update_";
 static PROVER_LATEST_COMMITTED: &str = "prover_latest_committed_";
+static PROVER_COMMITTED_COUNT: &str = "prover_committed_count_";
+static PROVER_SNAPSHOT: &str = "prover_snapshot_";
+static PROVER_LATEST_SNAPSHOT: &str = "prover_latest_snapshot_";
+static LIFECYCLE: &str = "lifecycle_";
+static UPDATE_RELAYED: &str = "update_relayed_";
+static PRUNED: &str = "pruned_";
+static PRUNED_BELOW: &str = "pruned_below";
+static PROOFS_PUSHED_BELOW: &str = "proofs_pushed_below";
+static PRUNE_SCANNED_BELOW: &str = "prune_scanned_below";
+static CURRENT_NONCE: &str = "current_nonce_";
 
 /// DB handle for storing data tied to a specific home.
 ///
 /// Key structure: ```<entity>_<additional_prefix(es)>_<key>```
+///
+/// Changing the layout of existing keys requires a migration. See
+/// `nomad_core::db::MIGRATIONS`.
 #[derive(Debug, Clone)]
 pub struct NomadDB(TypedDB);
 
@@ -57,6 +72,17 @@ impl NomadDB {
         Self(TypedDB::new(entity.as_ref().to_owned(), db))
     }
 
+    /// Run `f` against a handle whose writes are committed atomically if it
+    /// succeeds, so that a crash cannot leave partially stored items. Reads
+    /// through the handle see its own writes. See `DB::batch`.
+    pub fn batch<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&NomadDB) -> Result<T, E>,
+        E: From<DbError>,
+    {
+        self.0.batch(|db| f(&NomadDB(db.clone())))
+    }
+
     /// Check if db is empty
     pub fn is_empty(&self) -> Result<bool> {
         let no_updates = self.update_by_previous_root(H256::zero())?.is_none();
@@ -64,82 +90,109 @@ impl NomadDB {
         Ok(no_updates && no_messages)
     }
 
-    /// Store list of messages
-    pub fn store_messages(&self, messages: &[RawCommittedMessage]) -> Result<()> {
-        for message in messages {
-            self.store_latest_message(message)?;
+    /// Store list of messages and record their dispatch. All messages are
+    /// stored atomically.
+    pub fn store_messages(&self, messages: &[RawCommittedMessageWithMeta]) -> Result<()> {
+        self.batch(|db| {
+            for message_with_meta in messages {
+                let message = &message_with_meta.raw_message;
+                db.store_latest_message(message)?;
+                db.store_message_event(
+                    message.leaf_index,
+                    LifecycleStage::Dispatched,
+                    &message_with_meta.metadata.into(),
+                )?;
 
-            let committed_message: CommittedMessage = message.clone().try_into()?;
-            info!(
-                leaf_index = &committed_message.leaf_index,
-                origin = &committed_message.message.origin,
-                destination = &committed_message.message.destination,
-                nonce = &committed_message.message.nonce,
-                "Stored new message in db.",
-            );
-        }
+                let committed_message: CommittedMessage = message.clone().try_into()?;
+                info!(
+                    leaf_index = &committed_message.leaf_index,
+                    origin = &committed_message.message.origin,
+                    destination = &committed_message.message.destination,
+                    nonce = &committed_message.message.nonce,
+                    "Stored new message in db.",
+                );
+            }
 
-        Ok(())
+            Ok(())
+        })
     }
 
-    /// Store a raw committed message
+    /// Store a raw committed message atomically
     ///
     /// Keys --> Values:
     /// - `destination_and_nonce` --> `leaf`
     /// - `leaf_index` --> `leaf`
     /// - `leaf` --> `message`
     pub fn store_raw_committed_message(&self, message: &RawCommittedMessage) -> Result<()> {
-        let parsed = NomadMessage::read_from(&mut message.message.clone().as_slice())?;
+        self.batch(|db| {
+            let parsed = NomadMessage::read_from(&mut message.message.clone().as_slice())?;
 
-        let destination_and_nonce = parsed.destination_and_nonce();
+            let destination_and_nonce = parsed.destination_and_nonce();
 
-        let leaf = message.leaf();
+            let leaf = message.leaf();
 
-        debug!(
-            leaf = ?leaf,
-            destination_and_nonce,
-            destination = parsed.destination,
-            nonce = parsed.nonce,
-            leaf_index = message.leaf_index,
-            "storing raw committed message in db"
-        );
-        self.store_leaf(message.leaf_index, destination_and_nonce, leaf)?;
-        self.store_keyed_encodable(MESSAGE, &leaf, message)?;
-        Ok(())
+            debug!(
+                leaf = ?leaf,
+                destination_and_nonce,
+                destination = parsed.destination,
+                nonce = parsed.nonce,
+                leaf_index = message.leaf_index,
+                "storing raw committed message in db"
+            );
+            db.store_leaf(message.leaf_index, destination_and_nonce, leaf)?;
+            db.column(Column::Messages)
+                .store_keyed_encodable(MESSAGE, &leaf, message)?;
+            Ok(())
+        })
     }
 
-    /// Store a raw committed message building off of the latest leaf index
+    /// Store a raw committed message building off of the latest leaf index.
+    /// If the message fills a gap, the latest leaf index moves past the
+    /// messages already stored after it.
     pub fn store_latest_message(&self, message: &RawCommittedMessage) -> Result<()> {
-        // If there is no latest root, or if this update is on the latest root
-        // update latest root
-        match self.retrieve_latest_leaf_index()? {
-            Some(idx) => {
-                if idx == message.leaf_index - 1 {
-                    self.update_latest_leaf_index(message.leaf_index)?;
-                } else {
-                    debug!(
-                        "Attempted to store message not building off latest leaf index. Latest leaf index: {}. Attempted leaf index: {}.",
-                        idx,
-                        message.leaf_index,
-                    )
+        self.batch(|db| {
+            // If there is no latest leaf index, or if this message builds on
+            // it, update latest leaf index
+            let builds_on_latest = match db.retrieve_latest_leaf_index()? {
+                Some(idx) => {
+                    let builds_on_latest = idx + 1 == message.leaf_index;
+                    if !builds_on_latest {
+                        debug!(
+                            "Attempted to store message not building off latest leaf index. Latest leaf index: {}. Attempted leaf index: {}.",
+                            idx,
+                            message.leaf_index,
+                        )
+                    }
+                    builds_on_latest
                 }
-            }
-            None => self.update_latest_leaf_index(message.leaf_index)?,
-        }
+                None => true,
+            };
 
-        self.store_raw_committed_message(message)
+            db.store_raw_committed_message(message)?;
+
+            if builds_on_latest {
+                let mut latest = message.leaf_index;
+                while db.leaf_by_leaf_index(latest + 1)?.is_some() {
+                    latest += 1;
+                }
+                db.update_latest_leaf_index(latest)?;
+            }
+            Ok(())
+        })
     }
 
     /// Store the latest known leaf_index
     ///
     /// Key --> value: `LATEST_LEAF_INDEX` --> `leaf_index`
     pub fn update_latest_leaf_index(&self, leaf_index: u32) -> Result<(), DbError> {
-        self.store_encodable("", LATEST_LEAF_INDEX, &leaf_index)
+        self.column(Column::Metadata)
+            .store_encodable("", LATEST_LEAF_INDEX, &leaf_index)
     }
 
     /// Retrieve the highest known leaf_index
     pub fn retrieve_latest_leaf_index(&self) -> Result<Option<u32>, DbError> {
-        self.retrieve_decodable("", LATEST_LEAF_INDEX)
+        self.column(Column::Metadata)
+            .retrieve_decodable("", LATEST_LEAF_INDEX)
     }
 
     /// Store the leaf keyed by leaf_index
@@ -154,24 +207,45 @@ impl NomadDB {
             leaf = ?leaf,
             "storing leaf hash keyed by index and dest+nonce"
         );
-        self.store_keyed_encodable(LEAF, &destination_and_nonce, &leaf)?;
-        self.store_keyed_encodable(LEAF, &leaf_index, &leaf)
+        self.column(Column::Leaves)
+            .store_keyed_encodable(LEAF, &destination_and_nonce, &leaf)?;
+        self.column(Column::Leaves)
+            .store_keyed_encodable(LEAF, &leaf_index, &leaf)
     }
 
     /// Retrieve a raw committed message by its leaf hash
     pub fn message_by_leaf(&self, leaf: H256) -> Result<Option<RawCommittedMessage>, DbError> {
-        self.retrieve_keyed_decodable(MESSAGE, &leaf)
+        self.column(Column::Messages)
+            .retrieve_keyed_decodable(MESSAGE, &leaf)
     }
 
     /// Retrieve the leaf hash keyed by leaf index
     pub fn leaf_by_leaf_index(&self, leaf_index: u32) -> Result<Option<H256>, DbError> {
-        self.retrieve_keyed_decodable(LEAF, &leaf_index)
+        self.column(Column::Leaves)
+            .retrieve_keyed_decodable(LEAF, &leaf_index)
+    }
+
+    /// Retrieve the leaf indices stored in `first..=last`, in order, with a
+    /// single range scan. Leaves keyed by destination and nonce share the
+    /// prefix and are skipped. Does not see the writes buffered by a batch.
+    pub fn leaf_indices_in(&self, first: u32, last: u32) -> Vec<u32> {
+        self.column(Column::Leaves)
+            .prefix_entries_from(LEAF, &first, Direction::Forward)
+            .map_while(|(key, _)| {
+                let mut index = [0u8; 4];
+                index.copy_from_slice(key.get(..4)?);
+                let index = u32::from_be_bytes(index);
+                (index <= last).then(|| (key.len() == 4, index))
+            })
+            .filter_map(|(is_leaf_index, index)| is_leaf_index.then(|| index))
+            .collect()
     }
 
     /// Retrieve the leaf hash keyed by destination and nonce
     pub fn leaf_by_nonce(&self, destination: u32, nonce: u32) -> Result<Option<H256>, DbError> {
         let dest_and_nonce = utils::destination_and_nonce(destination, nonce);
-        self.retrieve_keyed_decodable(LEAF, &dest_and_nonce)
+        self.column(Column::Leaves)
+            .retrieve_keyed_decodable(LEAF, &dest_and_nonce)
     }
 
     /// Retrieve a raw committed message by its leaf hash
@@ -202,30 +276,35 @@ impl NomadDB {
     /// Store the latest committed
     fn store_latest_root(&self, root: H256) -> Result<(), DbError> {
         debug!(root = ?root, "storing new latest root in DB");
-        self.store_encodable("", LATEST_ROOT, &root)
+        self.column(Column::Metadata)
+            .store_encodable("", LATEST_ROOT, &root)
     }
 
     /// Retrieve the latest committed
     pub fn retrieve_latest_root(&self) -> Result<Option<H256>, DbError> {
-        self.retrieve_decodable("", LATEST_ROOT)
+        self.column(Column::Metadata)
+            .retrieve_decodable("", LATEST_ROOT)
     }
 
-    /// Store list of sorted updates and their metadata
+    /// Store list of sorted updates and their metadata. All updates are
+    /// stored atomically.
     pub fn store_updates_and_meta(&self, updates: &[SignedUpdateWithMeta]) -> Result<()> {
-        for update_with_meta in updates {
-            self.store_latest_update(&update_with_meta.signed_update)?;
-            self.store_update_metadata(update_with_meta)?;
-
-            info!(
-                block_number = update_with_meta.metadata.block_number,
-                timestamp = ?update_with_meta.metadata.timestamp,
-                previous_root = ?&update_with_meta.signed_update.update.previous_root,
-                new_root = ?&update_with_meta.signed_update.update.new_root,
-                "Stored new update in db.",
-            );
-        }
+        self.batch(|db| {
+            for update_with_meta in updates {
+                db.store_latest_update(&update_with_meta.signed_update)?;
+                db.store_update_metadata(update_with_meta)?;
 
-        Ok(())
+                info!(
+                    block_number = update_with_meta.metadata.block_number,
+                    timestamp = ?update_with_meta.metadata.timestamp,
+                    previous_root = ?&update_with_meta.signed_update.update.previous_root,
+                    new_root = ?&update_with_meta.signed_update.update.new_root,
+                    "Stored new update in db.",
+                );
+            }
+
+            Ok(())
+        })
     }
 
     /// Store update metadata (by update's new root)
@@ -241,15 +320,19 @@ impl NomadDB {
 
         debug!(new_root = ?new_root, metadata = ?metadata, "storing update metadata in DB");
 
-        self.store_keyed_encodable(UPDATE_META, &new_root, &metadata)
+        self.column(Column::Metadata)
+            .store_keyed_encodable(UPDATE_META, &new_root, &metadata)
     }
 
     /// Retrieve update metadata (by update's new root)
     pub fn retrieve_update_metadata(&self, new_root: H256) -> Result<Option<UpdateMeta>, DbError> {
-        self.retrieve_keyed_decodable(UPDATE_META, &new_root)
+        self.column(Column::Metadata)
+            .retrieve_keyed_decodable(UPDATE_META, &new_root)
     }
 
-    /// Store a signed update building off latest root
+    /// Store a signed update building off latest root. If it does, the
+    /// latest root moves past the updates already stored on top of it, e.g.
+    /// by a backfill storing ranges out of order.
     ///
     /// Keys --> Values:
     /// - `LATEST_ROOT` --> `root`
@@ -262,23 +345,34 @@ impl NomadDB {
             "storing update in DB"
         );
 
-        // If there is no latest root, or if this update is on the latest root
-        // update latest root
-        match self.retrieve_latest_root()? {
-            Some(root) => {
-                if root == update.update.previous_root {
-                    self.store_latest_root(update.update.new_root)?;
-                } else {
-                    debug!(
-                        "Attempted to store update not building off latest root: {:?}",
-                        update
-                    )
+        self.batch(|db| {
+            // If there is no latest root, or if this update is on the latest
+            // root, update latest root
+            let builds_on_latest = match db.retrieve_latest_root()? {
+                Some(root) => {
+                    let builds_on_latest = root == update.update.previous_root;
+                    if !builds_on_latest {
+                        debug!(
+                            "Attempted to store update not building off latest root: {:?}",
+                            update
+                        )
+                    }
+                    builds_on_latest
                 }
-            }
-            None => self.store_latest_root(update.update.new_root)?,
-        }
+                None => true,
+            };
+
+            db.store_update(update)?;
 
-        self.store_update(update)
+            if builds_on_latest {
+                let mut latest = update.update.new_root;
+                while let Some(next) = db.update_by_previous_root(latest)? {
+                    latest = next.update.new_root;
+                }
+                db.store_latest_root(latest)?;
+            }
+            Ok(())
+        })
     }
 
     /// Store an update.
@@ -287,12 +381,15 @@ impl NomadDB {
     /// - `new_root` --> `prev_root`
     /// - `prev_root` --> `update`
     pub fn store_update(&self, update: &SignedUpdate) -> Result<(), DbError> {
-        self.store_keyed_encodable(UPDATE, &update.update.previous_root, update)?;
-        self.store_keyed_encodable(
-            PREV_ROOT,
-            &update.update.new_root,
-            &update.update.previous_root,
-        )
+        self.batch(|db| {
+            let updates = db.column(Column::Updates);
+            updates.store_keyed_encodable(UPDATE, &update.update.previous_root, update)?;
+            updates.store_keyed_encodable(
+                PREV_ROOT,
+                &update.update.new_root,
+                &update.update.previous_root,
+            )
+        })
     }
 
     /// Retrieve an update by its previous root
@@ -300,12 +397,15 @@ impl NomadDB {
         &self,
         previous_root: H256,
     ) -> Result<Option<SignedUpdate>, DbError> {
-        self.retrieve_keyed_decodable(UPDATE, &previous_root)
+        self.column(Column::Updates)
+            .retrieve_keyed_decodable(UPDATE, &previous_root)
     }
 
     /// Retrieve an update by its new root
     pub fn update_by_new_root(&self, new_root: H256) -> Result<Option<SignedUpdate>, DbError> {
-        let prev_root: Option<H256> = self.retrieve_keyed_decodable(PREV_ROOT, &new_root)?;
+        let prev_root: Option<H256> = self
+            .column(Column::Updates)
+            .retrieve_keyed_decodable(PREV_ROOT, &new_root)?;
 
         match prev_root {
             Some(prev_root) => self.update_by_previous_root(prev_root),
@@ -313,9 +413,27 @@ impl NomadDB {
         }
     }
 
+    /// The `(previous_root, new_root)` of every stored update, by new root.
+    /// Unlike `update_by_previous_root`, includes all updates building off
+    /// the same root.
+    pub fn update_roots(&self) -> Result<Vec<(H256, H256)>, DbError> {
+        self.column(Column::Updates)
+            .prefix_entries(PREV_ROOT)
+            .map(|(new_root, previous_root)| -> Result<_, DbError> {
+                Ok((
+                    H256::read_from(&mut previous_root.as_slice())?,
+                    H256::read_from(&mut new_root.as_slice())?,
+                ))
+            })
+            .collect()
+    }
+
     /// Iterate over all leaves
     pub fn leaf_iterator(&self) -> PrefixIterator<H256> {
-        PrefixIterator::new(self.0.as_ref().prefix_iterator(LEAF_IDX), LEAF_IDX.as_ref())
+        PrefixIterator::new(
+            self.0.as_ref().prefix_iterator_cf(Column::Leaves, LEAF_IDX),
+            LEAF_IDX.as_ref(),
+        )
     }
 
     /// Store a proof by its leaf index
@@ -324,12 +442,19 @@ impl NomadDB {
     /// - `leaf_index` --> `proof`
     pub fn store_proof(&self, leaf_index: u32, proof: &NomadProof) -> Result<(), DbError> {
         debug!(leaf_index, "storing proof in DB");
-        self.store_keyed_encodable(PROOF, &leaf_index, proof)
+        self.column(Column::Proofs)
+            .store_keyed_encodable(PROOF, &leaf_index, proof)
+    }
+
+    /// Delete the proof of the leaf at `leaf_index`
+    pub fn delete_proof(&self, leaf_index: u32) -> Result<(), DbError> {
+        self.column(Column::Proofs).delete_keyed(PROOF, &leaf_index)
     }
 
     /// Retrieve a proof by its leaf index
     pub fn proof_by_leaf_index(&self, leaf_index: u32) -> Result<Option<NomadProof>, DbError> {
-        self.retrieve_keyed_decodable(PROOF, &leaf_index)
+        self.column(Column::Proofs)
+            .retrieve_keyed_decodable(PROOF, &leaf_index)
     }
 
     // TODO(james): this is a quick-fix for the prover_sync and I don't like it
@@ -352,7 +477,11 @@ impl NomadDB {
         previous_root: H256,
         update: &SignedUpdate,
     ) -> Result<(), DbError> {
-        self.store_keyed_encodable(UPDATER_PRODUCED_UPDATE, &previous_root, update)
+        self.column(Column::Updates).store_keyed_encodable(
+            UPDATER_PRODUCED_UPDATE,
+            &previous_root,
+            update,
+        )
     }
 
     /// Retrieve a pending update from the DB (if one exists).
@@ -360,18 +489,547 @@ impl NomadDB {
         &self,
         previous_root: H256,
     ) -> Result<Option<SignedUpdate>, DbError> {
-        self.retrieve_keyed_decodable(UPDATER_PRODUCED_UPDATE, &previous_root)
+        self.column(Column::Updates)
+            .retrieve_keyed_decodable(UPDATER_PRODUCED_UPDATE, &previous_root)
     }
 
-    /// Store prover latest root for which db has all leaves/proofs under root
-    pub fn store_prover_latest_committed(&self, root: H256) -> Result<(), DbError> {
-        self.store_encodable("", PROVER_LATEST_COMMITTED, &root)
+    /// Move the prover latest root for which db has all leaves/proofs under
+    /// root from `previous` to `root`, the root of a tree of `count` leaves.
+    /// Returns false without storing if the latest committed root is no
+    /// longer `previous`, e.g. because it was rolled back.
+    pub fn advance_prover_latest_committed(
+        &self,
+        previous: Option<H256>,
+        root: H256,
+        count: u32,
+    ) -> Result<bool, DbError> {
+        self.batch(|db| {
+            if db.retrieve_prover_latest_committed()? != previous {
+                return Ok(false);
+            }
+            let metadata = db.column(Column::Metadata);
+            metadata.store_encodable("", PROVER_LATEST_COMMITTED, &root)?;
+            metadata.store_encodable("", PROVER_COMMITTED_COUNT, &count)?;
+            Ok(true)
+        })
     }
 
     /// Retrieve prover latest root for which db has all leaves/proofs under
     /// root
     pub fn retrieve_prover_latest_committed(&self) -> Result<Option<H256>, DbError> {
-        self.retrieve_decodable("", PROVER_LATEST_COMMITTED)
+        self.column(Column::Metadata)
+            .retrieve_decodable("", PROVER_LATEST_COMMITTED)
+    }
+
+    /// Retrieve the number of leaves under the prover latest committed root,
+    /// if known
+    pub fn retrieve_prover_committed_count(&self) -> Result<Option<u32>, DbError> {
+        self.column(Column::Metadata)
+            .retrieve_decodable("", PROVER_COMMITTED_COUNT)
+    }
+
+    /// Store a prover tree snapshot if it is at the latest committed root.
+    /// Returns false without storing otherwise, e.g. if the root was rolled
+    /// back since.
+    pub fn store_committed_prover_snapshot(
+        &self,
+        snapshot: &NomadTreeSnapshot,
+    ) -> Result<bool, DbError> {
+        self.batch(|db| {
+            if db.retrieve_prover_latest_committed()? != Some(snapshot.root()) {
+                return Ok(false);
+            }
+            db.store_prover_snapshot(snapshot)?;
+            Ok(true)
+        })
+    }
+
+    /// Store a prover tree snapshot by its root and mark it as the latest.
+    /// The previous latest snapshot is removed.
+    ///
+    /// Keys --> Values:
+    /// - `root` --> `snapshot`
+    /// - `latest_snapshot` --> `root`
+    pub fn store_prover_snapshot(&self, snapshot: &NomadTreeSnapshot) -> Result<(), DbError> {
+        let root = snapshot.root();
+        let previous: Option<H256> = self
+            .column(Column::Tree)
+            .retrieve_decodable("", PROVER_LATEST_SNAPSHOT)?;
+
+        debug!(root = ?root, count = snapshot.count, "storing prover snapshot in DB");
+        self.column(Column::Tree)
+            .store_keyed_encodable(PROVER_SNAPSHOT, &root, snapshot)?;
+        self.column(Column::Tree)
+            .store_encodable("", PROVER_LATEST_SNAPSHOT, &root)?;
+
+        match previous {
+            Some(previous) if previous != root => self
+                .column(Column::Tree)
+                .delete_keyed(PROVER_SNAPSHOT, &previous),
+            _ => Ok(()),
+        }
+    }
+
+    /// Retrieve a prover tree snapshot by its root
+    pub fn prover_snapshot_by_root(
+        &self,
+        root: H256,
+    ) -> Result<Option<NomadTreeSnapshot>, DbError> {
+        self.column(Column::Tree)
+            .retrieve_keyed_decodable(PROVER_SNAPSHOT, &root)
+    }
+
+    /// Delete the most recently stored prover tree snapshot
+    fn delete_latest_prover_snapshot(&self) -> Result<(), DbError> {
+        let tree = self.column(Column::Tree);
+        let root: Option<H256> = tree.retrieve_decodable("", PROVER_LATEST_SNAPSHOT)?;
+        if let Some(root) = root {
+            tree.delete_keyed(PROVER_SNAPSHOT, &root)?;
+            tree.delete("", PROVER_LATEST_SNAPSHOT)?;
+        }
+        Ok(())
+    }
+
+    /// Retrieve the most recently stored prover tree snapshot
+    pub fn retrieve_latest_prover_snapshot(&self) -> Result<Option<NomadTreeSnapshot>, DbError> {
+        match self
+            .column(Column::Tree)
+            .retrieve_decodable("", PROVER_LATEST_SNAPSHOT)?
+        {
+            Some(root) => self.prover_snapshot_by_root(root),
+            None => Ok(None),
+        }
+    }
+
+    /// Record that the message at `leaf_index` reached a lifecycle stage.
+    /// Does nothing if the message is not in the db.
+    ///
+    /// Keys --> Values:
+    /// - `leaf_index` --> `lifecycle`
+    pub fn store_message_event(
+        &self,
+        leaf_index: u32,
+        stage: LifecycleStage,
+        event: &LifecycleEvent,
+    ) -> Result<(), DbError> {
+        // Read and write in one batch, so that concurrent events are not lost
+        self.batch(|db| {
+            let lifecycle: Option<MessageLifecycle> = match db
+                .column(Column::Messages)
+                .retrieve_keyed_decodable(LIFECYCLE, &leaf_index)?
+            {
+                Some(lifecycle) => Some(lifecycle),
+                None => db
+                    .message_by_leaf_index(leaf_index)?
+                    .map(|raw| Self::new_lifecycle(&raw))
+                    .transpose()?,
+            };
+
+            match lifecycle {
+                Some(mut lifecycle) => {
+                    debug!(leaf_index, stage = %stage, event = ?event, "storing message event in DB");
+                    lifecycle.record(stage, *event);
+                    db.column(Column::Messages).store_keyed_encodable(
+                        LIFECYCLE,
+                        &leaf_index,
+                        &lifecycle,
+                    )
+                }
+                None => {
+                    debug!(
+                        leaf_index,
+                        stage = %stage,
+                        "Attempted to store event for unknown message"
+                    );
+                    Ok(())
+                }
+            }
+        })
+    }
+
+    /// Record that the update building off `previous_root` was relayed to
+    /// the replica on `destination`. Applies to every message committed
+    /// under `previous_root` and sent to `destination`.
+    ///
+    /// Keys --> Values:
+    /// - `destination` + `previous_root` --> `event`
+    pub fn store_relayed_update(
+        &self,
+        destination: u32,
+        previous_root: H256,
+        event: &LifecycleEvent,
+    ) -> Result<(), DbError> {
+        debug!(destination, previous_root = ?previous_root, "storing relayed update in DB");
+        self.column(Column::Updates).store_encodable(
+            UPDATE_RELAYED,
+            Self::relayed_key(destination, previous_root),
+            event,
+        )
+    }
+
+    /// Retrieve the relay of the update building off `previous_root` to the
+    /// replica on `destination`
+    pub fn relayed_update_event(
+        &self,
+        destination: u32,
+        previous_root: H256,
+    ) -> Result<Option<LifecycleEvent>, DbError> {
+        self.column(Column::Updates).retrieve_decodable(
+            UPDATE_RELAYED,
+            Self::relayed_key(destination, previous_root),
+        )
+    }
+
+    /// Retrieve the lifecycle of the message at `leaf_index`.
+    ///
+    /// The updated and relayed stages are shared by all messages in an
+    /// update, so they are looked up from the update containing the message
+    /// unless recorded on the message itself. The message body is needed for
+    /// the lookup, so pruned messages only have the stages recorded on them.
+    pub fn message_lifecycle(&self, leaf_index: u32) -> Result<Option<MessageLifecycle>, DbError> {
+        let raw = self.message_by_leaf_index(leaf_index)?;
+        let stored: Option<MessageLifecycle> = self
+            .column(Column::Messages)
+            .retrieve_keyed_decodable(LIFECYCLE, &leaf_index)?;
+
+        let mut lifecycle = match (stored, &raw) {
+            (Some(lifecycle), _) => lifecycle,
+            (None, Some(raw)) => Self::new_lifecycle(raw)?,
+            (None, None) => return Ok(None),
+        };
+
+        if let Some(raw) = raw {
+            if lifecycle.updated.is_none() {
+                if let Some(update) = self.update_by_previous_root(raw.committed_root)? {
+                    let metadata = self.retrieve_update_metadata(update.update.new_root)?;
+                    lifecycle.updated = Some(metadata.map(Into::into).unwrap_or_default());
+                }
+            }
+
+            if lifecycle.relayed.is_none() {
+                lifecycle.relayed =
+                    self.relayed_update_event(lifecycle.destination, raw.committed_root)?;
+            }
+        }
+
+        Ok(Some(lifecycle))
+    }
+
+    /// Retrieve the lifecycle of a message by its leaf hash
+    pub fn message_lifecycle_by_leaf(
+        &self,
+        leaf: H256,
+    ) -> Result<Option<MessageLifecycle>, DbError> {
+        match self.message_by_leaf(leaf)? {
+            Some(raw) => self.message_lifecycle(raw.leaf_index),
+            None => Ok(None),
+        }
+    }
+
+    /// Retrieve the lifecycle of a message by its destination and nonce
+    pub fn message_lifecycle_by_nonce(
+        &self,
+        destination: u32,
+        nonce: u32,
+    ) -> Result<Option<MessageLifecycle>, DbError> {
+        match self.message_by_nonce(destination, nonce)? {
+            Some(raw) => self.message_lifecycle(raw.leaf_index),
+            None => Ok(None),
+        }
+    }
+
+    fn new_lifecycle(raw: &RawCommittedMessage) -> Result<MessageLifecycle, DbError> {
+        let message = NomadMessage::read_from(&mut raw.message.as_slice())?;
+        Ok(MessageLifecycle::new(
+            raw.leaf_index,
+            message.destination,
+            message.nonce,
+        ))
+    }
+
+    fn relayed_key(destination: u32, previous_root: H256) -> Vec<u8> {
+        let mut key = destination.to_be_bytes().to_vec();
+        key.extend_from_slice(previous_root.as_ref());
+        key
+    }
+
+    /// Store the last nonce the processor handled for `replica_domain`
+    ///
+    /// Key --> value: `replica_domain` --> `nonce`
+    pub fn store_processor_current_nonce(
+        &self,
+        replica_domain: u32,
+        nonce: u32,
+    ) -> Result<(), DbError> {
+        self.column(Column::Metadata)
+            .store_keyed_encodable(CURRENT_NONCE, &replica_domain, &nonce)
+    }
+
+    /// Retrieve the last nonce the processor handled for `replica_domain`
+    pub fn retrieve_processor_current_nonce(
+        &self,
+        replica_domain: u32,
+    ) -> Result<Option<u32>, DbError> {
+        self.column(Column::Metadata)
+            .retrieve_keyed_decodable(CURRENT_NONCE, &replica_domain)
+    }
+
+    /// Delete the proof and message body of the leaf at `leaf_index`,
+    /// keeping its leaf hash and lifecycle. Returns false if there was no
+    /// leaf at `leaf_index`.
+    ///
+    /// Keys --> Values:
+    /// - `leaf_index` --> `true`
+    pub fn prune_leaf(&self, leaf_index: u32) -> Result<bool, DbError> {
+        let leaf = match self.leaf_by_leaf_index(leaf_index)? {
+            Some(leaf) => leaf,
+            None => return Ok(false),
+        };
+
+        debug!(leaf_index, leaf = ?leaf, "pruning proof and message from DB");
+        self.batch(|db| {
+            db.column(Column::Proofs).delete_keyed(PROOF, &leaf_index)?;
+            db.column(Column::Messages).delete_keyed(MESSAGE, &leaf)?;
+            // Leaves below the watermark need no marker
+            if leaf_index >= db.retrieve_pruned_below()?.unwrap_or_default() {
+                db.column(Column::Metadata)
+                    .store_keyed_encodable(PRUNED, &leaf_index, &1u32)?;
+            }
+            Ok(true)
+        })
+    }
+
+    /// Check if the proof and message body of the leaf at `leaf_index` were
+    /// pruned
+    pub fn is_pruned(&self, leaf_index: u32) -> Result<bool, DbError> {
+        if leaf_index < self.retrieve_pruned_below()?.unwrap_or_default() {
+            return Ok(true);
+        }
+        let marker: Option<u32> = self
+            .column(Column::Metadata)
+            .retrieve_keyed_decodable(PRUNED, &leaf_index)?;
+        Ok(marker.is_some())
+    }
+
+    /// Store the index below which all leaves are pruned, dropping the
+    /// markers of individually pruned leaves it now covers
+    ///
+    /// Key --> value: `PRUNED_BELOW` --> `leaf_index`
+    pub fn store_pruned_below(&self, leaf_index: u32) -> Result<(), DbError> {
+        self.batch(|db| {
+            let metadata = db.column(Column::Metadata);
+            for index in db.retrieve_pruned_below()?.unwrap_or_default()..leaf_index {
+                metadata.delete_keyed(PRUNED, &index)?;
+            }
+            metadata.store_encodable("", PRUNED_BELOW, &leaf_index)
+        })
+    }
+
+    /// Retrieve the index below which all leaves are pruned
+    pub fn retrieve_pruned_below(&self) -> Result<Option<u32>, DbError> {
+        self.column(Column::Metadata)
+            .retrieve_decodable("", PRUNED_BELOW)
+    }
+
+    /// Store the index below which the pruner checked every leaf for
+    /// processing
+    ///
+    /// Key --> value: `PRUNE_SCANNED_BELOW` --> `leaf_index`
+    pub fn store_prune_scanned_below(&self, leaf_index: u32) -> Result<(), DbError> {
+        self.column(Column::Metadata)
+            .store_encodable("", PRUNE_SCANNED_BELOW, &leaf_index)
+    }
+
+    /// Retrieve the index below which the pruner checked every leaf for
+    /// processing
+    pub fn retrieve_prune_scanned_below(&self) -> Result<Option<u32>, DbError> {
+        self.column(Column::Metadata)
+            .retrieve_decodable("", PRUNE_SCANNED_BELOW)
+    }
+
+    /// Store the index below which all proofs were pushed to S3
+    ///
+    /// Key --> value: `PROOFS_PUSHED_BELOW` --> `leaf_index`
+    pub fn store_proofs_pushed_below(&self, leaf_index: u32) -> Result<(), DbError> {
+        self.column(Column::Metadata)
+            .store_encodable("", PROOFS_PUSHED_BELOW, &leaf_index)
+    }
+
+    /// Retrieve the index below which all proofs were pushed to S3
+    pub fn retrieve_proofs_pushed_below(&self) -> Result<Option<u32>, DbError> {
+        self.column(Column::Metadata)
+            .retrieve_decodable("", PROOFS_PUSHED_BELOW)
+    }
+
+    /// Delete the messages dispatched above `block_number`, newest first,
+    /// including those stored past a gap, and move the latest leaf index
+    /// back below them. Messages are dispatched in leaf index order, so this
+    /// stops at the first message dispatched at or below `block_number`, or
+    /// with no known dispatch block. The prover state is rewound in the same
+    /// batch, see `rollback_prover_to`. Returns the deleted leaf indices.
+    pub fn rollback_messages_above(&self, block_number: u64) -> Result<Vec<u32>, DbError> {
+        self.batch(|db| {
+            let messages = db.column(Column::Messages);
+            let leaves = db.column(Column::Leaves);
+
+            let mut rollback = vec![];
+            for (key, value) in
+                messages.prefix_entries_from(LIFECYCLE, &u32::MAX, Direction::Reverse)
+            {
+                let leaf_index = u32::read_from(&mut key.as_slice())?;
+                let lifecycle = MessageLifecycle::read_from(&mut value.as_slice())?;
+                let dispatched_at = lifecycle.dispatched.and_then(|event| event.block_number);
+                if dispatched_at.map_or(true, |dispatched_at| dispatched_at <= block_number) {
+                    break;
+                }
+                rollback.push((leaf_index, lifecycle, dispatched_at));
+            }
+
+            let mut rolled_back = vec![];
+            for (leaf_index, lifecycle, dispatched_at) in rollback {
+                debug!(leaf_index, dispatched_at = ?dispatched_at, "rolling back message in DB");
+                if let Some(leaf) = db.leaf_by_leaf_index(leaf_index)? {
+                    messages.delete_keyed(MESSAGE, &leaf)?;
+                }
+                let destination_and_nonce =
+                    utils::destination_and_nonce(lifecycle.destination, lifecycle.nonce);
+                leaves.delete_keyed(LEAF, &destination_and_nonce)?;
+                leaves.delete_keyed(LEAF, &leaf_index)?;
+                messages.delete_keyed(LIFECYCLE, &leaf_index)?;
+                db.column(Column::Proofs).delete_keyed(PROOF, &leaf_index)?;
+                db.column(Column::Metadata)
+                    .delete_keyed(PRUNED, &leaf_index)?;
+
+                rolled_back.push(leaf_index);
+            }
+
+            if let Some(lowest) = rolled_back.last().copied() {
+                // messages past a gap did not move the latest leaf index
+                if db.retrieve_latest_leaf_index()? >= Some(lowest) {
+                    match lowest.checked_sub(1) {
+                        Some(leaf_index) => db.update_latest_leaf_index(leaf_index)?,
+                        None => db.column(Column::Metadata).delete("", LATEST_LEAF_INDEX)?,
+                    }
+                }
+                // the leaves stored again must be checked again
+                if db.retrieve_prune_scanned_below()? > Some(lowest) {
+                    db.store_prune_scanned_below(lowest)?;
+                }
+                db.rollback_prover_to(lowest)?;
+            }
+            Ok(rolled_back)
+        })
+    }
+
+    /// Delete the updates emitted above `block_number`, newest first, and
+    /// move the latest root back to the new root of the last one kept.
+    /// Stops at the first update emitted at or below `block_number`, or
+    /// without metadata. The prover latest committed root, its snapshot and
+    /// the proofs against deleted roots are rolled back in the same batch.
+    /// Returns the new roots of the deleted updates.
+    pub fn rollback_updates_above(&self, block_number: u64) -> Result<Vec<H256>, DbError> {
+        self.batch(|db| {
+            let mut rolled_back = vec![];
+            let committed = db.retrieve_prover_latest_committed()?;
+            let mut kept_committed = committed;
+
+            while let Some(root) = db.retrieve_latest_root()? {
+                match db.retrieve_update_metadata(root)? {
+                    Some(metadata) if metadata.block_number > block_number => {}
+                    _ => break,
+                }
+                let update = match db.update_by_new_root(root)? {
+                    Some(update) => update,
+                    None => break,
+                };
+
+                debug!(new_root = ?root, "rolling back update in DB");
+                let previous_root = update.update.previous_root;
+                let updates = db.column(Column::Updates);
+                updates.delete_keyed(UPDATE, &previous_root)?;
+                updates.delete_keyed(PREV_ROOT, &root)?;
+                db.column(Column::Metadata)
+                    .delete_keyed(UPDATE_META, &root)?;
+                db.store_latest_root(previous_root)?;
+
+                // the prover walks back with the updates
+                if kept_committed == Some(root) {
+                    kept_committed = Some(previous_root).filter(|root| !root.is_zero());
+                }
+                rolled_back.push(root);
+            }
+
+            if rolled_back.is_empty() {
+                return Ok(rolled_back);
+            }
+
+            if kept_committed != committed {
+                let metadata = db.column(Column::Metadata);
+                match kept_committed {
+                    Some(root) => metadata.store_encodable("", PROVER_LATEST_COMMITTED, &root)?,
+                    None => metadata.delete("", PROVER_LATEST_COMMITTED)?,
+                }
+                // the size of the tree under the kept root is not known
+                metadata.delete("", PROVER_COMMITTED_COUNT)?;
+            }
+            let snapshot_root: Option<H256> = db
+                .column(Column::Tree)
+                .retrieve_decodable("", PROVER_LATEST_SNAPSHOT)?;
+            if snapshot_root.map_or(false, |root| rolled_back.contains(&root)) {
+                db.delete_latest_prover_snapshot()?;
+            }
+
+            // Leaves are proven against the earliest root committing them,
+            // so proofs against deleted roots are the latest ones
+            let proofs = db.column(Column::Proofs);
+            let mut stale = vec![];
+            for (key, value) in proofs.prefix_entries_from(PROOF, &u32::MAX, Direction::Reverse) {
+                let proof = NomadProof::read_from(&mut value.as_slice())?;
+                if !rolled_back.contains(&proof.root()) {
+                    break;
+                }
+                stale.push(u32::read_from(&mut key.as_slice())?);
+            }
+            for leaf_index in stale {
+                debug!(leaf_index, "rolling back proof in DB");
+                proofs.delete_keyed(PROOF, &leaf_index)?;
+            }
+
+            Ok(rolled_back)
+        })
+    }
+
+    /// Rewind the prover state to hold no leaf at or after `count`. If the
+    /// latest committed root may cover later leaves, it moves back to the
+    /// latest snapshot when that holds fewer leaves, or is cleared along
+    /// with the snapshot, so that the prover rebuilds its tree.
+    fn rollback_prover_to(&self, count: u32) -> Result<(), DbError> {
+        let committed_count = self.retrieve_prover_committed_count()?;
+        if self.retrieve_prover_latest_committed()?.is_none()
+            || committed_count.map_or(false, |committed| committed <= count)
+        {
+            return Ok(());
+        }
+
+        let metadata = self.column(Column::Metadata);
+        match self.retrieve_latest_prover_snapshot()? {
+            Some(snapshot) if snapshot.count <= count as usize => {
+                info!(
+                    root = ?snapshot.root(),
+                    count = snapshot.count,
+                    "Rolling prover back to its snapshot"
+                );
+                metadata.store_encodable("", PROVER_LATEST_COMMITTED, &snapshot.root())?;
+                metadata.store_encodable("", PROVER_COMMITTED_COUNT, &(snapshot.count as u32))?;
+            }
+            _ => {
+                info!("Rolling prover back to an empty tree");
+                self.delete_latest_prover_snapshot()?;
+                metadata.delete("", PROVER_LATEST_COMMITTED)?;
+                metadata.delete("", PROVER_COMMITTED_COUNT)?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -379,8 +1037,12 @@ impl NomadDB {
 mod test {
     use super::*;
     use ethers::types::H256;
-    use nomad_core::{accumulator::Proof, Encode, NomadMessage, RawCommittedMessage};
-    use nomad_test::test_utils::run_test_db;
+    use nomad_core::{
+        accumulator::{Merkle, NomadTree, Proof, StoredTree, Tree, TREE_DEPTH},
+        db::DbNodeStore,
+        Encode, MessageMeta, NomadMessage, RawCommittedMessage, Update,
+    };
+    use nomad_test::test_utils::{dispatched_message, header, run_test_db, update};
 
     #[tokio::test]
     async fn db_stores_and_retrieves_messages() {
@@ -442,4 +1104,355 @@ mod test {
         })
         .await;
     }
+
+    #[tokio::test]
+    async fn db_batches_writes_atomically() {
+        run_test_db(|db| async move {
+            let db = NomadDB::new("home_1", db);
+            let proof = Proof {
+                leaf: H256::from_low_u64_be(15),
+                index: 32,
+                path: Default::default(),
+            };
+
+            // A failed batch writes nothing
+            let result: Result<()> = db.batch(|db| {
+                db.store_proof(13, &proof)?;
+                assert!(db.proof_by_leaf_index(13)?.is_some());
+                color_eyre::eyre::bail!("interrupted")
+            });
+            assert!(result.is_err());
+            assert!(db.proof_by_leaf_index(13).unwrap().is_none());
+
+            // A successful batch is visible once committed
+            db.batch(|batch| -> Result<(), DbError> {
+                batch.store_proof(13, &proof)?;
+                batch.update_latest_leaf_index(13)?;
+                assert!(db.proof_by_leaf_index(13)?.is_none());
+                Ok(())
+            })
+            .unwrap();
+            assert_eq!(db.proof_by_leaf_index(13).unwrap(), Some(proof));
+            assert_eq!(db.retrieve_latest_leaf_index().unwrap(), Some(13));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn db_backs_stored_tree() {
+        run_test_db(|db| async move {
+            let home_name = "home_1".to_owned();
+            let db = NomadDB::new(home_name, db);
+
+            let leaves: Vec<_> = (1..=10).map(H256::from_low_u64_be).collect();
+
+            let store = DbNodeStore::new(db.column(Column::Tree));
+            let mut stored = StoredTree::<_, TREE_DEPTH>::new(store.clone()).unwrap();
+            for leaf in leaves[..6].iter() {
+                stored.push_leaf(*leaf).unwrap();
+            }
+
+            // reopen over the same db and keep going
+            let mut stored = StoredTree::<_, TREE_DEPTH>::new(store).unwrap();
+            for leaf in leaves[6..].iter() {
+                stored.push_leaf(*leaf).unwrap();
+            }
+
+            let full = NomadTree::from_leaves(&leaves);
+            assert_eq!(stored.count(), leaves.len());
+            assert_eq!(stored.root(), full.root());
+            assert_eq!(stored.prove(7).unwrap(), full.prove(7).unwrap());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn db_stores_and_retrieves_prover_snapshots() {
+        run_test_db(|db| async move {
+            let home_name = "home_1".to_owned();
+            let db = NomadDB::new(home_name, db);
+            assert!(db.retrieve_latest_prover_snapshot().unwrap().is_none());
+
+            let leaves: Vec<_> = (1..=10).map(H256::from_low_u64_be).collect();
+            let first = NomadTree::from_leaves(&leaves[..4]).snapshot();
+            let tree = NomadTree::from_leaves(&leaves);
+            let second = tree.snapshot();
+
+            db.store_prover_snapshot(&first).unwrap();
+            db.store_prover_snapshot(&second).unwrap();
+
+            let latest = db.retrieve_latest_prover_snapshot().unwrap().unwrap();
+            assert_eq!(latest, second);
+            assert_eq!(Tree::from_snapshot(latest).unwrap(), tree);
+
+            // the previous snapshot was replaced
+            assert!(db.prover_snapshot_by_root(first.root()).unwrap().is_none());
+            assert_eq!(
+                db.prover_snapshot_by_root(tree.root()).unwrap().unwrap(),
+                second
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn db_tracks_message_lifecycle() {
+        run_test_db(|db| async move {
+            let home_name = "home_1".to_owned();
+            let db = NomadDB::new(home_name, db);
+
+            let m = NomadMessage {
+                origin: 10,
+                sender: H256::from_low_u64_be(4),
+                nonce: 11,
+                destination: 12,
+                recipient: H256::from_low_u64_be(5),
+                body: vec![1, 2, 3],
+            };
+            let committed_root = H256::from_low_u64_be(3);
+            let message = RawCommittedMessage {
+                leaf_index: 0,
+                committed_root,
+                message: m.to_vec(),
+            };
+            let dispatch = MessageMeta {
+                block_number: 100,
+                transaction_hash: H256::from_low_u64_be(6),
+                timestamp: Some(1_650_000_000),
+                block_hash: H256::from_low_u64_be(100),
+                parent_hash: H256::from_low_u64_be(99),
+            };
+
+            assert!(db.message_lifecycle(0).unwrap().is_none());
+
+            db.store_messages(&[RawCommittedMessageWithMeta {
+                raw_message: message.clone(),
+                metadata: dispatch,
+            }])
+            .unwrap();
+
+            let lifecycle = db.message_lifecycle(0).unwrap().unwrap();
+            assert_eq!(lifecycle.stage(), Some(LifecycleStage::Dispatched));
+            assert_eq!(lifecycle.dispatched, Some(dispatch.into()));
+            assert_eq!((lifecycle.destination, lifecycle.nonce), (12, 11));
+
+            // update-level stages are shared through the containing update
+            let update = update(committed_root, H256::from_low_u64_be(7), header(105, 0));
+            db.store_updates_and_meta(&[update.clone()]).unwrap();
+
+            let relay = LifecycleEvent::submitted(H256::from_low_u64_be(8));
+            db.store_relayed_update(12, committed_root, &relay).unwrap();
+
+            let process = LifecycleEvent::submitted(H256::from_low_u64_be(9));
+            db.store_message_event(0, LifecycleStage::Proven, &process)
+                .unwrap();
+            db.store_message_event(0, LifecycleStage::Processed, &process)
+                .unwrap();
+
+            let lifecycle = db.message_lifecycle_by_nonce(12, 11).unwrap().unwrap();
+            assert_eq!(lifecycle.stage(), Some(LifecycleStage::Processed));
+            assert_eq!(lifecycle.updated, Some(update.metadata.into()));
+            assert_eq!(lifecycle.relayed, Some(relay));
+            assert_eq!(lifecycle.proven, Some(process));
+            assert_eq!(
+                db.message_lifecycle_by_leaf(message.leaf()).unwrap(),
+                Some(lifecycle)
+            );
+
+            // events for unknown messages are ignored
+            db.store_message_event(1, LifecycleStage::Processed, &process)
+                .unwrap();
+            assert!(db.message_lifecycle(1).unwrap().is_none());
+
+            // concurrent events for the same message are all recorded
+            let stages = [LifecycleStage::Proven, LifecycleStage::Processed];
+            for _ in 0..20 {
+                db.column(Column::Messages)
+                    .delete_keyed(LIFECYCLE, &0u32)
+                    .unwrap();
+                let writers: Vec<_> = stages
+                    .iter()
+                    .map(|stage| {
+                        let (db, stage) = (db.clone(), *stage);
+                        std::thread::spawn(move || {
+                            db.store_message_event(0, stage, &process).unwrap()
+                        })
+                    })
+                    .collect();
+                writers
+                    .into_iter()
+                    .for_each(|writer| writer.join().unwrap());
+
+                let lifecycle = db.message_lifecycle(0).unwrap().unwrap();
+                assert_eq!(lifecycle.proven, Some(process));
+                assert_eq!(lifecycle.processed, Some(process));
+            }
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn db_rolls_back_reorged_events() {
+        run_test_db(|db| async move {
+            let db = NomadDB::new("home_1", db);
+
+            let messages: Vec<_> = (0..3u32)
+                .map(|i| dispatched_message(i, &[], header(100 + i as u64, 0)))
+                .collect();
+            db.store_messages(&messages).unwrap();
+
+            let updates: Vec<_> = (0..3u64)
+                .map(|i| {
+                    update(
+                        H256::from_low_u64_be(i),
+                        H256::from_low_u64_be(i + 1),
+                        header(100 + i, 0),
+                    )
+                })
+                .collect();
+            db.store_updates_and_meta(&updates).unwrap();
+
+            assert_eq!(db.rollback_messages_above(100).unwrap(), vec![2, 1]);
+            assert_eq!(db.retrieve_latest_leaf_index().unwrap(), Some(0));
+            assert!(db.leaf_by_leaf_index(1).unwrap().is_none());
+            assert!(db.message_by_nonce(2, 2).unwrap().is_none());
+            assert!(db.message_lifecycle(1).unwrap().is_none());
+            assert!(db.message_by_leaf_index(0).unwrap().is_some());
+
+            assert_eq!(
+                db.rollback_updates_above(101).unwrap(),
+                vec![H256::from_low_u64_be(3)]
+            );
+            assert_eq!(
+                db.retrieve_latest_root().unwrap(),
+                Some(H256::from_low_u64_be(2))
+            );
+            assert!(db
+                .update_by_previous_root(H256::from_low_u64_be(2))
+                .unwrap()
+                .is_none());
+
+            // Re-indexed events build off the rolled back state
+            db.store_messages(&messages[1..]).unwrap();
+            assert_eq!(db.retrieve_latest_leaf_index().unwrap(), Some(2));
+            db.store_updates_and_meta(&updates[2..]).unwrap();
+            assert_eq!(
+                db.retrieve_latest_root().unwrap(),
+                Some(H256::from_low_u64_be(3))
+            );
+
+            // Rolling back everything clears the latest leaf index
+            assert_eq!(db.rollback_messages_above(0).unwrap(), vec![2, 1, 0]);
+            assert!(db.retrieve_latest_leaf_index().unwrap().is_none());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn db_rolls_back_gapped_leaves_and_prover_state() {
+        run_test_db(|db| async move {
+            let db = NomadDB::new("home_1", db);
+
+            let messages: Vec<_> = (0..5u32)
+                .map(|i| dispatched_message(i, &[], header(100 + i as u64, 0)))
+                .collect();
+            let leaves: Vec<_> = messages.iter().map(|m| m.raw_message.leaf()).collect();
+            let tree = NomadTree::from_leaves(&leaves);
+            let (root_2, root_5) = (tree.root_at(2).unwrap(), tree.root_at(5).unwrap());
+
+            // leaf 2 is missing
+            db.store_messages(&messages[..2]).unwrap();
+            db.store_messages(&messages[3..]).unwrap();
+            assert_eq!(db.retrieve_latest_leaf_index().unwrap(), Some(1));
+
+            let updates: Vec<_> = [(H256::zero(), root_2, 100), (root_2, root_5, 103)]
+                .iter()
+                .map(|(previous_root, new_root, block_number)| {
+                    update(*previous_root, *new_root, header(*block_number, 0))
+                })
+                .collect();
+            db.store_updates_and_meta(&updates).unwrap();
+
+            // the prover committed both roots, snapshotting the first
+            for (i, count) in [(0, 2), (1, 2), (3, 5), (4, 5)] {
+                db.store_proof(i, &tree.prove_at(i as usize, count).unwrap())
+                    .unwrap();
+            }
+            assert!(db.advance_prover_latest_committed(None, root_2, 2).unwrap());
+            let snapshot = NomadTree::from_leaves(&leaves[..2]).snapshot();
+            assert!(db.store_committed_prover_snapshot(&snapshot).unwrap());
+            assert!(db
+                .advance_prover_latest_committed(Some(root_2), root_5, 5)
+                .unwrap());
+            assert!(!db.advance_prover_latest_committed(None, root_5, 5).unwrap());
+
+            // the prover walks back to the kept root, with its proofs
+            assert_eq!(db.rollback_updates_above(102).unwrap(), vec![root_5]);
+            assert_eq!(db.retrieve_prover_latest_committed().unwrap(), Some(root_2));
+            assert!(db.retrieve_prover_committed_count().unwrap().is_none());
+            assert!(db.proof_by_leaf_index(4).unwrap().is_none());
+            assert!(db.proof_by_leaf_index(3).unwrap().is_none());
+            assert!(db.proof_by_leaf_index(1).unwrap().is_some());
+            assert!(db.retrieve_latest_prover_snapshot().unwrap().is_some());
+
+            // leaves past the gap are rolled back too, and the snapshot
+            // holds rolled back leaves
+            assert_eq!(db.rollback_messages_above(100).unwrap(), vec![4, 3, 1]);
+            assert_eq!(db.retrieve_latest_leaf_index().unwrap(), Some(0));
+            assert!(db.message_lifecycle(4).unwrap().is_none());
+            assert!(db.proof_by_leaf_index(1).unwrap().is_none());
+            assert!(db.retrieve_prover_latest_committed().unwrap().is_none());
+            assert!(db.retrieve_latest_prover_snapshot().unwrap().is_none());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn db_moves_latest_root_over_stored_updates() {
+        run_test_db(|db| async move {
+            let db = NomadDB::new("home_1", db);
+
+            let updates: Vec<_> = (0..4u64)
+                .map(|i| {
+                    update(
+                        H256::from_low_u64_be(i),
+                        H256::from_low_u64_be(i + 1),
+                        header(i, 0),
+                    )
+                    .signed_update
+                })
+                .collect();
+
+            // Updates stored out of order don't move the latest root...
+            db.store_latest_update(&updates[0]).unwrap();
+            db.store_latest_update(&updates[2]).unwrap();
+            db.store_latest_update(&updates[3]).unwrap();
+            assert_eq!(
+                db.retrieve_latest_root().unwrap(),
+                Some(H256::from_low_u64_be(1))
+            );
+
+            // ...until the update before them is stored
+            db.store_latest_update(&updates[1]).unwrap();
+            assert_eq!(
+                db.retrieve_latest_root().unwrap(),
+                Some(H256::from_low_u64_be(4))
+            );
+
+            // A conflicting update is listed alongside the one it replaced
+            let conflicting = SignedUpdate {
+                update: Update {
+                    new_root: H256::from_low_u64_be(7),
+                    ..updates[2].update
+                },
+                ..updates[2].clone()
+            };
+            db.store_update(&conflicting).unwrap();
+            let roots = db.update_roots().unwrap();
+            assert_eq!(roots.len(), 5);
+            assert!(roots.contains(&(H256::from_low_u64_be(2), H256::from_low_u64_be(3))));
+            assert!(roots.contains(&(H256::from_low_u64_be(2), H256::from_low_u64_be(7))));
+        })
+        .await;
+    }
 }
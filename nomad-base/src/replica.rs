@@ -85,6 +85,13 @@ impl Replica for CachingReplica {
         self.replica.message_status(leaf).await
     }
 
+    async fn process_transaction(
+        &self,
+        leaf: H256,
+    ) -> Result<Option<H256>, ChainCommunicationError> {
+        self.replica.process_transaction(leaf).await
+    }
+
     async fn acceptable_root(&self, root: H256) -> Result<bool, ChainCommunicationError> {
         self.replica.acceptable_root(root).await
     }
@@ -265,6 +272,17 @@ impl Replica for ReplicaVariants {
         }
     }
 
+    async fn process_transaction(
+        &self,
+        leaf: H256,
+    ) -> Result<Option<H256>, ChainCommunicationError> {
+        match self {
+            ReplicaVariants::Ethereum(replica) => replica.process_transaction(leaf).await,
+            ReplicaVariants::Mock(mock_replica) => mock_replica.process_transaction(leaf).await,
+            ReplicaVariants::Other(replica) => replica.process_transaction(leaf).await,
+        }
+    }
+
     async fn prove_and_process(
         &self,
         message: &NomadMessage,
@@ -0,0 +1,317 @@
+use color_eyre::Result;
+use nomad_xyz_configuration::agent::processor::RetentionConfig;
+use std::{
+    cmp::{max, min},
+    fmt,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{task::JoinHandle, time::sleep};
+use tracing::{debug, info, info_span, instrument::Instrumented, Instrument};
+
+use crate::NomadDB;
+
+/// Returns the replica domains the processor handles messages for
+type Domains = Arc<dyn Fn() -> Vec<u32> + Send + Sync>;
+
+/// Prunes proofs and message bodies from a `NomadDB` according to a
+/// `RetentionConfig`. Leaf hashes are kept, so the tree can still be rebuilt.
+#[derive(Clone)]
+pub struct Pruner {
+    db: NomadDB,
+    config: RetentionConfig,
+    require_pushed: bool,
+    processor_domains: Option<Domains>,
+}
+
+impl fmt::Debug for Pruner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pruner")
+            .field("db", &self.db)
+            .field("config", &self.config)
+            .field("require_pushed", &self.require_pushed)
+            .finish()
+    }
+}
+
+impl Pruner {
+    /// Instantiate a new `Pruner`
+    pub fn new(db: NomadDB, config: RetentionConfig) -> Self {
+        Self {
+            db,
+            config,
+            require_pushed: false,
+            processor_domains: None,
+        }
+    }
+
+    /// Only prune leaves whose proofs were pushed to S3. See
+    /// `NomadDB::store_proofs_pushed_below`.
+    pub fn require_pushed(mut self, require_pushed: bool) -> Self {
+        self.require_pushed = require_pushed;
+        self
+    }
+
+    /// Never prune the first message the processor has yet to handle for
+    /// any of the replica domains `domains` returns, or any leaf after it.
+    /// `domains` is called on every pass, so it may follow reloaded
+    /// settings.
+    pub fn keep_unprocessed(
+        mut self,
+        domains: impl Fn() -> Vec<u32> + Send + Sync + 'static,
+    ) -> Self {
+        self.processor_domains = Some(Arc::new(domains));
+        self
+    }
+
+    /// Leaf index of the first message the processor has yet to handle for
+    /// any of `domains`. None if it handled every known message.
+    fn first_unprocessed(&self, domains: Vec<u32>) -> Result<Option<u32>> {
+        let mut first: Option<u32> = None;
+        for domain in domains {
+            let next_nonce = self
+                .db
+                .retrieve_processor_current_nonce(domain)?
+                .map(|nonce| nonce + 1)
+                .unwrap_or_default();
+            if let Some(message) = self.db.message_by_nonce(domain, next_nonce)? {
+                first =
+                    Some(first.map_or(message.leaf_index, |first| min(first, message.leaf_index)));
+            }
+        }
+        Ok(first)
+    }
+
+    /// Run a single pruning pass. Returns the number of leaves pruned.
+    pub fn prune(&self) -> Result<u32> {
+        let latest = match self.db.retrieve_latest_leaf_index()? {
+            Some(latest) => latest,
+            None => return Ok(0),
+        };
+
+        // Leaves at or above the limit are never pruned
+        let mut limit = latest + 1;
+        if self.require_pushed {
+            let pushed = self.db.retrieve_proofs_pushed_below()?;
+            limit = min(limit, pushed.unwrap_or_default());
+        }
+        if let Some(domains) = &self.processor_domains {
+            if let Some(first) = self.first_unprocessed(domains())? {
+                limit = min(limit, first);
+            }
+        }
+
+        let mut pruned = 0;
+        let start = self.db.retrieve_pruned_below()?.unwrap_or_default();
+
+        if let Some(keep_leaves) = self.config.keep_leaves {
+            let below = min(limit, latest.saturating_sub(keep_leaves));
+            for leaf_index in start..below {
+                if self.db.prune_leaf(leaf_index)? {
+                    pruned += 1;
+                }
+            }
+            if below > start {
+                self.db.store_pruned_below(below)?;
+            }
+        }
+
+        if self.config.prune_processed {
+            // Leaves are checked once. Below the limit, the processor handled
+            // every message it will handle, so leaves found unprocessed stay
+            // unprocessed, e.g. those sent to other replicas. They are left
+            // to `keep_leaves`
+            let pruned_below = self.db.retrieve_pruned_below()?.unwrap_or_default();
+            let scanned_below = self.db.retrieve_prune_scanned_below()?;
+            let start = max(pruned_below, scanned_below.unwrap_or_default());
+
+            // Advance the watermark over leading pruned leaves, so that
+            // `is_pruned` needs no marker for them
+            let mut watermark = pruned_below;
+            for leaf_index in start..limit {
+                let pruned_before = self.db.is_pruned(leaf_index)?;
+                let processed = self
+                    .db
+                    .message_lifecycle(leaf_index)?
+                    .map(|lifecycle| lifecycle.processed.is_some())
+                    .unwrap_or_default();
+
+                if !pruned_before && processed && self.db.prune_leaf(leaf_index)? {
+                    pruned += 1;
+                }
+                if watermark == leaf_index && (pruned_before || processed) {
+                    watermark += 1;
+                }
+            }
+            if watermark > pruned_below {
+                self.db.store_pruned_below(watermark)?;
+            }
+            if limit > start {
+                self.db.store_prune_scanned_below(limit)?;
+            }
+        }
+
+        Ok(pruned)
+    }
+
+    /// Spawn a task pruning every `interval` seconds
+    pub fn spawn(self) -> Instrumented<JoinHandle<Result<()>>> {
+        let span = info_span!(
+            "Pruner",
+            keep_leaves = ?self.config.keep_leaves,
+            prune_processed = self.config.prune_processed,
+        );
+
+        tokio::spawn(async move {
+            loop {
+                // Passes read and write the db synchronously
+                let pruner = self.clone();
+                let pruned = tokio::task::spawn_blocking(move || pruner.prune()).await??;
+                if pruned > 0 {
+                    info!(pruned, "Pruned proofs and messages");
+                } else {
+                    debug!("Nothing to prune");
+                }
+
+                sleep(Duration::from_secs(self.config.interval)).await;
+            }
+        })
+        .instrument(span)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nomad_core::{accumulator::NomadProof, LifecycleEvent, LifecycleStage};
+    use nomad_test::test_utils::{dispatched_message, header, run_test_db};
+
+    fn store_leaves(db: &NomadDB, count: u32) {
+        for leaf_index in 0..count {
+            let raw =
+                dispatched_message(leaf_index, &[], header(leaf_index as u64 + 1, 0)).raw_message;
+            db.store_latest_message(&raw).unwrap();
+            db.store_message_event(
+                leaf_index,
+                LifecycleStage::Dispatched,
+                &LifecycleEvent::default(),
+            )
+            .unwrap();
+            db.store_proof(
+                leaf_index,
+                &NomadProof {
+                    leaf: raw.leaf(),
+                    index: leaf_index as usize,
+                    path: Default::default(),
+                },
+            )
+            .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn it_prunes_old_and_processed_leaves() {
+        run_test_db(|db| async move {
+            let db = NomadDB::new("home_1", db);
+            store_leaves(&db, 10);
+            db.store_message_event(6, LifecycleStage::Processed, &LifecycleEvent::default())
+                .unwrap();
+
+            let pruner = Pruner::new(
+                db.clone(),
+                RetentionConfig {
+                    keep_leaves: Some(5),
+                    prune_processed: true,
+                    interval: 1,
+                },
+            );
+            assert_eq!(pruner.prune().unwrap(), 5);
+
+            // 0..4 are more than 5 leaves behind 9, and 6 was processed
+            for leaf_index in 0..10 {
+                let pruned = leaf_index < 4 || leaf_index == 6;
+                assert_eq!(db.is_pruned(leaf_index).unwrap(), pruned);
+                assert_eq!(
+                    db.proof_by_leaf_index(leaf_index).unwrap().is_none(),
+                    pruned
+                );
+                assert_eq!(
+                    db.message_by_leaf_index(leaf_index).unwrap().is_none(),
+                    pruned
+                );
+                // leaves and lifecycles are kept
+                assert!(db.leaf_by_leaf_index(leaf_index).unwrap().is_some());
+                assert!(db.message_lifecycle(leaf_index).unwrap().is_some());
+            }
+
+            // nothing left to do
+            assert_eq!(pruner.prune().unwrap(), 0);
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn it_keeps_messages_until_processed() {
+        run_test_db(|db| async move {
+            let db = NomadDB::new("home_1", db);
+            store_leaves(&db, 10);
+            let process = |leaf_index: u32| {
+                db.store_message_event(
+                    leaf_index,
+                    LifecycleStage::Processed,
+                    &LifecycleEvent::default(),
+                )
+                .unwrap();
+                db.store_processor_current_nonce(2, leaf_index).unwrap();
+            };
+
+            // The processor handled nonces 0..=2 for domain 2
+            (0..3).for_each(&process);
+            let pruner = Pruner::new(
+                db.clone(),
+                RetentionConfig {
+                    keep_leaves: None,
+                    prune_processed: true,
+                    interval: 1,
+                },
+            )
+            .keep_unprocessed(|| vec![2]);
+            assert_eq!(pruner.prune().unwrap(), 3);
+            assert_eq!(db.retrieve_pruned_below().unwrap(), Some(3));
+            assert!(db.message_by_leaf_index(3).unwrap().is_some());
+
+            // Later passes pick up from there
+            (3..5).for_each(&process);
+            assert_eq!(pruner.prune().unwrap(), 2);
+            assert_eq!(db.retrieve_pruned_below().unwrap(), Some(5));
+            assert_eq!(db.retrieve_prune_scanned_below().unwrap(), Some(5));
+            assert!(db.message_by_leaf_index(5).unwrap().is_some());
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn it_waits_for_proofs_to_be_pushed() {
+        run_test_db(|db| async move {
+            let db = NomadDB::new("home_1", db);
+            store_leaves(&db, 10);
+
+            let pruner = Pruner::new(
+                db.clone(),
+                RetentionConfig {
+                    keep_leaves: Some(0),
+                    prune_processed: false,
+                    interval: 1,
+                },
+            )
+            .require_pushed(true);
+            assert_eq!(pruner.prune().unwrap(), 0);
+
+            db.store_proofs_pushed_below(3).unwrap();
+            assert_eq!(pruner.prune().unwrap(), 3);
+            assert_eq!(db.retrieve_pruned_below().unwrap(), Some(3));
+            assert!(db.proof_by_leaf_index(3).unwrap().is_some());
+        })
+        .await
+    }
+}
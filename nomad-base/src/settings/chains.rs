@@ -13,12 +13,32 @@ use crate::{
 };
 
 /// Chain specific page settings for indexing
-#[derive(Clone, Debug, Deserialize, Default)]
+#[derive(Clone, Debug, Deserialize, Default, PartialEq)]
 pub struct PageSettings {
     /// What block to start indexing at
     pub from: u32,
     /// Index page size
     pub page_size: u32,
+    /// Smallest page size to shrink to when the RPC rejects a range
+    #[serde(default)]
+    pub min_page_size: Option<u32>,
+    /// Largest page size to grow to while pages are empty
+    #[serde(default)]
+    pub max_page_size: Option<u32>,
+}
+
+impl PageSettings {
+    /// Smallest page size. Defaults to a single block
+    pub fn min_page_size(&self) -> u32 {
+        self.min_page_size.unwrap_or(1).max(1)
+    }
+
+    /// Largest page size. Defaults to `page_size`
+    pub fn max_page_size(&self) -> u32 {
+        self.max_page_size
+            .unwrap_or(self.page_size)
+            .max(self.min_page_size())
+    }
 }
 
 /// What type of chain setup you are retrieving
@@ -45,7 +65,7 @@ pub enum ChainSetupType<'a> {
 
 /// A chain setup is a domain ID, an address on that chain (where the home or
 /// replica is deployed) and details for connecting to the chain API.
-#[derive(Clone, Debug, Deserialize, Default)]
+#[derive(Clone, Debug, Deserialize, Default, PartialEq)]
 pub struct ChainSetup {
     /// Chain name
     pub name: String,
@@ -105,6 +125,8 @@ impl ChainSetup {
                 let page_settings = PageSettings {
                     from: core.deploy_height,
                     page_size: domain.specs.index_page_size,
+                    min_page_size: domain.specs.min_index_page_size,
+                    max_page_size: domain.specs.max_index_page_size,
                 };
 
                 (address, page_settings)
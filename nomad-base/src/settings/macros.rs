@@ -24,11 +24,45 @@ macro_rules! decl_settings {
                 }
             }
 
+            impl AsMut<nomad_base::Settings> for [<$name Settings>] {
+                fn as_mut(&mut self) -> &mut nomad_base::Settings {
+                    &mut self.base
+                }
+            }
+
             impl [<$name Settings>] {
-                pub fn new() -> color_eyre::Result<Self>{
+                /// Homes listed in `AGENT_HOME`, comma separated
+                fn homes() -> Vec<String> {
+                    std::env::var("AGENT_HOME")
+                        .expect("missing AGENT_HOME env var")
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|home| !home.is_empty())
+                        .map(ToOwned::to_owned)
+                        .collect()
+                }
+
+                /// Settings for the single home in `AGENT_HOME`
+                #[allow(dead_code)]
+                pub fn new() -> color_eyre::Result<Self> {
+                    match Self::homes().as_slice() {
+                        [home] => Self::for_home(home),
+                        homes => color_eyre::eyre::bail!("Expected a single home in AGENT_HOME, found {:?}", homes),
+                    }
+                }
+
+                /// Settings for each home in `AGENT_HOME`
+                #[allow(dead_code)]
+                pub fn new_all() -> color_eyre::Result<Vec<Self>> {
+                    Self::homes().iter().map(|home| Self::for_home(home)).collect()
+                }
+
+                /// Settings for `home`
+                pub fn for_home(home: &str) -> color_eyre::Result<Self> {
+                    use color_eyre::eyre::WrapErr;
+
                     let agent = std::stringify!($name).to_lowercase();
                     let env = std::env::var("RUN_ENV").expect("missing RUN_ENV env var");
-                    let home = std::env::var("AGENT_HOME").expect("missing AGENT_HOME env var");
                     let secrets_path = std::env::var("SECRETS_PATH").ok();
                     let config_path = std::env::var("CONFIG_PATH").ok();
 
@@ -36,22 +70,29 @@ macro_rules! decl_settings {
                         Some(path) => {
                             let file = std::fs::File::open(&path)?;
                             let reader = std::io::BufReader::new(file);
-                            serde_json::from_reader(reader).expect("json malformed")
+                            serde_json::from_reader(reader).wrap_err("json malformed")?
                         }
                         None => nomad_xyz_configuration::get_builtin(&env).expect("!config").to_owned(),
                     };
                     config.validate()?;
 
                     let secrets = match secrets_path {
-                        Some(path) =>  nomad_xyz_configuration::AgentSecrets::from_file(path).expect("failed to build AgentSecrets from file"),
+                        Some(path) =>  nomad_xyz_configuration::AgentSecrets::from_file(path).wrap_err("failed to build AgentSecrets from file")?,
                         None => nomad_xyz_configuration::AgentSecrets::from_env("").expect("failed to build AgentSecrets from env"),
                     };
-                    secrets.validate(&agent, &env, &home)?;
+                    secrets.validate(&agent, &env, home)?;
 
-                    let base = nomad_base::Settings::from_config_and_secrets(&agent, &home, &config, &secrets);
-                    base.validate_against_config_and_secrets(&agent, &home, &config, &secrets)?;
+                    let mut base = nomad_base::Settings::from_config_and_secrets(&agent, home, &config, &secrets);
+                    base.validate_against_config_and_secrets(&agent, home, &config, &secrets)?;
+                    // The first home keeps its unscoped replica entities
+                    base.scoped_replicas = Self::homes().iter().position(|h| h == home).map_or(false, |i| i > 0);
 
-                    let agent = config.agent().get(&home).expect("agent config").[<$name:lower>].clone();
+                    let agent = config
+                        .agent()
+                        .get(home)
+                        .ok_or_else(|| color_eyre::eyre::eyre!("no agent config for home {}", home))?
+                        .[<$name:lower>]
+                        .clone();
 
                     Ok(Self {
                         base,
@@ -59,6 +100,20 @@ macro_rules! decl_settings {
                     })
                 }
             }
+
+            impl nomad_base::LoadSettings for [<$name Settings>] {
+                fn load(home: &str) -> color_eyre::Result<Self> {
+                    Self::for_home(home)
+                }
+
+                fn sources() -> Vec<std::path::PathBuf> {
+                    ["CONFIG_PATH", "SECRETS_PATH"]
+                        .iter()
+                        .filter_map(|var| std::env::var(var).ok())
+                        .map(Into::into)
+                        .collect()
+                }
+            }
         }
     }
 }
@@ -13,18 +13,28 @@
 //!  2. Override template secrets.json values (rpcs, tx signers, optional
 //!     attestation signer) with environment variables.
 //!  3. Run agents, passing in RUN_ENV and AGENT_HOME as environment variables.
+//!     AGENT_HOME may list several homes, comma separated, for agents able
+//!     to serve several homes from one process (see `run_homes`).
 
 use crate::{
     agent::AgentCore, CachingHome, CachingReplica, CommonIndexerVariants, CommonIndexers,
     ContractSync, ContractSyncMetrics, HomeIndexerVariants, HomeIndexers, Homes, NomadDB, Replicas,
+    SharedResources,
 };
 use color_eyre::{eyre::bail, Result};
-use nomad_core::{db::DB, Common, ContractLocator, Signers};
+use nomad_core::{db::DB, Common, ContractLocator, Signers, XAppRouters};
 use nomad_ethereum::{make_home_indexer, make_replica_indexer};
-use nomad_xyz_configuration::{agent::SignerConf, AgentSecrets};
+use nomad_xyz_configuration::{
+    agent::{IndexConfig, SignerConf},
+    AgentSecrets,
+};
 use nomad_xyz_configuration::{contracts::CoreContracts, ChainConf, NomadConfig, NomadGasConfig};
 use serde::Deserialize;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 /// Chain configuration
 pub mod chains;
@@ -37,6 +47,10 @@ pub use macros::*;
 /// Tracing subscriber management
 pub mod trace;
 
+/// Hot reload of settings
+pub mod reload;
+pub use reload::*;
+
 use nomad_xyz_configuration::agent::LogConfig;
 
 /// Agent types
@@ -78,6 +92,12 @@ pub struct IndexSettings {
     /// Whether or not to use timelag
     #[serde(default)]
     pub use_timelag: bool,
+    /// Number of concurrent workers indexing historical blocks on startup
+    #[serde(default)]
+    pub backfill_workers: Option<u32>,
+    /// Seconds to wait for new blocks once caught up to the tip
+    #[serde(default)]
+    pub poll_interval: Option<u64>,
 }
 
 impl IndexSettings {
@@ -87,22 +107,27 @@ impl IndexSettings {
             "kathy" => Self {
                 data_types: IndexDataTypes::Updates,
                 use_timelag: true,
+                ..Default::default()
             },
             "updater" => Self {
                 data_types: IndexDataTypes::Updates,
                 use_timelag: true,
+                ..Default::default()
             },
             "relayer" => Self {
                 data_types: IndexDataTypes::Updates,
                 use_timelag: false,
+                ..Default::default()
             },
             "processor" => Self {
                 data_types: IndexDataTypes::UpdatesAndMessages,
                 use_timelag: true,
+                ..Default::default()
             },
             "watcher" => Self {
                 data_types: IndexDataTypes::Updates,
                 use_timelag: false,
+                ..Default::default()
             },
             _ => std::panic!("Invalid agent-specific settings name!"),
         }
@@ -117,6 +142,27 @@ impl IndexSettings {
     pub fn timelag_on(&self) -> bool {
         self.use_timelag
     }
+
+    /// Apply the contract sync options of the agent config
+    pub fn with_config(self, config: &IndexConfig) -> Self {
+        Self {
+            backfill_workers: config.backfill_workers,
+            poll_interval: config.poll_interval,
+            ..self
+        }
+    }
+
+    /// Number of concurrent backfill workers. Historical blocks are indexed
+    /// sequentially if 1
+    pub fn backfill_workers(&self) -> u32 {
+        self.backfill_workers.unwrap_or(1).max(1)
+    }
+
+    /// Time to wait for new blocks once caught up to the tip. Defaults to 100
+    /// seconds
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.poll_interval.unwrap_or(100))
+    }
 }
 
 /// Settings. Usually this should be treated as a base config and used as
@@ -150,6 +196,9 @@ pub struct Settings {
     pub db: String,
     /// Port to listen for prometheus scrape requests
     pub metrics: Option<u16>,
+    /// Serve the read-only query API alongside metrics
+    #[serde(default)]
+    pub api: bool,
     /// Settings for the home indexer
     #[serde(default)]
     pub index: IndexSettings,
@@ -167,6 +216,19 @@ pub struct Settings {
     pub signers: HashMap<String, SignerConf>,
     /// Optional attestation signer
     pub attestation_signer: Option<SignerConf>,
+    /// The xApp routers of each domain, used to recognize message kinds
+    #[serde(skip)]
+    pub xapp_routers: HashMap<u32, XAppRouters>,
+    /// Set for the homes after the first when the process serves several,
+    /// sharing the db. Their replica entities are named after the home, as
+    /// the replicas of different homes live on the same chains. The first
+    /// home keeps the entities it used when served alone
+    #[serde(skip)]
+    pub scoped_replicas: bool,
+    /// Resources shared with the other homes served by the process. Opened
+    /// by `try_into_core` if not set
+    #[serde(skip)]
+    pub shared: Option<SharedResources>,
 }
 
 impl Settings {
@@ -175,6 +237,7 @@ impl Settings {
         Self {
             db: self.db.clone(),
             metrics: self.metrics,
+            api: self.api,
             index: self.index.clone(),
             home: self.home.clone(),
             replicas: self.replicas.clone(),
@@ -183,6 +246,9 @@ impl Settings {
             logging: self.logging,
             signers: self.signers.clone(),
             attestation_signer: self.attestation_signer.clone(),
+            xapp_routers: self.xapp_routers.clone(),
+            scoped_replicas: self.scoped_replicas,
+            shared: self.shared.clone(),
         }
     }
 }
@@ -288,6 +354,15 @@ impl Settings {
         replica_setup.try_into_replica(signer, gas).await
     }
 
+    /// Name of the db entity holding the data of replica `replica_name`
+    pub fn replica_db_entity(&self, replica_name: &str) -> String {
+        if self.scoped_replicas {
+            format!("{}_{}", self.home.name, replica_name)
+        } else {
+            replica_name.to_owned()
+        }
+    }
+
     /// Try to get a replica ContractSync
     pub async fn try_replica_contract_sync(
         &self,
@@ -303,13 +378,13 @@ impl Settings {
         let page_settings = replica_setup.page_settings.clone();
 
         let indexer = Arc::new(self.try_replica_indexer(replica_setup).await?);
-        let replica_name = &replica_setup.name;
+        let entity = self.replica_db_entity(&replica_setup.name);
 
-        let nomad_db = NomadDB::new(&replica_name, db);
+        let nomad_db = NomadDB::new(&entity, db);
 
         Ok(ContractSync::new(
             agent_name.to_owned(),
-            replica_name.to_owned(),
+            entity,
             nomad_db,
             indexer,
             index_settings,
@@ -331,7 +406,7 @@ impl Settings {
         let contract_sync = self
             .try_replica_contract_sync(replica_name, agent_name, db.clone(), metrics)
             .await?;
-        let nomad_db = NomadDB::new(replica.name(), db);
+        let nomad_db = NomadDB::new(self.replica_db_entity(replica.name()), db);
 
         Ok(CachingReplica::new(replica, contract_sync, nomad_db))
     }
@@ -411,15 +486,14 @@ impl Settings {
 
     /// Try to generate an agent core for a named agent
     pub async fn try_into_core(&self, name: &str) -> Result<AgentCore> {
-        let metrics = Arc::new(crate::metrics::CoreMetrics::new(
-            name,
-            &self.home.name,
-            self.metrics,
-            Arc::new(prometheus::Registry::new()),
-        )?);
+        let shared = match &self.shared {
+            Some(shared) => shared.clone(),
+            None => SharedResources::open(name, self)?,
+        };
+        let metrics = Arc::new(shared.metrics.for_home(&self.home.name));
         let sync_metrics = ContractSyncMetrics::new(metrics.clone());
 
-        let db = DB::from_path(&self.db)?;
+        let db = shared.db;
         let home = Arc::new(
             self.try_caching_home(name, db.clone(), sync_metrics.clone())
                 .await?,
@@ -430,11 +504,13 @@ impl Settings {
 
         Ok(AgentCore {
             home,
-            replicas,
+            replicas: Arc::new(RwLock::new(replicas)),
             db,
             settings: self.clone(),
             metrics,
             indexer: self.index.clone(),
+            shutdown: shared.shutdown,
+            channel_shutdowns: Default::default(),
         })
     }
 
@@ -449,7 +525,8 @@ impl Settings {
 
         let db = agent.db.to_str().expect("!db").to_owned();
         let metrics = agent.metrics;
-        let index = IndexSettings::from_agent_name(agent_name);
+        let api = agent.api;
+        let index = IndexSettings::from_agent_name(agent_name).with_config(&agent.index);
 
         let home = ChainSetup::from_config_and_secrets(
             ChainSetupType::Home { home_network },
@@ -505,6 +582,7 @@ impl Settings {
         Self {
             db,
             metrics,
+            api,
             home,
             replicas,
             managers,
@@ -513,6 +591,9 @@ impl Settings {
             logging: agent.logging,
             signers: secrets.transaction_signers.clone(),
             attestation_signer: secrets.attestation_signer.clone(),
+            xapp_routers: XAppRouters::from_config(config),
+            scoped_replicas: false,
+            shared: None,
         }
     }
 
@@ -527,9 +608,10 @@ impl Settings {
         let agent = config.agent().get(home_network).unwrap();
         assert_eq!(self.db, agent.db.to_str().unwrap());
         assert_eq!(self.metrics, agent.metrics);
+        assert_eq!(self.api, agent.api);
         assert_eq!(self.logging, agent.logging);
 
-        let index_settings = IndexSettings::from_agent_name(agent_name);
+        let index_settings = IndexSettings::from_agent_name(agent_name).with_config(&agent.index);
         assert_eq!(self.index, index_settings);
 
         let config_home_domain = config
@@ -542,6 +624,14 @@ impl Settings {
             self.home.page_settings.page_size,
             config_home_domain.specs.index_page_size
         );
+        assert_eq!(
+            self.home.page_settings.min_page_size,
+            config_home_domain.specs.min_index_page_size
+        );
+        assert_eq!(
+            self.home.page_settings.max_page_size,
+            config_home_domain.specs.max_index_page_size
+        );
         assert_eq!(
             self.home.finality,
             config_home_domain.specs.finalization_blocks
@@ -577,6 +667,14 @@ impl Settings {
                 replica_setup.page_settings.page_size,
                 config_replica_domain.specs.index_page_size
             );
+            assert_eq!(
+                replica_setup.page_settings.min_page_size,
+                config_replica_domain.specs.min_index_page_size
+            );
+            assert_eq!(
+                replica_setup.page_settings.max_page_size,
+                config_replica_domain.specs.max_index_page_size
+            );
             assert_eq!(
                 replica_setup.finality,
                 config_replica_domain.specs.finalization_blocks
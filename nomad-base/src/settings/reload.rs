@@ -0,0 +1,226 @@
+//! Hot reload of agent settings.
+//!
+//! Agents poll the files their settings were loaded from (`CONFIG_PATH` and
+//! `SECRETS_PATH`). When one of them changes, the settings are loaded again
+//! and diffed against the running ones. Replicas can be added, removed or
+//! have their connection details rotated live. Other changes only take effect
+//! on restart.
+
+use color_eyre::Result;
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+use tokio::sync::mpsc;
+use tracing::{info, info_span, warn, Instrument};
+
+use crate::{Settings, ShutdownToken};
+
+/// How often settings sources are checked for changes
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Settings that can be loaded again from their sources
+pub trait LoadSettings: Sized {
+    /// Load the settings of `home` from their sources
+    fn load(home: &str) -> Result<Self>;
+
+    /// Files the settings are loaded from. Empty if they can't change while
+    /// the agent runs (builtin config, secrets from env)
+    fn sources() -> Vec<PathBuf>;
+}
+
+/// Changes between two settings objects
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SettingsDiff {
+    /// Replicas only enabled in the new settings
+    pub added: Vec<String>,
+    /// Replicas only enabled in the old settings
+    pub removed: Vec<String>,
+    /// Replicas whose chain setup, gas or signer changed
+    pub changed: Vec<String>,
+    /// Changed settings which only take effect on restart
+    pub restart_required: Vec<&'static str>,
+}
+
+fn enabled_replicas(settings: &Settings) -> HashSet<String> {
+    settings
+        .replicas
+        .iter()
+        .filter(|(_, setup)| setup.disabled.is_none())
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+fn sorted<'a>(names: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let mut names: Vec<String> = names.cloned().collect();
+    names.sort();
+    names
+}
+
+impl SettingsDiff {
+    /// Diff `old` and `new` settings
+    pub fn between(old: &Settings, new: &Settings) -> Self {
+        let old_replicas = enabled_replicas(old);
+        let new_replicas = enabled_replicas(new);
+
+        let chain_changed = |name: &str| {
+            old.gas.get(name) != new.gas.get(name) || old.signers.get(name) != new.signers.get(name)
+        };
+
+        let changed = old_replicas.intersection(&new_replicas).filter(|name| {
+            old.replicas.get(*name) != new.replicas.get(*name) || chain_changed(name)
+        });
+
+        let restart_required = [
+            ("db", old.db != new.db),
+            ("metrics", old.metrics != new.metrics),
+            ("api", old.api != new.api),
+            ("index", old.index != new.index),
+            (
+                "home",
+                old.home != new.home || chain_changed(&old.home.name),
+            ),
+            ("managers", old.managers != new.managers),
+            ("logging", old.logging != new.logging),
+            (
+                "attestationSigner",
+                old.attestation_signer != new.attestation_signer,
+            ),
+        ]
+        .iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(field, _)| *field)
+        .collect();
+
+        Self {
+            added: sorted(new_replicas.difference(&old_replicas)),
+            removed: sorted(old_replicas.difference(&new_replicas)),
+            changed: sorted(changed),
+            restart_required,
+        }
+    }
+
+    /// Whether nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.restart_required.is_empty()
+    }
+}
+
+fn last_modified(sources: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    sources
+        .iter()
+        .map(|path| {
+            std::fs::metadata(path)
+                .and_then(|meta| meta.modified())
+                .ok()
+        })
+        .collect()
+}
+
+/// Poll the sources of `S` every `interval`, and send the settings of `home`
+/// loaded again whenever any of them changed. Settings failing to load are
+/// logged and skipped. The channel closes on shutdown, or right away if there
+/// is nothing to watch.
+pub fn watch_settings<S>(
+    home: String,
+    interval: Duration,
+    shutdown: ShutdownToken,
+) -> mpsc::Receiver<S>
+where
+    S: LoadSettings + Send + 'static,
+{
+    let span = info_span!("watch_settings", home = %home);
+    let (tx, rx) = mpsc::channel(1);
+
+    tokio::spawn(
+        async move {
+            let sources = S::sources();
+            if sources.is_empty() {
+                info!("Settings are not loaded from files. Hot reload disabled");
+                return;
+            }
+
+            let mut modified = last_modified(&sources);
+            while !shutdown.sleep(interval).await {
+                let current = last_modified(&sources);
+                if current == modified {
+                    continue;
+                }
+                modified = current;
+
+                match S::load(&home) {
+                    Ok(settings) => {
+                        info!(sources = ?sources, "Settings changed. Reloading");
+                        if tx.send(settings).await.is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        warn!(error = ?e, "Failed to reload settings. Keeping current ones")
+                    }
+                }
+            }
+        }
+        .instrument(span),
+    );
+
+    rx
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ChainSetup;
+    use nomad_xyz_configuration::{chains::ethereum::Connection, ChainConf};
+
+    fn replica(name: &str, url: &str) -> (String, ChainSetup) {
+        (
+            name.to_owned(),
+            ChainSetup {
+                name: name.to_owned(),
+                chain: ChainConf::Ethereum(Connection::Http {
+                    url: url.to_owned(),
+                }),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn it_diffs_settings() {
+        let old = Settings {
+            replicas: [
+                replica("moonbeam", "https://moonbeam.one"),
+                replica("evmos", "https://evmos.one"),
+                replica("xdai", "https://xdai.one"),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        assert!(SettingsDiff::between(&old, &old).is_empty());
+
+        let mut new = Settings {
+            metrics: Some(9091),
+            replicas: [
+                replica("moonbeam", "https://moonbeam.two"),
+                replica("evmos", "https://evmos.one"),
+                replica("milkomeda", "https://milkomeda.one"),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        new.replicas.get_mut("evmos").unwrap().disabled = Some("true".into());
+
+        let diff = SettingsDiff::between(&old, &new);
+        assert_eq!(diff.added, vec!["milkomeda".to_owned()]);
+        assert_eq!(diff.removed, vec!["evmos".to_owned(), "xdai".to_owned()]);
+        assert_eq!(diff.changed, vec!["moonbeam".to_owned()]);
+        assert_eq!(diff.restart_required, vec!["metrics"]);
+    }
+}
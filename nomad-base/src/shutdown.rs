@@ -0,0 +1,286 @@
+//! Graceful shutdown.
+//!
+//! Agents share a `ShutdownToken` through `AgentCore`. On SIGTERM or SIGINT
+//! the token is triggered and the agent drains: work that must not be
+//! interrupted (a transaction submission and the DB writes recording it)
+//! runs under an `InFlight` guard, and the agent only exits once every guard
+//! has been dropped. Tasks stop starting new work once shutdown is
+//! triggered, everything else is dropped when the agent exits.
+//!
+//! Parts of an agent that stop on their own, e.g. a channel to a replica
+//! removed from the settings, run under a `child` token. Draining the child
+//! only waits for its own work, draining the agent waits for all of it.
+
+use color_eyre::{eyre::bail, Result};
+use futures_util::future::select_all;
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::{watch, OwnedRwLockReadGuard, RwLock},
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+use tracing::{info, warn};
+
+/// How long in-flight work gets to finish once shutdown has been triggered
+pub const SHUTDOWN_GRACE: Duration = Duration::from_secs(120);
+
+/// Guard held while doing work that must finish before the agent exits.
+/// Holds a guard on the token and each of its parents.
+#[derive(Debug)]
+pub struct InFlight(Vec<OwnedRwLockReadGuard<()>>);
+
+/// Shared token signalling that the agent should shut down
+#[derive(Debug, Clone)]
+pub struct ShutdownToken {
+    sender: Arc<watch::Sender<bool>>,
+    receiver: watch::Receiver<bool>,
+    in_flight: Arc<RwLock<()>>,
+    /// Token this one was derived from. Triggering it triggers this one too
+    parent: Option<Arc<ShutdownToken>>,
+}
+
+impl Default for ShutdownToken {
+    fn default() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        Self {
+            sender: Arc::new(sender),
+            receiver,
+            in_flight: Default::default(),
+            parent: None,
+        }
+    }
+}
+
+impl ShutdownToken {
+    /// Derive a token that is triggered with this one, or on its own.
+    /// Draining this one waits for the work in flight under the child.
+    pub fn child(&self) -> Self {
+        Self {
+            parent: Some(Arc::new(self.clone())),
+            ..Default::default()
+        }
+    }
+
+    /// This token and its parents, outermost first
+    fn lineage(&self) -> Vec<&ShutdownToken> {
+        let mut lineage = vec![self];
+        while let Some(parent) = lineage[lineage.len() - 1].parent.as_deref() {
+            lineage.push(parent);
+        }
+        lineage.reverse();
+        lineage
+    }
+
+    /// Trigger shutdown. Parents are not triggered
+    pub fn trigger(&self) {
+        // Cannot fail, we hold a receiver
+        let _ = self.sender.send(true);
+    }
+
+    /// Whether shutdown of this token or a parent has been triggered
+    pub fn is_triggered(&self) -> bool {
+        self.lineage().iter().any(|token| *token.receiver.borrow())
+    }
+
+    /// Resolve once shutdown of this token or a parent has been triggered
+    pub async fn triggered(&self) {
+        let mut receivers: Vec<_> = self
+            .lineage()
+            .iter()
+            .map(|token| token.receiver.clone())
+            .collect();
+        while !receivers.iter().any(|receiver| *receiver.borrow()) {
+            // Cannot fail, the lineage holds the senders
+            let changes = receivers
+                .iter_mut()
+                .map(|receiver| Box::pin(receiver.changed()));
+            let _ = select_all(changes).await;
+        }
+    }
+
+    /// Guards on the in-flight locks of this token and its parents
+    async fn guards(&self) -> InFlight {
+        let mut guards = vec![];
+        for token in self.lineage() {
+            guards.push(token.in_flight.clone().read_owned().await);
+        }
+        InFlight(guards)
+    }
+
+    /// Sleep for `duration`, waking early if shutdown is triggered. Returns
+    /// `true` if the agent is shutting down.
+    pub async fn sleep(&self, duration: Duration) -> bool {
+        tokio::select! {
+            _ = sleep(duration) => self.is_triggered(),
+            _ = self.triggered() => true,
+        }
+    }
+
+    /// Start work that must finish before the agent exits. Returns `None` if
+    /// the agent is shutting down, in which case the work must not start.
+    pub async fn in_flight(&self) -> Option<InFlight> {
+        let guards = self.guards().await;
+        if self.is_triggered() {
+            return None;
+        }
+        Some(guards)
+    }
+
+    /// Start work that must finish before the agent exits, even if it is
+    /// already shutting down, e.g. reporting fraud. Draining waits for it,
+    /// unless the drain already completed.
+    pub async fn critical_in_flight(&self) -> InFlight {
+        self.guards().await
+    }
+
+    /// Trigger shutdown and wait for in-flight work to finish. Errors if it
+    /// does not finish within `SHUTDOWN_GRACE`.
+    pub async fn drain(&self) -> Result<()> {
+        self.trigger();
+
+        // Readers that acquire after this check the trigger and back off
+        match timeout(SHUTDOWN_GRACE, self.in_flight.write()).await {
+            Ok(_) => Ok(()),
+            Err(_) => {
+                warn!("In-flight work did not finish within {:?}", SHUTDOWN_GRACE);
+                bail!(
+                    "In-flight work did not finish within {:?} of shutdown",
+                    SHUTDOWN_GRACE
+                )
+            }
+        }
+    }
+
+    /// Spawn a task triggering shutdown on SIGTERM or SIGINT
+    pub fn listen_for_signals(&self) -> JoinHandle<()> {
+        let token = self.clone();
+        tokio::spawn(async move {
+            let signal = wait_for_signal().await;
+            info!(
+                signal,
+                "Received {}. Finishing in-flight work before shutting down", signal
+            );
+            token.trigger();
+        })
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> &'static str {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+    tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> &'static str {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("failed to listen for ctrl-c");
+    "SIGINT"
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[tokio::test]
+    async fn it_wakes_sleepers_on_shutdown() {
+        let token = ShutdownToken::default();
+        assert!(!token.sleep(Duration::from_millis(1)).await);
+
+        let sleeper = token.clone();
+        let task = tokio::spawn(async move { sleeper.sleep(Duration::from_secs(3600)).await });
+        token.trigger();
+
+        assert!(timeout(Duration::from_secs(1), task)
+            .await
+            .unwrap()
+            .unwrap());
+        assert!(token.is_triggered());
+    }
+
+    #[tokio::test]
+    async fn it_drains_in_flight_work() {
+        let token = ShutdownToken::default();
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let guard = token.in_flight().await.unwrap();
+        let worker_finished = finished.clone();
+        tokio::spawn(async move {
+            let _guard = guard;
+            sleep(Duration::from_millis(50)).await;
+            worker_finished.store(true, Ordering::SeqCst);
+        });
+
+        token.drain().await.unwrap();
+        assert!(finished.load(Ordering::SeqCst));
+
+        // no new work once shutting down
+        assert!(token.in_flight().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn it_drains_critical_work_started_while_shutting_down() {
+        let token = ShutdownToken::default();
+        let finished = Arc::new(AtomicBool::new(false));
+        token.trigger();
+
+        let guard = token.critical_in_flight().await;
+        let worker_finished = finished.clone();
+        tokio::spawn(async move {
+            let _guard = guard;
+            sleep(Duration::from_millis(50)).await;
+            worker_finished.store(true, Ordering::SeqCst);
+        });
+
+        token.drain().await.unwrap();
+        assert!(finished.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn it_drains_children_on_their_own() {
+        let token = ShutdownToken::default();
+        let child = token.child();
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let guard = child.in_flight().await.unwrap();
+        let worker_finished = finished.clone();
+        tokio::spawn(async move {
+            let _guard = guard;
+            sleep(Duration::from_millis(50)).await;
+            worker_finished.store(true, Ordering::SeqCst);
+        });
+
+        child.drain().await.unwrap();
+        assert!(finished.load(Ordering::SeqCst));
+        assert!(child.in_flight().await.is_none());
+        assert!(!token.is_triggered());
+
+        // the parent still waits for work under its other children
+        let other = token.child();
+        let guard = other.in_flight().await.unwrap();
+        let worker_finished = finished.clone();
+        worker_finished.store(false, Ordering::SeqCst);
+        tokio::spawn(async move {
+            let _guard = guard;
+            sleep(Duration::from_millis(50)).await;
+            worker_finished.store(true, Ordering::SeqCst);
+        });
+
+        let waiter = other.clone();
+        let woken = tokio::spawn(async move { waiter.triggered().await });
+        token.drain().await.unwrap();
+        assert!(finished.load(Ordering::SeqCst));
+        assert!(other.is_triggered());
+        timeout(Duration::from_secs(1), woken)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,346 @@
+//! Supervision of agent tasks.
+//!
+//! Each long-running task of an agent (contract sync, home <> replica
+//! channel, prover sync, ...) is spawned by a `Supervisor` under a
+//! `RestartPolicy`. A failed task is restarted with exponential backoff until
+//! its policy is exhausted, so that a transient error in one task does not
+//! take down the others. Only a task finishing, or failing for good, stops
+//! the agent.
+
+use crate::{CoreMetrics, ShutdownToken};
+use color_eyre::{eyre::WrapErr, Report, Result};
+use futures_util::future::select_all;
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+use tracing::{error, info_span, instrument::Instrumented, warn, Instrument};
+
+/// Tasks running at least this long before failing are assumed to have
+/// recovered, and their backoff is reset
+const RESET_AFTER: Duration = Duration::from_secs(300);
+
+/// How a supervised task is restarted after an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// Consecutive restarts before the failure stops the agent. `None` for
+    /// unlimited
+    pub max_restarts: Option<u32>,
+    /// Delay before the first restart. Doubled on each consecutive failure
+    pub backoff: Duration,
+    /// Upper bound on the delay between restarts
+    pub max_backoff: Duration,
+}
+
+impl RestartPolicy {
+    /// Never restart. The task failing stops the agent
+    pub const NEVER: Self = Self::limited(0);
+
+    /// Always restart
+    pub const ALWAYS: Self = Self {
+        max_restarts: None,
+        backoff: Duration::from_secs(1),
+        max_backoff: Duration::from_secs(128),
+    };
+
+    /// Restart up to `max_restarts` consecutive times
+    pub const fn limited(max_restarts: u32) -> Self {
+        Self {
+            max_restarts: Some(max_restarts),
+            ..Self::ALWAYS
+        }
+    }
+
+    /// Whether to restart after `failures` consecutive failures
+    pub fn allows(&self, failures: u32) -> bool {
+        self.max_restarts.map_or(true, |max| failures <= max)
+    }
+
+    /// Delay before restarting after `failures` consecutive failures,
+    /// counting the one being restarted from
+    pub fn delay(&self, failures: u32) -> Duration {
+        self.backoff
+            .checked_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+}
+
+/// Aborts the task when dropped, so that cancelling a supervisor cancels the
+/// tasks it spawned
+struct Child(JoinHandle<Result<()>>);
+
+impl Drop for Child {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+type SpawnFn = Box<dyn Fn() -> Instrumented<JoinHandle<Result<()>>> + Send + Sync>;
+
+/// A named, restartable task
+pub struct SupervisedTask {
+    name: String,
+    policy: RestartPolicy,
+    spawn: SpawnFn,
+    on_fault: Option<Box<dyn Fn() + Send + Sync>>,
+}
+
+impl fmt::Debug for SupervisedTask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SupervisedTask")
+            .field("name", &self.name)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl SupervisedTask {
+    /// Supervise the tasks spawned by `spawn`. It is called again for each
+    /// restart.
+    pub fn new<F>(name: impl Into<String>, policy: RestartPolicy, spawn: F) -> Self
+    where
+        F: Fn() -> Instrumented<JoinHandle<Result<()>>> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            policy,
+            spawn: Box::new(spawn),
+            on_fault: None,
+        }
+    }
+
+    /// Supervise an already spawned task. It is never restarted
+    pub fn once(name: impl Into<String>, task: Instrumented<JoinHandle<Result<()>>>) -> Self {
+        let task = Mutex::new(Some(task));
+        Self::new(name, RestartPolicy::NEVER, move || {
+            task.lock()
+                .expect("!poisoned")
+                .take()
+                .expect("task is never restarted")
+        })
+    }
+
+    /// Call `on_fault` each time the task errors
+    pub fn on_fault<F>(mut self, on_fault: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_fault = Some(Box::new(on_fault));
+        self
+    }
+
+    /// Run the task, restarting it according to its policy
+    fn run(
+        self,
+        metrics: Arc<CoreMetrics>,
+        shutdown: ShutdownToken,
+    ) -> Instrumented<JoinHandle<Result<()>>> {
+        let span = info_span!("Supervised", task = %self.name);
+        let restarts = metrics.task_restarts(&self.name);
+        let task_failures = metrics.task_failures(&self.name);
+        let health = metrics.health();
+        // health is shared by all homes served by the process
+        let health_name = format!("{}/{}", metrics.home_name(), self.name);
+
+        tokio::spawn(async move {
+            let mut failures = 0;
+            loop {
+                let started = Instant::now();
+                health.task_started(&health_name);
+
+                let mut child = Child((self.spawn)().into_inner());
+                let err = match (&mut child.0).await {
+                    Ok(Ok(())) => return Ok(()),
+                    Ok(Err(e)) => e,
+                    Err(e) => Report::new(e),
+                };
+
+                health.task_faulted(&health_name);
+                if let Some(on_fault) = &self.on_fault {
+                    on_fault();
+                }
+
+                if shutdown.is_triggered() {
+                    warn!(task = %self.name, error = ?err, "Task {} errored while shutting down", self.name);
+                    return Ok(());
+                }
+
+                if started.elapsed() >= RESET_AFTER {
+                    failures = 0;
+                }
+                failures += 1;
+
+                if !self.policy.allows(failures) {
+                    task_failures.inc();
+                    error!(
+                        task = %self.name,
+                        failures,
+                        error = ?err,
+                        "Task {} failed {} times in a row. Not restarting",
+                        self.name,
+                        failures
+                    );
+                    return Err(err).wrap_err(format!("Task {} failed", self.name));
+                }
+
+                restarts.inc();
+                let delay = self.policy.delay(failures);
+                warn!(
+                    task = %self.name,
+                    failures,
+                    error = ?err,
+                    "Task {} errored. Restarting in {:?}",
+                    self.name,
+                    delay
+                );
+
+                if shutdown.sleep(delay).await {
+                    return Ok(());
+                }
+            }
+        })
+        .instrument(span)
+    }
+}
+
+/// Spawns tasks and restarts them according to their policies
+#[derive(Debug)]
+pub struct Supervisor {
+    metrics: Arc<CoreMetrics>,
+    shutdown: ShutdownToken,
+    tasks: Vec<SupervisedTask>,
+}
+
+impl Supervisor {
+    /// Instantiate a supervisor reporting to `metrics`
+    pub fn new(metrics: Arc<CoreMetrics>, shutdown: ShutdownToken) -> Self {
+        Self {
+            metrics,
+            shutdown,
+            tasks: vec![],
+        }
+    }
+
+    /// Add a task
+    pub fn add(&mut self, task: SupervisedTask) {
+        self.tasks.push(task);
+    }
+
+    /// Spawn all tasks. Resolves once any of them finishes or fails for
+    /// good, cancelling the others. Cancelling the returned task cancels them
+    /// all.
+    pub fn spawn(self) -> Instrumented<JoinHandle<Result<()>>> {
+        let span = info_span!("Supervisor", tasks = self.tasks.len());
+        let metrics = self.metrics;
+        let shutdown = self.shutdown;
+        let mut children: Vec<_> = self
+            .tasks
+            .into_iter()
+            .map(|task| Child(task.run(metrics.clone(), shutdown.clone()).into_inner()))
+            .collect();
+
+        tokio::spawn(async move {
+            if children.is_empty() {
+                return Ok(());
+            }
+
+            // remaining children are aborted when dropped
+            let (res, _, _) = select_all(children.iter_mut().map(|child| &mut child.0)).await;
+            res?
+        })
+        .instrument(span)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use color_eyre::eyre::eyre;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn test_metrics() -> Arc<CoreMetrics> {
+        Arc::new(
+            CoreMetrics::new(
+                "supervisor_test",
+                "home",
+                None,
+                Arc::new(prometheus::Registry::new()),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn failing_task(policy: RestartPolicy, runs: Arc<AtomicU32>) -> SupervisedTask {
+        SupervisedTask::new("failing", policy, move || {
+            let runs = runs.clone();
+            tokio::spawn(async move {
+                runs.fetch_add(1, Ordering::SeqCst);
+                Err(eyre!("transient"))
+            })
+            .in_current_span()
+        })
+    }
+
+    #[test]
+    fn it_backs_off_exponentially() {
+        let policy = RestartPolicy::ALWAYS;
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(4), Duration::from_secs(8));
+        assert_eq!(policy.delay(40), Duration::from_secs(128));
+
+        assert!(!RestartPolicy::NEVER.allows(1));
+        assert!(RestartPolicy::limited(2).allows(2));
+        assert!(!RestartPolicy::limited(2).allows(3));
+    }
+
+    #[tokio::test]
+    async fn it_restarts_until_policy_is_exhausted() {
+        let metrics = test_metrics();
+        let runs = Arc::new(AtomicU32::new(0));
+        let policy = RestartPolicy {
+            max_restarts: Some(2),
+            backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+        };
+
+        let mut supervisor = Supervisor::new(metrics.clone(), ShutdownToken::default());
+        supervisor.add(failing_task(policy, runs.clone()));
+        let res = supervisor.spawn().await.unwrap();
+
+        assert!(res.is_err());
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert_eq!(metrics.task_restarts("failing").get(), 2);
+        assert_eq!(metrics.task_failures("failing").get(), 1);
+    }
+
+    #[tokio::test]
+    async fn it_isolates_failing_tasks() {
+        let metrics = test_metrics();
+        let runs = Arc::new(AtomicU32::new(0));
+        let policy = RestartPolicy {
+            max_restarts: None,
+            backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+        };
+
+        let mut supervisor = Supervisor::new(metrics.clone(), ShutdownToken::default());
+        supervisor.add(failing_task(policy, runs.clone()));
+        supervisor.add(SupervisedTask::new("healthy", RestartPolicy::NEVER, || {
+            tokio::spawn(async {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Ok(())
+            })
+            .in_current_span()
+        }));
+
+        // the healthy task finishing ends supervision, failing task kept
+        // restarting until then
+        supervisor.spawn().await.unwrap().unwrap();
+        assert!(runs.load(Ordering::SeqCst) > 1);
+        assert!(metrics.task_restarts("failing").get() > 0);
+        assert_eq!(metrics.task_failures("failing").get(), 0);
+    }
+}
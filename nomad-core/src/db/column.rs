@@ -0,0 +1,82 @@
+use rocksdb::{BlockBasedOptions, Options};
+
+/// A RocksDB column family. Each data type lives in its own column family
+/// so it can be tuned and compacted separately. Entities share column
+/// families and are distinguished by key prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Column {
+    /// Anything not assigned to a specific column family
+    Default,
+    /// Raw committed messages and their lifecycles
+    Messages,
+    /// Leaf hashes by leaf index and by destination and nonce
+    Leaves,
+    /// Merkle proofs by leaf index
+    Proofs,
+    /// Signed updates
+    Updates,
+    /// Small, frequently overwritten values: latest roots, indexes, sync
+    /// heights and update metadata
+    Metadata,
+    /// Merkle tree nodes and snapshots
+    Tree,
+}
+
+impl Column {
+    /// All column families
+    pub const ALL: [Column; 7] = [
+        Column::Default,
+        Column::Messages,
+        Column::Leaves,
+        Column::Proofs,
+        Column::Updates,
+        Column::Metadata,
+        Column::Tree,
+    ];
+
+    /// The column family name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Default => rocksdb::DEFAULT_COLUMN_FAMILY_NAME,
+            Column::Messages => "messages",
+            Column::Leaves => "leaves",
+            Column::Proofs => "proofs",
+            Column::Updates => "updates",
+            Column::Metadata => "metadata",
+            Column::Tree => "tree",
+        }
+    }
+
+    /// Look up a column family by name
+    pub fn from_name(name: &str) -> Option<Column> {
+        Column::ALL
+            .iter()
+            .find(|column| column.name() == name)
+            .copied()
+    }
+
+    /// Tuning for the column family
+    pub fn options(&self) -> Options {
+        let mut opts = Options::default();
+        match self {
+            // Written once and read by key. Bloom filters let lookups for
+            // missing keys (e.g. proofs not yet built) skip reading blocks
+            Column::Messages | Column::Leaves | Column::Proofs | Column::Updates => {
+                let mut block_opts = BlockBasedOptions::default();
+                block_opts.set_bloom_filter(10.0, false);
+                opts.set_block_based_table_factory(&block_opts);
+                opts.set_level_compaction_dynamic_level_bytes(true);
+            }
+            // Nodes near the root are overwritten on every insertion, so
+            // buffer more writes before flushing
+            Column::Tree => {
+                let mut block_opts = BlockBasedOptions::default();
+                block_opts.set_bloom_filter(10.0, false);
+                opts.set_block_based_table_factory(&block_opts);
+                opts.set_write_buffer_size(128 << 20);
+            }
+            Column::Default | Column::Metadata => {}
+        }
+        opts
+    }
+}
@@ -0,0 +1,316 @@
+use rocksdb::checkpoint::Checkpoint;
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
+use tracing::info;
+
+use crate::{
+    db::{Column, DbError, DB, SCHEMA_VERSION},
+    Decode, Encode,
+};
+
+/// Magic bytes opening an export
+static EXPORT_MAGIC: &[u8; 8] = b"NOMADDB\0";
+
+/// Version of the export file format. Independent of the schema version,
+/// which describes the keys and values inside the export.
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// Entries imported per write batch
+const IMPORT_BATCH_SIZE: usize = 10_000;
+
+/// Header of an entity export
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportHeader {
+    /// Export file format version
+    pub format_version: u32,
+    /// Schema version of the exported db
+    pub schema_version: u32,
+    /// Entity the export was taken from
+    pub entity: String,
+}
+
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> std::io::Result<usize> {
+    let written = (bytes.len() as u32).write_to(writer)?;
+    writer.write_all(bytes)?;
+    Ok(written + bytes.len())
+}
+
+/// Read a length-prefixed byte string. The length is untrusted, so the
+/// buffer only grows as bytes arrive
+fn read_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>, DbError> {
+    let len = u32::read_from(reader)? as u64;
+    let mut bytes = vec![];
+    reader.by_ref().take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(DbError::InvalidExport("truncated export".to_owned()));
+    }
+    Ok(bytes)
+}
+
+impl ExportHeader {
+    fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<usize> {
+        writer.write_all(EXPORT_MAGIC)?;
+        let mut written = EXPORT_MAGIC.len();
+        written += self.format_version.write_to(writer)?;
+        written += self.schema_version.write_to(writer)?;
+        written += write_bytes(writer, self.entity.as_bytes())?;
+        Ok(written)
+    }
+
+    /// Read and validate an export header
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, DbError> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != EXPORT_MAGIC {
+            return Err(DbError::InvalidExport("not a nomad db export".to_owned()));
+        }
+
+        let format_version = u32::read_from(reader)?;
+        if format_version != EXPORT_FORMAT_VERSION {
+            return Err(DbError::InvalidExport(format!(
+                "unsupported format version {}",
+                format_version
+            )));
+        }
+
+        let schema_version = u32::read_from(reader)?;
+        let entity = String::from_utf8(read_bytes(reader)?)
+            .map_err(|_| DbError::InvalidExport("entity is not utf-8".to_owned()))?;
+
+        Ok(Self {
+            format_version,
+            schema_version,
+            entity,
+        })
+    }
+}
+
+/// Prefix of the keys registering entities. Not entity-prefixed, as it
+/// describes the whole db.
+static ENTITY_KEY: &str = "__entity_";
+
+fn entity_prefix(entity: &str) -> Vec<u8> {
+    let mut prefix = entity.as_bytes().to_vec();
+    prefix.push(b'_');
+    prefix
+}
+
+impl DB {
+    /// Record that `entity` has keys in the db. See `DB::entities`.
+    pub fn register_entity(&self, entity: &str) -> Result<(), DbError> {
+        self.column(Column::Default)
+            .prefix_store(ENTITY_KEY, entity, [])
+    }
+
+    /// Entities that wrote to the db. `TypedDB` registers its entity on its
+    /// first write. Entities are only separated by key prefix, so this is
+    /// what tells the keys of `polygon` apart from those of `polygon_pos`.
+    pub fn entities(&self) -> Result<Vec<String>, DbError> {
+        self.prefix_iterator_cf(Column::Default, ENTITY_KEY)
+            .take_while(|(key, _)| key.starts_with(ENTITY_KEY.as_bytes()))
+            .map(|(key, _)| {
+                String::from_utf8(key[ENTITY_KEY.len()..].to_vec())
+                    .map_err(|_| DbError::InvalidExport("entity is not utf-8".to_owned()))
+            })
+            .collect()
+    }
+
+    /// Stream every key belonging to `entity` to `writer`. Keys are written
+    /// without the entity prefix, so an export may be imported under another
+    /// entity name. Keys of registered entities named `<entity>_...` are
+    /// left out.
+    ///
+    /// Format: header, then per entry a `1` byte, column family name, key and
+    /// value, each length-prefixed. A `0` byte ends the export. Returns the
+    /// number of entries written.
+    pub fn export_entity<W: Write>(&self, entity: &str, writer: &mut W) -> Result<usize, DbError> {
+        let header = ExportHeader {
+            format_version: EXPORT_FORMAT_VERSION,
+            schema_version: self.schema_version()?.unwrap_or(SCHEMA_VERSION),
+            entity: entity.to_owned(),
+        };
+        header.write_to(writer)?;
+
+        let prefix = entity_prefix(entity);
+        let others: Vec<Vec<u8>> = self
+            .entities()?
+            .iter()
+            .filter(|other| other.len() > entity.len() && other.starts_with(entity))
+            .map(|other| entity_prefix(other))
+            .collect();
+
+        let mut entries = 0;
+        for column in Column::ALL.iter() {
+            let iter = self
+                .prefix_iterator_cf(*column, &prefix)
+                .take_while(|(key, _)| key.starts_with(&prefix))
+                .filter(|(key, _)| !others.iter().any(|other| key.starts_with(other)));
+
+            for (key, value) in iter {
+                writer.write_all(&[1])?;
+                write_bytes(writer, column.name().as_bytes())?;
+                write_bytes(writer, &key[prefix.len()..])?;
+                write_bytes(writer, &value)?;
+                entries += 1;
+            }
+        }
+        writer.write_all(&[0])?;
+        writer.flush()?;
+
+        info!(entity, entries, "Exported db entity");
+        Ok(entries)
+    }
+
+    /// Restore an export written by `export_entity` under `entity`. Existing
+    /// keys are overwritten. Entries are written in batches, so an
+    /// interrupted import leaves a partial entity that should be discarded.
+    /// Errors if the export was taken at a different schema version. Returns
+    /// the number of entries imported.
+    pub fn import_entity<R: Read>(&self, entity: &str, reader: &mut R) -> Result<usize, DbError> {
+        let header = ExportHeader::read_from(reader)?;
+        let supported = self.schema_version()?.unwrap_or(SCHEMA_VERSION);
+        if header.schema_version != supported {
+            return Err(DbError::InvalidExport(format!(
+                "export schema version {} does not match db schema version {}",
+                header.schema_version, supported
+            )));
+        }
+
+        let prefix = entity_prefix(entity);
+        let mut entries = 0;
+        let mut done = false;
+        while !done {
+            self.batch(|db| -> Result<(), DbError> {
+                db.register_entity(entity)?;
+                for _ in 0..IMPORT_BATCH_SIZE {
+                    let mut tag = [0u8; 1];
+                    reader.read_exact(&mut tag)?;
+                    if tag[0] == 0 {
+                        done = true;
+                        return Ok(());
+                    }
+
+                    let name = String::from_utf8(read_bytes(reader)?).unwrap_or_default();
+                    let column = Column::from_name(&name).ok_or_else(|| {
+                        DbError::InvalidExport(format!("unknown column family {:?}", name))
+                    })?;
+                    let key = read_bytes(reader)?;
+                    let value = read_bytes(reader)?;
+
+                    db.column(column).prefix_store(&prefix, key, value)?;
+                    entries += 1;
+                }
+                Ok(())
+            })?;
+        }
+
+        info!(
+            entity,
+            from = header.entity.as_str(),
+            entries,
+            "Imported db entity"
+        );
+        Ok(entries)
+    }
+
+    /// Write a consistent, openable copy of the live db to `path`, which
+    /// must not exist. Files are hard-linked where possible, so checkpoints
+    /// on the same filesystem are cheap.
+    pub fn checkpoint(&self, path: impl AsRef<Path>) -> Result<(), DbError> {
+        Checkpoint::new(&self.rocks)?.create_checkpoint(path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::TypedDB;
+    use rocksdb::{Options, DB as Rocks};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "nomad_export_test_{}_{}",
+            name,
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ))
+    }
+
+    #[test]
+    fn it_round_trips_entity_exports() {
+        let source_path = temp_path("source");
+        let target_path = temp_path("target");
+        {
+            let source = DB::open(&source_path).unwrap();
+            source.migrate().unwrap();
+            source
+                .column(Column::Leaves)
+                .store_encodable("home_leaf_", 1u32.to_be_bytes(), &7u32)
+                .unwrap();
+            source
+                .column(Column::Metadata)
+                .store_encodable("home_", "messages_last_block", &100u32)
+                .unwrap();
+            // other entities are not exported
+            source
+                .column(Column::Leaves)
+                .store_encodable("other_leaf_", 1u32.to_be_bytes(), &8u32)
+                .unwrap();
+            // nor are entities the exported one prefixes
+            TypedDB::new("home_pos".to_owned(), source.clone())
+                .column(Column::Leaves)
+                .store_encodable("leaf_", 1u32.to_be_bytes(), &9u32)
+                .unwrap();
+            assert_eq!(source.entities().unwrap(), vec!["home_pos".to_owned()]);
+
+            let mut export = vec![];
+            assert_eq!(source.export_entity("home", &mut export).unwrap(), 2);
+
+            let target = DB::open(&target_path).unwrap();
+            target.migrate().unwrap();
+            assert_eq!(
+                target
+                    .import_entity("copy", &mut export.as_slice())
+                    .unwrap(),
+                2
+            );
+
+            let leaf: Option<u32> = target
+                .column(Column::Leaves)
+                .retrieve_decodable("copy_leaf_", 1u32.to_be_bytes())
+                .unwrap();
+            assert_eq!(leaf, Some(7));
+            let block: Option<u32> = target
+                .column(Column::Metadata)
+                .retrieve_decodable("copy_", "messages_last_block")
+                .unwrap();
+            assert_eq!(block, Some(100));
+            let other: Option<u32> = target
+                .column(Column::Leaves)
+                .retrieve_decodable("other_leaf_", 1u32.to_be_bytes())
+                .unwrap();
+            assert!(other.is_none());
+
+            // garbage is rejected
+            assert!(matches!(
+                target.import_entity("copy", &mut &b"not an export"[..]),
+                Err(DbError::InvalidExport(_))
+            ));
+
+            // as are lengths past the end of the export
+            let mut truncated = export[..EXPORT_MAGIC.len() + 8].to_vec();
+            truncated.extend(u32::MAX.to_be_bytes());
+            assert!(matches!(
+                ExportHeader::read_from(&mut truncated.as_slice()),
+                Err(DbError::InvalidExport(_))
+            ));
+        }
+        let _ = Rocks::destroy(&Options::default(), &source_path);
+        let _ = Rocks::destroy(&Options::default(), &target_path);
+    }
+}
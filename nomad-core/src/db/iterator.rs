@@ -27,9 +27,11 @@ where
     type Item = V;
 
     fn next(&mut self) -> Option<Self::Item> {
+        // Keys are sorted, so the first key without the prefix ends the range
         let prefix = self.prefix;
         self.iter
-            .find(|(k, _)| k.strip_prefix(prefix).is_some())
+            .next()
+            .filter(|(k, _)| k.starts_with(prefix))
             .map(|(_, v)| v.to_vec())
             .map(|v| V::read_from(&mut v.as_slice()).expect("!corrupt"))
     }
@@ -0,0 +1,402 @@
+use std::path::Path;
+use tracing::info;
+
+use crate::db::{Column, DbError, DB};
+
+/// The on-disk layout version written by this binary
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// The version assumed for non-empty dbs written before versioning was
+/// introduced
+pub const LEGACY_SCHEMA_VERSION: u32 = 1;
+
+/// Key holding the schema version. Not entity-prefixed, as it describes the
+/// whole db.
+static SCHEMA_VERSION_KEY: &str = "__schema_version";
+
+/// A step upgrading the on-disk layout from `version - 1` to `version`.
+///
+/// Migrations operate on raw keys, as they must keep working against old
+/// layouts after the code that wrote them is gone. A migration may be
+/// interrupted before the version is stored, so it must be safe to run
+/// twice.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    /// Version this migration upgrades to
+    pub version: u32,
+    /// Short description, for logs
+    pub description: &'static str,
+    /// Perform the migration
+    pub migrate: fn(&DB) -> Result<(), DbError>,
+}
+
+/// All migrations, in version order. Add a migration here and bump
+/// `SCHEMA_VERSION` whenever a key layout changes.
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 2,
+        description: "move keys into per-type column families",
+        migrate: split_columns,
+    },
+    Migration {
+        version: 3,
+        description: "add block hashes to update metadata",
+        migrate: pad_update_metadata,
+    },
+];
+
+/// Key prefixes written to the default column family before schema version
+/// 2, and the column family each moved to
+static V1_COLUMN_PREFIXES: &[(&str, Column)] = &[
+    ("message_", Column::Messages),
+    ("lifecycle_", Column::Messages),
+    ("leaf_", Column::Leaves),
+    ("proof_", Column::Proofs),
+    ("update_", Column::Updates),
+    ("update_prev_root_", Column::Updates),
+    ("update_relayed_", Column::Updates),
+    ("updater_produced_update_", Column::Updates),
+    ("update_metadata_", Column::Metadata),
+    ("update_latest_root_", Column::Metadata),
+    ("latest_known_leaf_index_", Column::Metadata),
+    ("prover_latest_committed_", Column::Metadata),
+    ("updates_last_block", Column::Metadata),
+    ("messages_last_block", Column::Metadata),
+    ("current_nonce_", Column::Metadata),
+    ("tree_node_", Column::Tree),
+    ("tree_leaf_count", Column::Tree),
+    ("prover_snapshot_", Column::Tree),
+    ("prover_latest_snapshot_", Column::Tree),
+];
+
+/// Find the column family for a v1 key. Keys are
+/// ```<entity>_<prefix><key>```, and entities may contain underscores, so
+/// every split is tried. The longest matching prefix wins.
+fn v1_column(key: &[u8]) -> Option<Column> {
+    key.iter()
+        .enumerate()
+        .filter(|(_, byte)| **byte == b'_')
+        .find_map(|(i, _)| {
+            let rest = &key[i + 1..];
+            V1_COLUMN_PREFIXES
+                .iter()
+                .filter(|(prefix, _)| rest.starts_with(prefix.as_bytes()))
+                .max_by_key(|(prefix, _)| prefix.len())
+                .map(|(_, column)| *column)
+        })
+}
+
+fn split_columns(db: &DB) -> Result<(), DbError> {
+    let default = db.column(Column::Default);
+    let mut moved = 0usize;
+
+    // The iterator reads from an implicit snapshot, so moving keys while
+    // iterating is safe
+    for (key, value) in default.iterator() {
+        if let Some(column) = v1_column(&key) {
+            db.column(column)._store(&key, &value)?;
+            default._delete(&key)?;
+            moved += 1;
+        }
+    }
+
+    info!(moved, "Moved keys into column families");
+    Ok(())
+}
+
+/// Key prefix of update metadata, keyed by new root
+static UPDATE_META_PREFIX: &[u8] = b"update_metadata_";
+
+/// Update metadata written before schema version 3: block number and
+/// timestamp, without block hashes
+const V2_UPDATE_META_LEN: usize = 16;
+
+/// Whether a metadata column key is ```<entity>_update_metadata_<root>```
+fn is_update_meta_key(key: &[u8]) -> bool {
+    key.iter()
+        .enumerate()
+        .filter(|(_, byte)| **byte == b'_')
+        .any(|(i, _)| {
+            let rest = &key[i + 1..];
+            rest.starts_with(UPDATE_META_PREFIX) && rest.len() == UPDATE_META_PREFIX.len() + 32
+        })
+}
+
+/// Write unknown block hashes as zero, so that all update metadata has the
+/// same length
+fn pad_update_metadata(db: &DB) -> Result<(), DbError> {
+    let metadata = db.column(Column::Metadata);
+    let mut padded = 0usize;
+
+    for (key, value) in metadata.iterator() {
+        if value.len() == V2_UPDATE_META_LEN && is_update_meta_key(&key) {
+            let mut value = value.to_vec();
+            value.extend([0u8; 64]);
+            metadata._store(&key, &value)?;
+            padded += 1;
+        }
+    }
+
+    info!(padded, "Added block hashes to update metadata");
+    Ok(())
+}
+
+impl DB {
+    /// Retrieve the stored schema version, if any
+    pub fn schema_version(&self) -> Result<Option<u32>, DbError> {
+        self.column(Column::Default)
+            .retrieve_decodable("", SCHEMA_VERSION_KEY)
+    }
+
+    fn store_schema_version(&self, version: u32) -> Result<(), DbError> {
+        self.column(Column::Default)
+            .store_encodable("", SCHEMA_VERSION_KEY, &version)
+    }
+
+    /// Opens the existing db at `path` as it is, for tools that inspect it.
+    /// Does not migrate, and errors unless the db is at `SCHEMA_VERSION`, as
+    /// the keys of other versions would be misread.
+    pub fn open_current(path: impl AsRef<Path>) -> Result<DB, DbError> {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("no db at {}", path.display()),
+            )
+            .into());
+        }
+
+        let db = DB::open(path)?;
+        let found = db.schema_version()?.unwrap_or(LEGACY_SCHEMA_VERSION);
+        if found != SCHEMA_VERSION {
+            return Err(DbError::SchemaMismatch {
+                found,
+                supported: SCHEMA_VERSION,
+            });
+        }
+        Ok(db)
+    }
+
+    /// Upgrade the db to `SCHEMA_VERSION`
+    pub fn migrate(&self) -> Result<(), DbError> {
+        self.run_migrations(MIGRATIONS, SCHEMA_VERSION)
+    }
+
+    /// Upgrade the db to `target` by running `migrations` in order.
+    ///
+    /// Empty dbs are stamped with `target` without running anything. Errors
+    /// if the db is newer than `target`, or if a migration is missing.
+    pub fn run_migrations(&self, migrations: &[Migration], target: u32) -> Result<(), DbError> {
+        let stored = self.schema_version()?;
+        if stored.is_none() && self.is_empty() {
+            info!(version = target, "Stamping new db with schema version");
+            return self.store_schema_version(target);
+        }
+
+        let mut current = stored.unwrap_or(LEGACY_SCHEMA_VERSION);
+        if current > target {
+            return Err(DbError::SchemaTooNew {
+                found: current,
+                supported: target,
+            });
+        }
+
+        while current < target {
+            let migration = migrations
+                .iter()
+                .find(|migration| migration.version == current + 1)
+                .ok_or(DbError::MissingMigration(current + 1))?;
+
+            info!(
+                from = current,
+                to = migration.version,
+                description = migration.description,
+                "Migrating db schema"
+            );
+            (migration.migrate)(self)?;
+            self.store_schema_version(migration.version)?;
+            current = migration.version;
+        }
+
+        // stamp legacy dbs that are already current
+        if stored.is_none() {
+            self.store_schema_version(current)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{Options, DB as Rocks};
+
+    fn temp_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "nomad_migration_test_{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ))
+    }
+
+    fn with_db(test: impl FnOnce(&DB)) {
+        let path = temp_path();
+        {
+            let db = DB::open(&path).unwrap();
+            test(&db);
+        }
+        let _ = Rocks::destroy(&Options::default(), &path);
+    }
+
+    fn add_marker(db: &DB) -> Result<(), DbError> {
+        db.store_encodable("", "marker", &7u32)
+    }
+
+    static TEST_MIGRATIONS: &[Migration] = &[
+        Migration {
+            version: 2,
+            description: "add marker",
+            migrate: add_marker,
+        },
+        Migration {
+            version: 3,
+            description: "no-op",
+            migrate: |_| Ok(()),
+        },
+    ];
+
+    #[test]
+    fn it_stamps_new_dbs() {
+        with_db(|db| {
+            db.run_migrations(TEST_MIGRATIONS, 3).unwrap();
+            assert_eq!(db.schema_version().unwrap(), Some(3));
+            // nothing was run
+            let marker: Option<u32> = db.retrieve_decodable("", "marker").unwrap();
+            assert!(marker.is_none());
+        });
+    }
+
+    #[test]
+    fn it_migrates_legacy_dbs() {
+        with_db(|db| {
+            db.store_encodable("", "legacy", &1u32).unwrap();
+            db.run_migrations(TEST_MIGRATIONS, 3).unwrap();
+            assert_eq!(db.schema_version().unwrap(), Some(3));
+            assert_eq!(db.retrieve_decodable("", "marker").unwrap(), Some(7u32));
+
+            // already current
+            db.run_migrations(TEST_MIGRATIONS, 3).unwrap();
+        });
+    }
+
+    #[test]
+    fn it_refuses_newer_dbs() {
+        with_db(|db| {
+            db.run_migrations(TEST_MIGRATIONS, 3).unwrap();
+            assert!(matches!(
+                db.run_migrations(TEST_MIGRATIONS, 2),
+                Err(DbError::SchemaTooNew {
+                    found: 3,
+                    supported: 2
+                })
+            ));
+        });
+    }
+
+    #[test]
+    fn it_opens_only_current_dbs_as_is() {
+        let path = temp_path();
+        assert!(matches!(DB::open_current(&path), Err(DbError::IoError(_))));
+
+        DB::open(&path)
+            .unwrap()
+            .store_encodable("", "legacy", &1u32)
+            .unwrap();
+        assert!(matches!(
+            DB::open_current(&path),
+            Err(DbError::SchemaMismatch {
+                found: LEGACY_SCHEMA_VERSION,
+                supported: SCHEMA_VERSION,
+            })
+        ));
+        // nothing was migrated
+        assert_eq!(DB::open(&path).unwrap().schema_version().unwrap(), None);
+
+        DB::open(&path).unwrap().migrate().unwrap();
+        assert!(DB::open_current(&path).is_ok());
+        let _ = Rocks::destroy(&Options::default(), &path);
+    }
+
+    #[test]
+    fn it_errors_on_missing_migrations() {
+        with_db(|db| {
+            db.store_encodable("", "legacy", &1u32).unwrap();
+            assert!(matches!(
+                db.run_migrations(&TEST_MIGRATIONS[1..], 3),
+                Err(DbError::MissingMigration(2))
+            ));
+        });
+    }
+
+    #[test]
+    fn it_pads_v2_update_metadata() {
+        with_db(|db| {
+            let root = [7u8; 32];
+            let mut key = b"polygon_pos_update_metadata_".to_vec();
+            key.extend(root);
+            let mut current_key = b"ethereum_update_metadata_".to_vec();
+            current_key.extend(root);
+            let metadata = db.column(Column::Metadata);
+            metadata._store(&key, [1u8; 16]).unwrap();
+            metadata._store(&current_key, [2u8; 80]).unwrap();
+            db.store_schema_version(2).unwrap();
+
+            db.run_migrations(MIGRATIONS, 3).unwrap();
+
+            let mut padded = vec![1u8; 16];
+            padded.extend([0u8; 64]);
+            assert_eq!(metadata._retrieve(&key).unwrap(), Some(padded));
+            assert_eq!(
+                metadata._retrieve(&current_key).unwrap(),
+                Some(vec![2u8; 80])
+            );
+            assert_eq!(db.schema_version().unwrap(), Some(3));
+        });
+    }
+
+    #[test]
+    fn it_splits_v1_keys_into_columns() {
+        with_db(|db| {
+            let leaf_key = b"polygon_pos_leaf_\x00\x00\x00\x01".to_vec();
+            let meta_key = b"ethereum_update_metadata_root".to_vec();
+            let nonce_key = b"ethereum_current_nonce_\x00\x00\x03\xe8".to_vec();
+            db._store(&leaf_key, b"leaf").unwrap();
+            db._store(&meta_key, b"meta").unwrap();
+            db._store(&nonce_key, b"nonce").unwrap();
+            db._store(b"unrelated", b"value").unwrap();
+
+            db.run_migrations(MIGRATIONS, 2).unwrap();
+
+            let leaves = db.column(Column::Leaves);
+            assert_eq!(leaves._retrieve(&leaf_key).unwrap(), Some(b"leaf".to_vec()));
+            assert!(db._retrieve(&leaf_key).unwrap().is_none());
+
+            let metadata = db.column(Column::Metadata);
+            assert_eq!(
+                metadata._retrieve(&meta_key).unwrap(),
+                Some(b"meta".to_vec())
+            );
+            assert_eq!(
+                metadata._retrieve(&nonce_key).unwrap(),
+                Some(b"nonce".to_vec())
+            );
+
+            // unknown keys stay put
+            assert_eq!(db._retrieve(b"unrelated").unwrap(), Some(b"value".to_vec()));
+            assert_eq!(db.schema_version().unwrap(), Some(2));
+        });
+    }
+}
@@ -1,6 +1,14 @@
 use color_eyre::eyre::WrapErr;
-use rocksdb::{DBIterator, Options, DB as Rocks};
-use std::{path::Path, sync::Arc};
+pub use rocksdb::Direction;
+use rocksdb::{
+    ColumnFamily, ColumnFamilyDescriptor, DBIterator, IteratorMode, Options, WriteBatch,
+    DB as Rocks,
+};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+};
 use tracing::info;
 
 /// Shared functionality surrounding use of rocksdb
@@ -10,16 +18,37 @@ pub mod iterator;
 mod typed_db;
 pub use typed_db::*;
 
+/// RocksDB-backed storage for sparse merkle trees
+mod node_store;
+pub use node_store::*;
+
+/// Schema versioning and migrations
+mod migration;
+pub use migration::*;
+
+/// Column families
+mod column;
+pub use column::*;
+
+/// Entity export, import and checkpoints
+mod export;
+pub use export::*;
+
 use crate::{Decode, Encode, NomadError};
 
-#[derive(Debug, Clone)]
-/// A KV Store
-pub struct DB(Arc<Rocks>);
+/// Writes buffered by a batch, by column family and key. `None` is a delete.
+type PendingWrites = HashMap<(Column, Vec<u8>), Option<Vec<u8>>>;
 
-impl From<Rocks> for DB {
-    fn from(rocks: Rocks) -> Self {
-        Self(Arc::new(rocks))
-    }
+#[derive(Debug, Clone)]
+/// A KV Store. Each handle reads and writes a single column family, see
+/// `DB::column`. Handles created by `DB::batch` buffer their writes, see
+/// `DB::batch`.
+pub struct DB {
+    rocks: Arc<Rocks>,
+    column: Column,
+    pending: Option<Arc<Mutex<PendingWrites>>>,
+    /// Held while a batch runs, see `DB::batch`
+    batches: Arc<Mutex<()>>,
 }
 
 /// DB Error type
@@ -31,12 +60,43 @@ pub enum DbError {
     /// Nomad Error
     #[error("{0}")]
     NomadError(#[from] NomadError),
+    /// DB was written by a newer binary
+    #[error("DB schema version {found} is newer than supported version {supported}")]
+    SchemaTooNew {
+        /// Version found on disk
+        found: u32,
+        /// Latest version this binary supports
+        supported: u32,
+    },
+    /// DB is not at the schema version this binary reads. Running an agent
+    /// of this version migrates it
+    #[error("DB schema version {found} is not the supported version {supported}")]
+    SchemaMismatch {
+        /// Version found on disk
+        found: u32,
+        /// Version this binary reads
+        supported: u32,
+    },
+    /// No migration to upgrade to a schema version
+    #[error("No migration to DB schema version {0}")]
+    MissingMigration(u32),
+    /// IO Error
+    #[error("{0}")]
+    IoError(#[from] std::io::Error),
+    /// Malformed or incompatible export
+    #[error("Invalid DB export: {0}")]
+    InvalidExport(String),
+    /// Stored merkle tree is missing nodes or cannot hold more leaves
+    #[error("Invalid stored tree: {0}")]
+    InvalidTree(String),
 }
 
 type Result<T> = std::result::Result<T, DbError>;
 
 impl DB {
-    /// Opens db at `db_path` and creates if missing
+    /// Opens db at `db_path` and creates if missing. Older schemas are
+    /// migrated to `SCHEMA_VERSION`. Errors if the schema is newer than this
+    /// binary supports.
     #[tracing::instrument(err)]
     pub fn from_path(db_path: &str) -> color_eyre::Result<DB> {
         // Canonicalize ensures existence, so we have to do that, then extend
@@ -51,25 +111,145 @@ impl DB {
             false => info!("Creating db at {path}", path = path.to_str().unwrap()),
         }
 
+        let db = Self::open(&path).wrap_err(format!(
+            "Failed to open db path {}, canonicalized as {:?}",
+            db_path, path
+        ))?;
+
+        db.migrate()
+            .wrap_err(format!("Failed to migrate db at {:?}", path))?;
+
+        Ok(db)
+    }
+
+    /// Opens db at `path` with all column families, creating any that are
+    /// missing. Does not migrate.
+    pub fn open(path: impl AsRef<Path>) -> Result<DB> {
         let mut opts = Options::default();
         opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+
+        let columns = Column::ALL
+            .iter()
+            .map(|column| ColumnFamilyDescriptor::new(column.name(), column.options()));
+
+        Ok(Self {
+            rocks: Arc::new(Rocks::open_cf_descriptors(&opts, path, columns)?),
+            column: Column::Default,
+            pending: None,
+            batches: Default::default(),
+        })
+    }
+
+    /// Get a handle to `column` on the same db
+    pub fn column(&self, column: Column) -> DB {
+        Self {
+            rocks: self.rocks.clone(),
+            column,
+            pending: self.pending.clone(),
+            batches: self.batches.clone(),
+        }
+    }
+
+    /// Get the handle for a column family
+    fn cf_for(&self, column: Column) -> &ColumnFamily {
+        self.rocks
+            .cf_handle(column.name())
+            .expect("!column family opened")
+    }
+
+    /// Get the column family this handle reads and writes
+    fn cf(&self) -> &ColumnFamily {
+        self.cf_for(self.column)
+    }
+
+    /// Run `f` against a handle whose writes are buffered, then commit all
+    /// of them atomically if `f` succeeds. If `f` errors nothing is written.
+    ///
+    /// Reads through the handle see its own buffered writes. Iterators do
+    /// not. Calling `batch` on a batch handle joins the outer batch.
+    ///
+    /// Batches on the same db run one at a time, so a read-modify-write in
+    /// a batch cannot interleave with one in another batch. Do not start a
+    /// batch from a handle other than `f`'s argument within `f`.
+    pub fn batch<T, E, F>(&self, f: F) -> std::result::Result<T, E>
+    where
+        F: FnOnce(&DB) -> std::result::Result<T, E>,
+        E: From<DbError>,
+    {
+        if self.pending.is_some() {
+            return f(self);
+        }
+
+        let _running = self.batches.lock().unwrap_or_else(PoisonError::into_inner);
+        let pending = Arc::new(Mutex::new(PendingWrites::new()));
+        let batch = Self {
+            rocks: self.rocks.clone(),
+            column: self.column,
+            pending: Some(pending.clone()),
+            batches: self.batches.clone(),
+        };
+        let result = f(&batch)?;
+
+        let writes = std::mem::take(&mut *pending.lock().expect("!poisoned"));
+        let mut write_batch = WriteBatch::default();
+        for ((column, key), value) in writes {
+            match value {
+                Some(value) => write_batch.put_cf(self.cf_for(column), key, value),
+                None => write_batch.delete_cf(self.cf_for(column), key),
+            }
+        }
+        self.rocks.write(write_batch).map_err(DbError::from)?;
+
+        Ok(result)
+    }
 
-        Rocks::open(&opts, &path)
-            .wrap_err(format!(
-                "Failed to open db path {}, canonicalized as {:?}",
-                db_path, path
-            ))
-            .map(Into::into)
+    /// Buffer a write if this is a batch handle. Returns false otherwise.
+    fn buffer(&self, key: &[u8], value: Option<&[u8]>) -> bool {
+        match &self.pending {
+            Some(pending) => {
+                pending
+                    .lock()
+                    .expect("!poisoned")
+                    .insert((self.column, key.to_vec()), value.map(<[u8]>::to_vec));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Check if the db holds no keys in any column family
+    pub fn is_empty(&self) -> bool {
+        Column::ALL
+            .iter()
+            .all(|column| self.column(*column).iterator().next().is_none())
     }
 
     /// Store a value in the DB
     fn _store(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<()> {
-        Ok(self.0.put(key, value)?)
+        if self.buffer(key.as_ref(), Some(value.as_ref())) {
+            return Ok(());
+        }
+        Ok(self.rocks.put_cf(self.cf(), key, value)?)
     }
 
     /// Retrieve a value from the DB
     fn _retrieve(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
-        Ok(self.0.get(key)?)
+        if let Some(pending) = &self.pending {
+            let pending = pending.lock().expect("!poisoned");
+            if let Some(value) = pending.get(&(self.column, key.as_ref().to_vec())) {
+                return Ok(value.clone());
+            }
+        }
+        Ok(self.rocks.get_cf(self.cf(), key)?)
+    }
+
+    /// Delete a value from the DB
+    fn _delete(&self, key: impl AsRef<[u8]>) -> Result<()> {
+        if self.buffer(key.as_ref(), None) {
+            return Ok(());
+        }
+        Ok(self.rocks.delete_cf(self.cf(), key)?)
     }
 
     /// Prefix a key and store in the DB
@@ -119,6 +299,14 @@ impl DB {
             .transpose()?)
     }
 
+    /// Prefix the key and delete
+    fn prefix_delete(&self, prefix: impl AsRef<[u8]>, key: impl AsRef<[u8]>) -> Result<()> {
+        let mut buf = vec![];
+        buf.extend(prefix.as_ref());
+        buf.extend(key.as_ref());
+        self._delete(buf)
+    }
+
     /// Store any encodeable
     pub fn store_keyed_encodable<K: Encode, V: Encode>(
         &self,
@@ -138,8 +326,37 @@ impl DB {
         self.retrieve_decodable(prefix, key.to_vec())
     }
 
+    /// Delete the value stored under `key`
+    pub fn delete(&self, prefix: impl AsRef<[u8]>, key: impl AsRef<[u8]>) -> Result<()> {
+        self.prefix_delete(prefix, key)
+    }
+
+    /// Delete any value stored under an encodable key
+    pub fn delete_keyed<K: Encode>(&self, prefix: impl AsRef<[u8]>, key: &K) -> Result<()> {
+        self.prefix_delete(prefix, key.to_vec())
+    }
+
     /// Get prefix db iterator for `prefix`
     pub fn prefix_iterator(&self, prefix: impl AsRef<[u8]>) -> DBIterator {
-        self.0.prefix_iterator(prefix)
+        self.prefix_iterator_cf(self.column, prefix)
+    }
+
+    /// Get prefix db iterator for `prefix` in another column family. Unlike
+    /// `column(..).prefix_iterator(..)`, the iterator borrows this handle.
+    pub fn prefix_iterator_cf(&self, column: Column, prefix: impl AsRef<[u8]>) -> DBIterator {
+        self.rocks.prefix_iterator_cf(self.cf_for(column), prefix)
+    }
+
+    /// Get db iterator over the whole column family
+    pub fn iterator(&self) -> DBIterator {
+        self.rocks.iterator_cf(self.cf(), IteratorMode::Start)
+    }
+
+    /// Get db iterator over the column family, starting at `key` and moving
+    /// in `direction`. Reversed, it starts at the last key before `key` if
+    /// `key` itself is absent.
+    pub fn iterator_from(&self, key: impl AsRef<[u8]>, direction: Direction) -> DBIterator {
+        self.rocks
+            .iterator_cf(self.cf(), IteratorMode::From(key.as_ref(), direction))
     }
 }
@@ -0,0 +1,80 @@
+use crate::db::{DbError, TypedDB};
+use accumulator::{NodeStore, StoreError};
+use ethers::core::types::H256;
+
+static TREE_NODE: &str = "tree_node_";
+static TREE_LEAF_COUNT: &str = "tree_leaf_count";
+
+/// A RocksDB-backed `NodeStore` for an `accumulator::StoredTree`.
+///
+/// Key structure: ```<entity>_tree_node_<height><index>```
+#[derive(Debug, Clone)]
+pub struct DbNodeStore {
+    db: TypedDB,
+}
+
+impl DbNodeStore {
+    /// Instantiate a new `DbNodeStore`
+    pub fn new(db: TypedDB) -> Self {
+        Self { db }
+    }
+
+    fn node_key(height: usize, index: usize) -> Vec<u8> {
+        let mut key = Vec::with_capacity(12);
+        key.extend((height as u32).to_be_bytes());
+        key.extend((index as u64).to_be_bytes());
+        key
+    }
+}
+
+impl NodeStore for DbNodeStore {
+    type Error = DbError;
+
+    fn node(&self, height: usize, index: usize) -> Result<Option<H256>, Self::Error> {
+        self.db
+            .retrieve_decodable(TREE_NODE, Self::node_key(height, index))
+    }
+
+    fn store_node(&mut self, height: usize, index: usize, node: H256) -> Result<(), Self::Error> {
+        self.db
+            .store_encodable(TREE_NODE, Self::node_key(height, index), &node)
+    }
+
+    fn delete_node(&mut self, height: usize, index: usize) -> Result<(), Self::Error> {
+        self.db.delete(TREE_NODE, Self::node_key(height, index))
+    }
+
+    fn leaf_count(&self) -> Result<Option<usize>, Self::Error> {
+        Ok(self
+            .db
+            .retrieve_decodable::<u64>("", TREE_LEAF_COUNT)?
+            .map(|count| count as usize))
+    }
+
+    fn store_leaf_count(&mut self, count: usize) -> Result<(), Self::Error> {
+        self.db
+            .store_encodable("", TREE_LEAF_COUNT, &(count as u64))
+    }
+
+    fn store_path(
+        &mut self,
+        nodes: &[(usize, usize, H256)],
+        count: usize,
+    ) -> Result<(), Self::Error> {
+        self.db.batch(|db| {
+            for (height, index, node) in nodes {
+                db.store_encodable(TREE_NODE, Self::node_key(*height, *index), node)?;
+            }
+            db.store_encodable("", TREE_LEAF_COUNT, &(count as u64))
+        })
+    }
+}
+
+impl From<StoreError<DbError>> for DbError {
+    fn from(e: StoreError<DbError>) -> Self {
+        match e {
+            StoreError::Store(e) => e,
+            e => DbError::InvalidTree(e.to_string()),
+        }
+    }
+}
@@ -1,8 +1,12 @@
 use crate::{
-    db::{DbError, DB},
+    db::{Column, DbError, Direction, DB},
     Decode, Encode,
 };
 use color_eyre::Result;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
 /// DB handle for storing data tied to a specific type/entity.
 ///
@@ -11,6 +15,8 @@ use color_eyre::Result;
 pub struct TypedDB {
     entity: String,
     db: DB,
+    /// Whether this handle registered its entity, see `DB::entities`
+    registered: Arc<AtomicBool>,
 }
 
 impl AsRef<DB> for TypedDB {
@@ -22,7 +28,41 @@ impl AsRef<DB> for TypedDB {
 impl TypedDB {
     /// Instantiate new `TypedDB`
     pub fn new(entity: String, db: DB) -> Self {
-        Self { entity, db }
+        Self {
+            entity,
+            db,
+            registered: Default::default(),
+        }
+    }
+
+    /// Get a handle to `column` for the same entity
+    pub fn column(&self, column: Column) -> TypedDB {
+        Self {
+            entity: self.entity.clone(),
+            db: self.db.column(column),
+            registered: self.registered.clone(),
+        }
+    }
+
+    /// Run `f` against a handle whose writes are committed atomically if it
+    /// succeeds. See `DB::batch`.
+    pub fn batch<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&TypedDB) -> Result<T, E>,
+        E: From<DbError>,
+    {
+        self.db
+            .batch(|db| f(&TypedDB::new(self.entity.clone(), db.clone())))
+    }
+
+    /// Register the entity before its first write. Batch handles register
+    /// it in every batch, as the batch may not commit.
+    fn register(&self) -> Result<(), DbError> {
+        if !self.registered.load(Ordering::Relaxed) {
+            self.db.register_entity(&self.entity)?;
+            self.registered.store(true, Ordering::Relaxed);
+        }
+        Ok(())
     }
 
     fn full_prefix(&self, prefix: impl AsRef<[u8]>) -> Vec<u8> {
@@ -40,6 +80,7 @@ impl TypedDB {
         key: impl AsRef<[u8]>,
         value: &V,
     ) -> Result<(), DbError> {
+        self.register()?;
         self.db
             .store_encodable(self.full_prefix(prefix), key, value)
     }
@@ -60,6 +101,7 @@ impl TypedDB {
         key: &K,
         value: &V,
     ) -> Result<(), DbError> {
+        self.register()?;
         self.db
             .store_keyed_encodable(self.full_prefix(prefix), key, value)
     }
@@ -73,4 +115,53 @@ impl TypedDB {
         self.db
             .retrieve_keyed_decodable(self.full_prefix(prefix), key)
     }
+
+    /// Iterate over the keys and values stored under `prefix`, in key order.
+    /// Keys are returned without the prefix. Like all iterators, does not
+    /// see the writes buffered by a batch.
+    pub fn prefix_entries(
+        &self,
+        prefix: impl AsRef<[u8]>,
+    ) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> + '_ {
+        let full_prefix = self.full_prefix(prefix);
+        let len = full_prefix.len();
+        self.db
+            .prefix_iterator(full_prefix.clone())
+            .take_while(move |(key, _)| key.starts_with(&full_prefix))
+            .map(move |(key, value)| (key[len..].to_vec(), value.to_vec()))
+    }
+
+    /// Iterate over the keys and values stored under `prefix`, from `from`
+    /// in `direction`. Keys are returned without the prefix. Encoded integer
+    /// keys are big-endian, so this scans a range of them in order. Like
+    /// `prefix_entries`, does not see the writes buffered by a batch.
+    pub fn prefix_entries_from<K: Encode>(
+        &self,
+        prefix: impl AsRef<[u8]>,
+        from: &K,
+        direction: Direction,
+    ) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> + '_ {
+        let full_prefix = self.full_prefix(prefix);
+        let len = full_prefix.len();
+        let mut start = full_prefix.clone();
+        start.extend(from.to_vec());
+        self.db
+            .iterator_from(start, direction)
+            .take_while(move |(key, _)| key.starts_with(&full_prefix))
+            .map(move |(key, value)| (key[len..].to_vec(), value.to_vec()))
+    }
+
+    /// Delete value stored under `key`
+    pub fn delete(&self, prefix: impl AsRef<[u8]>, key: impl AsRef<[u8]>) -> Result<(), DbError> {
+        self.db.delete(self.full_prefix(prefix), key)
+    }
+
+    /// Delete value stored under encodable key
+    pub fn delete_keyed<K: Encode>(
+        &self,
+        prefix: impl AsRef<[u8]>,
+        key: &K,
+    ) -> Result<(), DbError> {
+        self.db.delete_keyed(self.full_prefix(prefix), key)
+    }
 }
@@ -84,4 +84,13 @@ pub enum NomadError {
     /// IO error from Read/Write usage
     #[error(transparent)]
     IoError(#[from] std::io::Error),
+    /// Tree snapshot was written in a format this version cannot read
+    #[error("Unsupported tree snapshot version: {0}")]
+    UnsupportedSnapshotVersion(u8),
+    /// Message body has an unrecognized type tag
+    #[error("Unknown message body type: {0}")]
+    UnknownBodyType(u8),
+    /// Message kind name is not recognized
+    #[error("Unknown message kind: {0}")]
+    UnknownMessageKind(String),
 }
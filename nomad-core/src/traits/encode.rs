@@ -152,3 +152,71 @@ impl<const N: usize> Decode for accumulator::Proof<N> {
         Ok(Self { leaf, index, path })
     }
 }
+
+impl<const N: usize> Encode for accumulator::TreeSnapshot<N> {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        let mut written = 0;
+        writer.write_all(&[accumulator::SNAPSHOT_VERSION])?;
+        written += 1;
+        written += (self.count as u64).write_to(writer)?;
+        for hash in self.branch.iter() {
+            written += hash.write_to(writer)?;
+        }
+        match &self.nodes {
+            Some(nodes) => {
+                writer.write_all(&[1])?;
+                written += 1;
+                written += (nodes.len() as u64).write_to(writer)?;
+                for hash in nodes.iter() {
+                    written += hash.write_to(writer)?;
+                }
+            }
+            None => {
+                writer.write_all(&[0])?;
+                written += 1;
+            }
+        }
+        Ok(written)
+    }
+}
+
+impl<const N: usize> Decode for accumulator::TreeSnapshot<N> {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+        Self: Sized,
+    {
+        let mut version = [0u8; 1];
+        reader.read_exact(&mut version)?;
+        if version[0] != accumulator::SNAPSHOT_VERSION {
+            return Err(NomadError::UnsupportedSnapshotVersion(version[0]));
+        }
+
+        let count = u64::read_from(reader)? as usize;
+        let mut branch = [H256::default(); N];
+        for item in &mut branch {
+            *item = H256::read_from(reader)?;
+        }
+
+        let mut has_nodes = [0u8; 1];
+        reader.read_exact(&mut has_nodes)?;
+        let nodes = if has_nodes[0] == 0 {
+            None
+        } else {
+            let len = u64::read_from(reader)?;
+            (0..len)
+                .map(|_| H256::read_from(reader))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Some)?
+        };
+
+        Ok(Self {
+            count,
+            branch,
+            nodes,
+        })
+    }
+}
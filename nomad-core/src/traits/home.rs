@@ -4,7 +4,7 @@ use crate::{
     db::DbError,
     traits::{ChainCommunicationError, Common, TxOutcome},
     utils::home_domain_hash,
-    Decode, Encode, Message, NomadError, NomadMessage, SignedUpdate, Update,
+    BlockHeader, Decode, Encode, Message, NomadError, NomadMessage, SignedUpdate, Update,
 };
 use async_trait::async_trait;
 use color_eyre::Result;
@@ -69,6 +69,41 @@ impl Decode for RawCommittedMessage {
     }
 }
 
+/// Metadata about the event that dispatched a message
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MessageMeta {
+    /// Block number
+    pub block_number: u64,
+    /// Transaction hash
+    pub transaction_hash: H256,
+    /// Timestamp seconds (optional because fetching timestamp is fallible)
+    pub timestamp: Option<u64>,
+    /// Hash of the block the message was dispatched in. Zero if unknown
+    pub block_hash: H256,
+    /// Hash of the parent of that block. Zero if unknown
+    pub parent_hash: H256,
+}
+
+impl MessageMeta {
+    /// The header of the block the message was dispatched in
+    pub fn block(&self) -> BlockHeader {
+        BlockHeader {
+            number: self.block_number,
+            hash: self.block_hash,
+            parent_hash: self.parent_hash,
+        }
+    }
+}
+
+/// A raw committed message with metadata
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawCommittedMessageWithMeta {
+    /// Raw committed message
+    pub raw_message: RawCommittedMessage,
+    /// Metadata
+    pub metadata: MessageMeta,
+}
+
 // ember: tracingify these across usage points
 /// A Stamped message that has been committed at some leaf index
 #[derive(Debug, Default, Clone)]
@@ -9,8 +9,9 @@
 
 use async_trait::async_trait;
 use color_eyre::Result;
+use std::time::Duration;
 
-use crate::{RawCommittedMessage, SignedUpdateWithMeta};
+use crate::{BlockHeader, RawCommittedMessageWithMeta, SignedUpdateWithMeta};
 
 /// Interface for Common contract indexer. Interface that allows for other
 /// entities to retrieve chain-specific data from a home or replica.
@@ -19,7 +20,18 @@ pub trait CommonIndexer: Send + Sync + std::fmt::Debug {
     /// Get chain's latest block number
     async fn get_block_number(&self) -> Result<u32>;
 
-    /// Fetch sequentially sorted list of updates between blocks `from` and `to`
+    /// Get the header of the canonical block at `number`, if it exists
+    async fn get_block_header(&self, number: u32) -> Result<Option<BlockHeader>>;
+
+    /// Wait up to `timeout` for a block above `number`, if subscribed to new
+    /// blocks. Returns false without waiting otherwise, e.g. while the
+    /// subscription is disconnected, in which case callers poll instead.
+    async fn wait_for_block(&self, _number: u32, _timeout: Duration) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Fetch sequentially sorted list of updates between blocks `from` and
+    /// `to`, along with the hashes of the blocks they were emitted in
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>>;
 }
 
@@ -27,7 +39,11 @@ pub trait CommonIndexer: Send + Sync + std::fmt::Debug {
 /// entities to retrieve chain-specific data from a home.
 #[async_trait]
 pub trait HomeIndexer: CommonIndexer + Send + Sync + std::fmt::Debug {
-    /// Fetch list of messages between blocks `from` and `to`.
-    async fn fetch_sorted_messages(&self, _from: u32, _to: u32)
-        -> Result<Vec<RawCommittedMessage>>;
+    /// Fetch list of messages and their metadata between blocks `from` and
+    /// `to`, including the hashes of the blocks they were dispatched in.
+    async fn fetch_sorted_messages(
+        &self,
+        _from: u32,
+        _to: u32,
+    ) -> Result<Vec<RawCommittedMessageWithMeta>>;
 }
@@ -48,6 +48,13 @@ pub trait Replica: Common + Send + Sync + std::fmt::Debug {
     /// Fetch the status of a message
     async fn message_status(&self, leaf: H256) -> Result<MessageStatus, ChainCommunicationError>;
 
+    /// Fetch the hash of the transaction that processed a message, if it
+    /// was processed
+    async fn process_transaction(
+        &self,
+        leaf: H256,
+    ) -> Result<Option<H256>, ChainCommunicationError>;
+
     /// Fetch the confirmation time for a specific root
     async fn acceptable_root(&self, root: H256) -> Result<bool, ChainCommunicationError>;
 }
@@ -0,0 +1,48 @@
+use ethers::types::H256;
+
+use crate::{Decode, Encode, NomadError};
+
+/// The header fields of a block needed to follow the canonical chain
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BlockHeader {
+    /// Block number
+    pub number: u64,
+    /// Block hash
+    pub hash: H256,
+    /// Hash of the parent block
+    pub parent_hash: H256,
+}
+
+impl BlockHeader {
+    /// Whether `self` is the child of `parent`
+    pub fn builds_on(&self, parent: &BlockHeader) -> bool {
+        self.number == parent.number + 1 && self.parent_hash == parent.hash
+    }
+}
+
+impl Encode for BlockHeader {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        let mut written = 0;
+        written += self.number.write_to(writer)?;
+        written += self.hash.write_to(writer)?;
+        written += self.parent_hash.write_to(writer)?;
+        Ok(written)
+    }
+}
+
+impl Decode for BlockHeader {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+        Self: Sized,
+    {
+        Ok(Self {
+            number: u64::read_from(reader)?,
+            hash: H256::read_from(reader)?,
+            parent_hash: H256::read_from(reader)?,
+        })
+    }
+}
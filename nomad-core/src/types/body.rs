@@ -0,0 +1,659 @@
+use ethers::{
+    types::{H256, U256},
+    utils::keccak256,
+};
+use nomad_xyz_configuration::{bridge::BridgeContracts, contracts::CoreContracts, NomadConfig};
+use std::collections::HashMap;
+
+use crate::{Decode, Encode, NomadError};
+
+const TOKEN_ID_LEN: usize = 36;
+const TRANSFER_LEN: usize = 97;
+const TRANSFER_TO_HOOK_PREFIX_LEN: usize = 129;
+const BATCH_LEN: usize = 33;
+const TRANSFER_GOVERNOR_LEN: usize = 37;
+
+/// BridgeRouter action type tags
+mod bridge_types {
+    pub const TRANSFER: u8 = 3;
+    pub const FAST_TRANSFER: u8 = 4;
+    pub const TRANSFER_TO_HOOK: u8 = 5;
+}
+
+/// GovernanceRouter message type tags
+mod governance_types {
+    pub const BATCH: u8 = 1;
+    pub const TRANSFER_GOVERNOR: u8 = 2;
+}
+
+fn read_u8<R: std::io::Read>(reader: &mut R) -> Result<u8, NomadError> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u256<R: std::io::Read>(reader: &mut R) -> Result<U256, NomadError> {
+    let mut buf = [0u8; 32];
+    reader.read_exact(&mut buf)?;
+    Ok(U256::from_big_endian(&buf))
+}
+
+fn write_u256<W: std::io::Write>(writer: &mut W, value: U256) -> std::io::Result<usize> {
+    let mut buf = [0u8; 32];
+    value.to_big_endian(&mut buf);
+    writer.write_all(&buf)?;
+    Ok(32)
+}
+
+/// A token identifier: its canonical domain and its address there
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TokenId {
+    /// 4   Canonical domain of the token
+    pub domain: u32,
+    /// 32  Address of the token on its canonical domain
+    pub id: H256,
+}
+
+impl Encode for TokenId {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        writer.write_all(&self.domain.to_be_bytes())?;
+        writer.write_all(self.id.as_ref())?;
+        Ok(TOKEN_ID_LEN)
+    }
+}
+
+impl Decode for TokenId {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+    {
+        Ok(Self {
+            domain: u32::read_from(reader)?,
+            id: H256::read_from(reader)?,
+        })
+    }
+}
+
+/// An action sent by the BridgeRouter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BridgeAction {
+    /// Transfer tokens to a recipient
+    Transfer {
+        /// 32  Recipient of the tokens
+        recipient: H256,
+        /// 32  Amount of tokens
+        amount: U256,
+        /// 32  Hash of the token name, symbol and decimals
+        details_hash: H256,
+    },
+    /// Deprecated fast liquidity transfer
+    FastTransfer {
+        /// 32  Recipient of the tokens
+        recipient: H256,
+        /// 32  Amount of tokens
+        amount: U256,
+        /// 32  Hash of the token name, symbol and decimals
+        details_hash: H256,
+    },
+    /// Transfer tokens to a hook contract, which is called with extra data
+    TransferToHook {
+        /// 32  Hook contract
+        hook: H256,
+        /// 32  Amount of tokens
+        amount: U256,
+        /// 32  Hash of the token name, symbol and decimals
+        details_hash: H256,
+        /// 32  Sender on the origin domain
+        sender: H256,
+        /// 0+  Data passed to the hook
+        extra_data: Vec<u8>,
+    },
+}
+
+/// A full BridgeRouter message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BridgeMessage {
+    /// 36  The token being transferred
+    pub token: TokenId,
+    /// 97+ The action to take
+    pub action: BridgeAction,
+}
+
+impl Encode for BridgeMessage {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        let mut written = self.token.write_to(writer)?;
+        match &self.action {
+            BridgeAction::Transfer {
+                recipient,
+                amount,
+                details_hash,
+            }
+            | BridgeAction::FastTransfer {
+                recipient,
+                amount,
+                details_hash,
+            } => {
+                let tag = if matches!(self.action, BridgeAction::Transfer { .. }) {
+                    bridge_types::TRANSFER
+                } else {
+                    bridge_types::FAST_TRANSFER
+                };
+                writer.write_all(&[tag])?;
+                writer.write_all(recipient.as_ref())?;
+                write_u256(writer, *amount)?;
+                writer.write_all(details_hash.as_ref())?;
+                written += TRANSFER_LEN;
+            }
+            BridgeAction::TransferToHook {
+                hook,
+                amount,
+                details_hash,
+                sender,
+                extra_data,
+            } => {
+                writer.write_all(&[bridge_types::TRANSFER_TO_HOOK])?;
+                writer.write_all(hook.as_ref())?;
+                write_u256(writer, *amount)?;
+                writer.write_all(details_hash.as_ref())?;
+                writer.write_all(sender.as_ref())?;
+                writer.write_all(extra_data)?;
+                written += TRANSFER_TO_HOOK_PREFIX_LEN + extra_data.len();
+            }
+        }
+        Ok(written)
+    }
+}
+
+impl Decode for BridgeMessage {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+    {
+        let token = TokenId::read_from(reader)?;
+        let action = match read_u8(reader)? {
+            tag @ (bridge_types::TRANSFER | bridge_types::FAST_TRANSFER) => {
+                let recipient = H256::read_from(reader)?;
+                let amount = read_u256(reader)?;
+                let details_hash = H256::read_from(reader)?;
+                if tag == bridge_types::TRANSFER {
+                    BridgeAction::Transfer {
+                        recipient,
+                        amount,
+                        details_hash,
+                    }
+                } else {
+                    BridgeAction::FastTransfer {
+                        recipient,
+                        amount,
+                        details_hash,
+                    }
+                }
+            }
+            bridge_types::TRANSFER_TO_HOOK => {
+                let hook = H256::read_from(reader)?;
+                let amount = read_u256(reader)?;
+                let details_hash = H256::read_from(reader)?;
+                let sender = H256::read_from(reader)?;
+                let mut extra_data = vec![];
+                reader.read_to_end(&mut extra_data)?;
+                BridgeAction::TransferToHook {
+                    hook,
+                    amount,
+                    details_hash,
+                    sender,
+                    extra_data,
+                }
+            }
+            tag => return Err(NomadError::UnknownBodyType(tag)),
+        };
+
+        Ok(Self { token, action })
+    }
+}
+
+/// A call to be executed by the GovernanceRouter
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Call {
+    /// 32  Contract to call
+    pub to: H256,
+    /// 0+  Calldata
+    pub data: Vec<u8>,
+}
+
+impl Encode for Call {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        writer.write_all(self.to.as_ref())?;
+        writer.write_all(&(self.data.len() as u32).to_be_bytes())?;
+        writer.write_all(&self.data)?;
+        Ok(32 + 4 + self.data.len())
+    }
+}
+
+impl Call {
+    /// Calculate the hash committed to by a `GovernanceMessage::Batch`
+    /// containing `calls`
+    pub fn batch_hash(calls: &[Call]) -> H256 {
+        let mut buf = vec![calls.len() as u8];
+        for call in calls {
+            call.write_to(&mut buf).expect("!alloc");
+        }
+        keccak256(buf).into()
+    }
+}
+
+/// A GovernanceRouter message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GovernanceMessage {
+    /// Commit to a batch of calls, which are executed separately
+    Batch {
+        /// 32  Hash of the batched calls. See `Call::batch_hash`
+        batch_hash: H256,
+    },
+    /// Transfer governorship
+    TransferGovernor {
+        /// 4   Domain of the new governor
+        domain: u32,
+        /// 32  Address of the new governor
+        governor: H256,
+    },
+}
+
+impl Encode for GovernanceMessage {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        match self {
+            GovernanceMessage::Batch { batch_hash } => {
+                writer.write_all(&[governance_types::BATCH])?;
+                writer.write_all(batch_hash.as_ref())?;
+                Ok(BATCH_LEN)
+            }
+            GovernanceMessage::TransferGovernor { domain, governor } => {
+                writer.write_all(&[governance_types::TRANSFER_GOVERNOR])?;
+                writer.write_all(&domain.to_be_bytes())?;
+                writer.write_all(governor.as_ref())?;
+                Ok(TRANSFER_GOVERNOR_LEN)
+            }
+        }
+    }
+}
+
+impl Decode for GovernanceMessage {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+    {
+        match read_u8(reader)? {
+            governance_types::BATCH => Ok(GovernanceMessage::Batch {
+                batch_hash: H256::read_from(reader)?,
+            }),
+            governance_types::TRANSFER_GOVERNOR => Ok(GovernanceMessage::TransferGovernor {
+                domain: u32::read_from(reader)?,
+                governor: H256::read_from(reader)?,
+            }),
+            tag => Err(NomadError::UnknownBodyType(tag)),
+        }
+    }
+}
+
+/// The kind of action a message body carries. Useful for filtering and
+/// display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKind {
+    /// BridgeRouter transfer
+    Transfer,
+    /// BridgeRouter fast transfer
+    FastTransfer,
+    /// BridgeRouter transfer to hook
+    TransferToHook,
+    /// GovernanceRouter call batch
+    GovernanceBatch,
+    /// GovernanceRouter governor transfer
+    TransferGovernor,
+    /// Unrecognized body
+    Unknown,
+}
+
+impl MessageKind {
+    /// All message kinds
+    pub const ALL: [MessageKind; 6] = [
+        MessageKind::Transfer,
+        MessageKind::FastTransfer,
+        MessageKind::TransferToHook,
+        MessageKind::GovernanceBatch,
+        MessageKind::TransferGovernor,
+        MessageKind::Unknown,
+    ];
+
+    /// The kind's name, as used in configs and on the command line
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageKind::Transfer => "transfer",
+            MessageKind::FastTransfer => "fastTransfer",
+            MessageKind::TransferToHook => "transferToHook",
+            MessageKind::GovernanceBatch => "governanceBatch",
+            MessageKind::TransferGovernor => "transferGovernor",
+            MessageKind::Unknown => "unknown",
+        }
+    }
+}
+
+impl std::fmt::Display for MessageKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for MessageKind {
+    type Err = NomadError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MessageKind::ALL
+            .iter()
+            .find(|kind| kind.as_str() == s)
+            .copied()
+            .ok_or_else(|| NomadError::UnknownMessageKind(s.to_owned()))
+    }
+}
+
+/// The xApp routers deployed on a domain. Anyone can dispatch a body shaped
+/// like a router message, so bodies are only decoded as router messages when
+/// sent to that router.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct XAppRouters {
+    /// The BridgeRouter, if the bridge is deployed on the domain
+    pub bridge: Option<H256>,
+    /// The GovernanceRouter
+    pub governance: Option<H256>,
+}
+
+impl XAppRouters {
+    /// The routers of each network in `config`, by domain
+    pub fn from_config(config: &NomadConfig) -> HashMap<u32, XAppRouters> {
+        config
+            .protocol()
+            .networks
+            .iter()
+            .map(|(network, info)| {
+                let bridge = config.bridge().get(network).map(|bridge| match bridge {
+                    BridgeContracts::Evm(bridge) => bridge.bridge_router.proxy.into(),
+                });
+                let governance = config.core().get(network).map(|core| match core {
+                    CoreContracts::Evm(core) => core.governance_router.proxy.into(),
+                });
+                (info.domain, XAppRouters { bridge, governance })
+            })
+            .collect()
+    }
+}
+
+/// A decoded `NomadMessage` body.
+///
+/// Bodies are recognized by their structure. Bridge messages are a 36 byte
+/// token id followed by a transfer action. Governance messages are exactly
+/// 33 or 37 bytes. Anything else is `Unknown`. See `MessageBody::classify`
+/// to also check the recipient.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageBody {
+    /// A BridgeRouter message
+    Bridge(BridgeMessage),
+    /// A GovernanceRouter message
+    Governance(GovernanceMessage),
+    /// An unrecognized body
+    Unknown(Vec<u8>),
+}
+
+impl MessageBody {
+    /// Decode a message body sent to `recipient` on a domain with `routers`.
+    /// Bodies are only bridge or governance messages if sent to the
+    /// BridgeRouter or GovernanceRouter. Never fails, anything else is
+    /// `Unknown`.
+    pub fn classify(body: &[u8], recipient: H256, routers: &XAppRouters) -> Self {
+        match Self::parse(body) {
+            MessageBody::Bridge(message) if routers.bridge == Some(recipient) => {
+                MessageBody::Bridge(message)
+            }
+            MessageBody::Governance(message) if routers.governance == Some(recipient) => {
+                MessageBody::Governance(message)
+            }
+            _ => MessageBody::Unknown(body.to_vec()),
+        }
+    }
+
+    /// Decode a raw message body by its structure alone. Never fails,
+    /// unrecognized bodies are `Unknown`.
+    pub fn parse(body: &[u8]) -> Self {
+        let bridge_tag = body.get(TOKEN_ID_LEN).copied();
+        let recognized = match (body.len(), body.first().copied(), bridge_tag) {
+            (BATCH_LEN, Some(governance_types::BATCH), _)
+            | (TRANSFER_GOVERNOR_LEN, Some(governance_types::TRANSFER_GOVERNOR), _) => {
+                GovernanceMessage::read_from(&mut &body[..])
+                    .ok()
+                    .map(MessageBody::Governance)
+            }
+            (len, _, Some(bridge_types::TRANSFER | bridge_types::FAST_TRANSFER))
+                if len == TOKEN_ID_LEN + TRANSFER_LEN =>
+            {
+                BridgeMessage::read_from(&mut &body[..])
+                    .ok()
+                    .map(MessageBody::Bridge)
+            }
+            (len, _, Some(bridge_types::TRANSFER_TO_HOOK))
+                if len >= TOKEN_ID_LEN + TRANSFER_TO_HOOK_PREFIX_LEN =>
+            {
+                BridgeMessage::read_from(&mut &body[..])
+                    .ok()
+                    .map(MessageBody::Bridge)
+            }
+            _ => None,
+        };
+
+        recognized.unwrap_or_else(|| MessageBody::Unknown(body.to_vec()))
+    }
+
+    /// The kind of action this body carries
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            MessageBody::Bridge(message) => match message.action {
+                BridgeAction::Transfer { .. } => MessageKind::Transfer,
+                BridgeAction::FastTransfer { .. } => MessageKind::FastTransfer,
+                BridgeAction::TransferToHook { .. } => MessageKind::TransferToHook,
+            },
+            MessageBody::Governance(GovernanceMessage::Batch { .. }) => {
+                MessageKind::GovernanceBatch
+            }
+            MessageBody::Governance(GovernanceMessage::TransferGovernor { .. }) => {
+                MessageKind::TransferGovernor
+            }
+            MessageBody::Unknown(_) => MessageKind::Unknown,
+        }
+    }
+}
+
+impl Encode for MessageBody {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        match self {
+            MessageBody::Bridge(message) => message.write_to(writer),
+            MessageBody::Governance(message) => message.write_to(writer),
+            MessageBody::Unknown(body) => {
+                writer.write_all(body)?;
+                Ok(body.len())
+            }
+        }
+    }
+}
+
+impl Decode for MessageBody {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+    {
+        let mut body = vec![];
+        reader.read_to_end(&mut body)?;
+        Ok(Self::parse(&body))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip(body: MessageBody, kind: MessageKind) {
+        let encoded = body.to_vec();
+        assert_eq!(
+            MessageBody::read_from(&mut encoded.as_slice()).unwrap(),
+            body
+        );
+        assert_eq!(body.kind(), kind);
+        assert_eq!(kind.as_str().parse::<MessageKind>().unwrap(), kind);
+    }
+
+    #[test]
+    fn it_round_trips_bridge_messages() {
+        let token = TokenId {
+            domain: 6648936,
+            id: H256::repeat_byte(0x11),
+        };
+
+        round_trip(
+            MessageBody::Bridge(BridgeMessage {
+                token,
+                action: BridgeAction::Transfer {
+                    recipient: H256::repeat_byte(0x22),
+                    amount: U256::exp10(18),
+                    details_hash: H256::repeat_byte(0x33),
+                },
+            }),
+            MessageKind::Transfer,
+        );
+        round_trip(
+            MessageBody::Bridge(BridgeMessage {
+                token,
+                action: BridgeAction::FastTransfer {
+                    recipient: H256::repeat_byte(0x22),
+                    amount: U256::from(7),
+                    details_hash: H256::repeat_byte(0x33),
+                },
+            }),
+            MessageKind::FastTransfer,
+        );
+        round_trip(
+            MessageBody::Bridge(BridgeMessage {
+                token,
+                action: BridgeAction::TransferToHook {
+                    hook: H256::repeat_byte(0x44),
+                    amount: U256::from(7),
+                    details_hash: H256::repeat_byte(0x33),
+                    sender: H256::repeat_byte(0x55),
+                    extra_data: vec![1, 2, 3],
+                },
+            }),
+            MessageKind::TransferToHook,
+        );
+    }
+
+    #[test]
+    fn it_round_trips_governance_messages() {
+        let calls = vec![
+            Call {
+                to: H256::repeat_byte(0x01),
+                data: vec![0xde, 0xad],
+            },
+            Call::default(),
+        ];
+        round_trip(
+            MessageBody::Governance(GovernanceMessage::Batch {
+                batch_hash: Call::batch_hash(&calls),
+            }),
+            MessageKind::GovernanceBatch,
+        );
+        round_trip(
+            MessageBody::Governance(GovernanceMessage::TransferGovernor {
+                domain: 1000,
+                governor: H256::repeat_byte(0x66),
+            }),
+            MessageKind::TransferGovernor,
+        );
+    }
+
+    #[test]
+    fn it_falls_back_to_unknown() {
+        round_trip(MessageBody::Unknown(vec![]), MessageKind::Unknown);
+        round_trip(MessageBody::Unknown(vec![1, 2, 3]), MessageKind::Unknown);
+
+        // a transfer with a trailing byte is not a transfer
+        let mut body = BridgeMessage {
+            token: TokenId::default(),
+            action: BridgeAction::Transfer {
+                recipient: H256::zero(),
+                amount: U256::zero(),
+                details_hash: H256::zero(),
+            },
+        }
+        .to_vec();
+        body.push(0);
+        assert_eq!(MessageBody::parse(&body).kind(), MessageKind::Unknown);
+
+        assert!("notAKind".parse::<MessageKind>().is_err());
+    }
+
+    #[test]
+    fn it_rejects_router_bodies_sent_elsewhere() {
+        let routers = XAppRouters {
+            bridge: Some(H256::repeat_byte(0xb1)),
+            governance: Some(H256::repeat_byte(0x90)),
+        };
+        let transfer = BridgeMessage {
+            token: TokenId::default(),
+            action: BridgeAction::Transfer {
+                recipient: H256::zero(),
+                amount: U256::one(),
+                details_hash: H256::zero(),
+            },
+        }
+        .to_vec();
+        let batch = GovernanceMessage::Batch {
+            batch_hash: H256::repeat_byte(0x01),
+        }
+        .to_vec();
+
+        let kind = |body: &[u8], recipient| MessageBody::classify(body, recipient, &routers).kind();
+        assert_eq!(
+            kind(&transfer, H256::repeat_byte(0xb1)),
+            MessageKind::Transfer
+        );
+        assert_eq!(
+            kind(&batch, H256::repeat_byte(0x90)),
+            MessageKind::GovernanceBatch
+        );
+
+        // a spoofed transfer, sent to another contract or to the wrong router
+        assert_eq!(
+            kind(&transfer, H256::repeat_byte(0x5e)),
+            MessageKind::Unknown
+        );
+        assert_eq!(
+            kind(&transfer, H256::repeat_byte(0x90)),
+            MessageKind::Unknown
+        );
+        assert_eq!(kind(&batch, H256::repeat_byte(0xb1)), MessageKind::Unknown);
+
+        // no routers configured for the domain
+        let unknown =
+            MessageBody::classify(&transfer, H256::repeat_byte(0xb1), &Default::default());
+        assert_eq!(unknown, MessageBody::Unknown(transfer));
+    }
+}
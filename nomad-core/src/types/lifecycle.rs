@@ -0,0 +1,275 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ethers::types::H256;
+
+use crate::{Decode, Encode, MessageMeta, NomadError, UpdateMeta};
+
+/// A stage in the life of a message, in the order they are reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LifecycleStage {
+    /// Dispatched on the home
+    Dispatched,
+    /// Included in a signed update on the home
+    Updated,
+    /// Containing update relayed to the destination replica
+    Relayed,
+    /// Proven on the destination replica
+    Proven,
+    /// Processed on the destination replica
+    Processed,
+}
+
+impl LifecycleStage {
+    /// All stages, in order
+    pub const ALL: [LifecycleStage; 5] = [
+        LifecycleStage::Dispatched,
+        LifecycleStage::Updated,
+        LifecycleStage::Relayed,
+        LifecycleStage::Proven,
+        LifecycleStage::Processed,
+    ];
+}
+
+impl std::fmt::Display for LifecycleStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            LifecycleStage::Dispatched => "dispatched",
+            LifecycleStage::Updated => "updated",
+            LifecycleStage::Relayed => "relayed",
+            LifecycleStage::Proven => "proven",
+            LifecycleStage::Processed => "processed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Where and when a message reached a lifecycle stage. Any field may be
+/// unknown, e.g. when a stage was observed rather than performed by an agent.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LifecycleEvent {
+    /// Block number
+    pub block_number: Option<u64>,
+    /// Transaction hash
+    pub transaction_hash: Option<H256>,
+    /// Timestamp seconds
+    pub timestamp: Option<u64>,
+}
+
+impl LifecycleEvent {
+    /// An event for a transaction submitted by an agent, stamped with the
+    /// local time
+    pub fn submitted(transaction_hash: H256) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("!timestamp")
+            .as_secs();
+
+        Self {
+            block_number: None,
+            transaction_hash: Some(transaction_hash),
+            timestamp: Some(timestamp),
+        }
+    }
+}
+
+impl From<MessageMeta> for LifecycleEvent {
+    fn from(meta: MessageMeta) -> Self {
+        Self {
+            block_number: Some(meta.block_number),
+            transaction_hash: Some(meta.transaction_hash),
+            timestamp: meta.timestamp,
+        }
+    }
+}
+
+impl From<UpdateMeta> for LifecycleEvent {
+    fn from(meta: UpdateMeta) -> Self {
+        Self {
+            block_number: Some(meta.block_number),
+            transaction_hash: None,
+            timestamp: meta.timestamp,
+        }
+    }
+}
+
+impl Encode for LifecycleEvent {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        // Unknown fields are written as zero, as in `UpdateMeta`
+        let mut written = 0;
+        written += self.block_number.unwrap_or_default().write_to(writer)?;
+        written += self.transaction_hash.unwrap_or_default().write_to(writer)?;
+        written += self.timestamp.unwrap_or_default().write_to(writer)?;
+        Ok(written)
+    }
+}
+
+impl Decode for LifecycleEvent {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+        Self: Sized,
+    {
+        let block_number = u64::read_from(reader)?;
+        let transaction_hash = H256::read_from(reader)?;
+        let timestamp = u64::read_from(reader)?;
+
+        Ok(Self {
+            block_number: Some(block_number).filter(|n| *n != 0),
+            transaction_hash: Some(transaction_hash).filter(|h| !h.is_zero()),
+            timestamp: Some(timestamp).filter(|t| *t != 0),
+        })
+    }
+}
+
+/// The lifecycle of a single message, from dispatch to processing
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MessageLifecycle {
+    /// Leaf index of the message on the home
+    pub leaf_index: u32,
+    /// Destination domain
+    pub destination: u32,
+    /// Nonce on the destination
+    pub nonce: u32,
+    /// Dispatched on the home
+    pub dispatched: Option<LifecycleEvent>,
+    /// Included in a signed update on the home
+    pub updated: Option<LifecycleEvent>,
+    /// Containing update relayed to the destination replica
+    pub relayed: Option<LifecycleEvent>,
+    /// Proven on the destination replica
+    pub proven: Option<LifecycleEvent>,
+    /// Processed on the destination replica
+    pub processed: Option<LifecycleEvent>,
+}
+
+impl MessageLifecycle {
+    /// Instantiate a lifecycle with no stages reached
+    pub fn new(leaf_index: u32, destination: u32, nonce: u32) -> Self {
+        Self {
+            leaf_index,
+            destination,
+            nonce,
+            ..Default::default()
+        }
+    }
+
+    /// Get the event for a stage, if it has been reached
+    pub fn event(&self, stage: LifecycleStage) -> Option<&LifecycleEvent> {
+        match stage {
+            LifecycleStage::Dispatched => self.dispatched.as_ref(),
+            LifecycleStage::Updated => self.updated.as_ref(),
+            LifecycleStage::Relayed => self.relayed.as_ref(),
+            LifecycleStage::Proven => self.proven.as_ref(),
+            LifecycleStage::Processed => self.processed.as_ref(),
+        }
+    }
+
+    /// Record the event for a stage, replacing any previous event
+    pub fn record(&mut self, stage: LifecycleStage, event: LifecycleEvent) {
+        let slot = match stage {
+            LifecycleStage::Dispatched => &mut self.dispatched,
+            LifecycleStage::Updated => &mut self.updated,
+            LifecycleStage::Relayed => &mut self.relayed,
+            LifecycleStage::Proven => &mut self.proven,
+            LifecycleStage::Processed => &mut self.processed,
+        };
+        *slot = Some(event);
+    }
+
+    /// The latest stage the message has reached
+    pub fn stage(&self) -> Option<LifecycleStage> {
+        LifecycleStage::ALL
+            .iter()
+            .rev()
+            .find(|stage| self.event(**stage).is_some())
+            .copied()
+    }
+}
+
+impl Encode for MessageLifecycle {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        let mut written = 0;
+        written += self.leaf_index.write_to(writer)?;
+        written += self.destination.write_to(writer)?;
+        written += self.nonce.write_to(writer)?;
+
+        for stage in LifecycleStage::ALL.iter() {
+            match self.event(*stage) {
+                Some(event) => {
+                    writer.write_all(&[1])?;
+                    written += 1 + event.write_to(writer)?;
+                }
+                None => {
+                    writer.write_all(&[0])?;
+                    written += 1;
+                }
+            }
+        }
+
+        Ok(written)
+    }
+}
+
+impl Decode for MessageLifecycle {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+        Self: Sized,
+    {
+        let mut lifecycle = Self::new(
+            u32::read_from(reader)?,
+            u32::read_from(reader)?,
+            u32::read_from(reader)?,
+        );
+
+        for stage in LifecycleStage::ALL.iter() {
+            let mut flag = [0u8; 1];
+            reader.read_exact(&mut flag)?;
+            if flag[0] != 0 {
+                lifecycle.record(*stage, LifecycleEvent::read_from(reader)?);
+            }
+        }
+
+        Ok(lifecycle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_round_trips_lifecycles() {
+        let mut lifecycle = MessageLifecycle::new(3, 2000, 7);
+        assert_eq!(lifecycle.stage(), None);
+        assert_eq!(
+            MessageLifecycle::read_from(&mut lifecycle.to_vec().as_slice()).unwrap(),
+            lifecycle
+        );
+
+        lifecycle.record(
+            LifecycleStage::Dispatched,
+            LifecycleEvent {
+                block_number: Some(100),
+                transaction_hash: Some(H256::repeat_byte(1)),
+                timestamp: Some(1_650_000_000),
+            },
+        );
+        lifecycle.record(
+            LifecycleStage::Processed,
+            LifecycleEvent::submitted(H256::repeat_byte(2)),
+        );
+        assert_eq!(lifecycle.stage(), Some(LifecycleStage::Processed));
+        assert!(lifecycle.event(LifecycleStage::Proven).is_none());
+        assert_eq!(
+            MessageLifecycle::read_from(&mut lifecycle.to_vec().as_slice()).unwrap(),
+            lifecycle
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use ethers::{types::H256, utils::keccak256};
 
-use crate::{utils, Decode, Encode, NomadError};
+use crate::{utils, Decode, Encode, MessageBody, NomadError, XAppRouters};
 
 const NOMAD_MESSAGE_PREFIX_LEN: usize = 76;
 
@@ -91,6 +91,12 @@ impl NomadMessage {
     pub fn destination_and_nonce(&self) -> u64 {
         utils::destination_and_nonce(self.destination, self.nonce)
     }
+
+    /// Decode the message body into a typed bridge or governance payload,
+    /// given the routers of the destination domain
+    pub fn decoded_body(&self, routers: &XAppRouters) -> MessageBody {
+        MessageBody::classify(&self.body, self.recipient, routers)
+    }
 }
 
 impl std::fmt::Display for NomadMessage {
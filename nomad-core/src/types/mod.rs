@@ -1,7 +1,14 @@
+mod block;
+/// Typed message bodies for the bridge and governance routers
+pub mod body;
 mod failure;
+mod lifecycle;
 mod messages;
 mod update;
 
+pub use block::*;
+pub use body::{MessageBody, MessageKind, XAppRouters};
 pub use failure::*;
+pub use lifecycle::*;
 pub use messages::*;
 pub use update::*;
@@ -1,6 +1,6 @@
 use std::fmt::Display;
 
-use crate::{utils::home_domain_hash, Decode, Encode, NomadError, SignerExt};
+use crate::{utils::home_domain_hash, BlockHeader, Decode, Encode, NomadError, SignerExt};
 use ethers::{
     prelude::{Address, Signature},
     types::H256,
@@ -103,6 +103,21 @@ pub struct UpdateMeta {
     pub block_number: u64,
     /// Timestamp seconds (optional because fetching timestamp is fallible)
     pub timestamp: Option<u64>,
+    /// Hash of the block the update was emitted in. Zero if unknown
+    pub block_hash: H256,
+    /// Hash of the parent of that block. Zero if unknown
+    pub parent_hash: H256,
+}
+
+impl UpdateMeta {
+    /// The header of the block the update was emitted in
+    pub fn block(&self) -> BlockHeader {
+        BlockHeader {
+            number: self.block_number,
+            hash: self.block_hash,
+            parent_hash: self.parent_hash,
+        }
+    }
 }
 
 impl Encode for UpdateMeta {
@@ -119,6 +134,9 @@ impl Encode for UpdateMeta {
             written += 0_u64.write_to(writer)?;
         }
 
+        written += self.block_hash.write_to(writer)?;
+        written += self.parent_hash.write_to(writer)?;
+
         Ok(written)
     }
 }
@@ -141,9 +159,14 @@ impl Decode for UpdateMeta {
             Some(timestamp_num)
         };
 
+        let block_hash = H256::read_from(reader)?;
+        let parent_hash = H256::read_from(reader)?;
+
         Ok(Self {
             block_number: u64::from_be_bytes(block_number),
             timestamp,
+            block_hash,
+            parent_hash,
         })
     }
 }
@@ -212,3 +235,28 @@ impl SignedUpdate {
             .verify(self.update.prepended_hash(), signer)?)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_decodes_metadata_of_fixed_length() {
+        let meta = UpdateMeta {
+            block_number: 100,
+            timestamp: Some(1_650_000_000),
+            block_hash: H256::repeat_byte(1),
+            parent_hash: H256::repeat_byte(2),
+        };
+        let encoded = meta.to_vec();
+        assert_eq!(
+            UpdateMeta::read_from(&mut encoded.as_slice()).unwrap(),
+            meta
+        );
+
+        // Metadata stored before block hashes were recorded is padded by
+        // the schema version 3 migration
+        assert!(UpdateMeta::read_from(&mut &encoded[..16]).is_err());
+        assert!(UpdateMeta::read_from(&mut &encoded[..48]).is_err());
+    }
+}
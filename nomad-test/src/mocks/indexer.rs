@@ -10,9 +10,11 @@ mock! {
     pub Indexer {
         pub fn _get_block_number(&self) -> Result<u32> {}
 
+        pub fn _get_block_header(&self, number: u32) -> Result<Option<BlockHeader>> {}
+
         pub fn _fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {}
 
-        pub fn _fetch_sorted_messages(&self, from: u32, to: u32) -> Result<Vec<RawCommittedMessage>> {}
+        pub fn _fetch_sorted_messages(&self, from: u32, to: u32) -> Result<Vec<RawCommittedMessageWithMeta>> {}
     }
 }
 
@@ -28,6 +30,10 @@ impl CommonIndexer for MockIndexer {
         self._get_block_number()
     }
 
+    async fn get_block_header(&self, number: u32) -> Result<Option<BlockHeader>> {
+        self._get_block_header(number)
+    }
+
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {
         self._fetch_sorted_updates(from, to)
     }
@@ -35,7 +41,7 @@ impl CommonIndexer for MockIndexer {
 
 #[async_trait]
 impl HomeIndexer for MockIndexer {
-    async fn fetch_sorted_messages(&self, from: u32, to: u32) -> Result<Vec<RawCommittedMessage>> {
+    async fn fetch_sorted_messages(&self, from: u32, to: u32) -> Result<Vec<RawCommittedMessageWithMeta>> {
         self._fetch_sorted_messages(from, to)
     }
 }
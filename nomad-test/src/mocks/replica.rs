@@ -43,6 +43,8 @@ mock! {
 
         pub fn _message_status(&self, leaf: H256) -> Result<MessageStatus, ChainCommunicationError> {}
 
+        pub fn _process_transaction(&self, leaf: H256) -> Result<Option<H256>, ChainCommunicationError> {}
+
         pub fn _acceptable_root(&self, root: H256) -> Result<bool, ChainCommunicationError> {}
     }
 }
@@ -83,6 +85,13 @@ impl Replica for MockReplicaContract {
         self._message_status(leaf)
     }
 
+    async fn process_transaction(
+        &self,
+        leaf: H256,
+    ) -> Result<Option<H256>, ChainCommunicationError> {
+        self._process_transaction(leaf)
+    }
+
     async fn acceptable_root(&self, root: H256) -> Result<bool, ChainCommunicationError> {
         self._acceptable_root(root)
     }
@@ -1,5 +1,9 @@
+use ethers::core::types::{Signature, H256, U256};
 use futures_util::FutureExt;
-use nomad_core::db::DB;
+use nomad_core::{
+    db::DB, BlockHeader, Encode, MessageMeta, NomadMessage, RawCommittedMessage,
+    RawCommittedMessageWithMeta, SignedUpdate, SignedUpdateWithMeta, Update, UpdateMeta,
+};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use std::{future::Future, panic};
@@ -8,11 +12,7 @@ use rocksdb::Options;
 
 /// Sets up a db
 pub fn setup_db(db_path: String) -> DB {
-    let mut opts = Options::default();
-    opts.create_if_missing(true);
-    rocksdb::DB::open(&opts, db_path)
-        .expect("Failed to open db path")
-        .into()
+    DB::open(db_path).expect("Failed to open db path")
 }
 
 /// Runs test for a db
@@ -38,3 +38,70 @@ where
     let _ = rocksdb::DB::destroy(&Options::default(), rand_path);
     assert!(result.is_ok())
 }
+
+/// Header of block `number` on the chain `fork`. Blocks of different forks
+/// have different hashes
+pub fn header(number: u64, fork: u64) -> BlockHeader {
+    let hash = |number: u64| H256::from_low_u64_be(number | fork << 32);
+    BlockHeader {
+        number,
+        hash: hash(number),
+        parent_hash: hash(number.saturating_sub(1)),
+    }
+}
+
+/// Message from domain 1 to domain 2 with nonce `leaf_index`, committed at
+/// `leaf_index` and dispatched in `block`
+pub fn dispatched_message(
+    leaf_index: u32,
+    body: &[u8],
+    block: BlockHeader,
+) -> RawCommittedMessageWithMeta {
+    RawCommittedMessageWithMeta {
+        raw_message: RawCommittedMessage {
+            leaf_index,
+            committed_root: H256::zero(),
+            message: NomadMessage {
+                origin: 1,
+                sender: H256::from_low_u64_be(4),
+                nonce: leaf_index,
+                destination: 2,
+                recipient: H256::from_low_u64_be(5),
+                body: body.to_vec(),
+            }
+            .to_vec(),
+        },
+        metadata: MessageMeta {
+            block_number: block.number,
+            transaction_hash: H256::from_low_u64_be(6),
+            timestamp: None,
+            block_hash: block.hash,
+            parent_hash: block.parent_hash,
+        },
+    }
+}
+
+/// Update of domain 10 from `previous_root` to `new_root`, emitted in
+/// `block`. The signature is a placeholder
+pub fn update(previous_root: H256, new_root: H256, block: BlockHeader) -> SignedUpdateWithMeta {
+    SignedUpdateWithMeta {
+        signed_update: SignedUpdate {
+            update: Update {
+                home_domain: 10,
+                previous_root,
+                new_root,
+            },
+            signature: Signature {
+                r: U256::zero(),
+                s: U256::zero(),
+                v: 27,
+            },
+        },
+        metadata: UpdateMeta {
+            block_number: block.number,
+            timestamp: None,
+            block_hash: block.hash,
+            parent_hash: block.parent_hash,
+        },
+    }
+}
@@ -1,6 +1,6 @@
 use structopt::StructOpt;
 
-use crate::subcommands::{db_state::DbStateCommand, prove::ProveCommand};
+use crate::subcommands::{db::DbCommand, db_state::DbStateCommand, prove::ProveCommand};
 
 #[derive(StructOpt)]
 pub enum Commands {
@@ -8,4 +8,6 @@ pub enum Commands {
     Prove(ProveCommand),
     /// Print the processor's db state
     DbState(DbStateCommand),
+    /// Export, import and checkpoint dbs
+    Db(DbCommand),
 }
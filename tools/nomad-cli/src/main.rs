@@ -15,5 +15,6 @@ async fn main() -> Result<()> {
     match command {
         Commands::Prove(prove) => prove.run().await,
         Commands::DbState(db_state) => db_state.run().await,
+        Commands::Db(db) => db.run().await,
     }
 }
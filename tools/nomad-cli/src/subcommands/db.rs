@@ -0,0 +1,136 @@
+use color_eyre::{eyre::bail, Result};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+};
+use structopt::StructOpt;
+
+use nomad_base::NomadDB;
+use nomad_core::db::{ExportHeader, DB};
+
+use crate::subcommands::db_verify::DbVerifyCommand;
+
+#[derive(StructOpt, Debug)]
+pub enum DbCommand {
+    /// Export an entity (e.g. a home) from a db to a portable file
+    Export(DbExportCommand),
+    /// Import an entity from a file written by `db export`
+    Import(DbImportCommand),
+    /// Write a consistent copy of a db
+    Checkpoint(DbCheckpointCommand),
+    /// Check a db's invariants, printing a json report. Exits non-zero if
+    /// any are violated
+    Verify(DbVerifyCommand),
+}
+
+impl DbCommand {
+    pub async fn run(&self) -> Result<()> {
+        match self {
+            DbCommand::Export(export) => export.run(),
+            DbCommand::Import(import) => import.run(),
+            DbCommand::Checkpoint(checkpoint) => checkpoint.run(),
+            DbCommand::Verify(verify) => verify.run().await,
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub struct DbExportCommand {
+    /// Path to db
+    #[structopt(long)]
+    db_path: String,
+
+    /// Name of the entity to export, e.g. the home name
+    #[structopt(long)]
+    home_name: String,
+
+    /// File to write the export to
+    #[structopt(long)]
+    output: PathBuf,
+}
+
+impl DbExportCommand {
+    fn run(&self) -> Result<()> {
+        let db = DB::open_current(&self.db_path)?;
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&self.output)?;
+        let entries = db.export_entity(&self.home_name, &mut BufWriter::new(file))?;
+
+        println!(
+            "Exported {} entries for {} to {}",
+            entries,
+            self.home_name,
+            self.output.display()
+        );
+        Ok(())
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub struct DbImportCommand {
+    /// Path to db. Created if missing
+    #[structopt(long)]
+    db_path: String,
+
+    /// Entity to import as. Defaults to the exported entity
+    #[structopt(long)]
+    home_name: Option<String>,
+
+    /// File written by `db export`
+    #[structopt(long)]
+    input: PathBuf,
+
+    /// Import even if the db already holds data for the entity
+    #[structopt(long)]
+    overwrite: bool,
+}
+
+impl DbImportCommand {
+    fn run(&self) -> Result<()> {
+        let header = ExportHeader::read_from(&mut File::open(&self.input)?)?;
+        let entity = self.home_name.as_ref().unwrap_or(&header.entity);
+
+        let db = DB::from_path(&self.db_path)?;
+        if !self.overwrite && !NomadDB::new(entity, db.clone()).is_empty()? {
+            bail!(
+                "db at {} already holds data for {}, pass --overwrite to import anyway",
+                self.db_path,
+                entity
+            );
+        }
+
+        let mut file = BufReader::new(File::open(&self.input)?);
+        let entries = db.import_entity(entity, &mut file)?;
+
+        println!(
+            "Imported {} entries for {} as {}",
+            entries, header.entity, entity
+        );
+        Ok(())
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub struct DbCheckpointCommand {
+    /// Path to db
+    #[structopt(long)]
+    db_path: String,
+
+    /// Directory to write the checkpoint to. Must not exist
+    #[structopt(long)]
+    output: PathBuf,
+}
+
+impl DbCheckpointCommand {
+    fn run(&self) -> Result<()> {
+        let db = DB::from_path(&self.db_path)?;
+        db.checkpoint(&self.output)?;
+
+        println!("Wrote checkpoint to {}", self.output.display());
+        Ok(())
+    }
+}
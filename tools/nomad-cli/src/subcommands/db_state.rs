@@ -1,10 +1,16 @@
 use color_eyre::Result;
 use serde_json::{json, Value};
-use std::{collections::HashMap, convert::TryInto, fs::OpenOptions, io::Write};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fs::{File, OpenOptions},
+    io::{BufReader, Write},
+};
 use structopt::StructOpt;
 
 use nomad_base::NomadDB;
-use nomad_core::{db::DB, CommittedMessage};
+use nomad_core::{db::DB, CommittedMessage, MessageKind, XAppRouters};
+use nomad_xyz_configuration::NomadConfig;
 
 use ethers::types::H256;
 
@@ -21,6 +27,15 @@ pub struct DbStateCommand {
     /// Save output to json file
     #[structopt(long)]
     json: bool,
+
+    /// Only include messages of this kind (e.g. transfer, governanceBatch)
+    #[structopt(long)]
+    kind: Option<MessageKind>,
+
+    /// Path to the Nomad config, used to recognize messages sent to the
+    /// xApp routers. Without it, all messages are of kind unknown
+    #[structopt(long)]
+    config: Option<String>,
 }
 
 type OutputVec = Vec<((H256, u64), Vec<CommittedMessage>)>;
@@ -28,23 +43,37 @@ type OutputVec = Vec<((H256, u64), Vec<CommittedMessage>)>;
 impl DbStateCommand {
     pub async fn run(&self) -> Result<()> {
         let db = NomadDB::new(&self.home_name, DB::from_path(&self.db_path)?);
+        let routers = self.routers()?;
 
-        let messages_by_committed_roots = self.create_comitted_root_to_message_map(&db)?;
+        let messages_by_committed_roots =
+            self.create_comitted_root_to_message_map(&db, &routers)?;
 
         let output_vec = self.create_output_vec(&db, messages_by_committed_roots)?;
 
         if self.json {
-            DbStateCommand::save_to_json(output_vec)?;
+            DbStateCommand::save_to_json(output_vec, &routers)?;
         } else {
-            DbStateCommand::print_output(output_vec);
+            DbStateCommand::print_output(output_vec, &routers);
         }
 
         Ok(())
     }
 
+    fn routers(&self) -> Result<HashMap<u32, XAppRouters>> {
+        Ok(match &self.config {
+            Some(path) => {
+                let config: NomadConfig =
+                    serde_json::from_reader(BufReader::new(File::open(path)?))?;
+                XAppRouters::from_config(&config)
+            }
+            None => Default::default(),
+        })
+    }
+
     fn create_comitted_root_to_message_map(
         &self,
         db: &NomadDB,
+        routers: &HashMap<u32, XAppRouters>,
     ) -> Result<HashMap<H256, Vec<CommittedMessage>>> {
         let mut messages_by_committed_roots: HashMap<H256, Vec<CommittedMessage>> = HashMap::new();
         for index in 0.. {
@@ -54,6 +83,12 @@ impl DbStateCommand {
                         println!("Failed to find proof for leaf index {}!", index);
                     }
 
+                    let message: CommittedMessage = message.try_into()?;
+                    let kind = kind(&message, routers);
+                    if matches!(self.kind, Some(filter) if filter != kind) {
+                        continue;
+                    }
+
                     let committed_root = message.committed_root;
                     let bucket_opt = messages_by_committed_roots.get_mut(&committed_root);
 
@@ -70,8 +105,9 @@ impl DbStateCommand {
                     };
 
                     // Add message to bucket for committed root
-                    bucket.push(message.try_into()?);
+                    bucket.push(message);
                 }
+                None if db.is_pruned(index)? => continue,
                 None => break,
             }
         }
@@ -112,7 +148,7 @@ impl DbStateCommand {
         Ok(output_vec)
     }
 
-    fn print_output(output_vec: OutputVec) {
+    fn print_output(output_vec: OutputVec, routers: &HashMap<u32, XAppRouters>) {
         for ((update_root, block_number), mut bucket) in output_vec {
             println!("Update root: {:?}", update_root);
             println!("Block number: {}", block_number);
@@ -120,23 +156,28 @@ impl DbStateCommand {
             bucket.sort_by(|x, y| x.leaf_index.cmp(&y.leaf_index));
             print!("Leaves:");
             for message in bucket {
-                print!(" {} ", message.leaf_index);
+                print!(" {} ({}) ", message.leaf_index, kind(&message, routers));
             }
 
             println!("\n");
         }
     }
 
-    fn save_to_json(output_vec: OutputVec) -> Result<()> {
+    fn save_to_json(output_vec: OutputVec, routers: &HashMap<u32, XAppRouters>) -> Result<()> {
         let mut json_entries: Vec<Value> = Vec::new();
         for ((update_root, block_number), mut bucket) in output_vec {
             bucket.sort_by(|x, y| x.leaf_index.cmp(&y.leaf_index));
             let leaf_indexes: Vec<_> = bucket.iter().map(|leaf| leaf.leaf_index).collect();
+            let kinds: Vec<_> = bucket
+                .iter()
+                .map(|leaf| kind(leaf, routers).to_string())
+                .collect();
 
             json_entries.push(json!({
                 "updateRoot": update_root,
                 "blockNumber": block_number,
                 "leaves": leaf_indexes,
+                "kinds": kinds,
             }));
         }
 
@@ -155,3 +196,12 @@ impl DbStateCommand {
         Ok(())
     }
 }
+
+/// The kind of `message`, given the routers of its destination
+fn kind(message: &CommittedMessage, routers: &HashMap<u32, XAppRouters>) -> MessageKind {
+    let routers = routers
+        .get(&message.message.destination)
+        .copied()
+        .unwrap_or_default();
+    message.message.decoded_body(&routers).kind()
+}
@@ -0,0 +1,217 @@
+use color_eyre::{eyre::bail, Result};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use structopt::StructOpt;
+
+use nomad_base::NomadDB;
+use nomad_core::{accumulator::MerkleProof, db::DB};
+
+use ethers::types::H256;
+
+#[derive(StructOpt, Debug)]
+pub struct DbVerifyCommand {
+    /// Path to db
+    #[structopt(long)]
+    db_path: String,
+
+    /// Name of associated home
+    #[structopt(long)]
+    home_name: String,
+}
+
+/// Result of walking the db. Issues are invariant violations, each a json
+/// object with a `kind` and the offending keys.
+#[derive(Debug, Default)]
+struct Report {
+    leaves: u32,
+    proofs: u32,
+    updates: u32,
+    issues: Vec<Value>,
+}
+
+impl Report {
+    fn issue(&mut self, issue: Value) {
+        self.issues.push(issue);
+    }
+
+    fn to_json(&self, home_name: &str) -> Value {
+        json!({
+            "home": home_name,
+            "ok": self.issues.is_empty(),
+            "leaves": self.leaves,
+            "proofs": self.proofs,
+            "updates": self.updates,
+            "issues": self.issues,
+        })
+    }
+}
+
+impl DbVerifyCommand {
+    /// Walk the db, print a json report, and error if any invariant is
+    /// violated
+    pub async fn run(&self) -> Result<()> {
+        let db = NomadDB::new(&self.home_name, DB::open_current(&self.db_path)?);
+
+        let mut report = Report::default();
+        let roots = Self::verify_updates(&db, &mut report)?;
+        Self::verify_leaves(&db, &roots, &mut report)?;
+
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report.to_json(&self.home_name))?
+        );
+
+        if !report.issues.is_empty() {
+            bail!("Found {} issues in db", report.issues.len());
+        }
+        Ok(())
+    }
+
+    /// Walk the update chain from the zero root. Every update must be
+    /// reachable by its new root, have metadata, and the chain must end at
+    /// the latest root without revisiting a root. No two updates may build
+    /// off the same root. Returns all roots in the chain.
+    fn verify_updates(db: &NomadDB, report: &mut Report) -> Result<HashSet<H256>> {
+        // Only one update is stored per previous root, so a fork overwrites
+        // it. The index by new root keeps both.
+        let mut new_roots: HashMap<H256, Vec<H256>> = HashMap::new();
+        for (previous_root, new_root) in db.update_roots()? {
+            new_roots.entry(previous_root).or_default().push(new_root);
+        }
+        for (previous_root, new_roots) in new_roots {
+            if new_roots.len() > 1 {
+                report.issue(json!({
+                    "kind": "conflictingUpdates",
+                    "previousRoot": previous_root,
+                    "newRoots": new_roots,
+                }));
+            }
+        }
+
+        let mut roots = HashSet::new();
+        let mut current = H256::zero();
+        roots.insert(current);
+
+        while let Some(update) = db.update_by_previous_root(current)? {
+            let new_root = update.update.new_root;
+            report.updates += 1;
+
+            if update.update.previous_root != current {
+                report.issue(json!({
+                    "kind": "misfiledUpdate",
+                    "previousRoot": current,
+                    "storedPreviousRoot": update.update.previous_root,
+                }));
+            }
+
+            // A different update stored under the same new root means the
+            // chain forked
+            match db.update_by_new_root(new_root)? {
+                Some(indexed) if indexed == update => {}
+                Some(indexed) => report.issue(json!({
+                    "kind": "forkedUpdate",
+                    "newRoot": new_root,
+                    "previousRoot": current,
+                    "otherPreviousRoot": indexed.update.previous_root,
+                })),
+                None => report.issue(json!({
+                    "kind": "unindexedUpdate",
+                    "newRoot": new_root,
+                })),
+            }
+
+            if db.retrieve_update_metadata(new_root)?.is_none() {
+                report.issue(json!({
+                    "kind": "missingUpdateMetadata",
+                    "newRoot": new_root,
+                }));
+            }
+
+            if !roots.insert(new_root) {
+                report.issue(json!({
+                    "kind": "updateCycle",
+                    "newRoot": new_root,
+                }));
+                break;
+            }
+            current = new_root;
+        }
+
+        let latest_root = db.retrieve_latest_root()?;
+        if latest_root.unwrap_or_default() != current {
+            report.issue(json!({
+                "kind": "latestRootMismatch",
+                "latestRoot": latest_root,
+                "chainTip": current,
+            }));
+        }
+
+        Ok(roots)
+    }
+
+    /// Check every leaf up to the latest leaf index. Leaves must be
+    /// contiguous, hash their message unless pruned, and any stored proof
+    /// must prove the leaf against a root in the update chain.
+    fn verify_leaves(db: &NomadDB, roots: &HashSet<H256>, report: &mut Report) -> Result<()> {
+        let latest = match db.retrieve_latest_leaf_index()? {
+            Some(latest) => latest,
+            None => {
+                if db.leaf_by_leaf_index(0)?.is_some() {
+                    report.issue(json!({ "kind": "missingLatestLeafIndex" }));
+                }
+                return Ok(());
+            }
+        };
+
+        // Leaves past the latest index are legal, e.g. when messages were
+        // stored out of order, so walk until both are exhausted
+        for index in 0.. {
+            let leaf = match db.leaf_by_leaf_index(index)? {
+                Some(leaf) => leaf,
+                None if index <= latest => {
+                    report.issue(json!({ "kind": "missingLeaf", "leafIndex": index }));
+                    continue;
+                }
+                None => break,
+            };
+            report.leaves += 1;
+
+            match db.message_by_leaf(leaf)? {
+                Some(message) => {
+                    if message.leaf() != leaf || message.leaf_index != index {
+                        report.issue(json!({
+                            "kind": "leafMismatch",
+                            "leafIndex": index,
+                            "leaf": leaf,
+                            "messageLeaf": message.leaf(),
+                            "messageLeafIndex": message.leaf_index,
+                        }));
+                    }
+                }
+                None if db.is_pruned(index)? => {}
+                None => report.issue(json!({
+                    "kind": "missingMessage",
+                    "leafIndex": index,
+                    "leaf": leaf,
+                })),
+            }
+
+            if let Some(proof) = db.proof_by_leaf_index(index)? {
+                report.proofs += 1;
+                let root = proof.root();
+                if proof.leaf != leaf || proof.index != index as usize || !roots.contains(&root) {
+                    report.issue(json!({
+                        "kind": "invalidProof",
+                        "leafIndex": index,
+                        "leaf": leaf,
+                        "proofLeaf": proof.leaf,
+                        "proofIndex": proof.index,
+                        "proofRoot": root,
+                    }));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
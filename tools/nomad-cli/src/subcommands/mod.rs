@@ -1,5 +1,9 @@
+pub mod db;
 pub mod db_state;
+pub mod db_verify;
 pub mod prove;
 
+pub use db::*;
 pub use db_state::*;
+pub use db_verify::*;
 pub use prove::*;